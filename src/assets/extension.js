@@ -1,8 +1,15 @@
 (function () {{
     const SERVER = '{server}';
+    const TOKEN = {token};
 
     const OUTPUT_UPDATE_INTERVAL_MS = {pull_interval_ms};
     const OUTPUT_MAX_SIZE = 1024 * 1024;
+    const SESSION_STORAGE_KEY = 'netsblox-vm-session';
+
+    let SESSION = null;
+    function sessionUrl(path) {{
+        return `${{SERVER}}/sessions/${{SESSION}}${{path}}`;
+    }}
 
     function request(info) {{
         const req = new XMLHttpRequest();
@@ -16,6 +23,7 @@
             }}
         }};
         req.open(info.method, info.url, true);
+        if (TOKEN !== null) req.setRequestHeader('Authorization', `Bearer ${{TOKEN}}`);
         req.send(info.body);
     }}
 
@@ -32,6 +40,46 @@
         return new XMLSerializer().serializeToString(xml);
     }}
 
+    function findBlocks(ide) {{
+        const lookup = {{}};
+        const walk = root => {{
+            if (root.id) (lookup[root.id] || (lookup[root.id] = [])).push(root);
+            for (const child of root.children) {{
+                walk(child);
+            }}
+        }};
+
+        walk(world);
+        for (const block of ide.stage.globalBlocks) {{
+            walk(block.body.expression);
+        }}
+        for (const entity of [ide.stage, ...ide.sprites.contents]) {{
+            for (const block of entity.customBlocks) {{
+                walk(block.body.expression);
+            }}
+        }}
+        return lookup;
+    }}
+
+    const breakpoints = new Set();
+    const oldBlockUserMenu = BlockMorph.prototype.userMenu;
+    BlockMorph.prototype.userMenu = function () {{
+        const menu = oldBlockUserMenu.call(this);
+        if (this.id) {{
+            menu.addLine();
+            menu.addItem(breakpoints.has(this.id) ? 'remove breakpoint' : 'add breakpoint', () => {{
+                if (!breakpoints.delete(this.id)) breakpoints.add(this.id);
+                request({{
+                    method: 'POST',
+                    url: sessionUrl('/debug/breakpoints'),
+                    onErr: alert,
+                    body: JSON.stringify([...breakpoints]),
+                }});
+            }});
+        }}
+        return menu;
+    }};
+
     function TerminalMorph(ext) {{
         this.init();
         this.ext = ext;
@@ -59,6 +107,9 @@
 
         this.doUpdates = false;
         this.previousRunning = null;
+        this.previousBreak = null;
+        this.breakBlocks = [];
+        this.watchers = new Map();
 
         this.bounds.setWidth(Math.max(this.defaultWidth, this.minWidth));
         this.bounds.setHeight(Math.max(this.defaultHeight, this.minHeight));
@@ -93,7 +144,7 @@
 
         this.leftTools.add(this.setProjectButton = new PushButtonMorph(null, () => request({{
             method: 'POST',
-            url: `${{SERVER}}/project`,
+            url: sessionUrl('/project'),
             onErr: alert,
             body: cleanXML(this.ext.ide.getSerializedRole()),
         }}), 'Upload'));
@@ -101,14 +152,15 @@
         this.leftTools.add(makeSpacer(10));
 
         this.leftTools.add(this.getProjectButton = new PushButtonMorph(null, () => {{
-            window.open(`${{SERVER}}/project`, '_blank');
+            const query = TOKEN !== null ? `?token=${{encodeURIComponent(TOKEN)}}` : '';
+            window.open(sessionUrl(`/project${{query}}`), '_blank');
         }}, 'Download'));
 
         // ----------------------------------------------------------------------------------------
 
         this.centerTools.add(this.startButton = new PushButtonMorph(null, () => request({{
             method: 'POST',
-            url: `${{SERVER}}/input`,
+            url: sessionUrl('/input'),
             onErr: alert,
             body: 'start',
         }}), new SymbolMorph('flag', 12)));
@@ -122,7 +174,7 @@
 
         this.centerTools.add(this.togglePausedButton = new PushButtonMorph(null, () => request({{
             method: 'POST',
-            url: `${{SERVER}}/toggle-paused`,
+            url: sessionUrl('/toggle-paused'),
             onErr: alert,
         }}), '$'));
         this.togglePausedButton.color = darkBackgroundColor;
@@ -135,7 +187,7 @@
 
         this.centerTools.add(this.stopButton = new PushButtonMorph(null, () => request({{
             method: 'POST',
-            url: `${{SERVER}}/input`,
+            url: sessionUrl('/input'),
             onErr: alert,
             body: 'stop',
         }}), new SymbolMorph('octagon', 12)));
@@ -145,6 +197,32 @@
         this.stopButton.label.color = new Color(200, 0, 0);
         this.stopButton.label.shadowColor = null;
 
+        this.centerTools.add(makeSpacer(20));
+
+        const debugButton = (label, path, body) => {{
+            const button = new PushButtonMorph(null, () => request({{
+                method: 'POST',
+                url: sessionUrl(`/debug/${{path}}`),
+                onErr: alert,
+                body,
+            }}), label);
+            button.color = darkBackgroundColor;
+            button.highlightColor = darkHighlightColor;
+            button.pressColor = darkPressColor;
+            button.labelColor = new Color(255, 255, 255);
+            button.labelShadowColor = null;
+            button.fixLayout();
+            button.rerender();
+            this.centerTools.add(button);
+            this.centerTools.add(makeSpacer(5));
+            return button;
+        }};
+        this.breakButton = debugButton('Break', 'break');
+        this.continueButton = debugButton('Continue', 'resume', 'continue');
+        this.stepButton = debugButton('Step', 'resume', 'step');
+        this.stepOverButton = debugButton('Over', 'resume', 'step-over');
+        this.stepOutButton = debugButton('Out', 'resume', 'step-out');
+
         // ----------------------------------------------------------------------------------------
 
         this.rightTools.add(this.clearButton = new PushButtonMorph(null, () => this.setText(''), 'Clear'));
@@ -161,16 +239,16 @@
         this.fixLayout();
 
         const updateLoop = () => {{
-            if (!this.doUpdates) {{
+            if (!this.doUpdates || SESSION === null) {{
                 this.updateLoopTimer = setTimeout(updateLoop, OUTPUT_UPDATE_INTERVAL_MS);
                 return;
             }}
 
             request({{
                 method: 'POST',
-                url: `${{SERVER}}/pull`,
+                url: sessionUrl('/pull'),
                 onOk: res => {{
-                    const {{ running, output, errors }} = JSON.parse(res);
+                    const {{ running, output, errors, debug, watchers }} = JSON.parse(res);
                     try {{
                         if (this.previousRunning !== running) {{
                             this.previousRunning = running;
@@ -186,25 +264,20 @@
                             this.setText(clipped);
                             this.gotoBottom();
                         }}
-                        if (errors.length > 0) {{
-                            const ide = this.ext.ide;
-                            const lookup = {{}};
-                            const walk = root => {{
-                                if (root.id) (lookup[root.id] || (lookup[root.id] = [])).push(root);
-                                for (const child of root.children) {{
-                                    walk(child);
-                                }}
-                            }};
-
-                            walk(world);
-                            for (const block of ide.stage.globalBlocks) {{
-                                walk(block.body.expression);
+                        const breakLocation = debug ? debug.location : null;
+                        if (this.previousBreak !== breakLocation) {{
+                            this.previousBreak = breakLocation;
+                            for (const block of this.breakBlocks) {{
+                                block.removeHighlight();
                             }}
-                            for (const entity of [ide.stage, ...ide.sprites.contents]) {{
-                                for (const block of entity.customBlocks) {{
-                                    walk(block.body.expression);
-                                }}
+                            this.breakBlocks = breakLocation !== null ? findBlocks(this.ext.ide)[breakLocation] || [] : [];
+                            for (const block of this.breakBlocks) {{
+                                block.addHighlight();
                             }}
+                        }}
+                        this.updateWatchers(watchers);
+                        if (errors.length > 0) {{
+                            const lookup = findBlocks(this.ext.ide);
 
                             const formatVars = entries => entries.map(entry => `${{entry.name}} = ${{entry.value}}`).join('\n');
 
@@ -278,6 +351,35 @@
         this.doUpdates = false;
     }};
 
+    TerminalMorph.prototype.updateWatchers = function (watchers) {{
+        const stage = this.ext.ide.stage;
+        const active = new Set();
+        for (const watcher of watchers) {{
+            const key = `${{watcher.entity}}/${{watcher.name}}`;
+            active.add(key);
+
+            let entry = this.watchers.get(key);
+            if (!entry || entry.morph.parent !== stage) {{
+                if (entry) entry.morph.destroy();
+                const source = {{ value: watcher.value, getValue() {{ return this.value; }} }};
+                const morph = new WatcherMorph(watcher.name, SpriteMorph.prototype.blockColor.variables, source, 'getValue');
+                stage.add(morph);
+                morph.setPosition(stage.topLeft().add(new Point(5, 5 + 25 * (active.size - 1))));
+                morph.fixLayout();
+                morph.keepWithin(stage);
+                entry = {{ source, morph }};
+                this.watchers.set(key, entry);
+            }}
+            entry.source.value = watcher.value;
+            entry.morph.update();
+        }}
+        for (const [key, entry] of this.watchers) {{
+            if (active.has(key)) continue;
+            entry.morph.destroy();
+            this.watchers.delete(key);
+        }}
+    }};
+
     TerminalMorph.prototype.setText = function (txt) {{
         this.content.text = txt;
         this.content.changed();
@@ -328,11 +430,26 @@
             super('Native');
             this.ide = ide;
 
-            setTimeout(() => request({{
-                method: 'GET',
-                url: `${{SERVER}}/project`,
+            const createSession = () => request({{
+                method: 'POST',
+                url: `${{SERVER}}/sessions`,
                 onErr: alert,
-                onOk: x => ide.droppedText(x),
+                onOk: id => {{
+                    SESSION = id;
+                    sessionStorage.setItem(SESSION_STORAGE_KEY, id);
+                }},
+            }});
+
+            const previous = sessionStorage.getItem(SESSION_STORAGE_KEY);
+            if (previous === null) createSession();
+            else setTimeout(() => request({{
+                method: 'GET',
+                url: `${{SERVER}}/sessions/${{previous}}/project`,
+                onErr: createSession,
+                onOk: x => {{
+                    SESSION = previous;
+                    ide.droppedText(x);
+                }},
             }}), 500);
         }}
 
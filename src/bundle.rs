@@ -0,0 +1,174 @@
+//! Versioned bundles of compiled projects for deployment.
+//!
+//! [`ByteCode`], [`InitInfo`], and [`Locations`] can each be serialized on their own,
+//! but they are tagged with a fingerprint of the exact build of `netsblox-vm` that produced them,
+//! so they can only be loaded by that same binary.
+//! A [`Bundle`] instead packages all three (along with some [`BundleInfo`] metadata) into a single file
+//! tagged with an explicit [`BUNDLE_FORMAT_VERSION`], which only changes when the bytecode or bundle format itself changes.
+//! This makes it possible to compile a project once and run it on any device with a compatible version of `netsblox-vm`,
+//! without needing to parse the original project xml.
+//!
+//! [`Bundle::load`] checks the format version before anything else, so loading a bundle made by an incompatible version
+//! results in a [`BundleError::IncompatibleVersion`] rather than a parsing error.
+//! The contained bytecode is also checked by [`ByteCode::verify`] before it is returned.
+//!
+//! Access to this submodule requires the `serde` feature flag.
+
+use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::fmt;
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use monostate::MustBeU128;
+
+use crate::*;
+use crate::meta::*;
+use crate::json::*;
+use crate::bytecode::*;
+use crate::runtime::FromAstError;
+use crate::util::{to_hex, from_hex};
+
+/// The value of the `format` field which identifies a bundle file.
+const BUNDLE_FORMAT_NAME: &str = "netsblox-vm-bundle";
+
+/// The version of the bundle format produced by [`Bundle::save`], and the only version accepted by [`Bundle::load`].
+///
+/// This must be incremented whenever a change would prevent existing bundles from loading or running correctly,
+/// such as changing the encoding of any [`ByteCode`] instruction or operator, or the layout of [`InitInfo`] or [`Locations`].
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// An error from loading a [`Bundle`].
+#[derive(Debug)]
+pub enum BundleError {
+    /// The content was not a bundle.
+    NotABundle,
+    /// The bundle was created with a different (incompatible) format version.
+    IncompatibleVersion { version: u64, vm_version: Option<String> },
+    /// The bundle had a compatible version, but its content was malformed.
+    BadContent { error: serde_json::Error },
+    /// The bytecode in the bundle failed verification.
+    VerifyError { error: VerifyError },
+}
+impl From<VerifyError> for BundleError { fn from(error: VerifyError) -> Self { Self::VerifyError { error } } }
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::NotABundle => write!(f, "not a netsblox-vm bundle"),
+            BundleError::IncompatibleVersion { version, vm_version } => {
+                write!(f, "bundle has format version {version}")?;
+                if let Some(vm_version) = vm_version { write!(f, " (created by netsblox-vm {vm_version})")? }
+                write!(f, ", but this is netsblox-vm {}, which requires format version {BUNDLE_FORMAT_VERSION} (the project must be recompiled)", env!("CARGO_PKG_VERSION"))
+            }
+            BundleError::BadContent { error } => write!(f, "bundle content is malformed: {error}"),
+            BundleError::VerifyError { error } => write!(f, "bundle contains invalid bytecode: {error:?}"),
+        }
+    }
+}
+
+mod hex_bytes {
+    use super::*;
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_hex(bytes))
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<[u8]>, D::Error> {
+        from_hex(&String::deserialize(deserializer)?).map(Vec::into_boxed_slice).ok_or_else(|| D::Error::custom("invalid hex string"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ByteCode")]
+struct ByteCodeDef {
+    #[serde(skip)] tag: MustBeU128<FINGERPRINT>,
+
+    #[serde(with = "hex_bytes")] code: Box<[u8]>,
+    #[serde(with = "hex_bytes")] data: Box<[u8]>,
+}
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "InitInfo")]
+struct InitInfoDef {
+    #[serde(skip)] tag: MustBeU128<FINGERPRINT>,
+
+    proj_name: String,
+    ref_values: Vec<RefValue>,
+    globals: Vec<(String, InitValue)>,
+    entities: Vec<EntityInitInfo>,
+}
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Locations")]
+struct LocationsDef {
+    #[serde(skip)] tag: MustBeU128<FINGERPRINT>,
+
+    prefix: String,
+    base_token: isize,
+    token_data: Vec<u8>,
+    locs: Vec<(usize, usize)>,
+    starts: Vec<(usize, usize)>,
+}
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Bundle")]
+struct BundleDef {
+    info: BundleInfo,
+    #[serde(with = "ByteCodeDef")] bytecode: ByteCode,
+    #[serde(with = "InitInfoDef")] init_info: InitInfo,
+    #[serde(with = "LocationsDef")] locations: Locations,
+}
+
+/// Information about where a [`Bundle`] came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleInfo {
+    /// The name of the project that was compiled.
+    pub project: String,
+    /// The name of the role (within the project) that was compiled.
+    pub role: String,
+    /// The version of `netsblox-vm` that compiled the bundle.
+    pub vm_version: String,
+}
+
+/// A compiled role of a project, along with everything needed to run it.
+pub struct Bundle {
+    pub info: BundleInfo,
+    pub bytecode: ByteCode,
+    pub init_info: InitInfo,
+    pub locations: Locations,
+}
+impl Bundle {
+    /// Compiles and verifies a role of a project (see [`ByteCode::compile_with`]) to produce a new bundle.
+    pub fn compile<'a>(project: &str, role: &'a ast::Role, settings: CompileSettings) -> Result<Self, FromAstError<'a>> {
+        let (bytecode, init_info, locations, _) = ByteCode::compile_with(role, settings)?;
+        bytecode.verify(&init_info)?;
+        let info = BundleInfo { project: project.into(), role: role.name.clone(), vm_version: env!("CARGO_PKG_VERSION").into() };
+        Ok(Self { info, bytecode, init_info, locations })
+    }
+    /// Encodes the bundle in the current bundle format, which can be loaded again with [`Bundle::load`].
+    pub fn save(&self) -> Vec<u8> {
+        let mut res = match BundleDef::serialize(self, serde_json::value::Serializer).unwrap() {
+            Json::Object(x) => x,
+            _ => unreachable!(),
+        };
+        res.insert("format".into(), BUNDLE_FORMAT_NAME.into());
+        res.insert("version".into(), BUNDLE_FORMAT_VERSION.into());
+        serde_json::to_vec(&res).unwrap()
+    }
+    /// Loads and verifies a bundle produced by [`Bundle::save`].
+    pub fn load(content: &[u8]) -> Result<Self, BundleError> {
+        let content = match parse_json_slice::<Json>(content) {
+            Ok(x @ Json::Object(_)) => x,
+            _ => return Err(BundleError::NotABundle),
+        };
+        if content.get("format").and_then(Json::as_str) != Some(BUNDLE_FORMAT_NAME) { return Err(BundleError::NotABundle) }
+
+        let version = content.get("version").and_then(Json::as_u64).ok_or(BundleError::NotABundle)?;
+        if version != BUNDLE_FORMAT_VERSION as u64 {
+            let vm_version = content.get("info").and_then(|x| x.get("vm_version")).and_then(Json::as_str).map(Into::into);
+            return Err(BundleError::IncompatibleVersion { version, vm_version });
+        }
+
+        let bundle = BundleDef::deserialize(content).map_err(|error| BundleError::BadContent { error })?;
+        bundle.bytecode.verify(&bundle.init_info)?;
+        Ok(bundle)
+    }
+}
@@ -24,8 +24,9 @@ use bin_pool::BinPool;
 
 use crate::*;
 use crate::meta::*;
-use crate::runtime::{Color, Number, NumberError, Event, KeyCode, Property, PrintStyle, Type, CustomTypes, System};
+use crate::runtime::{Color, Number, NumberError, parse_number, Event, KeyCode, Property, PrintStyle, Type, CustomTypes, System};
 use crate::util::LosslessJoin;
+use crate::process::ops;
 
 /// Number of bytes to display on each line of a hex dump
 #[cfg(feature = "std")]
@@ -52,6 +53,45 @@ pub enum CompileError<'a> {
 }
 impl From<NumberError> for CompileError<'_> { fn from(error: NumberError) -> Self { Self::BadNumber { error } } }
 
+/// Settings to use when compiling a project via [`ByteCode::compile_with`].
+#[derive(Clone, Copy, Debug)]
+pub struct CompileSettings {
+    /// If enabled, applies an optimization pass to the generated instructions before encoding them.
+    /// This includes constant folding, jump threading, and dead code elimination.
+    pub optimize: bool,
+}
+impl Default for CompileSettings {
+    fn default() -> Self {
+        Self { optimize: true }
+    }
+}
+
+/// An error generated by [`ByteCode::verify`].
+/// All positions are code addresses of the offending instruction.
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The instruction could not be decoded (invalid opcode, truncated/invalid operands, or out of bounds data).
+    BadInstruction { pos: usize },
+    /// A jump, call, closure, or handler target does not lie on an instruction boundary.
+    BadTarget { pos: usize, target: usize },
+    /// The instruction has malformed tokens (e.g., parameter or field names).
+    BadTokens { pos: usize },
+    /// The instruction consumes more values than are guaranteed to be on the value stack.
+    StackUnderflow { pos: usize, depth: usize, needed: usize },
+    /// The instruction pops an error handler when none is guaranteed to exist.
+    HandlerUnderflow { pos: usize },
+    /// The instruction can be reached with different value stack or handler stack depths.
+    StackMismatch { pos: usize, depths: ((usize, usize), (usize, usize)) },
+    /// Execution can continue past the end of the code.
+    FallsOffEnd { pos: usize },
+    /// A script in the [`InitInfo`] (or a snapshot) does not begin at the start of an instruction.
+    BadScript { entity: usize, pos: usize },
+    /// A value in the [`InitInfo`] references a nonexistent (or wrongly typed) value.
+    BadRef { index: usize },
+    /// A code address held by a snapshot does not refer to the start of a reachable instruction.
+    BadPosition { pos: usize },
+}
+
 #[derive(Clone, Copy, Debug, FromPrimitive)]
 #[repr(u8)]
 pub(crate) enum Relation {
@@ -84,6 +124,7 @@ pub(crate) enum BinaryOp {
     SplitBy,
     Range, Random,
     StrGet,
+    StrContains, StrIndexOf,
 }
 #[derive(Clone, Copy, Debug, FromPrimitive)]
 #[repr(u8)]
@@ -98,6 +139,7 @@ pub(crate) enum UnaryOp {
     SplitLetter, SplitWord, SplitTab, SplitCR, SplitLF, SplitCsv, SplitJson,
     StrLen,
     StrGetLast, StrGetRandom,
+    StrUpper, StrLower, StrTrim,
     UnicodeToChar, CharToUnicode,
 }
 
@@ -124,6 +166,12 @@ impl BinaryRead<'_> for VariadicLen {
             (x, aft) => (VariadicLen::Fixed(x - 1), aft),
         }
     }
+    fn checked_read(code: &[u8], data: &[u8], start: usize) -> Option<(Self, usize)> {
+        Some(match BinaryRead::checked_read(code, data, start)? {
+            (0, aft) => (VariadicLen::Dynamic, aft),
+            (x, aft) => (VariadicLen::Fixed(x - 1), aft),
+        })
+    }
 }
 impl BinaryWrite for VariadicLen {
     fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>) {
@@ -150,6 +198,13 @@ impl BasicType {
         }
     }
 }
+#[derive(Clone, Copy, Debug, FromPrimitive)]
+#[repr(u8)]
+pub(crate) enum StopMode {
+    All, AllButThisScript, ThisScript, OtherScriptsInSprite,
+    /// Returns from the current custom block or closure, or stops the script if there is none.
+    ThisBlock,
+}
 
 pub(crate) enum InternalInstruction<'a> {
     /// Triggers an error when encountered.
@@ -230,6 +285,11 @@ pub(crate) enum Instruction<'a> {
     ListRev,
     /// Consumes 1 value, `list`, from the value stack and pushes a flattened list containing all the same values in order onto the value stack.
     ListFlatten,
+    /// Consumes 1 value, `list`, from the value stack and pushes a new list containing its items in ascending order onto the value stack.
+    /// If `keyed` is true, first consumes an additional value, `keys`, which is a list of the same length as `list`
+    /// whose values are compared in place of the corresponding items (e.g., the result of mapping a key function over the list).
+    /// Items are compared with normal Snap!-style ordering and the sort is stable.
+    ListSort { keyed: bool },
     /// Consumes `len` values from the value stack (in reverse order) representing target dimensions, as well as another value, `list`.
     /// Pushes a new tensor (list) with the desired dimensions by sourcing from `list` (with cyclic repetition) onto the value stack.
     ListReshape { len: VariadicLen },
@@ -259,6 +319,9 @@ pub(crate) enum Instruction<'a> {
     ListGetLast,
     /// Consumes 1 value, `list`, from the value stack and pushes a random item from the list onto the value stack.
     ListGetRandom,
+    /// Consumes two values, `list` and `key`, from the value stack, where `list` is treated as a list of key/value pairs (lists of length 2).
+    /// Pushes the value of the first pair whose key is `key` onto the value stack, or an empty string if there is no such pair.
+    ListGetKey,
 
     /// Consumes three values, `value`, `list`, and `index`, from the value stack and assigns `list[index] = value`.
     ListAssign,
@@ -266,6 +329,9 @@ pub(crate) enum Instruction<'a> {
     ListAssignLast,
     /// Consumes two values, `value` and `list`, from the value stack and assigns `value` to a random position in the list.
     ListAssignRandom,
+    /// Consumes three values, `value`, `list`, and `key`, from the value stack, where `list` is treated as a list of key/value pairs (lists of length 2).
+    /// Assigns `value` as the value of the first pair whose key is `key`, or appends a new pair `[key, value]` to `list` if there is no such pair.
+    ListAssignKey,
 
     /// Consumes two values, `list` and `index`, from the value stack and deletes item `index` from `list`.
     ListRemove,
@@ -288,6 +354,9 @@ pub(crate) enum Instruction<'a> {
     Identical,
     /// Consumes 1 value, `x`, from the value stack, and pushes the value `f(x)` onto the value stack.
     UnaryOp { op: UnaryOp },
+    /// Consumes 3 values, `replacement`, `pattern`, and `text`, from the value stack,
+    /// and pushes a copy of `text` with every occurrence of `pattern` replaced by `replacement` onto the value stack.
+    StrReplace,
 
     /// Re/Declares a set of local variables, which are initialized to 0.
     /// Note that this is not equivalent to assigning a value of zero to the variable due to the potential issue of [`Shared::Aliased`].
@@ -311,6 +380,8 @@ pub(crate) enum Instruction<'a> {
     Watcher { create: bool, var: &'a str },
     /// Generate a pause request and return it to the executor.
     Pause,
+    /// Stops the current process, all processes, or all other processes (possibly restricted to the current entity), depending on `mode`.
+    Stop { mode: StopMode },
 
     /// Unconditionally jumps to the given location.
     Jump { to: usize },
@@ -383,6 +454,8 @@ pub(crate) enum Instruction<'a> {
     Sleep,
     /// Computes a specific query about the real-world time and pushes the result onto the value stack.
     PushRealTime { query: TimeQuery },
+    /// Pushes a bool value onto the value stack denoting if the given key (or any key if [`None`]) is currently held down.
+    KeyPressed { key: Option<KeyCode> },
 
     /// `tokens` is a [`LosslessJoin`] of msg type and 0+ field names.
     /// Consumes 1 value, `target` from the value stack, which is either a single or a list of targets.
@@ -417,6 +490,9 @@ pub(crate) enum Instruction<'a> {
 
     /// Pops one value, `target`, from the value stack and pushes a clone of entity `target` onto the value stack.
     Clone,
+    /// Deletes the current entity if it is a clone (otherwise does nothing).
+    /// The clone is marked as dead and all of its processes are stopped.
+    DeleteClone,
 
     /// Clears all graphic effects on the entity.
     ClearEffects,
@@ -438,11 +514,178 @@ pub(crate) enum Instruction<'a> {
     /// Consumes 1 value, `dist`, and asynchronously moves the entity forward by that distance (or backwards if negative).
     Forward,
 
+    /// Consumes 1 value, `sound`, and asynchronously plays it.
+    /// This can be an audio value or the name of a sound on the entity.
+    /// Empty string can be used to play nothing.
+    /// If `blocking` is true, the command is not complete until the sound has finished playing.
+    PlaySound { blocking: bool },
+    /// Consumes 2 values, `beats` and `notes`, and asynchronously plays the notes (a single note or a list of notes) for that many beats.
+    /// An empty list of notes is treated as a rest.
+    PlayNote,
+    /// Asynchronously stops all sounds that are currently playing.
+    StopSounds,
+
+    /// Consumes 1 value, `target`, and asynchronously pushes a bool representing if the entity is touching the target entity.
+    /// The target can be an entity or the name of an entity.
+    IsTouchingEntity,
+    /// Asynchronously pushes a bool representing if the entity is touching the mouse pointer.
+    IsTouchingMouse,
+    /// Asynchronously pushes a bool representing if the entity is touching the edge of the stage.
+    IsTouchingEdge,
+    /// Asynchronously pushes a bool representing if the entity is touching any drawings.
+    IsTouchingDrawings,
+    /// Consumes 1 value, `target`, and asynchronously pushes the distance from the entity to the target entity.
+    DistanceTo,
+    /// Consumes 1 value, `target`, and asynchronously pushes the direction from the entity to the target entity.
+    /// For both of these, the target can be an entity or the name of an entity.
+    DirectionTo,
+    /// Consumes 2 values, `target` and `attr`, and pushes the value of the named attribute of the target entity.
+    /// The target can be an entity or the name of an entity, and the attribute can be the name of a field of the target
+    /// or one of the Snap!-style built-in attributes such as `"x position"` or `"costume #"`.
+    /// Attributes backed by a [`Property`] are requested asynchronously on behalf of the target entity.
+    PushAttributeOf,
+
     /// Consumes `args` values (in reverse order) representing arguments to an unknown function.
     /// This is then handed over to the system to fulfill or error out.
     /// The result of the async request is then pushed onto the value stack.
     UnknownBlock { name: &'a str, args: usize },
 }
+impl Instruction<'_> {
+    /// Gets the code position referenced by this instruction (jump target, function address, etc.), if any.
+    pub(crate) fn target(&self) -> Option<usize> {
+        match self {
+            Instruction::Jump { to } | Instruction::ConditionalJump { to, .. } | Instruction::ListPopFirstOrElse { goto: to } => Some(*to),
+            Instruction::Call { pos, .. } | Instruction::MakeClosure { pos, .. } | Instruction::PushHandler { pos, .. } => Some(*pos),
+            _ => None,
+        }
+    }
+    fn target_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Instruction::Jump { to } | Instruction::ConditionalJump { to, .. } | Instruction::ListPopFirstOrElse { goto: to } => Some(to),
+            Instruction::Call { pos, .. } | Instruction::MakeClosure { pos, .. } | Instruction::PushHandler { pos, .. } => Some(pos),
+            _ => None,
+        }
+    }
+    /// Gets the effect of this instruction on the value stack as `(consumed, produced)` when execution continues to the next instruction.
+    /// Instructions that inspect values without consuming them (e.g., [`Instruction::DupeValue`]) count them as both consumed and produced.
+    /// Returns [`None`] if the instruction's tokens are malformed.
+    fn stack_effect(&self) -> Option<(usize, usize)> {
+        fn token_count(tokens: &str) -> Option<usize> {
+            match tokens.is_empty() {
+                true => Some(0),
+                false => if tokens.starts_with('\0') { Some(tokens.split('\0').count() - 1) } else { None },
+            }
+        }
+        fn variadic(len: VariadicLen) -> usize {
+            match len {
+                VariadicLen::Fixed(x) => x,
+                VariadicLen::Dynamic => 1,
+            }
+        }
+
+        Some(match *self {
+            Instruction::Yield | Instruction::WarpStart | Instruction::WarpStop => (0, 0),
+
+            Instruction::PushBool { .. } | Instruction::PushInt { .. } | Instruction::PushNumber { .. } | Instruction::PushColor { .. } |
+            Instruction::PushString { .. } | Instruction::PushVariable { .. } | Instruction::PushEntity { .. } | Instruction::PushSelf => (0, 1),
+
+            Instruction::PopValue => (1, 0),
+            Instruction::DupeValue { top_index } => (top_index as usize + 1, top_index as usize + 2),
+            Instruction::SwapValues { top_index_1, top_index_2 } => {
+                let n = top_index_1.max(top_index_2) as usize + 1;
+                (n, n)
+            }
+
+            Instruction::TypeQuery { .. } | Instruction::ToBool | Instruction::ToNumber => (1, 1),
+
+            Instruction::ListCons | Instruction::ListFind | Instruction::ListContains => (2, 1),
+            Instruction::ListCdr | Instruction::ListIsEmpty | Instruction::ListLength | Instruction::ListDims | Instruction::ListRank |
+            Instruction::ListRev | Instruction::ListFlatten => (1, 1),
+            Instruction::ListSort { keyed } => (1 + keyed as usize, 1),
+            Instruction::ListReshape { len } => (variadic(len) + 1, 1),
+            Instruction::ListCartesianProduct { len } => (variadic(len), 1),
+            Instruction::ListJson | Instruction::ListCsv | Instruction::ListColumns | Instruction::ListLines => (1, 1),
+
+            Instruction::ListInsert => (3, 0),
+            Instruction::ListInsertLast | Instruction::ListInsertRandom => (2, 0),
+            Instruction::ListGet | Instruction::ListGetKey => (2, 1),
+            Instruction::ListGetLast | Instruction::ListGetRandom => (1, 1),
+            Instruction::ListAssign | Instruction::ListAssignKey => (3, 0),
+            Instruction::ListAssignLast | Instruction::ListAssignRandom => (2, 0),
+            Instruction::ListRemove => (2, 0),
+            Instruction::ListRemoveLast | Instruction::ListRemoveAll => (1, 0),
+            Instruction::ListPopFirstOrElse { .. } => (1, 1),
+
+            Instruction::BinaryOp { .. } | Instruction::Cmp { .. } | Instruction::Identical => (2, 1),
+            Instruction::VariadicOp { len, .. } => (variadic(len), 1),
+            Instruction::UnaryOp { .. } => (1, 1),
+            Instruction::StrReplace => (3, 1),
+
+            Instruction::DeclareLocal { .. } | Instruction::InitUpvar { .. } => (0, 0),
+            Instruction::Assign { .. } | Instruction::BinaryOpAssign { .. } => (1, 0),
+
+            Instruction::Watcher { .. } | Instruction::Pause | Instruction::Stop { .. } => (0, 0),
+
+            Instruction::Jump { .. } => (0, 0),
+            Instruction::ConditionalJump { .. } => (1, 0),
+
+            Instruction::Call { tokens, .. } => (token_count(tokens)?, 1),
+            Instruction::MakeClosure { params, tokens, .. } => if params <= token_count(tokens)? { (0, 1) } else { return None },
+            Instruction::CallClosure { new_entity, args } => (args + 1 + new_entity as usize, 1),
+            Instruction::ForkClosure { args } => (args + 1, 0),
+            Instruction::Return => (1, 1),
+
+            Instruction::PushHandler { .. } | Instruction::PopHandler => (0, 0),
+            Instruction::Throw => (1, 0),
+
+            Instruction::CallRpc { tokens } => (token_count(tokens)?.checked_sub(2)?, 1),
+            Instruction::PushRpcError => (0, 1),
+            Instruction::Syscall { len } => (variadic(len) + 1, 1),
+            Instruction::PushSyscallError => (0, 1),
+
+            Instruction::SendLocalMessage { target, .. } => (1 + target as usize, 0),
+            Instruction::PushLocalMessage => (0, 1),
+
+            Instruction::Print { .. } | Instruction::Ask => (1, 0),
+            Instruction::PushAnswer => (0, 1),
+
+            Instruction::ResetTimer => (0, 0),
+            Instruction::PushTimer | Instruction::PushRealTime { .. } | Instruction::KeyPressed { .. } => (0, 1),
+            Instruction::Sleep => (1, 0),
+
+            Instruction::SendNetworkMessage { tokens, expect_reply } => match token_count(tokens)? {
+                0 => return None,
+                n => (n, expect_reply as usize), // msg type is not on the stack, but the targets are
+            }
+            Instruction::SendNetworkReply => (1, 0),
+
+            Instruction::PushProperty { .. } => (0, 1),
+            Instruction::SetProperty { .. } | Instruction::ChangeProperty { .. } => (1, 0),
+
+            Instruction::PushCostume | Instruction::PushCostumeNumber | Instruction::PushCostumeList => (0, 1),
+            Instruction::SetCostume => (1, 0),
+            Instruction::NextCostume => (0, 0),
+
+            Instruction::Clone => (1, 1),
+            Instruction::DeleteClone => (0, 0),
+            Instruction::ClearEffects | Instruction::ClearDrawings => (0, 0),
+
+            Instruction::GotoXY | Instruction::PointTowardsXY => (2, 0),
+            Instruction::Goto | Instruction::PointTowards | Instruction::Forward => (1, 0),
+
+            Instruction::PlaySound { .. } => (1, 0),
+            Instruction::PlayNote => (2, 0),
+            Instruction::StopSounds => (0, 0),
+
+            Instruction::IsTouchingEntity => (1, 1),
+            Instruction::IsTouchingMouse | Instruction::IsTouchingEdge | Instruction::IsTouchingDrawings => (0, 1),
+            Instruction::DistanceTo | Instruction::DirectionTo => (1, 1),
+            Instruction::PushAttributeOf => (2, 1),
+
+            Instruction::UnknownBlock { args, .. } => (args, 1),
+        })
+    }
+}
 #[test]
 fn test_bin_sizes() {
     if core::mem::size_of::<Instruction>() > 40 {
@@ -462,6 +705,9 @@ pub(crate) trait BinaryRead<'a>: Sized {
     /// Reads a value from `src` starting at `start`.
     /// Returns the read value and the position of the first byte after the read segment.
     fn read(code: &'a [u8], data: &'a [u8], start: usize) -> (Self, usize);
+    /// Equivalent to [`BinaryRead::read`], but returns [`None`] rather than panicking if the encoded value is malformed or out of bounds.
+    /// This is used to validate code that we did not generate ourselves (see [`ByteCode::verify`]).
+    fn checked_read(code: &'a [u8], data: &'a [u8], start: usize) -> Option<(Self, usize)>;
 }
 trait BinaryWrite: Sized {
     /// Appends a binary representation of the value to the given buffers.
@@ -470,10 +716,16 @@ trait BinaryWrite: Sized {
     fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>);
 }
 
-impl BinaryRead<'_> for u8 { fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) { (code[start], start + 1) } }
+impl BinaryRead<'_> for u8 {
+    fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) { (code[start], start + 1) }
+    fn checked_read(code: &[u8], _: &[u8], start: usize) -> Option<(Self, usize)> { Some((*code.get(start)?, start + 1)) }
+}
 impl BinaryWrite for u8 { fn append(val: &Self, code: &mut Vec<u8>, _: &mut BinPool, _: &mut Vec<RelocateInfo>) { code.push(*val) } }
 
-impl BinaryRead<'_> for bool { fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) { (code[start] != 0, start + 1) } }
+impl BinaryRead<'_> for bool {
+    fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) { (code[start] != 0, start + 1) }
+    fn checked_read(code: &[u8], _: &[u8], start: usize) -> Option<(Self, usize)> { Some((*code.get(start)? != 0, start + 1)) }
+}
 impl BinaryWrite for bool { fn append(val: &Self, code: &mut Vec<u8>, _: &mut BinPool, _: &mut Vec<RelocateInfo>) { code.push(if *val { 1 } else { 0 }) } }
 
 macro_rules! read_write_u8_type {
@@ -482,6 +734,9 @@ macro_rules! read_write_u8_type {
             fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) {
                 (Self::from_u8(code[start]).unwrap(), start + 1)
             }
+            fn checked_read(code: &[u8], _: &[u8], start: usize) -> Option<(Self, usize)> {
+                Some((Self::from_u8(*code.get(start)?)?, start + 1))
+            }
         }
         impl BinaryWrite for $t {
             fn append(val: &Self, code: &mut Vec<u8>, _: &mut BinPool, _: &mut Vec<RelocateInfo>) {
@@ -491,7 +746,7 @@ macro_rules! read_write_u8_type {
         }
     )*}
 }
-read_write_u8_type! { PrintStyle, Property, Relation, TimeQuery, BinaryOp, UnaryOp, VariadicOp, BasicType }
+read_write_u8_type! { PrintStyle, Property, Relation, TimeQuery, BinaryOp, UnaryOp, VariadicOp, BasicType, StopMode }
 
 /// encodes values as a sequence of bytes of form [1: next][7: bits] in little-endian order.
 /// `bytes` can be used to force a specific size (too small will panic), otherwise calculates and uses the smallest possible size.
@@ -521,11 +776,19 @@ fn decode_u64(data: &[u8], start: usize) -> (u64, usize) {
     }
     (val, aft)
 }
+/// Equivalent to [`decode_u64`], but fails if the encoded value is truncated or longer than [`MAX_U64_ENCODED_BYTES`].
+fn checked_decode_u64(data: &[u8], start: usize) -> Option<(u64, usize)> {
+    let len = data.get(start..)?.iter().take(MAX_U64_ENCODED_BYTES).position(|b| b & 0x80 == 0)? + 1;
+    Some(decode_u64(data, start)).filter(|x| x.1 == start + len)
+}
 
 impl BinaryRead<'_> for u64 {
     fn read(code: &[u8], _: &[u8], start: usize) -> (Self, usize) {
         decode_u64(code, start)
     }
+    fn checked_read(code: &[u8], _: &[u8], start: usize) -> Option<(Self, usize)> {
+        checked_decode_u64(code, start)
+    }
 }
 impl BinaryWrite for u64 {
     fn append(val: &Self, code: &mut Vec<u8>, _: &mut BinPool, _: &mut Vec<RelocateInfo>) {
@@ -572,6 +835,11 @@ impl BinaryRead<'_> for i32 {
         let v = (raw >> 1) as u32;
         (if raw & 1 == 0 { v } else { !v } as i32, aft)
     }
+    fn checked_read(code: &[u8], data: &[u8], start: usize) -> Option<(Self, usize)> {
+        let (raw, aft) = <u64 as BinaryRead>::checked_read(code, data, start)?;
+        let v = u32::try_from(raw >> 1).ok()?;
+        Some((if raw & 1 == 0 { v } else { !v } as i32, aft))
+    }
 }
 impl BinaryWrite for i32 {
     fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>) {
@@ -589,6 +857,10 @@ impl BinaryRead<'_> for Color {
         let [a, r, g, b] = res;
         (Color { r, g, b, a }, start + 4)
     }
+    fn checked_read(code: &[u8], _: &[u8], start: usize) -> Option<(Self, usize)> {
+        let [a, r, g, b]: [u8; 4] = code.get(start..start.checked_add(4)?)?.try_into().ok()?;
+        Some((Color { r, g, b, a }, start + 4))
+    }
 }
 impl BinaryWrite for Color {
     fn append(val: &Self, code: &mut Vec<u8>, _: &mut BinPool, _: &mut Vec<RelocateInfo>) {
@@ -597,6 +869,59 @@ impl BinaryWrite for Color {
     }
 }
 
+impl BinaryRead<'_> for Option<KeyCode> {
+    fn read(code: &[u8], data: &[u8], start: usize) -> (Self, usize) {
+        Self::checked_read(code, data, start).unwrap()
+    }
+    fn checked_read(code: &[u8], data: &[u8], start: usize) -> Option<(Self, usize)> {
+        Some(match *code.get(start)? {
+            0 => (None, start + 1),
+            1 => (Some(KeyCode::Up), start + 1),
+            2 => (Some(KeyCode::Down), start + 1),
+            3 => (Some(KeyCode::Left), start + 1),
+            4 => (Some(KeyCode::Right), start + 1),
+            5 => (Some(KeyCode::Enter), start + 1),
+            6 => {
+                let (ch, aft) = <usize as BinaryRead>::checked_read(code, data, start + 1)?;
+                (Some(KeyCode::Char(char::from_u32(u32::try_from(ch).ok()?)?)), aft)
+            }
+            _ => return None,
+        })
+    }
+}
+impl BinaryWrite for Option<KeyCode> {
+    fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>) {
+        match val {
+            None => code.push(0),
+            Some(KeyCode::Up) => code.push(1),
+            Some(KeyCode::Down) => code.push(2),
+            Some(KeyCode::Left) => code.push(3),
+            Some(KeyCode::Right) => code.push(4),
+            Some(KeyCode::Enter) => code.push(5),
+            Some(KeyCode::Char(ch)) => {
+                code.push(6);
+                BinaryWrite::append(&(*ch as usize), code, data, relocate_info);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_binary_key_code() {
+    let mut buf = vec![];
+    let mut discard = (BinPool::new(), vec![]);
+    let tests = [None, Some(KeyCode::Up), Some(KeyCode::Down), Some(KeyCode::Left), Some(KeyCode::Right), Some(KeyCode::Enter), Some(KeyCode::Char('a')), Some(KeyCode::Char(' ')), Some(KeyCode::Char('\u{1F600}'))];
+    for v in tests {
+        buf.clear();
+        BinaryWrite::append(&v, &mut buf, &mut discard.0, &mut discard.1);
+        let (back, aft) = <Option<KeyCode> as BinaryRead>::read(&buf, &[], 0);
+        assert_eq!(back, v);
+        assert_eq!(aft, buf.len());
+    }
+    assert!(<Option<KeyCode> as BinaryRead>::checked_read(&[7], &[], 0).is_none());
+    assert!(<Option<KeyCode> as BinaryRead>::checked_read(&[6], &[], 0).is_none());
+}
+
 #[test]
 fn test_binary_i32() {
     let mut buf = vec![];
@@ -635,6 +960,10 @@ impl BinaryRead<'_> for f64 {
         let (v, aft) = <u64 as BinaryRead>::read(code, data, start);
         (f64::from_bits(v.swap_bytes()), aft)
     }
+    fn checked_read(code: &[u8], data: &[u8], start: usize) -> Option<(Self, usize)> {
+        let (v, aft) = <u64 as BinaryRead>::checked_read(code, data, start)?;
+        Some((f64::from_bits(v.swap_bytes()), aft))
+    }
 }
 impl BinaryWrite for f64 {
     fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>) {
@@ -648,6 +977,10 @@ impl BinaryRead<'_> for usize {
         debug_assert!(v <= usize::MAX as u64);
         (v as usize, aft)
     }
+    fn checked_read(code: &[u8], data: &[u8], start: usize) -> Option<(Self, usize)> {
+        let (v, aft) = <u64 as BinaryRead>::checked_read(code, data, start)?;
+        Some((usize::try_from(v).ok()?, aft))
+    }
 }
 impl BinaryWrite for usize {
     fn append(val: &Self, code: &mut Vec<u8>, data: &mut BinPool, relocate_info: &mut Vec<RelocateInfo>) {
@@ -661,195 +994,231 @@ impl<'a> BinaryRead<'a> for &'a str {
         let (data_len, aft) = <usize as BinaryRead>::read(code, data, aft);
         (core::str::from_utf8(&data[data_pos..data_pos + data_len]).unwrap(), aft)
     }
+    fn checked_read(code: &'a [u8], data: &'a [u8], start: usize) -> Option<(Self, usize)> {
+        let (data_pos, aft) = <usize as BinaryRead>::checked_read(code, data, start)?;
+        let (data_len, aft) = <usize as BinaryRead>::checked_read(code, data, aft)?;
+        Some((core::str::from_utf8(data.get(data_pos..data_pos.checked_add(data_len)?)?).ok()?, aft))
+    }
 }
 
 impl<'a> BinaryRead<'a> for Instruction<'a> {
     fn read(code: &'a [u8], data: &'a [u8], start: usize) -> (Self, usize) {
-        macro_rules! read_prefixed {
-            (Instruction::$root:ident) => {
-                (Instruction::$root, start + 1)
-            };
-            (Instruction::$root:ident { $($tt:tt)* } $(: $($vals:ident),+$(,)? )?) => {{
-                #[allow(unused_mut)]
-                let mut parsing_stop = start + 1;
-                $($(let $vals = {
-                    let x = BinaryRead::read(code, data, parsing_stop);
-                    parsing_stop = x.1;
-                    x.0
-                };)*)?
-                (Instruction::$root { $($tt)* $($($vals),+ )? }, parsing_stop)
-            }};
-        }
-        match code[start] {
-            0 => read_prefixed!(Instruction::Yield),
-            1 => read_prefixed!(Instruction::WarpStart),
-            2 => read_prefixed!(Instruction::WarpStop),
-
-            3 => read_prefixed!(Instruction::PushBool { value: false }),
-            4 => read_prefixed!(Instruction::PushBool { value: true }),
-
-            5 => read_prefixed!(Instruction::PushInt {} : value),
-            6 => read_prefixed!(Instruction::PushNumber {} : value),
-            7 => read_prefixed!(Instruction::PushColor {} : value),
-
-            8 => read_prefixed!(Instruction::PushString { value: "" }),
-            9 => read_prefixed!(Instruction::PushString {} : value),
-
-            10 => read_prefixed!(Instruction::PushVariable {} : var),
-            11 => read_prefixed!(Instruction::PushEntity {} : name),
-            12 => read_prefixed!(Instruction::PushSelf),
+        read_instruction::<false>(code, data, start).unwrap()
+    }
+    fn checked_read(code: &'a [u8], data: &'a [u8], start: usize) -> Option<(Self, usize)> {
+        read_instruction::<true>(code, data, start)
+    }
+}
+/// Shared implementation of [`BinaryRead`] for [`Instruction`].
+/// If `CHECKED` is false, malformed code will panic rather than returning [`None`].
+fn read_instruction<'a, const CHECKED: bool>(code: &'a [u8], data: &'a [u8], start: usize) -> Option<(Instruction<'a>, usize)> {
+    macro_rules! read_prefixed {
+        (Instruction::$root:ident) => {
+            Some((Instruction::$root, start + 1))
+        };
+        (Instruction::$root:ident { $($tt:tt)* } $(: $($vals:ident),+$(,)? )?) => {{
+            #[allow(unused_mut)]
+            let mut parsing_stop = start + 1;
+            $($(let $vals = {
+                let x = if CHECKED { BinaryRead::checked_read(code, data, parsing_stop)? } else { BinaryRead::read(code, data, parsing_stop) };
+                parsing_stop = x.1;
+                x.0
+            };)*)?
+            Some((Instruction::$root { $($tt)* $($($vals),+ )? }, parsing_stop))
+        }};
+    }
+    match if CHECKED { *code.get(start)? } else { code[start] } {
+        0 => read_prefixed!(Instruction::Yield),
+        1 => read_prefixed!(Instruction::WarpStart),
+        2 => read_prefixed!(Instruction::WarpStop),
 
-            13 => read_prefixed!(Instruction::PopValue),
+        3 => read_prefixed!(Instruction::PushBool { value: false }),
+        4 => read_prefixed!(Instruction::PushBool { value: true }),
+
+        5 => read_prefixed!(Instruction::PushInt {} : value),
+        6 => read_prefixed!(Instruction::PushNumber {} : value),
+        7 => read_prefixed!(Instruction::PushColor {} : value),
+
+        8 => read_prefixed!(Instruction::PushString { value: "" }),
+        9 => read_prefixed!(Instruction::PushString {} : value),
+
+        10 => read_prefixed!(Instruction::PushVariable {} : var),
+        11 => read_prefixed!(Instruction::PushEntity {} : name),
+        12 => read_prefixed!(Instruction::PushSelf),
 
-            14 => read_prefixed!(Instruction::DupeValue {} : top_index),
-            15 => read_prefixed!(Instruction::SwapValues {} : top_index_1, top_index_2),
+        13 => read_prefixed!(Instruction::PopValue),
+
+        14 => read_prefixed!(Instruction::DupeValue {} : top_index),
+        15 => read_prefixed!(Instruction::SwapValues {} : top_index_1, top_index_2),
+
+        16 => read_prefixed!(Instruction::TypeQuery {} : ty),
+        17 => read_prefixed!(Instruction::ToBool),
+        18 => read_prefixed!(Instruction::ToNumber),
+
+        19 => read_prefixed!(Instruction::ListCons),
+        20 => read_prefixed!(Instruction::ListCdr),
 
-            16 => read_prefixed!(Instruction::TypeQuery {} : ty),
-            17 => read_prefixed!(Instruction::ToBool),
-            18 => read_prefixed!(Instruction::ToNumber),
+        21 => read_prefixed!(Instruction::ListFind),
+        22 => read_prefixed!(Instruction::ListContains),
 
-            19 => read_prefixed!(Instruction::ListCons),
-            20 => read_prefixed!(Instruction::ListCdr),
+        23 => read_prefixed!(Instruction::ListIsEmpty),
+        24 => read_prefixed!(Instruction::ListLength),
+        25 => read_prefixed!(Instruction::ListDims),
+        26 => read_prefixed!(Instruction::ListRank),
 
-            21 => read_prefixed!(Instruction::ListFind),
-            22 => read_prefixed!(Instruction::ListContains),
+        27 => read_prefixed!(Instruction::ListRev),
+        28 => read_prefixed!(Instruction::ListFlatten),
+        29 => read_prefixed!(Instruction::ListReshape {} : len),
+        30 => read_prefixed!(Instruction::ListCartesianProduct {} : len),
+
+        31 => read_prefixed!(Instruction::ListJson),
+        32 => read_prefixed!(Instruction::ListCsv),
+        33 => read_prefixed!(Instruction::ListColumns),
+        34 => read_prefixed!(Instruction::ListLines),
+
+        35 => read_prefixed!(Instruction::ListInsert),
+        36 => read_prefixed!(Instruction::ListInsertLast),
+        37 => read_prefixed!(Instruction::ListInsertRandom),
+
+        38 => read_prefixed!(Instruction::ListGet),
+        39 => read_prefixed!(Instruction::ListGetLast),
+        40 => read_prefixed!(Instruction::ListGetRandom),
+
+        41 => read_prefixed!(Instruction::ListAssign),
+        42 => read_prefixed!(Instruction::ListAssignLast),
+        43 => read_prefixed!(Instruction::ListAssignRandom),
 
-            23 => read_prefixed!(Instruction::ListIsEmpty),
-            24 => read_prefixed!(Instruction::ListLength),
-            25 => read_prefixed!(Instruction::ListDims),
-            26 => read_prefixed!(Instruction::ListRank),
+        44 => read_prefixed!(Instruction::ListRemove),
+        45 => read_prefixed!(Instruction::ListRemoveLast),
+        46 => read_prefixed!(Instruction::ListRemoveAll),
 
-            27 => read_prefixed!(Instruction::ListRev),
-            28 => read_prefixed!(Instruction::ListFlatten),
-            29 => read_prefixed!(Instruction::ListReshape {} : len),
-            30 => read_prefixed!(Instruction::ListCartesianProduct {} : len),
+        47 => read_prefixed!(Instruction::ListPopFirstOrElse {} : goto),
 
-            31 => read_prefixed!(Instruction::ListJson),
-            32 => read_prefixed!(Instruction::ListCsv),
-            33 => read_prefixed!(Instruction::ListColumns),
-            34 => read_prefixed!(Instruction::ListLines),
+        48 => read_prefixed!(Instruction::Cmp { relation: Relation::Equal }),
+        49 => read_prefixed!(Instruction::Cmp { relation: Relation::NotEqual }),
+        50 => read_prefixed!(Instruction::Cmp { relation: Relation::Less }),
+        51 => read_prefixed!(Instruction::Cmp { relation: Relation::LessEq }),
+        52 => read_prefixed!(Instruction::Cmp { relation: Relation::Greater }),
+        53 => read_prefixed!(Instruction::Cmp { relation: Relation::GreaterEq }),
+        54 => read_prefixed!(Instruction::Identical),
 
-            35 => read_prefixed!(Instruction::ListInsert),
-            36 => read_prefixed!(Instruction::ListInsertLast),
-            37 => read_prefixed!(Instruction::ListInsertRandom),
+        55 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Add }),
+        56 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Sub }),
+        57 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Mul }),
+        58 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Div }),
+        59 => read_prefixed!(Instruction::BinaryOp {} : op),
 
-            38 => read_prefixed!(Instruction::ListGet),
-            39 => read_prefixed!(Instruction::ListGetLast),
-            40 => read_prefixed!(Instruction::ListGetRandom),
+        60 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::Add, } : len),
+        61 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::Mul, } : len),
+        62 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::StrCat, } : len),
+        63 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::MakeList, } : len),
+        64 => read_prefixed!(Instruction::VariadicOp {} : op, len),
 
-            41 => read_prefixed!(Instruction::ListAssign),
-            42 => read_prefixed!(Instruction::ListAssignLast),
-            43 => read_prefixed!(Instruction::ListAssignRandom),
+        65 => read_prefixed!(Instruction::UnaryOp { op: UnaryOp::Not }),
+        66 => read_prefixed!(Instruction::UnaryOp { op: UnaryOp::Round }),
+        67 => read_prefixed!(Instruction::UnaryOp {} : op),
 
-            44 => read_prefixed!(Instruction::ListRemove),
-            45 => read_prefixed!(Instruction::ListRemoveLast),
-            46 => read_prefixed!(Instruction::ListRemoveAll),
+        68 => read_prefixed!(Instruction::DeclareLocal {} : var),
+        69 => read_prefixed!(Instruction::InitUpvar {} : var),
+        70 => read_prefixed!(Instruction::Assign {} : var),
 
-            47 => read_prefixed!(Instruction::ListPopFirstOrElse {} : goto),
+        71 => read_prefixed!(Instruction::BinaryOpAssign { op: BinaryOp::Add, } : var),
+        72 => read_prefixed!(Instruction::BinaryOpAssign {} : var, op),
 
-            48 => read_prefixed!(Instruction::Cmp { relation: Relation::Equal }),
-            49 => read_prefixed!(Instruction::Cmp { relation: Relation::NotEqual }),
-            50 => read_prefixed!(Instruction::Cmp { relation: Relation::Less }),
-            51 => read_prefixed!(Instruction::Cmp { relation: Relation::LessEq }),
-            52 => read_prefixed!(Instruction::Cmp { relation: Relation::Greater }),
-            53 => read_prefixed!(Instruction::Cmp { relation: Relation::GreaterEq }),
-            54 => read_prefixed!(Instruction::Identical),
+        73 => read_prefixed!(Instruction::Watcher {} : create, var),
+        74 => read_prefixed!(Instruction::Pause),
 
-            55 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Add }),
-            56 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Sub }),
-            57 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Mul }),
-            58 => read_prefixed!(Instruction::BinaryOp { op: BinaryOp::Div }),
-            59 => read_prefixed!(Instruction::BinaryOp {} : op),
+        75 => read_prefixed!(Instruction::Jump {} : to),
+        76 => read_prefixed!(Instruction::ConditionalJump { when: false, } : to),
+        77 => read_prefixed!(Instruction::ConditionalJump { when: true, } : to),
 
-            60 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::Add, } : len),
-            61 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::Mul, } : len),
-            62 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::StrCat, } : len),
-            63 => read_prefixed!(Instruction::VariadicOp { op: VariadicOp::MakeList, } : len),
-            64 => read_prefixed!(Instruction::VariadicOp {} : op, len),
+        78 => read_prefixed!(Instruction::Call {} : pos, tokens),
+        79 => read_prefixed!(Instruction::MakeClosure {} : pos, params, tokens),
+        80 => read_prefixed!(Instruction::CallClosure { new_entity: false, } : args),
+        81 => read_prefixed!(Instruction::CallClosure { new_entity: true, } : args),
+        82 => read_prefixed!(Instruction::ForkClosure {} : args),
+        83 => read_prefixed!(Instruction::Return),
 
-            65 => read_prefixed!(Instruction::UnaryOp { op: UnaryOp::Not }),
-            66 => read_prefixed!(Instruction::UnaryOp { op: UnaryOp::Round }),
-            67 => read_prefixed!(Instruction::UnaryOp {} : op),
+        84 => read_prefixed!(Instruction::PushHandler {} : pos, var),
+        85 => read_prefixed!(Instruction::PopHandler),
+        86 => read_prefixed!(Instruction::Throw),
 
-            68 => read_prefixed!(Instruction::DeclareLocal {} : var),
-            69 => read_prefixed!(Instruction::InitUpvar {} : var),
-            70 => read_prefixed!(Instruction::Assign {} : var),
+        87 => read_prefixed!(Instruction::CallRpc {} : tokens),
+        88 => read_prefixed!(Instruction::PushRpcError),
 
-            71 => read_prefixed!(Instruction::BinaryOpAssign { op: BinaryOp::Add, } : var),
-            72 => read_prefixed!(Instruction::BinaryOpAssign {} : var, op),
+        89 => read_prefixed!(Instruction::Syscall {} : len),
+        90 => read_prefixed!(Instruction::PushSyscallError),
 
-            73 => read_prefixed!(Instruction::Watcher {} : create, var),
-            74 => read_prefixed!(Instruction::Pause),
+        91 => read_prefixed!(Instruction::SendLocalMessage { wait: false, target: false }),
+        92 => read_prefixed!(Instruction::SendLocalMessage { wait: false, target: true }),
+        93 => read_prefixed!(Instruction::SendLocalMessage { wait: true, target: false }),
+        94 => read_prefixed!(Instruction::SendLocalMessage { wait: true, target: true }),
 
-            75 => read_prefixed!(Instruction::Jump {} : to),
-            76 => read_prefixed!(Instruction::ConditionalJump { when: false, } : to),
-            77 => read_prefixed!(Instruction::ConditionalJump { when: true, } : to),
+        95 => read_prefixed!(Instruction::PushLocalMessage),
 
-            78 => read_prefixed!(Instruction::Call {} : pos, tokens),
-            79 => read_prefixed!(Instruction::MakeClosure {} : pos, params, tokens),
-            80 => read_prefixed!(Instruction::CallClosure { new_entity: false, } : args),
-            81 => read_prefixed!(Instruction::CallClosure { new_entity: true, } : args),
-            82 => read_prefixed!(Instruction::ForkClosure {} : args),
-            83 => read_prefixed!(Instruction::Return),
+        96 => read_prefixed!(Instruction::Print { style: PrintStyle::Say }),
+        97 => read_prefixed!(Instruction::Print { style: PrintStyle::Think }),
+        98 => read_prefixed!(Instruction::Ask),
+        99 => read_prefixed!(Instruction::PushAnswer),
 
-            84 => read_prefixed!(Instruction::PushHandler {} : pos, var),
-            85 => read_prefixed!(Instruction::PopHandler),
-            86 => read_prefixed!(Instruction::Throw),
+        100 => read_prefixed!(Instruction::ResetTimer),
+        101 => read_prefixed!(Instruction::PushTimer),
+        102 => read_prefixed!(Instruction::Sleep),
+        103 => read_prefixed!(Instruction::PushRealTime {} : query),
 
-            87 => read_prefixed!(Instruction::CallRpc {} : tokens),
-            88 => read_prefixed!(Instruction::PushRpcError),
+        104 => read_prefixed!(Instruction::SendNetworkMessage { expect_reply: false, } : tokens),
+        105 => read_prefixed!(Instruction::SendNetworkMessage { expect_reply: true, } : tokens),
+        106 => read_prefixed!(Instruction::SendNetworkReply),
 
-            89 => read_prefixed!(Instruction::Syscall {} : len),
-            90 => read_prefixed!(Instruction::PushSyscallError),
+        107 => read_prefixed!(Instruction::PushProperty {} : prop),
+        108 => read_prefixed!(Instruction::SetProperty {} : prop),
+        109 => read_prefixed!(Instruction::ChangeProperty {} : prop),
 
-            91 => read_prefixed!(Instruction::SendLocalMessage { wait: false, target: false }),
-            92 => read_prefixed!(Instruction::SendLocalMessage { wait: false, target: true }),
-            93 => read_prefixed!(Instruction::SendLocalMessage { wait: true, target: false }),
-            94 => read_prefixed!(Instruction::SendLocalMessage { wait: true, target: true }),
+        110 => read_prefixed!(Instruction::PushCostume),
+        111 => read_prefixed!(Instruction::PushCostumeNumber),
+        112 => read_prefixed!(Instruction::PushCostumeList),
+        113 => read_prefixed!(Instruction::SetCostume),
+        114 => read_prefixed!(Instruction::NextCostume),
 
-            95 => read_prefixed!(Instruction::PushLocalMessage),
+        115 => read_prefixed!(Instruction::Clone),
 
-            96 => read_prefixed!(Instruction::Print { style: PrintStyle::Say }),
-            97 => read_prefixed!(Instruction::Print { style: PrintStyle::Think }),
-            98 => read_prefixed!(Instruction::Ask),
-            99 => read_prefixed!(Instruction::PushAnswer),
+        116 => read_prefixed!(Instruction::ClearEffects),
+        117 => read_prefixed!(Instruction::ClearDrawings),
 
-            100 => read_prefixed!(Instruction::ResetTimer),
-            101 => read_prefixed!(Instruction::PushTimer),
-            102 => read_prefixed!(Instruction::Sleep),
-            103 => read_prefixed!(Instruction::PushRealTime {} : query),
+        118 => read_prefixed!(Instruction::GotoXY),
+        119 => read_prefixed!(Instruction::Goto),
 
-            104 => read_prefixed!(Instruction::SendNetworkMessage { expect_reply: false, } : tokens),
-            105 => read_prefixed!(Instruction::SendNetworkMessage { expect_reply: true, } : tokens),
-            106 => read_prefixed!(Instruction::SendNetworkReply),
+        120 => read_prefixed!(Instruction::PointTowardsXY),
+        121 => read_prefixed!(Instruction::PointTowards),
 
-            107 => read_prefixed!(Instruction::PushProperty {} : prop),
-            108 => read_prefixed!(Instruction::SetProperty {} : prop),
-            109 => read_prefixed!(Instruction::ChangeProperty {} : prop),
+        122 => read_prefixed!(Instruction::Forward),
 
-            110 => read_prefixed!(Instruction::PushCostume),
-            111 => read_prefixed!(Instruction::PushCostumeNumber),
-            112 => read_prefixed!(Instruction::PushCostumeList),
-            113 => read_prefixed!(Instruction::SetCostume),
-            114 => read_prefixed!(Instruction::NextCostume),
+        123 => read_prefixed!(Instruction::UnknownBlock {} : name, args),
 
-            115 => read_prefixed!(Instruction::Clone),
+        124 => read_prefixed!(Instruction::Stop {} : mode),
 
-            116 => read_prefixed!(Instruction::ClearEffects),
-            117 => read_prefixed!(Instruction::ClearDrawings),
+        125 => read_prefixed!(Instruction::KeyPressed {} : key),
 
-            118 => read_prefixed!(Instruction::GotoXY),
-            119 => read_prefixed!(Instruction::Goto),
+        126 => read_prefixed!(Instruction::DeleteClone),
 
-            120 => read_prefixed!(Instruction::PointTowardsXY),
-            121 => read_prefixed!(Instruction::PointTowards),
+        127 => read_prefixed!(Instruction::PlaySound {} : blocking),
+        128 => read_prefixed!(Instruction::PlayNote),
+        129 => read_prefixed!(Instruction::StopSounds),
 
-            122 => read_prefixed!(Instruction::Forward),
+        130 => read_prefixed!(Instruction::IsTouchingEntity),
+        131 => read_prefixed!(Instruction::IsTouchingMouse),
+        132 => read_prefixed!(Instruction::IsTouchingEdge),
+        133 => read_prefixed!(Instruction::IsTouchingDrawings),
+        134 => read_prefixed!(Instruction::DistanceTo),
+        135 => read_prefixed!(Instruction::DirectionTo),
+        136 => read_prefixed!(Instruction::PushAttributeOf),
 
-            123 => read_prefixed!(Instruction::UnknownBlock {} : name, args),
+        137 => read_prefixed!(Instruction::ListSort {} : keyed),
+        138 => read_prefixed!(Instruction::StrReplace),
+        139 => read_prefixed!(Instruction::ListGetKey),
+        140 => read_prefixed!(Instruction::ListAssignKey),
 
-            _ => unreachable!(),
-        }
+        _ => if CHECKED { None } else { unreachable!() },
     }
 }
 impl BinaryWrite for Instruction<'_> {
@@ -1038,6 +1407,29 @@ impl BinaryWrite for Instruction<'_> {
             Instruction::Forward => append_prefixed!(122),
 
             Instruction::UnknownBlock { name, args } => append_prefixed!(123: move str name, args),
+
+            Instruction::Stop { mode } => append_prefixed!(124: mode),
+
+            Instruction::KeyPressed { key } => append_prefixed!(125: key),
+
+            Instruction::DeleteClone => append_prefixed!(126),
+
+            Instruction::PlaySound { blocking } => append_prefixed!(127: blocking),
+            Instruction::PlayNote => append_prefixed!(128),
+            Instruction::StopSounds => append_prefixed!(129),
+
+            Instruction::IsTouchingEntity => append_prefixed!(130),
+            Instruction::IsTouchingMouse => append_prefixed!(131),
+            Instruction::IsTouchingEdge => append_prefixed!(132),
+            Instruction::IsTouchingDrawings => append_prefixed!(133),
+            Instruction::DistanceTo => append_prefixed!(134),
+            Instruction::DirectionTo => append_prefixed!(135),
+            Instruction::PushAttributeOf => append_prefixed!(136),
+
+            Instruction::ListSort { keyed } => append_prefixed!(137: keyed),
+            Instruction::StrReplace => append_prefixed!(138),
+            Instruction::ListGetKey => append_prefixed!(139),
+            Instruction::ListAssignKey => append_prefixed!(140),
         }
     }
 }
@@ -1054,7 +1446,7 @@ impl BinaryWrite for Instruction<'_> {
 /// This type supports serde serialization if the `serde` feature flag is enabled.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ByteCode {
-    #[allow(dead_code)] tag: MustBeU128<FINGERPRINT>,
+    #[allow(dead_code)] pub(crate) tag: MustBeU128<FINGERPRINT>,
 
     pub(crate) code: Box<[u8]>,
     pub(crate) data: Box<[u8]>,
@@ -1070,6 +1462,7 @@ pub(crate) enum InitValue {
 pub(crate) enum RefValue {
     List(Vec<InitValue>),
     Image(Vec<u8>),
+    Audio(Vec<u8>),
     String(String),
 }
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -1077,6 +1470,8 @@ pub(crate) struct EntityInitInfo {
     pub(crate) name: String,
     pub(crate) fields: Vec<(String, InitValue)>,
     pub(crate) costumes: Vec<(String, InitValue)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) sounds: Vec<(String, InitValue)>,
     pub(crate) scripts: Vec<(Event, usize)>,
 
     pub(crate) visible: bool,
@@ -1096,7 +1491,7 @@ pub(crate) struct EntityInitInfo {
 /// This type supports serde serialization if the `serde` feature flag is enabled.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InitInfo {
-    #[allow(dead_code)] tag: MustBeU128<FINGERPRINT>,
+    #[allow(dead_code)] pub(crate) tag: MustBeU128<FINGERPRINT>,
 
     pub(crate) proj_name: String,
     pub(crate) ref_values: Vec<RefValue>,
@@ -1108,6 +1503,8 @@ pub struct InitInfo {
 pub struct EntityScriptInfo<'a> {
     pub funcs: Vec<(&'a ast::Function, usize)>,
     pub scripts: Vec<(&'a ast::Script, usize)>,
+    /// The condition code for each script with a "when" hat block, which is separate from the body of the script.
+    pub conditions: Vec<(&'a ast::Script, usize)>,
 }
 /// Script information in a [`ByteCode`] object.
 /// 
@@ -1131,22 +1528,25 @@ pub struct ScriptInfo<'a> {
 /// This type supports serde serialization if the `serde` feature flag is enabled.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Locations {
-    #[allow(dead_code)] tag: MustBeU128<FINGERPRINT>,
+    #[allow(dead_code)] pub(crate) tag: MustBeU128<FINGERPRINT>,
 
-    prefix: String,
-    base_token: isize,
-    token_data: Vec<u8>,
-    locs: Vec<(usize, usize)>,
+    pub(crate) prefix: String,
+    pub(crate) base_token: isize,
+    pub(crate) token_data: Vec<u8>,
+    pub(crate) locs: Vec<(usize, usize)>,
+    pub(crate) starts: Vec<(usize, usize)>,
 }
 impl Locations {
-    fn condense(orig_locs: BTreeMap<usize, &str>) -> Result<Self, CompileError> {
+    fn condense<'a>(orig_locs: BTreeMap<usize, &'a str>, mut orig_starts: Vec<(usize, &'a str)>) -> Result<Self, CompileError<'a>> {
         if orig_locs.is_empty() {
+            debug_assert!(orig_starts.is_empty());
             return Ok(Self {
                 tag: Default::default(),
                 prefix: String::new(),
                 base_token: 0,
                 token_data: Default::default(),
                 locs: Default::default(),
+                starts: Default::default(),
             });
         }
 
@@ -1158,8 +1558,9 @@ impl Locations {
             }
         };
 
-        let mut token_map = Vec::with_capacity(orig_locs.len());
-        for (&pos, &loc) in orig_locs.iter() {
+        let mut token_map = BTreeMap::new();
+        for loc in orig_locs.values().chain(orig_starts.iter().map(|x| &x.1)).copied() {
+            if token_map.contains_key(loc) { continue }
             if !loc.starts_with(&prefix) { return Err(CompileError::InvalidLocation { loc }) }
             debug_assert!(loc[prefix.len()..].starts_with('_'));
 
@@ -1174,22 +1575,26 @@ impl Locations {
                 }
                 tokens.push(v);
             }
-            token_map.push((pos, tokens));
+            token_map.insert(loc, tokens);
         }
 
-        let base_token = token_map.iter().flat_map(|x| &x.1).copied().min().unwrap_or(0);
+        let base_token = token_map.values().flatten().copied().min().unwrap_or(0);
 
         let mut token_data = Vec::with_capacity(token_map.len());
-        let mut locs = Vec::with_capacity(token_map.len());
-        for (pos, toks) in token_map {
-            locs.push((pos, token_data.len()));
+        let mut token_starts = BTreeMap::new();
+        for (loc, toks) in token_map {
+            token_starts.insert(loc, token_data.len());
             for tok in toks {
                 encode_u64((tok - base_token + 1) as u64, &mut token_data, None);
             }
             encode_u64(0, &mut token_data, None); // null terminator
         }
 
-        let res = Self { tag: Default::default(), prefix, base_token, token_data, locs };
+        let locs = orig_locs.iter().map(|(pos, loc)| (*pos, token_starts[loc])).collect();
+        orig_starts.sort_unstable();
+        let starts = orig_starts.iter().map(|(pos, loc)| (*pos, token_starts[loc])).collect();
+
+        let res = Self { tag: Default::default(), prefix, base_token, token_data, locs, starts };
 
         #[cfg(test)]
         {
@@ -1210,16 +1615,30 @@ impl Locations {
     /// Note that it is possible for blocks to not have location information,
     /// hence returning the most local location that was provided in the ast.
     pub fn lookup(&self, bytecode_pos: usize) -> Option<String> {
-        let mut start = {
+        let start = {
             let p = self.locs.lower_bound_by_key(&(bytecode_pos + 1), |x| x.0);
             debug_assert!(p <= self.locs.len());
             self.locs.get(p)?.1
         };
-
+        Some(self.decode(start))
+    }
+    /// Performs a reverse lookup to find the bytecode addresses where the code for the block with the given location begins.
+    /// This is typically a single address, but can be several if the code for the block was generated more than once.
+    /// If the location does not exist, an empty list is returned.
+    ///
+    /// Note that the code for a block begins by evaluating its inputs, so this is not always the same as an address which [`Locations::lookup`] maps to `loc`.
+    pub fn find(&self, loc: &str) -> Vec<usize> {
+        self.starts.iter().filter(|x| self.decode(x.1) == loc).map(|x| x.0).collect()
+    }
+    /// Gets the (sorted) bytecode addresses where the code for any block begins (see [`Locations::find`]).
+    pub(crate) fn block_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.starts.iter().map(|x| x.0)
+    }
+    fn decode(&self, mut start: usize) -> String {
         let mut res = self.prefix.clone();
         loop {
             let (v, aft) = decode_u64(&self.token_data, start);
-            if v == 0 { return Some(res) }
+            if v == 0 { return res }
 
             start = aft;
             res.push('_');
@@ -1228,11 +1647,44 @@ impl Locations {
     }
 }
 
+fn fold_unary_op(op: UnaryOp, x: f64) -> Option<f64> {
+    ops::num_unary_op(op, Number::new(x).ok()?)?.ok().map(|x| x.get())
+}
+fn fold_binary_op(op: BinaryOp, a: f64, b: f64) -> Option<f64> {
+    ops::num_binary_op(op, Number::new(a).ok()?, Number::new(b).ok()?)?.ok().map(|x| x.get())
+}
+fn fold_variadic_op(op: VariadicOp, values: &[f64]) -> Option<f64> {
+    let (combine_op, empty) = match op {
+        VariadicOp::Add => (BinaryOp::Add, Some(0.0)),
+        VariadicOp::Mul => (BinaryOp::Mul, Some(1.0)),
+        VariadicOp::Min | VariadicOp::Max => {
+            let relation = if let VariadicOp::Min = op { Relation::Less } else { Relation::Greater };
+            let values = values.iter().map(|x| Number::new(*x).ok()).collect::<Option<Vec<_>>>()?;
+            return values.into_iter().reduce(|a, b| if ops::num_is_better(a, b, relation) { b } else { a }).map(|x| x.get());
+        }
+        _ => return None,
+    };
+    match values {
+        [] => empty,
+        [x] => fold_unary_op(UnaryOp::ToNumber, *x),
+        [first, rest @ ..] => rest.iter().try_fold(*first, |acc, &x| fold_binary_op(combine_op, acc, x)),
+    }
+}
+
+/// Parses a key name as used in key hat blocks and key queries, where `"any key"` is represented as [`None`].
+fn parse_key_filter(key: &str) -> Result<Option<KeyCode>, CompileError<'_>> {
+    match key {
+        "any key" => Ok(None),
+        _ => KeyCode::from_name(key).map(Some).ok_or(CompileError::BadKeycode { key }),
+    }
+}
+
 struct ByteCodeBuilder<'a: 'b, 'b> {
     ins: Vec<InternalInstruction<'b>>,
     call_holes: Vec<(usize, &'a ast::FnRef, Option<&'a ast::Entity>)>, // (hole pos, function, entity)
     closure_holes: VecDeque<(usize, &'a [ast::VariableDef], &'a [ast::VariableRef], &'a [ast::Stmt], Option<&'a ast::Entity>)>, // (hole pos, params, captures, stmts, entity)
     ins_locations: BTreeMap<usize, &'a str>,
+    ins_block_starts: Vec<(usize, &'a str)>, // (first instruction, location) of each block with a location
     string_arena: &'b typed_arena::Arena<String>,
 }
 impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
@@ -1243,6 +1695,30 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
         self.ins.push(op.into());
         Ok(())
     }
+    /// Consumes two values, `list` and `f`, from the value stack and pushes the list of results of calling `f` on each item of `list`.
+    fn append_map(&mut self) {
+        self.ins.push(Instruction::VariadicOp { op: VariadicOp::MakeList, len: VariadicLen::Dynamic }.into()); // shallow copy the input list
+        self.ins.push(Instruction::VariadicOp { op: VariadicOp::MakeList, len: VariadicLen::Fixed(0) }.into()); // push an empty list
+
+        let top = self.ins.len();
+        self.ins.push(Instruction::DupeValue { top_index: 2 }.into());
+        self.ins.push(Instruction::DupeValue { top_index: 2 }.into());
+        let exit_jump_pos = self.ins.len();
+        self.ins.push(InternalInstruction::Illegal);
+        self.ins.push(Instruction::CallClosure { new_entity: false, args: 1 }.into());
+        self.ins.push(Instruction::DupeValue { top_index: 1 }.into());
+        self.ins.push(Instruction::ListInsertLast.into());
+        self.ins.push(Instruction::Yield.into());
+        self.ins.push(Instruction::Jump { to: top }.into());
+        let aft = self.ins.len();
+
+        self.ins[exit_jump_pos] = Instruction::ListPopFirstOrElse { goto: aft }.into();
+
+        self.ins.push(Instruction::SwapValues { top_index_1: 1, top_index_2: 3 }.into());
+        self.ins.push(Instruction::PopValue.into());
+        self.ins.push(Instruction::PopValue.into());
+        self.ins.push(Instruction::PopValue.into());
+    }
     fn append_variadic_op(&mut self, entity: Option<&'a ast::Entity>, src: &'a ast::Expr, op: VariadicOp) -> Result<(), CompileError<'a>> {
         let len = self.append_variadic(src, entity)?;
         self.ins.push(Instruction::VariadicOp { op, len}.into());
@@ -1289,6 +1765,7 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
         Ok(())
     }
     fn append_expr(&mut self, expr: &'a ast::Expr, entity: Option<&'a ast::Entity>) -> Result<(), CompileError<'a>> {
+        let start_pos = self.ins.len();
         match &expr.kind {
             ast::ExprKind::Value(v) => self.append_value(v, entity)?,
             ast::ExprKind::Variable { var } => self.ins.push(Instruction::PushVariable { var: &var.trans_name }.into()),
@@ -1362,6 +1839,10 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
             ast::ExprKind::Size => self.ins.push(Instruction::PushProperty { prop: Property::Size }.into()),
             ast::ExprKind::IsVisible => self.ins.push(Instruction::PushProperty { prop: Property::Visible }.into()),
             ast::ExprKind::Entity { trans_name, .. } => self.ins.push(Instruction::PushEntity { name: trans_name }.into()),
+            ast::ExprKind::IsTouchingEntity { entity: target } => self.append_simple_ins(entity, &[target], Instruction::IsTouchingEntity)?,
+            ast::ExprKind::IsTouchingMouse => self.ins.push(Instruction::IsTouchingMouse.into()),
+            ast::ExprKind::IsTouchingEdge => self.ins.push(Instruction::IsTouchingEdge.into()),
+            ast::ExprKind::IsTouchingDrawings => self.ins.push(Instruction::IsTouchingDrawings.into()),
             ast::ExprKind::Add { values } => self.append_variadic_op(entity, values, VariadicOp::Add)?,
             ast::ExprKind::Mul { values } => self.append_variadic_op(entity, values, VariadicOp::Mul)?,
             ast::ExprKind::Min { values } => self.append_variadic_op(entity, values, VariadicOp::Min)?,
@@ -1389,6 +1870,79 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
                     if !args.is_empty() { return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }) }
                     self.ins.push(Instruction::PushSyscallError.into());
                 }
+                "reportKeyPressed" => {
+                    let key = match args.as_slice() {
+                        [ast::Expr { kind: ast::ExprKind::Value(ast::Value::String(key)), .. }] => parse_key_filter(key)?,
+                        _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    };
+                    self.ins.push(Instruction::KeyPressed { key }.into());
+                }
+                "getTempo" | "getVolume" | "getPan" => {
+                    if !args.is_empty() { return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }) }
+                    let prop = match name.as_str() {
+                        "getTempo" => Property::Tempo,
+                        "getVolume" => Property::Volume,
+                        _ => Property::Balance,
+                    };
+                    self.ins.push(Instruction::PushProperty { prop }.into());
+                }
+                "reportRelationTo" => {
+                    let (relation, target) = match args.as_slice() {
+                        [ast::Expr { kind: ast::ExprKind::Value(ast::Value::String(relation)), .. }, target] => (relation, target),
+                        _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    };
+                    let ins = match relation.as_str() {
+                        "distance" => Instruction::DistanceTo,
+                        "direction" => Instruction::DirectionTo,
+                        _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    };
+                    self.append_simple_ins(entity, &[target], ins)?;
+                }
+                "reportAttributeOf" => match args.as_slice() {
+                    [ast::Expr { kind: ast::ExprKind::Value(ast::Value::String(attr)), .. }, _] if attr.is_empty() => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    [attr, target] => self.append_simple_ins(entity, &[attr, target], Instruction::PushAttributeOf)?,
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
+                "reportSorted" => match args.as_slice() {
+                    [list] => self.append_simple_ins(entity, &[list], Instruction::ListSort { keyed: false })?,
+                    [list, key] => {
+                        self.append_expr(list, entity)?;
+                        self.append_expr(key, entity)?;
+                        self.ins.push(Instruction::DupeValue { top_index: 1 }.into());
+                        self.append_map();
+                        self.ins.push(Instruction::ListSort { keyed: true }.into());
+                    }
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
+                "reportTextContains" => match args.as_slice() {
+                    [text, pattern] => self.append_simple_ins(entity, &[text, pattern], BinaryOp::StrContains.into())?,
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
+                "reportTextIndexOf" => match args.as_slice() {
+                    [pattern, text] => self.append_simple_ins(entity, &[text, pattern], BinaryOp::StrIndexOf.into())?,
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
+                "reportTextReplace" => match args.as_slice() {
+                    [text, pattern, replacement] => self.append_simple_ins(entity, &[text, pattern, replacement], Instruction::StrReplace)?,
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
+                "reportTextFunction" => {
+                    let (func, text) = match args.as_slice() {
+                        [ast::Expr { kind: ast::ExprKind::Value(ast::Value::String(func)), .. }, text] => (func, text),
+                        _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    };
+                    let op = match func.as_str() {
+                        "upper case" => UnaryOp::StrUpper,
+                        "lower case" => UnaryOp::StrLower,
+                        "trim" => UnaryOp::StrTrim,
+                        _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                    };
+                    self.append_simple_ins(entity, &[text], op.into())?;
+                }
+                "reportListValueAtKey" => match args.as_slice() {
+                    [key, list] => self.append_simple_ins(entity, &[key, list], Instruction::ListGetKey)?,
+                    _ => return Err(CompileError::InvalidBlock { loc: expr.info.location.as_deref() }),
+                }
                 _ => {
                     for arg in args {
                         self.append_expr(arg, entity)?;
@@ -1528,27 +2082,7 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
             ast::ExprKind::Map { f, list } => {
                 self.append_expr(f, entity)?;
                 self.append_expr(list, entity)?;
-                self.ins.push(Instruction::VariadicOp { op: VariadicOp::MakeList, len: VariadicLen::Dynamic }.into()); // shallow copy the input list
-                self.ins.push(Instruction::VariadicOp { op: VariadicOp::MakeList, len: VariadicLen::Fixed(0) }.into()); // push an empty list
-
-                let top = self.ins.len();
-                self.ins.push(Instruction::DupeValue { top_index: 2 }.into());
-                self.ins.push(Instruction::DupeValue { top_index: 2 }.into());
-                let exit_jump_pos = self.ins.len();
-                self.ins.push(InternalInstruction::Illegal);
-                self.ins.push(Instruction::CallClosure { new_entity: false, args: 1 }.into());
-                self.ins.push(Instruction::DupeValue { top_index: 1 }.into());
-                self.ins.push(Instruction::ListInsertLast.into());
-                self.ins.push(Instruction::Yield.into());
-                self.ins.push(Instruction::Jump { to: top }.into());
-                let aft = self.ins.len();
-
-                self.ins[exit_jump_pos] = Instruction::ListPopFirstOrElse { goto: aft }.into();
-
-                self.ins.push(Instruction::SwapValues { top_index_1: 1, top_index_2: 3 }.into());
-                self.ins.push(Instruction::PopValue.into());
-                self.ins.push(Instruction::PopValue.into());
-                self.ins.push(Instruction::PopValue.into());
+                self.append_map();
             }
             ast::ExprKind::Keep { f, list } => {
                 self.append_expr(f, entity)?;
@@ -1652,11 +2186,13 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
 
         if let Some(location) = expr.info.location.as_deref() {
             self.ins_locations.insert(self.ins.len(), location);
+            self.ins_block_starts.push((start_pos, location));
         }
 
         Ok(())
     }
     fn append_stmt(&mut self, stmt: &'a ast::Stmt, entity: Option<&'a ast::Entity>) -> Result<(), CompileError<'a>> {
+        let start_pos = self.ins.len();
         match &stmt.kind {
             ast::StmtKind::Assign { var, value } => self.append_simple_ins(entity, &[value], Instruction::Assign { var: &var.trans_name })?,
             ast::StmtKind::AddAssign { var, value } => self.append_simple_ins(entity, &[value], Instruction::BinaryOpAssign { var: &var.trans_name, op: BinaryOp::Add })?,
@@ -2013,6 +2549,71 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
                     self.ins.push(Instruction::Syscall { len }.into());
                     self.ins.push(Instruction::PopValue.into());
                 }
+                "doSetListKey" => match args.as_slice() {
+                    [key, list, value] => self.append_simple_ins(entity, &[key, list, value], Instruction::ListAssignKey)?,
+                    _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                }
+                "removeClone" => {
+                    if !args.is_empty() { return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }) }
+                    self.ins.push(Instruction::DeleteClone.into());
+                }
+                "doStopThis" | "doStopOthers" | "doStop" | "doStopAll" => {
+                    let mode = match (name.as_str(), args.as_slice()) {
+                        ("doStop", []) => StopMode::ThisScript,
+                        ("doStopAll", []) => StopMode::All,
+                        (_, [ast::Expr { kind: ast::ExprKind::Value(ast::Value::String(mode)), .. }]) => match mode.as_str() {
+                            "all" => StopMode::All,
+                            "all but this script" => StopMode::AllButThisScript,
+                            "this script" => StopMode::ThisScript,
+                            "this block" => StopMode::ThisBlock,
+                            "other scripts in sprite" => StopMode::OtherScriptsInSprite,
+                            _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                        }
+                        _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                    };
+                    self.ins.push(Instruction::Stop { mode }.into());
+                }
+                "playSound" | "doPlaySoundUntilDone" => {
+                    let sound = match args.as_slice() {
+                        [sound] => sound,
+                        _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                    };
+                    self.append_simple_ins(entity, &[sound], Instruction::PlaySound { blocking: name == "doPlaySoundUntilDone" })?;
+                }
+                "doPlayNote" => {
+                    let (note, beats) = match args.as_slice() {
+                        [note, beats] => (note, beats),
+                        _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                    };
+                    self.append_simple_ins(entity, &[note, beats], Instruction::PlayNote)?;
+                }
+                "doRest" => {
+                    let beats = match args.as_slice() {
+                        [beats] => beats,
+                        _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                    };
+                    self.ins.push(Instruction::VariadicOp { op: VariadicOp::MakeList, len: VariadicLen::Fixed(0) }.into());
+                    self.append_simple_ins(entity, &[beats], Instruction::PlayNote)?;
+                }
+                "doStopAllSounds" => {
+                    if !args.is_empty() { return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }) }
+                    self.ins.push(Instruction::StopSounds.into());
+                }
+                "doSetTempo" | "doChangeTempo" | "setVolume" | "changeVolume" | "setPan" | "changePan" => {
+                    let value = match args.as_slice() {
+                        [value] => value,
+                        _ => return Err(CompileError::InvalidBlock { loc: stmt.info.location.as_deref() }),
+                    };
+                    let ins = match name.as_str() {
+                        "doSetTempo" => Instruction::SetProperty { prop: Property::Tempo },
+                        "doChangeTempo" => Instruction::ChangeProperty { prop: Property::Tempo },
+                        "setVolume" => Instruction::SetProperty { prop: Property::Volume },
+                        "changeVolume" => Instruction::ChangeProperty { prop: Property::Volume },
+                        "setPan" => Instruction::SetProperty { prop: Property::Balance },
+                        _ => Instruction::ChangeProperty { prop: Property::Balance },
+                    };
+                    self.append_simple_ins(entity, &[value], ins)?;
+                }
                 _ => {
                     for arg in args {
                         self.append_expr(arg, entity)?;
@@ -2026,6 +2627,7 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
 
         if let Some(location) = stmt.info.location.as_deref() {
             self.ins_locations.insert(self.ins.len(), location);
+            self.ins_block_starts.push((start_pos, location));
         }
 
         Ok(())
@@ -2041,7 +2643,7 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
         self.ins.push(Instruction::Return.into());
         Ok(())
     }
-    fn link(mut self, funcs: Vec<(&'a ast::Function, usize)>, entities: Vec<(&'a ast::Entity, EntityScriptInfo<'a>)>) -> Result<(ByteCode, ScriptInfo<'a>, Locations), CompileError<'a>> {
+    fn link(mut self, funcs: Vec<(&'a ast::Function, usize)>, entities: Vec<(&'a ast::Entity, EntityScriptInfo<'a>)>, settings: CompileSettings) -> Result<(ByteCode, ScriptInfo<'a>, Locations), CompileError<'a>> {
         assert!(self.closure_holes.is_empty());
 
         let global_fn_to_info = {
@@ -2076,123 +2678,910 @@ impl<'a: 'b, 'b> ByteCodeBuilder<'a, 'b> {
             self.ins[*hole_pos] = Instruction::Call { pos, tokens: self.string_arena.alloc(tokens.finish()) }.into();
         }
 
+        let (mut funcs, mut entities) = (funcs, entities);
+        if settings.optimize {
+            self.optimize(&mut funcs, &mut entities);
+        }
+
         self.finalize(funcs, entities)
     }
-    fn finalize(self, funcs: Vec<(&'a ast::Function, usize)>, entities: Vec<(&'a ast::Entity, EntityScriptInfo<'a>)>) -> Result<(ByteCode, ScriptInfo<'a>, Locations), CompileError<'a>> {
-        let mut code = Vec::with_capacity(self.ins.len() * 4);
-        let mut data = BinPool::new();
-        let mut relocate_info = Vec::with_capacity(64);
-
-        let mut final_ins_pos = Vec::with_capacity(self.ins.len());
+    fn optimize(&mut self, funcs: &mut [(&'a ast::Function, usize)], entities: &mut [(&'a ast::Entity, EntityScriptInfo<'a>)]) {
+        loop {
+            let mut changed = self.thread_jumps();
+            for pass in [Self::remove_dead_code, Self::fold_constants, Self::remove_unused_pushes] {
+                let targets = self.get_targets(funcs, entities);
+                let mut removed = vec![false; self.ins.len()];
+                if pass(self, &targets, &mut removed) {
+                    self.remove_instructions(&removed, funcs, entities);
+                    changed = true;
+                }
+            }
+            if !changed { break }
+        }
+    }
+    /// Gets a mask of all instruction positions that can be entered from somewhere other than the previous instruction.
+    fn get_targets(&self, funcs: &[(&'a ast::Function, usize)], entities: &[(&'a ast::Entity, EntityScriptInfo<'a>)]) -> Vec<bool> {
+        let mut res = vec![false; self.ins.len() + 1];
         for ins in self.ins.iter() {
-            final_ins_pos.push(code.len());
+            if let InternalInstruction::Valid(ins) = ins {
+                if let Some(target) = ins.target() { res[target] = true; }
+            }
+        }
+        for (_, pos) in funcs.iter() { res[*pos] = true; }
+        for (_, entity_info) in entities.iter() {
+            for (_, pos) in entity_info.funcs.iter() { res[*pos] = true; }
+            for (_, pos) in entity_info.scripts.iter() { res[*pos] = true; }
+            for (_, pos) in entity_info.conditions.iter() { res[*pos] = true; }
+        }
+        res
+    }
+    /// Removes all instructions marked in `removed`, relocating all code addresses and locations to match.
+    /// Addresses of removed instructions are relocated to the next instruction that was not removed.
+    fn remove_instructions(&mut self, removed: &[bool], funcs: &mut [(&'a ast::Function, usize)], entities: &mut [(&'a ast::Entity, EntityScriptInfo<'a>)]) {
+        debug_assert_eq!(removed.len(), self.ins.len());
+
+        let mut new_pos = Vec::with_capacity(removed.len() + 1);
+        let mut count = 0;
+        for &removed in removed.iter() {
+            new_pos.push(count);
+            if !removed { count += 1; }
+        }
+        new_pos.push(count);
+
+        let ins = mem::take(&mut self.ins);
+        self.ins = ins.into_iter().zip(removed).filter(|x| !*x.1).map(|x| x.0).collect();
+        for ins in self.ins.iter_mut() {
+            if let InternalInstruction::Valid(ins) = ins {
+                if let Some(target) = ins.target_mut() { *target = new_pos[*target]; }
+            }
+        }
+
+        for func in funcs.iter_mut() { func.1 = new_pos[func.1]; }
+        for entity in entities.iter_mut() {
+            for func in entity.1.funcs.iter_mut() { func.1 = new_pos[func.1]; }
+            for script in entity.1.scripts.iter_mut() { script.1 = new_pos[script.1]; }
+            for condition in entity.1.conditions.iter_mut() { condition.1 = new_pos[condition.1]; }
+        }
+
+        // if several locations collapse to the same position, the first one is the one still in use
+        let mut ins_locations = BTreeMap::new();
+        for (pos, loc) in mem::take(&mut self.ins_locations) {
+            ins_locations.entry(new_pos[pos]).or_insert(loc);
+        }
+        self.ins_locations = ins_locations;
+
+        for (pos, _) in self.ins_block_starts.iter_mut() { *pos = new_pos[*pos]; }
+    }
+    /// Redirects jumps whose targets are unconditional jumps to the final destination.
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.ins.len() {
+            let mut target = match &self.ins[i] {
+                InternalInstruction::Valid(Instruction::Jump { to } | Instruction::ConditionalJump { to, .. }) => *to,
+                _ => continue,
+            };
+            for _ in 0..self.ins.len() {
+                match &self.ins[target] {
+                    InternalInstruction::Valid(Instruction::Jump { to }) if *to != target => target = *to,
+                    _ => break,
+                }
+            }
+            if let InternalInstruction::Valid(Instruction::Jump { to } | Instruction::ConditionalJump { to, .. }) = &mut self.ins[i] {
+                if *to != target {
+                    *to = target;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+    /// Marks all instructions that are unreachable due to a preceding unconditional control flow transfer,
+    /// as well as unconditional jumps to the next instruction.
+    fn remove_dead_code(&mut self, targets: &[bool], removed: &mut [bool]) -> bool {
+        let mut changed = false;
+        let mut dead = false;
+        for (i, ins) in self.ins.iter().enumerate() {
+            if targets[i] { dead = false; }
+            if dead {
+                removed[i] = true;
+                changed = true;
+                continue;
+            }
+            match ins {
+                InternalInstruction::Valid(Instruction::Jump { to }) if *to == i + 1 => {
+                    removed[i] = true;
+                    changed = true;
+                }
+                InternalInstruction::Valid(Instruction::Return | Instruction::Throw | Instruction::Jump { .. }) => dead = true,
+                _ => (),
+            }
+        }
+        changed
+    }
+    /// Evaluates arithmetic operations on constant values at compile time.
+    /// Folding is only performed if the result is a valid number, so runtime errors are still reported at runtime.
+    fn fold_constants(&mut self, targets: &[bool], removed: &mut [bool]) -> bool {
+        fn get_const(ins: &InternalInstruction, allow_strings: bool) -> Option<f64> {
             match ins {
-                InternalInstruction::Illegal => unreachable!(),
-                InternalInstruction::Valid(val) => BinaryWrite::append(val, &mut code, &mut data, &mut relocate_info),
+                InternalInstruction::Valid(Instruction::PushInt { value }) => Some(*value as f64),
+                InternalInstruction::Valid(Instruction::PushNumber { value }) => Some(*value),
+                InternalInstruction::Valid(Instruction::PushString { value }) if allow_strings => parse_number(value).map(|x| x.get()),
+                _ => None,
             }
         }
 
-        let data_backing = data.into_backing();
-        let (data, data_backing_pos) = {
-            let mut data = Vec::with_capacity(data_backing.0.iter().map(Vec::len).sum::<usize>());
-            let mut data_backing_pos = Vec::with_capacity(data_backing.0.len());
-            for backing in data_backing.0.iter() {
-                data_backing_pos.push(data.len());
-                data.extend_from_slice(backing);
+        let mut changed = false;
+        for i in 0..self.ins.len() {
+            let args = match &self.ins[i] {
+                InternalInstruction::Valid(Instruction::UnaryOp { .. }) => 1,
+                InternalInstruction::Valid(Instruction::BinaryOp { .. }) => 2,
+                InternalInstruction::Valid(Instruction::VariadicOp { len: VariadicLen::Fixed(len), .. }) => *len,
+                _ => continue,
+            };
+            if args > i || (i + 1 - args..=i).any(|x| targets[x]) || removed[i - args..i].iter().any(|x| *x) { continue }
+            // min/max return one of their inputs (as opposed to converting to number), so strings can't be folded for them
+            let allow_strings = !matches!(self.ins[i], InternalInstruction::Valid(Instruction::VariadicOp { op: VariadicOp::Min | VariadicOp::Max, .. }));
+            let values = match self.ins[i - args..i].iter().map(|x| get_const(x, allow_strings)).collect::<Option<Vec<_>>>() {
+                Some(x) => x,
+                None => continue,
+            };
+            let res = match &self.ins[i] {
+                InternalInstruction::Valid(Instruction::UnaryOp { op }) => fold_unary_op(*op, values[0]),
+                InternalInstruction::Valid(Instruction::BinaryOp { op }) => fold_binary_op(*op, values[0], values[1]),
+                InternalInstruction::Valid(Instruction::VariadicOp { op, .. }) => fold_variadic_op(*op, &values),
+                _ => unreachable!(),
+            };
+            if let Some(value) = res {
+                for x in removed[i - args..i].iter_mut() { *x = true; }
+                self.ins[i] = match value as i32 {
+                    x if (x as f64).to_bits() == value.to_bits() => Instruction::PushInt { value: x },
+                    _ => Instruction::PushNumber { value },
+                }.into();
+                changed = true;
+            }
+        }
+        changed
+    }
+    /// Removes pushed values that are immediately discarded.
+    fn remove_unused_pushes(&mut self, targets: &[bool], removed: &mut [bool]) -> bool {
+        let mut changed = false;
+        for i in 1..self.ins.len() {
+            if targets[i] || removed[i - 1] || !matches!(self.ins[i], InternalInstruction::Valid(Instruction::PopValue)) { continue }
+            let pure = match &self.ins[i - 1] {
+                InternalInstruction::Valid(ins) => match ins {
+                    Instruction::PushBool { .. } | Instruction::PushInt { .. } | Instruction::PushColor { .. } | Instruction::PushString { .. } | Instruction::PushSelf => true,
+                    Instruction::PushNumber { value } => Number::new(*value).is_ok(),
+                    _ => false,
+                }
+                InternalInstruction::Illegal => false,
+            };
+            if pure {
+                removed[i - 1] = true;
+                removed[i] = true;
+                changed = true;
+            }
+        }
+        changed
+    }
+    fn finalize(self, funcs: Vec<(&'a ast::Function, usize)>, entities: Vec<(&'a ast::Entity, EntityScriptInfo<'a>)>) -> Result<(ByteCode, ScriptInfo<'a>, Locations), CompileError<'a>> {
+        let (bytecode, final_ins_pos, locations) = encode(&self.ins, &self.ins_locations, &self.ins_block_starts)?;
+        let (mut funcs, mut entities) = (funcs, entities);
+
+        for func in funcs.iter_mut() { func.1 = final_ins_pos[func.1]; }
+        for entity in entities.iter_mut() {
+            for func in entity.1.funcs.iter_mut() { func.1 = final_ins_pos[func.1]; }
+            for script in entity.1.scripts.iter_mut() { script.1 = final_ins_pos[script.1]; }
+            for condition in entity.1.conditions.iter_mut() { condition.1 = final_ins_pos[condition.1]; }
+        }
+
+        Ok((bytecode, ScriptInfo { funcs, entities }, locations))
+    }
+}
+
+/// Encodes a sequence of linked instructions into a [`ByteCode`] object.
+/// Also returns the final address of each instruction (plus one extra entry for the end of the code),
+/// as well as the [`Locations`] table generated from `ins_locations` and `ins_block_starts`.
+fn encode<'a>(ins: &[InternalInstruction], ins_locations: &BTreeMap<usize, &'a str>, ins_block_starts: &[(usize, &'a str)]) -> Result<(ByteCode, Vec<usize>, Locations), CompileError<'a>> {
+    let mut code = Vec::with_capacity(ins.len() * 4);
+    let mut data = BinPool::new();
+    let mut relocate_info = Vec::with_capacity(64);
+
+    let mut final_ins_pos = Vec::with_capacity(ins.len() + 1);
+    for ins in ins.iter() {
+        final_ins_pos.push(code.len());
+        match ins {
+            InternalInstruction::Illegal => unreachable!(),
+            InternalInstruction::Valid(val) => BinaryWrite::append(val, &mut code, &mut data, &mut relocate_info),
+        }
+    }
+    final_ins_pos.push(code.len()); // end of code (the optimizer can leave locations here after removing trailing instructions)
+
+    let data_backing = data.into_backing();
+    let (data, data_backing_pos) = {
+        let mut data = Vec::with_capacity(data_backing.0.iter().map(Vec::len).sum::<usize>());
+        let mut data_backing_pos = Vec::with_capacity(data_backing.0.len());
+        for backing in data_backing.0.iter() {
+            data_backing_pos.push(data.len());
+            data.extend_from_slice(backing);
+        }
+        (data, data_backing_pos)
+    };
+
+    fn apply_shrinking_plan(plan: &[(usize, usize, usize)], final_relocates: &mut [usize], code: &mut Vec<u8>, final_ins_pos: &mut [usize]) -> usize {
+        let old_pos_to_ins: BTreeMap<usize, usize> = final_ins_pos.iter().copied().enumerate().map(|(a, b)| (b, a)).collect();
+        let orig_code_size = code.len();
+
+        let mut final_ins_pos_update_iter = final_ins_pos.iter_mut().fuse().peekable();
+        let mut old_hole_pos_to_new_pos = BTreeMap::default();
+        let (mut dest_pos, mut src_pos, mut total_shift) = (0, 0, 0);
+        for (code_addr, prev_size, new_size) in plan.iter().copied() {
+            debug_assert!(prev_size >= new_size);
+            debug_assert!(code_addr >= src_pos);
+
+            while let Some(old) = final_ins_pos_update_iter.peek() {
+                if **old > code_addr { break }
+                *final_ins_pos_update_iter.next().unwrap() -= total_shift;
+            }
+
+            code.copy_within(src_pos..code_addr + new_size, dest_pos);
+            dest_pos += code_addr + new_size - src_pos;
+            src_pos = code_addr + prev_size;
+            old_hole_pos_to_new_pos.insert(code_addr, dest_pos - new_size);
+            total_shift += prev_size - new_size;
+        }
+        for old in final_ins_pos_update_iter { *old -= total_shift; }
+        code.copy_within(src_pos..src_pos + (orig_code_size - total_shift - dest_pos), dest_pos);
+        code.truncate(orig_code_size - total_shift);
+
+        let mut buf = Vec::with_capacity(MAX_U64_ENCODED_BYTES);
+        for code_addr in final_relocates.iter_mut() {
+            *code_addr = old_hole_pos_to_new_pos[code_addr];
+            let old_pos = <usize as BinaryRead>::read(code, &[], *code_addr);
+            buf.clear();
+            encode_u64(final_ins_pos[old_pos_to_ins[&old_pos.0]] as u64, &mut buf, Some(old_pos.1 - *code_addr));
+            debug_assert_eq!(buf.len(), old_pos.1 - *code_addr);
+            code[*code_addr..old_pos.1].copy_from_slice(&buf);
+        }
+
+        total_shift
+    }
+
+    let mut fmt_buf = Vec::with_capacity(MAX_U64_ENCODED_BYTES);
+    let mut shrinking_plan = vec![];
+    let mut final_relocates = vec![];
+    for info in relocate_info {
+        fmt_buf.clear();
+        let (code_addr, prev_size) = match info {
+            RelocateInfo::Code { code_addr } => {
+                final_relocates.push(code_addr);
+                let pos = <usize as BinaryRead>::read(&code, &data, code_addr);
+                encode_u64(final_ins_pos[pos.0] as u64, &mut fmt_buf, None);
+                (code_addr, pos.1 - code_addr)
+            }
+            RelocateInfo::Data { code_addr } => {
+                let pool_index = <usize as BinaryRead>::read(&code, &data, code_addr);
+                let slice = &data_backing.1[pool_index.0];
+                encode_u64((data_backing_pos[slice.src] + slice.start) as u64, &mut fmt_buf, None);
+                (code_addr, pool_index.1 - code_addr)
             }
-            (data, data_backing_pos)
         };
+        debug_assert!(prev_size >= fmt_buf.len());
+        shrinking_plan.push((code_addr, prev_size, fmt_buf.len()));
+        code[code_addr..code_addr + fmt_buf.len()].copy_from_slice(&fmt_buf);
+    }
 
-        fn apply_shrinking_plan(plan: &[(usize, usize, usize)], final_relocates: &mut [usize], code: &mut Vec<u8>, final_ins_pos: &mut [usize]) -> usize {
-            let old_pos_to_ins: BTreeMap<usize, usize> = final_ins_pos.iter().copied().enumerate().map(|(a, b)| (b, a)).collect();
-            let orig_code_size = code.len();
+    apply_shrinking_plan(&shrinking_plan, &mut final_relocates, &mut code, &mut final_ins_pos);
+
+    for _ in 0..SHRINK_CYCLES {
+        shrinking_plan.clear();
+        for code_addr in final_relocates.iter().copied() {
+            let val = <usize as BinaryRead>::read(&code, &data, code_addr);
+            fmt_buf.clear();
+            encode_u64(val.0 as u64, &mut fmt_buf, None);
+            debug_assert!(fmt_buf.len() <= val.1 - code_addr);
+            code[code_addr..code_addr + fmt_buf.len()].copy_from_slice(&fmt_buf);
+            shrinking_plan.push((code_addr, val.1 - code_addr, fmt_buf.len()));
+        }
+        let delta = apply_shrinking_plan(&shrinking_plan, &mut final_relocates, &mut code, &mut final_ins_pos);
+        if delta == 0 { break }
+    }
 
-            let mut final_ins_pos_update_iter = final_ins_pos.iter_mut().fuse().peekable();
-            let mut old_hole_pos_to_new_pos = BTreeMap::default();
-            let (mut dest_pos, mut src_pos, mut total_shift) = (0, 0, 0);
-            for (code_addr, prev_size, new_size) in plan.iter().copied() {
-                debug_assert!(prev_size >= new_size);
-                debug_assert!(code_addr >= src_pos);
+    let locations = Locations::condense(ins_locations.iter().map(|(p, v)| (final_ins_pos[*p], *v)).collect(), ins_block_starts.iter().map(|(p, v)| (final_ins_pos[*p], *v)).collect())?;
 
-                while let Some(old) = final_ins_pos_update_iter.peek() {
-                    if **old > code_addr { break }
-                    *final_ins_pos_update_iter.next().unwrap() -= total_shift;
+    Ok((ByteCode { tag: Default::default(), code: code.into_boxed_slice(), data: data.into_boxed_slice() }, final_ins_pos, locations))
+}
+
+/// An error generated by [`ByteCode::assemble`].
+///
+/// Line numbers are 1-based.
+#[derive(Debug)]
+pub enum AssembleError {
+    /// A line could not be parsed.
+    BadSyntax { line: usize, info: String },
+    /// An instruction name was not recognized.
+    UnknownInstruction { line: usize, name: String },
+    /// An instruction or directive field was missing, unknown, or had an invalid value.
+    BadField { line: usize, field: String },
+    /// A label or address was referenced but never defined.
+    UndefinedLabel { line: usize, label: String },
+    /// A label or address was defined more than once.
+    DuplicateLabel { line: usize, label: String },
+    /// A directive that requires an entity was used before any `.entity` directive.
+    NoEntity { line: usize },
+    /// A location was not a valid collab id.
+    InvalidLocation { loc: String },
+    /// A numeric literal was not a valid [`Number`].
+    BadNumber { error: NumberError },
+}
+impl From<NumberError> for AssembleError { fn from(error: NumberError) -> Self { Self::BadNumber { error } } }
+
+#[derive(Debug, PartialEq)]
+enum AsmToken {
+    Word(String),
+    Str(String),
+    Char(char),
+    Punct(char),
+}
+enum AsmValue {
+    Word(String),
+    Str(String),
+    Char(char),
+    List(Vec<AsmValue>),
+    Tuple(String, Vec<AsmValue>),
+    Struct(String, BTreeMap<String, AsmValue>),
+}
+enum AsmLabel {
+    Name(String),
+    Addr(usize),
+}
+
+fn asm_tokenize(line: &str) -> Result<Vec<AsmToken>, String> {
+    fn is_word_char(ch: char) -> bool {
+        ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.' | '+' | '-')
+    }
+    fn read_escaped(chars: &mut core::iter::Peekable<core::str::Chars>, end: char) -> Result<String, String> {
+        let mut res = String::new();
+        loop {
+            match chars.next() {
+                None => return Err("unterminated literal".into()),
+                Some(ch) if ch == end => return Ok(res),
+                Some('\\') => match chars.next() {
+                    Some('n') => res.push('\n'),
+                    Some('r') => res.push('\r'),
+                    Some('t') => res.push('\t'),
+                    Some('0') => res.push('\0'),
+                    Some(x @ ('\\' | '\'' | '"')) => res.push(x),
+                    Some('u') => {
+                        if chars.next() != Some('{') { return Err("expected '{' in unicode escape".into()) }
+                        let mut code = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('}') => break,
+                                Some(x) => code.push(x),
+                                None => return Err("unterminated unicode escape".into()),
+                            }
+                        }
+                        match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                            Some(x) => res.push(x),
+                            None => return Err(format!("invalid unicode escape '{code}'")),
+                        }
+                    }
+                    x => return Err(format!("unknown escape sequence {x:?}")),
                 }
+                Some(ch) => res.push(ch),
+            }
+        }
+    }
 
-                code.copy_within(src_pos..code_addr + new_size, dest_pos);
-                dest_pos += code_addr + new_size - src_pos;
-                src_pos = code_addr + prev_size;
-                old_hole_pos_to_new_pos.insert(code_addr, dest_pos - new_size);
-                total_shift += prev_size - new_size;
+    let mut res = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            '#' => break,
+            '"' | '\'' => {
+                chars.next();
+                let content = read_escaped(&mut chars, ch)?;
+                match ch {
+                    '"' => res.push(AsmToken::Str(content)),
+                    _ => {
+                        let mut content_chars = content.chars();
+                        match (content_chars.next(), content_chars.next()) {
+                            (Some(x), None) => res.push(AsmToken::Char(x)),
+                            _ => return Err(format!("invalid char literal '{content}'")),
+                        }
+                    }
+                }
             }
-            for old in final_ins_pos_update_iter { *old -= total_shift; }
-            code.copy_within(src_pos..src_pos + (orig_code_size - total_shift - dest_pos), dest_pos);
-            code.truncate(orig_code_size - total_shift);
+            '{' | '}' | '(' | ')' | '[' | ']' | ':' | ',' | '@' => {
+                chars.next();
+                res.push(AsmToken::Punct(ch));
+            }
+            x if x.is_whitespace() => { chars.next(); }
+            x if is_word_char(x) => {
+                let mut word = String::new();
+                while let Some(&x) = chars.peek() {
+                    if !is_word_char(x) { break }
+                    word.push(x);
+                    chars.next();
+                }
+                res.push(AsmToken::Word(word));
+            }
+            x => return Err(format!("unexpected character {x:?}")),
+        }
+    }
+    Ok(res)
+}
 
-            let mut buf = Vec::with_capacity(MAX_U64_ENCODED_BYTES);
-            for code_addr in final_relocates.iter_mut() {
-                *code_addr = old_hole_pos_to_new_pos[code_addr];
-                let old_pos = <usize as BinaryRead>::read(code, &[], *code_addr);
-                buf.clear();
-                encode_u64(final_ins_pos[old_pos_to_ins[&old_pos.0]] as u64, &mut buf, Some(old_pos.1 - *code_addr));
-                debug_assert_eq!(buf.len(), old_pos.1 - *code_addr);
-                code[*code_addr..old_pos.1].copy_from_slice(&buf);
+fn asm_parse_value(tokens: &[AsmToken], pos: &mut usize) -> Result<AsmValue, String> {
+    fn expect(tokens: &[AsmToken], pos: &mut usize, punct: char) -> Result<(), String> {
+        match tokens.get(*pos) {
+            Some(AsmToken::Punct(x)) if *x == punct => { *pos += 1; Ok(()) }
+            x => Err(format!("expected '{punct}', got {x:?}")),
+        }
+    }
+    fn parse_seq<T>(tokens: &[AsmToken], pos: &mut usize, close: char, mut item: impl FnMut(&[AsmToken], &mut usize) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut res = vec![];
+        loop {
+            if let Some(AsmToken::Punct(x)) = tokens.get(*pos) {
+                if *x == close { *pos += 1; return Ok(res) }
+            }
+            res.push(item(tokens, pos)?);
+            match tokens.get(*pos) {
+                Some(AsmToken::Punct(',')) => *pos += 1,
+                _ => { expect(tokens, pos, close)?; return Ok(res) }
             }
+        }
+    }
 
-            total_shift
+    let token = tokens.get(*pos).ok_or_else(|| "unexpected end of line".to_owned())?;
+    *pos += 1;
+    Ok(match token {
+        AsmToken::Str(x) => AsmValue::Str(x.clone()),
+        AsmToken::Char(x) => AsmValue::Char(*x),
+        AsmToken::Punct('[') => AsmValue::List(parse_seq(tokens, pos, ']', asm_parse_value)?),
+        AsmToken::Punct(x) => return Err(format!("unexpected '{x}'")),
+        AsmToken::Word(name) => match tokens.get(*pos) {
+            Some(AsmToken::Punct('(')) => {
+                *pos += 1;
+                AsmValue::Tuple(name.clone(), parse_seq(tokens, pos, ')', asm_parse_value)?)
+            }
+            Some(AsmToken::Punct('{')) => {
+                *pos += 1;
+                let fields = parse_seq(tokens, pos, '}', |tokens, pos| {
+                    let field = match tokens.get(*pos) {
+                        Some(AsmToken::Word(x)) => x.clone(),
+                        x => return Err(format!("expected field name, got {x:?}")),
+                    };
+                    *pos += 1;
+                    expect(tokens, pos, ':')?;
+                    Ok((field, asm_parse_value(tokens, pos)?))
+                })?;
+                AsmValue::Struct(name.clone(), fields.into_iter().collect())
+            }
+            _ => AsmValue::Word(name.clone()),
         }
+    })
+}
 
-        let mut fmt_buf = Vec::with_capacity(MAX_U64_ENCODED_BYTES);
-        let mut shrinking_plan = vec![];
-        let mut final_relocates = vec![];
-        for info in relocate_info {
-            fmt_buf.clear();
-            let (code_addr, prev_size) = match info {
-                RelocateInfo::Code { code_addr } => {
-                    final_relocates.push(code_addr);
-                    let pos = <usize as BinaryRead>::read(&code, &data, code_addr);
-                    encode_u64(final_ins_pos[pos.0] as u64, &mut fmt_buf, None);
-                    (code_addr, pos.1 - code_addr)
-                }
-                RelocateInfo::Data { code_addr } => {
-                    let pool_index = <usize as BinaryRead>::read(&code, &data, code_addr);
-                    let slice = &data_backing.1[pool_index.0];
-                    encode_u64((data_backing_pos[slice.src] + slice.start) as u64, &mut fmt_buf, None);
-                    (code_addr, pool_index.1 - code_addr)
+trait FromAsm<'a>: Sized {
+    fn from_asm(value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Option<Self>;
+}
+macro_rules! from_asm_parsed {
+    ($($t:ty),*$(,)?) => {$(
+        impl FromAsm<'_> for $t {
+            fn from_asm(value: AsmValue, _: &typed_arena::Arena<String>) -> Option<Self> {
+                match value {
+                    AsmValue::Word(x) => x.parse().ok(),
+                    _ => None,
                 }
-            };
-            debug_assert!(prev_size >= fmt_buf.len());
-            shrinking_plan.push((code_addr, prev_size, fmt_buf.len()));
-            code[code_addr..code_addr + fmt_buf.len()].copy_from_slice(&fmt_buf);
+            }
+        }
+    )*}
+}
+from_asm_parsed! { bool, u8, usize, i32, f64 }
+macro_rules! from_asm_u8_type {
+    ($($t:ty),*$(,)?) => {$(
+        impl FromAsm<'_> for $t {
+            fn from_asm(value: AsmValue, _: &typed_arena::Arena<String>) -> Option<Self> {
+                match value {
+                    AsmValue::Word(x) => (0..=u8::MAX).filter_map(<$t>::from_u8).find(|v| format!("{v:?}") == x),
+                    _ => None,
+                }
+            }
+        }
+    )*}
+}
+from_asm_u8_type! { PrintStyle, Property, Relation, TimeQuery, BinaryOp, UnaryOp, VariadicOp, BasicType, StopMode }
+impl<'a> FromAsm<'a> for &'a str {
+    fn from_asm(value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Str(x) => Some(arena.alloc(x).as_str()),
+            _ => None,
+        }
+    }
+}
+impl FromAsm<'_> for String {
+    fn from_asm(value: AsmValue, _: &typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Str(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+impl<'a, T: FromAsm<'a>> FromAsm<'a> for Option<T> {
+    fn from_asm(value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Word(x) if x == "None" => Some(None),
+            AsmValue::Tuple(x, mut values) if x == "Some" && values.len() == 1 => Some(Some(T::from_asm(values.pop().unwrap(), arena)?)),
+            _ => None,
+        }
+    }
+}
+impl<'a, T: FromAsm<'a>> FromAsm<'a> for Vec<T> {
+    fn from_asm(value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::List(values) => values.into_iter().map(|x| T::from_asm(x, arena)).collect(),
+            _ => None,
+        }
+    }
+}
+impl FromAsm<'_> for VariadicLen {
+    fn from_asm(value: AsmValue, arena: &typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Word(x) if x == "Dynamic" => Some(VariadicLen::Dynamic),
+            AsmValue::Tuple(x, mut values) if x == "Fixed" && values.len() == 1 => Some(VariadicLen::Fixed(usize::from_asm(values.pop().unwrap(), arena)?)),
+            _ => None,
+        }
+    }
+}
+impl FromAsm<'_> for KeyCode {
+    fn from_asm(value: AsmValue, _: &typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Word(x) => match x.as_str() {
+                "Up" => Some(KeyCode::Up),
+                "Down" => Some(KeyCode::Down),
+                "Left" => Some(KeyCode::Left),
+                "Right" => Some(KeyCode::Right),
+                "Enter" => Some(KeyCode::Enter),
+                _ => None,
+            }
+            AsmValue::Tuple(x, values) if x == "Char" => match values.as_slice() {
+                [AsmValue::Char(x)] => Some(KeyCode::Char(*x)),
+                _ => None,
+            }
+            _ => None,
         }
+    }
+}
 
-        apply_shrinking_plan(&shrinking_plan, &mut final_relocates, &mut code, &mut final_ins_pos);
+struct AsmFields<'a> {
+    line: usize,
+    fields: BTreeMap<String, AsmValue>,
+    arena: &'a typed_arena::Arena<String>,
+}
+impl<'a> AsmFields<'a> {
+    fn take<T: FromAsm<'a>>(&mut self, field: &str) -> Result<T, AssembleError> {
+        self.fields.remove(field).and_then(|x| T::from_asm(x, self.arena)).ok_or_else(|| AssembleError::BadField { line: self.line, field: field.into() })
+    }
+    fn finish(self) -> Result<(), AssembleError> {
+        match self.fields.into_keys().next() {
+            Some(field) => Err(AssembleError::BadField { line: self.line, field }),
+            None => Ok(()),
+        }
+    }
+}
+impl<'a> FromAsm<'a> for Color {
+    fn from_asm(value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Option<Self> {
+        match value {
+            AsmValue::Struct(x, fields) if x == "Color" => {
+                let mut fields = AsmFields { line: 0, fields, arena };
+                let res = Color { r: fields.take("r").ok()?, g: fields.take("g").ok()?, b: fields.take("b").ok()?, a: fields.take("a").ok()? };
+                fields.finish().ok()?;
+                Some(res)
+            }
+            _ => None,
+        }
+    }
+}
 
-        for _ in 0..SHRINK_CYCLES {
-            shrinking_plan.clear();
-            for code_addr in final_relocates.iter().copied() {
-                let val = <usize as BinaryRead>::read(&code, &data, code_addr);
-                fmt_buf.clear();
-                encode_u64(val.0 as u64, &mut fmt_buf, None);
-                debug_assert!(fmt_buf.len() <= val.1 - code_addr);
-                code[code_addr..code_addr + fmt_buf.len()].copy_from_slice(&fmt_buf);
-                shrinking_plan.push((code_addr, val.1 - code_addr, fmt_buf.len()));
+macro_rules! asm_instruction {
+    ($name:expr, $fields:expr, $line:expr => $($ins:ident $({ $($field:ident),*$(,)? })?),*$(,)?) => {
+        match $name {
+            $(stringify!($ins) => Instruction::$ins $({ $($field: $fields.take(stringify!($field))?),* })?,)*
+            _ => return Err(AssembleError::UnknownInstruction { line: $line, name: $name.into() }),
+        }
+    }
+}
+fn asm_instruction<'a>(line: usize, value: AsmValue, arena: &'a typed_arena::Arena<String>) -> Result<(Instruction<'a>, Option<AsmLabel>), AssembleError> {
+    let (name, fields) = match value {
+        AsmValue::Word(name) => (name, Default::default()),
+        AsmValue::Struct(name, fields) => (name, fields),
+        _ => return Err(AssembleError::BadSyntax { line, info: "expected an instruction".into() }),
+    };
+    let mut fields = AsmFields { line, fields, arena };
+
+    // code addresses are resolved after all labels have been defined, so we use a placeholder for now
+    let mut target = None;
+    for field in ["to", "goto", "pos"] {
+        if let Some(label) = asm_take_label(line, &mut fields.fields, field)? {
+            target = Some(label);
+        }
+    }
+
+    let ins = asm_instruction! { name.as_str(), fields, line =>
+        Yield, WarpStart, WarpStop,
+        PushBool { value }, PushInt { value }, PushNumber { value }, PushColor { value }, PushString { value },
+        PushVariable { var }, PushEntity { name }, PushSelf,
+        PopValue, DupeValue { top_index }, SwapValues { top_index_1, top_index_2 },
+        TypeQuery { ty }, ToBool, ToNumber,
+        ListCons, ListCdr, ListFind, ListContains, ListIsEmpty, ListLength, ListDims, ListRank,
+        ListRev, ListFlatten, ListSort { keyed }, ListReshape { len }, ListCartesianProduct { len },
+        ListJson, ListCsv, ListColumns, ListLines,
+        ListInsert, ListInsertLast, ListInsertRandom,
+        ListGet, ListGetLast, ListGetRandom, ListGetKey,
+        ListAssign, ListAssignLast, ListAssignRandom, ListAssignKey,
+        ListRemove, ListRemoveLast, ListRemoveAll,
+        ListPopFirstOrElse { goto },
+        BinaryOp { op }, VariadicOp { op, len }, Cmp { relation }, Identical, UnaryOp { op }, StrReplace,
+        DeclareLocal { var }, InitUpvar { var }, Assign { var }, BinaryOpAssign { var, op },
+        Watcher { create, var }, Pause, Stop { mode },
+        Jump { to }, ConditionalJump { to, when },
+        Call { pos, tokens }, MakeClosure { pos, params, tokens }, CallClosure { new_entity, args }, ForkClosure { args }, Return,
+        PushHandler { pos, var }, PopHandler, Throw,
+        CallRpc { tokens }, PushRpcError, Syscall { len }, PushSyscallError,
+        SendLocalMessage { wait, target }, PushLocalMessage,
+        Print { style }, Ask, PushAnswer,
+        ResetTimer, PushTimer, Sleep, PushRealTime { query }, KeyPressed { key },
+        SendNetworkMessage { tokens, expect_reply }, SendNetworkReply,
+        PushProperty { prop }, SetProperty { prop }, ChangeProperty { prop },
+        PushCostume, PushCostumeNumber, PushCostumeList, SetCostume, NextCostume,
+        Clone, DeleteClone, ClearEffects, ClearDrawings,
+        GotoXY, Goto, PointTowardsXY, PointTowards, Forward,
+        PlaySound { blocking }, PlayNote, StopSounds,
+        IsTouchingEntity, IsTouchingMouse, IsTouchingEdge, IsTouchingDrawings, DistanceTo, DirectionTo, PushAttributeOf,
+        UnknownBlock { name, args },
+    };
+    fields.finish()?;
+    Ok((ins, target))
+}
+/// Replaces a code address field with a placeholder and returns the label to resolve later (if the field is present).
+fn asm_take_label(line: usize, fields: &mut BTreeMap<String, AsmValue>, field: &str) -> Result<Option<AsmLabel>, AssembleError> {
+    let label = match fields.remove(field) {
+        Some(AsmValue::Word(x)) => match x.parse::<usize>() {
+            Ok(x) => AsmLabel::Addr(x),
+            Err(_) => AsmLabel::Name(x),
+        }
+        Some(_) => return Err(AssembleError::BadField { line, field: field.into() }),
+        None => return Ok(None),
+    };
+    fields.insert(field.into(), AsmValue::Word("0".into()));
+    Ok(Some(label))
+}
+fn asm_event(line: usize, value: AsmValue, arena: &typed_arena::Arena<String>) -> Result<(Event, Option<AsmLabel>), AssembleError> {
+    let (name, fields) = match value {
+        AsmValue::Word(name) => (name, Default::default()),
+        AsmValue::Struct(name, fields) => (name, fields),
+        _ => return Err(AssembleError::BadSyntax { line, info: "expected an event".into() }),
+    };
+    let mut fields = AsmFields { line, fields, arena };
+
+    // the condition code address of a when event is resolved later, just like instruction targets
+    let condition = asm_take_label(line, &mut fields.fields, "condition")?;
+    let res = match name.as_str() {
+        "OnFlag" => Event::OnFlag,
+        "OnClone" => Event::OnClone,
+        "LocalMessage" => Event::LocalMessage { msg_type: fields.take("msg_type")? },
+        "NetworkMessage" => Event::NetworkMessage { msg_type: fields.take("msg_type")?, fields: fields.take("fields")? },
+        "OnKey" => Event::OnKey { key_filter: fields.take("key_filter")? },
+        "Custom" => Event::Custom { name: fields.take("name")?, fields: fields.take("fields")? },
+        "When" => Event::When { condition: fields.take("condition")? },
+        _ => return Err(AssembleError::BadField { line, field: name }),
+    };
+    fields.finish()?;
+    Ok((res, condition))
+}
+fn asm_init_value(line: usize, value: AsmValue, ref_values: &mut Vec<RefValue>) -> Result<InitValue, AssembleError> {
+    Ok(match value {
+        AsmValue::Word(x) => match x.as_str() {
+            "true" => InitValue::Bool(true),
+            "false" => InitValue::Bool(false),
+            _ => match x.parse::<f64>() {
+                Ok(x) => InitValue::Number(Number::new(x)?),
+                Err(_) => return Err(AssembleError::BadSyntax { line, info: format!("unknown value '{x}'") }),
             }
-            let delta = apply_shrinking_plan(&shrinking_plan, &mut final_relocates, &mut code, &mut final_ins_pos);
-            if delta == 0 { break }
         }
+        AsmValue::Str(x) => {
+            ref_values.push(RefValue::String(x));
+            InitValue::Ref(ref_values.len() - 1)
+        }
+        AsmValue::List(values) => {
+            let values = values.into_iter().map(|x| asm_init_value(line, x, ref_values)).collect::<Result<_,_>>()?;
+            ref_values.push(RefValue::List(values));
+            InitValue::Ref(ref_values.len() - 1)
+        }
+        _ => return Err(AssembleError::BadSyntax { line, info: "expected a bool, number, string, or list".into() }),
+    })
+}
 
-        let (mut funcs, mut entities) = (funcs, entities);
+#[test]
+fn test_optimize() {
+    let template = include_str!("test/templates/generic-static.xml");
+    let sources = [
+        include_str!("test/projects/counting.xml").to_owned(),
+        include_str!("test/projects/cloning.xml").to_owned(),
+        include_str!("test/projects/broadcast.xml").to_owned(),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/all-arithmetic.xml")),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/early-return.xml")),
+    ];
+    for (i, src) in sources.iter().enumerate() {
+        let role = &ast::Parser::default().parse(src).unwrap().roles[0];
+        let (plain, _, _, _) = ByteCode::compile_with(role, CompileSettings { optimize: false }).unwrap();
+        let (optimized, _, _, _) = ByteCode::compile_with(role, CompileSettings { optimize: true }).unwrap();
+        assert!(optimized.total_size() <= plain.total_size(), "source {i}");
 
-        for func in funcs.iter_mut() { func.1 = final_ins_pos[func.1]; }
-        for entity in entities.iter_mut() {
-            for func in entity.1.funcs.iter_mut() { func.1 = final_ins_pos[func.1]; }
-            for script in entity.1.scripts.iter_mut() { script.1 = final_ins_pos[script.1]; }
+        let mut pos = 0;
+        while pos < optimized.code.len() {
+            let (ins, aft) = Instruction::read(&optimized.code, &optimized.data, pos);
+            if i == 3 { assert!(!matches!(ins, Instruction::BinaryOp { .. } | Instruction::UnaryOp { .. }), "{ins:?}"); }
+            if let Some(target) = ins.target() { assert!(target < optimized.code.len()); }
+            pos = aft;
         }
-        let locations = Locations::condense(self.ins_locations.iter().map(|(p, v)| (final_ins_pos[*p], *v)).collect())?;
+    }
+}
 
-        Ok((ByteCode { tag: Default::default(), code: code.into_boxed_slice(), data: data.into_boxed_slice() }, ScriptInfo { funcs, entities }, locations))
+#[test]
+fn test_optimize_unused_pushes() {
+    let string_arena = Default::default();
+    let mut code = ByteCodeBuilder {
+        ins: vec![
+            Instruction::PushVariable { var: "foo" }.into(), Instruction::PopValue.into(),
+            Instruction::PushInt { value: 4 }.into(), Instruction::PopValue.into(),
+            Instruction::PushString { value: "bar" }.into(), Instruction::PopValue.into(),
+            Instruction::Return.into(),
+        ],
+        call_holes: Default::default(),
+        closure_holes: Default::default(),
+        ins_locations: Default::default(),
+        ins_block_starts: Default::default(),
+        string_arena: &string_arena,
+    };
+    let mut removed = vec![false; code.ins.len()];
+    assert!(code.remove_unused_pushes(&vec![false; code.ins.len() + 1], &mut removed));
+    assert_eq!(removed, [false, false, true, true, true, true, false]); // variable lookups can fail, so they must be kept
+}
+
+#[test]
+fn test_optimize_locations() {
+    let template = include_str!("test/templates/generic-static.xml");
+    let funcs = r#"<blocks><block-definition s="main" type="reporter" category="custom"><header></header><code></code><translations></translations><inputs></inputs><script><block collabId="item_1" s="doReport"><block collabId="item_2" s="reportProduct"><block collabId="item_3" s="reportVariadicSum"><list><l>1</l><l>2</l></list></block><l>3</l></block></block></script></block-definition></blocks>"#;
+    let src = template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", funcs);
+    let role = &ast::Parser::default().parse(&src).unwrap().roles[0];
+    let (code, _, locations, _) = ByteCode::compile_with(role, CompileSettings { optimize: true }).unwrap();
+
+    let (ins, aft) = Instruction::read(&code.code, &code.data, 0);
+    assert!(matches!(ins, Instruction::PushInt { value: 9 }), "{ins:?}");
+    assert_eq!(locations.lookup(0).as_deref(), Some("item_2"));
+    assert_eq!(locations.lookup(aft).as_deref(), Some("item_1"));
+    assert_eq!(locations.find("item_2"), [0]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_assemble() {
+    let template = include_str!("test/templates/generic-static.xml");
+    let sources = [
+        include_str!("test/projects/counting.xml").to_owned(),
+        include_str!("test/projects/cloning.xml").to_owned(),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/all-arithmetic.xml")),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/autofill-closure-params.xml")),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/try-catch-throw.xml")),
+    ];
+    for (i, src) in sources.iter().enumerate() {
+        let role = &ast::Parser::default().parse(src).unwrap().roles[0];
+        for optimize in [false, true] {
+            let (code, _, _, _) = ByteCode::compile_with(role, CompileSettings { optimize }).unwrap();
+            let mut listing = Vec::new();
+            code.dump_code(&mut listing).unwrap();
+            let (assembled, _, _, _) = ByteCode::assemble(core::str::from_utf8(&listing).unwrap()).unwrap();
+            assert_eq!(assembled.code, code.code, "source {i}");
+            assert_eq!(assembled.data, code.data, "source {i}");
+        }
+    }
+
+    let (code, init_info, locations, labels) = ByteCode::assemble(r#"
+        .project "test"
+        .global "foo" [1, "two", true]
+        .entity "Sprite"
+        .field "bar" 5
+        .script start OnFlag
+        .script start LocalMessage { msg_type: Some("go") }
+        .script start When { condition: done }
+
+        start:
+            PushInt { value: 0 }                      @ item_1
+        loop: DupeValue { top_index: 0 }
+            PushString { value: "5" }
+            Cmp { relation: Less }
+            ConditionalJump { to: done, when: false } # exit the loop
+            PushInt { value: 1 }                      @ item_2_1
+            BinaryOp { op: Add }
+            Jump { to: loop }
+        done:
+            Return
+    "#).unwrap();
+    assert_eq!(init_info.proj_name, "test");
+    assert_eq!(init_info.ref_values.len(), 2);
+    assert_eq!(init_info.globals.len(), 1);
+    assert_eq!(init_info.entities.len(), 1);
+    assert_eq!(init_info.entities[0].fields.len(), 1);
+    assert_eq!(init_info.entities[0].scripts.len(), 3);
+    assert!(init_info.entities[0].scripts.iter().all(|x| x.1 == labels["start"]));
+    assert!(matches!(init_info.entities[0].scripts[2].0, Event::When { condition } if condition == labels["done"]));
+    assert_eq!(labels["start"], 0);
+    assert!(labels["loop"] < labels["done"] && labels["done"] < code.code.len());
+    assert_eq!(locations.lookup(labels["loop"]).as_deref(), Some("item_2_1"));
+
+    let mut pos = 0;
+    let mut jumps = vec![];
+    while pos < code.code.len() {
+        let (ins, aft) = Instruction::read(&code.code, &code.data, pos);
+        if let Some(target) = ins.target() { jumps.push(target); }
+        pos = aft;
+    }
+    assert_eq!(jumps, [labels["done"], labels["loop"]]);
+
+    assert!(matches!(ByteCode::assemble("Jump { to: nowhere }"), Err(AssembleError::UndefinedLabel { line: 1, .. })));
+    assert!(matches!(ByteCode::assemble("a: Return\na: Return"), Err(AssembleError::DuplicateLabel { line: 2, .. })));
+    assert!(matches!(ByteCode::assemble("Frobnicate"), Err(AssembleError::UnknownInstruction { line: 1, .. })));
+    assert!(matches!(ByteCode::assemble("PushInt { val: 4 }"), Err(AssembleError::BadField { line: 1, .. })));
+    assert!(matches!(ByteCode::assemble(".field \"x\" 4"), Err(AssembleError::NoEntity { line: 1 })));
+}
+
+#[test]
+fn test_verify() {
+    let template = include_str!("test/templates/generic-static.xml");
+    let sources = [
+        include_str!("test/projects/counting.xml").to_owned(),
+        include_str!("test/projects/cloning.xml").to_owned(),
+        include_str!("test/projects/broadcast.xml").to_owned(),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/try-catch-throw.xml")),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/autofill-closure-params.xml")),
+        template.replace("{globals}", "").replace("{fields}", "").replace("{methods}", "").replace("{funcs}", include_str!("test/blocks/list-index-blocks.xml")),
+    ];
+    for (i, src) in sources.iter().enumerate() {
+        let role = &ast::Parser::default().parse(src).unwrap().roles[0];
+        for optimize in [false, true] {
+            let (code, init_info, _, _) = ByteCode::compile_with(role, CompileSettings { optimize }).unwrap();
+            if let Err(e) = code.verify(&init_info) { panic!("source {i} (optimize = {optimize}): {e:?}") }
+        }
     }
+
+    let verify = |body: &str| {
+        let (code, init_info, _, _) = ByteCode::assemble(&format!(".entity \"sprite\"\n.script start OnFlag\nstart:\n{body}")).unwrap();
+        code.verify(&init_info)
+    };
+    assert!(verify("PushInt { value: 1 }\nReturn").is_ok());
+    assert!(matches!(verify("PopValue\nPushInt { value: 1 }\nReturn"), Err(VerifyError::StackUnderflow { pos: 0, depth: 0, needed: 1 })));
+    assert!(matches!(verify("PushInt { value: 1 }\nDupeValue { top_index: 1 }\nReturn"), Err(VerifyError::StackUnderflow { depth: 1, needed: 2, .. })));
+    assert!(matches!(verify("loop: PushInt { value: 1 }\nJump { to: loop }"), Err(VerifyError::StackMismatch { pos: 0, depths: ((0, 0), (1, 0)) })));
+    assert!(matches!(verify("PushInt { value: 1 }"), Err(VerifyError::FallsOffEnd { pos: 0 })));
+    assert!(matches!(verify("PopHandler\nPushInt { value: 1 }\nReturn"), Err(VerifyError::HandlerUnderflow { pos: 0 })));
+    assert!(matches!(verify("CallRpc { tokens: \"\\0svc\" }\nReturn"), Err(VerifyError::BadTokens { pos: 0 })));
+    assert!(verify("PushHandler { pos: catch, var: \"err\" }\nPushString { value: \"oops\" }\nThrow\ncatch: PopHandler\nPushInt { value: 1 }\nReturn").is_ok());
+    assert!(verify("PushInt { value: 0 }\nReturn\nPopHandler\nPopValue").is_ok()); // unreachable code is not analyzed
+
+    let (_, mut init_info, _, _) = ByteCode::assemble(".entity \"sprite\"\n.script start OnFlag\nstart: Return").unwrap();
+    let raw = |code: &[u8], data: &[u8]| ByteCode { tag: Default::default(), code: code.into(), data: data.into() };
+    assert!(matches!(raw(&[200], &[]).verify(&init_info), Err(VerifyError::BadInstruction { pos: 0 })));
+    assert!(matches!(raw(&[5], &[]).verify(&init_info), Err(VerifyError::BadInstruction { pos: 0 })));
+    assert!(matches!(raw(&[5, 0x80], &[]).verify(&init_info), Err(VerifyError::BadInstruction { pos: 0 })));
+    assert!(matches!(raw(&[9, 0, 4], b"abc").verify(&init_info), Err(VerifyError::BadInstruction { pos: 0 })));
+    assert!(matches!(raw(&[9, 0, 2], &[0xff, 0xfe]).verify(&init_info), Err(VerifyError::BadInstruction { pos: 0 })));
+    assert!(matches!(raw(&[75, 1], &[]).verify(&init_info), Err(VerifyError::BadTarget { pos: 0, target: 1 })));
+    assert!(matches!(raw(&[75, 5], &[]).verify(&init_info), Err(VerifyError::BadTarget { pos: 0, target: 5 })));
+    assert!(raw(&[5, 2, 83], &[]).verify(&init_info).is_ok());
+    init_info.entities[0].scripts[0].1 = 1;
+    assert!(matches!(raw(&[5, 2, 83], &[]).verify(&init_info), Err(VerifyError::BadScript { entity: 0, pos: 1 })));
 }
 
 impl ByteCode {
@@ -2203,13 +3592,21 @@ impl ByteCode {
     /// which is needed to provide human-readable error locations at runtime,
     /// as well as a [`ScriptInfo`] object that contains a symbol table of functions and scripts
     /// (needed to execute a specific segment of code).
+    ///
+    /// This uses the default [`CompileSettings`]; see [`ByteCode::compile_with`] to use custom settings.
     pub fn compile(role: &ast::Role) -> Result<(ByteCode, InitInfo, Locations, ScriptInfo), CompileError> {
+        Self::compile_with(role, Default::default())
+    }
+    /// Compiles a single project role into an executable form (see [`ByteCode::compile`]).
+    /// The `settings` argument controls code generation options such as optimization (see [`CompileSettings`]).
+    pub fn compile_with(role: &ast::Role, settings: CompileSettings) -> Result<(ByteCode, InitInfo, Locations, ScriptInfo<'_>), CompileError<'_>> {
         let string_arena = Default::default();
         let mut code = ByteCodeBuilder {
             ins: Default::default(),
             call_holes: Default::default(),
             closure_holes: Default::default(),
             ins_locations: Default::default(),
+            ins_block_starts: Default::default(),
             string_arena: &string_arena,
         };
 
@@ -2228,12 +3625,19 @@ impl ByteCode {
             }
 
             let mut scripts = Vec::with_capacity(entity.scripts.len());
+            let mut conditions = vec![];
             for script in entity.scripts.iter() {
                 scripts.push((script, code.ins.len()));
                 code.append_stmts_ret(&[], &script.stmts, Some(entity))?;
+
+                if let Some(ast::HatKind::When { condition }) = script.hat.as_ref().map(|x| &x.kind) {
+                    conditions.push((script, code.ins.len()));
+                    code.append_expr(condition, Some(entity))?;
+                    code.ins.push(Instruction::Return.into());
+                }
             }
 
-            entities.push((entity, EntityScriptInfo { funcs, scripts }));
+            entities.push((entity, EntityScriptInfo { funcs, scripts, conditions }));
         }
 
         while let Some((hole_pos, params, captures, stmts, entity)) = code.closure_holes.pop_front() {
@@ -2251,7 +3655,7 @@ impl ByteCode {
             code.ins[hole_pos] = Instruction::MakeClosure { pos, params: params.len(), tokens: string_arena.alloc(tokens.finish()) }.into();
         }
 
-        let (bytecode, script_info, locations) = code.link(funcs, entities)?;
+        let (bytecode, script_info, locations) = code.link(funcs, entities, settings)?;
         let init_info = Self::extract_init_info(role, &script_info)?;
 
         Ok((bytecode, init_info, locations, script_info))
@@ -2353,6 +3757,7 @@ impl ByteCode {
             let mut fields = vec![];
             let mut scripts = vec![];
             let mut costumes = vec![];
+            let sounds = vec![]; // the ast does not currently expose the sounds of an entity
 
             let visible = entity.visible;
             let active_costume = entity.active_costume;
@@ -2380,35 +3785,311 @@ impl ByteCode {
                     ast::HatKind::LocalMessage { msg_type } => Event::LocalMessage { msg_type: msg_type.clone() },
                     ast::HatKind::NetworkMessage { msg_type, fields } => Event::NetworkMessage { msg_type: msg_type.clone(), fields: fields.iter().map(|x| x.trans_name.clone()).collect() },
                     ast::HatKind::Unknown { name, fields } => Event::Custom { name: name.clone(), fields: fields.iter().map(|x| x.trans_name.clone()).collect() },
-                    ast::HatKind::OnKey { key } => Event::OnKey {
-                        key_filter: match key.as_str() {
-                            "any key" => None,
-                            "up arrow" => Some(KeyCode::Up),
-                            "down arrow" => Some(KeyCode::Down),
-                            "left arrow" => Some(KeyCode::Left),
-                            "right arrow" => Some(KeyCode::Right),
-                            "enter" => Some(KeyCode::Enter),
-                            "space" => Some(KeyCode::Char(' ')),
-                            _ => {
-                                let mut chars = key.chars();
-                                let res = chars.next().map(|x| KeyCode::Char(x.to_ascii_lowercase()));
-                                if res.is_none() || chars.next().is_some() { return Err(CompileError::BadKeycode { key }); }
-                                Some(res.unwrap())
-                            }
-                        }
-                    },
+                    ast::HatKind::OnKey { key } => Event::OnKey { key_filter: parse_key_filter(key)? },
+                    ast::HatKind::When { .. } => Event::When { condition: entity_info.conditions.iter().find(|x| core::ptr::eq(x.0, script)).unwrap().1 },
                     kind => return Err(CompileError::UnsupportedEvent { kind }),
                 };
                 scripts.push((event, pos));
             }
 
-            entities.push(EntityInitInfo { name, fields, costumes, scripts, active_costume, pos, heading, size, visible, color });
+            entities.push(EntityInitInfo { name, fields, costumes, sounds, scripts, active_costume, pos, heading, size, visible, color });
         }
 
         let ref_values = ref_values.into_iter().map(|x| x.0.ok_or(CompileError::UndefinedRef { value: x.1 })).collect::<Result<_,_>>()?;
 
         Ok(InitInfo { tag: Default::default(), proj_name, ref_values, globals, entities })
     }
+    /// Assembles a textual listing into an executable form.
+    /// This is primarily intended for writing targeted tests of [`Process`](crate::process::Process) without going through the compiler.
+    ///
+    /// The syntax is one instruction per line, written the same way as in the output of [`ByteCode::dump_code`]
+    /// (e.g., `PushString { value: "hello" }`), which can be assembled directly.
+    /// Comments start with `#`, and a line may begin with any number of `label:` definitions.
+    /// Code addresses (the `to`, `goto`, and `pos` fields of jumps, calls, closures, and handlers, and the `condition` field of `When` events) may be either a label name
+    /// or a numeric address from a [`ByteCode::dump_code`] listing.
+    /// An instruction may be followed by `@ loc` to give it a location (collab id) for error reporting.
+    ///
+    /// The initial project state is given by the following directives:
+    /// `.project "name"`, `.global "name" value`, `.entity "name"`, `.field "name" value`, and `.script label Event`,
+    /// where values are numbers, booleans, strings, or lists thereof, and events are written in the same form as their [`Event`] variants.
+    ///
+    /// On success, this also returns the final address of each named label.
+    pub fn assemble(src: &str) -> Result<(ByteCode, InitInfo, Locations, BTreeMap<String, usize>), AssembleError> {
+        fn is_hex_byte(tokens: &[AsmToken], pos: usize) -> bool {
+            match (tokens.get(pos), tokens.get(pos + 1)) {
+                (_, Some(AsmToken::Punct(':'))) => false,
+                (Some(AsmToken::Word(x)), _) => x.len() == 2 && x.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')),
+                _ => false,
+            }
+        }
+        fn take_name(line: usize, tokens: &[AsmToken], pos: &mut usize) -> Result<String, AssembleError> {
+            match tokens.get(*pos) {
+                Some(AsmToken::Str(x)) => { *pos += 1; Ok(x.clone()) }
+                _ => Err(AssembleError::BadSyntax { line, info: "expected a string name".into() }),
+            }
+        }
+
+        let arena = typed_arena::Arena::new();
+        let mut ins: Vec<InternalInstruction> = vec![];
+        let mut ins_locations = BTreeMap::new();
+        let mut targets = vec![];
+        let mut labels = BTreeMap::new();
+        let mut addrs = BTreeMap::new();
+
+        let mut proj_name = String::new();
+        let mut ref_values = vec![];
+        let mut globals = vec![];
+        let mut entities: Vec<EntityInitInfo> = vec![];
+        let mut scripts = vec![];
+
+        for (line, content) in src.lines().enumerate() {
+            let line = line + 1;
+            let tokens = asm_tokenize(content).map_err(|info| AssembleError::BadSyntax { line, info })?;
+
+            let mut pos = 0;
+            let addr = match tokens.first() {
+                Some(AsmToken::Word(x)) if x.bytes().all(|b| b.is_ascii_digit()) && !is_hex_byte(&tokens, 0) => { pos += 1; Some(x.clone()) }
+                _ => None,
+            };
+            while is_hex_byte(&tokens, pos) { pos += 1; }
+            if pos >= tokens.len() { continue } // blank lines, comments, and hex dump continuation lines
+
+            if let Some(addr) = addr {
+                let value = addr.parse::<usize>().map_err(|_| AssembleError::BadSyntax { line, info: format!("invalid address '{addr}'") })?;
+                if addrs.insert(value, ins.len()).is_some() { return Err(AssembleError::DuplicateLabel { line, label: addr }) }
+            }
+            while let (Some(AsmToken::Word(label)), Some(AsmToken::Punct(':'))) = (tokens.get(pos), tokens.get(pos + 1)) {
+                if label.parse::<usize>().is_ok() { return Err(AssembleError::BadSyntax { line, info: format!("label '{label}' cannot be numeric") }) }
+                if labels.insert(label.clone(), ins.len()).is_some() { return Err(AssembleError::DuplicateLabel { line, label: label.clone() }) }
+                pos += 2;
+            }
+            if pos >= tokens.len() { continue }
+
+            match &tokens[pos] {
+                AsmToken::Word(directive) if directive.starts_with('.') => {
+                    pos += 1;
+                    match directive.as_str() {
+                        ".project" => proj_name = take_name(line, &tokens, &mut pos)?,
+                        ".global" => {
+                            let name = take_name(line, &tokens, &mut pos)?;
+                            let value = asm_parse_value(&tokens, &mut pos).map_err(|info| AssembleError::BadSyntax { line, info })?;
+                            globals.push((name, asm_init_value(line, value, &mut ref_values)?));
+                        }
+                        ".entity" => {
+                            let name = take_name(line, &tokens, &mut pos)?;
+                            let zero = Number::new(0.0)?;
+                            entities.push(EntityInitInfo {
+                                name, fields: vec![], costumes: vec![], sounds: vec![], scripts: vec![],
+                                visible: true, active_costume: None, size: Number::new(100.0)?, color: (0, 0, 0, 255), pos: (zero, zero), heading: zero,
+                            });
+                        }
+                        ".field" => {
+                            let name = take_name(line, &tokens, &mut pos)?;
+                            let value = asm_parse_value(&tokens, &mut pos).map_err(|info| AssembleError::BadSyntax { line, info })?;
+                            let value = asm_init_value(line, value, &mut ref_values)?;
+                            entities.last_mut().ok_or(AssembleError::NoEntity { line })?.fields.push((name, value));
+                        }
+                        ".script" => {
+                            if entities.is_empty() { return Err(AssembleError::NoEntity { line }) }
+                            let label = match tokens.get(pos) {
+                                Some(AsmToken::Word(x)) => match x.parse::<usize>() {
+                                    Ok(x) => AsmLabel::Addr(x),
+                                    Err(_) => AsmLabel::Name(x.clone()),
+                                }
+                                _ => return Err(AssembleError::BadSyntax { line, info: "expected a script label".into() }),
+                            };
+                            pos += 1;
+                            let event = asm_parse_value(&tokens, &mut pos).map_err(|info| AssembleError::BadSyntax { line, info })?;
+                            let (event, condition) = asm_event(line, event, &arena)?;
+                            scripts.push((line, entities.len() - 1, label, event, condition));
+                        }
+                        _ => return Err(AssembleError::BadSyntax { line, info: format!("unknown directive '{directive}'") }),
+                    }
+                }
+                _ => {
+                    let value = asm_parse_value(&tokens, &mut pos).map_err(|info| AssembleError::BadSyntax { line, info })?;
+                    let (instruction, target) = asm_instruction(line, value, &arena)?;
+                    if let Some(target) = target { targets.push((line, ins.len(), target)); }
+                    ins.push(instruction.into());
+
+                    if let Some(AsmToken::Punct('@')) = tokens.get(pos) {
+                        pos += 1;
+                        match tokens.get(pos) {
+                            Some(AsmToken::Word(loc)) => ins_locations.insert(ins.len(), arena.alloc(loc.clone()).as_str()),
+                            _ => return Err(AssembleError::BadSyntax { line, info: "expected a location".into() }),
+                        };
+                        pos += 1;
+                    }
+                }
+            }
+            if pos != tokens.len() { return Err(AssembleError::BadSyntax { line, info: "unexpected trailing tokens".into() }) }
+        }
+
+        let resolve = |line: usize, label: AsmLabel| match label {
+            AsmLabel::Name(x) => labels.get(&x).copied().ok_or(AssembleError::UndefinedLabel { line, label: x }),
+            AsmLabel::Addr(x) => addrs.get(&x).copied().ok_or_else(|| AssembleError::UndefinedLabel { line, label: x.to_string() }),
+        };
+        for (line, i, label) in targets {
+            let target = resolve(line, label)?;
+            if let InternalInstruction::Valid(x) = &mut ins[i] { *x.target_mut().unwrap() = target; }
+        }
+        let scripts = scripts.into_iter().map(|(line, entity, label, event, condition)| Ok((entity, resolve(line, label)?, event, condition.map(|x| resolve(line, x)).transpose()?))).collect::<Result<Vec<_>, AssembleError>>()?;
+
+        let (bytecode, final_ins_pos, locations) = encode(&ins, &ins_locations, &[]).map_err(|e| match e {
+            CompileError::InvalidLocation { loc } => AssembleError::InvalidLocation { loc: loc.into() },
+            e => unreachable!("{e:?}"),
+        })?;
+
+        for (entity, pos, mut event, condition) in scripts {
+            if let (Event::When { condition: x }, Some(condition)) = (&mut event, condition) { *x = final_ins_pos[condition]; }
+            entities[entity].scripts.push((event, final_ins_pos[pos]));
+        }
+        let labels = labels.into_iter().map(|(k, v)| (k, final_ins_pos[v])).collect();
+
+        Ok((bytecode, InitInfo { tag: Default::default(), proj_name, ref_values, globals, entities }, locations, labels))
+    }
+    /// Statically checks that the code and initial project state are well-formed and safe to execute.
+    /// This is done automatically by [`Project::from_init`](crate::project::Project::from_init), which is the entry point for all code,
+    /// including deserialized [`ByteCode`] and [`InitInfo`] objects that we did not generate ourselves.
+    ///
+    /// Specifically, this checks that every instruction can be decoded (including any referenced data),
+    /// that all code addresses (jumps, calls, closures, error handlers, and scripts) refer to the start of an instruction,
+    /// and that no reachable instruction can consume more values from the value stack (or handler stack) than are guaranteed to exist.
+    pub fn verify(&self, init_info: &InitInfo) -> Result<(), VerifyError> {
+        fn check_value(value: &InitValue, ref_values: &[RefValue]) -> Result<(), VerifyError> {
+            match value {
+                InitValue::Ref(index) if *index >= ref_values.len() => Err(VerifyError::BadRef { index: *index }),
+                _ => Ok(()),
+            }
+        }
+
+        for value in init_info.ref_values.iter() {
+            if let RefValue::List(values) = value {
+                for value in values { check_value(value, &init_info.ref_values)?; }
+            }
+        }
+        for (_, value) in init_info.globals.iter() { check_value(value, &init_info.ref_values)?; }
+        for entity in init_info.entities.iter() {
+            for (_, value) in entity.fields.iter() { check_value(value, &init_info.ref_values)?; }
+            for (_, value) in entity.costumes.iter() {
+                match value {
+                    InitValue::Ref(index) if matches!(init_info.ref_values.get(*index), Some(RefValue::Image(_))) => (),
+                    InitValue::Ref(index) => return Err(VerifyError::BadRef { index: *index }),
+                    _ => return Err(VerifyError::BadRef { index: usize::MAX }),
+                }
+            }
+            for (_, value) in entity.sounds.iter() {
+                match value {
+                    InitValue::Ref(index) if matches!(init_info.ref_values.get(*index), Some(RefValue::Audio(_))) => (),
+                    InitValue::Ref(index) => return Err(VerifyError::BadRef { index: *index }),
+                    _ => return Err(VerifyError::BadRef { index: usize::MAX }),
+                }
+            }
+        }
+
+        let mut entries = vec![];
+        for (entity, entity_info) in init_info.entities.iter().enumerate() {
+            for (event, pos) in entity_info.scripts.iter() {
+                let condition = match event { Event::When { condition } => Some(*condition), _ => None };
+                entries.extend(core::iter::once(*pos).chain(condition).map(|pos| (entity, pos)));
+            }
+        }
+        self.verify_code(&entries).map(|_| ())
+    }
+    /// Checks that a [`ProjectSnapshot`](crate::snapshot::ProjectSnapshot) is safe to restore with this code (see [`ByteCode::verify`]).
+    /// The code is checked with the given script entry points (paired with their script index),
+    /// and every other code address held by the snapshot must refer to the start of a reachable instruction.
+    pub(crate) fn verify_snapshot(&self, entries: &[(usize, usize)], positions: &[usize]) -> Result<(), VerifyError> {
+        let reachable = self.verify_code(entries)?;
+        match positions.iter().find(|pos| reachable.binary_search(pos).is_err()) {
+            Some(pos) => Err(VerifyError::BadPosition { pos: *pos }),
+            None => Ok(()),
+        }
+    }
+    /// Checks the code as described by [`ByteCode::verify`], given the script entry points (paired with an index for error reporting).
+    /// On success, returns the (sorted) addresses of all reachable instructions.
+    fn verify_code(&self, entries: &[(usize, usize)]) -> Result<Vec<usize>, VerifyError> {
+        fn visit(depths: &mut [Option<(usize, usize)>], pending: &mut Vec<usize>, pos: usize, i: usize, depth: (usize, usize)) -> Result<(), VerifyError> {
+            match depths[i] {
+                None => {
+                    depths[i] = Some(depth);
+                    pending.push(i);
+                    Ok(())
+                }
+                Some(x) if x == depth => Ok(()),
+                Some(x) => Err(VerifyError::StackMismatch { pos, depths: (x, depth) }),
+            }
+        }
+
+        let mut instructions = vec![];
+        let mut pos = 0;
+        while pos < self.code.len() {
+            let (ins, aft) = Instruction::checked_read(&self.code, &self.data, pos).ok_or(VerifyError::BadInstruction { pos })?;
+            instructions.push((pos, ins));
+            pos = aft;
+        }
+        let index_of = |pos: usize, target: usize| instructions.binary_search_by_key(&target, |x| x.0).map_err(|_| VerifyError::BadTarget { pos, target });
+
+        // depths are (value stack, handler stack) relative to the current call frame.
+        // every entry point (function, closure, or script) begins with empty stacks.
+        let mut depths = vec![None; instructions.len()];
+        let mut pending = vec![];
+        for &(entity, pos) in entries {
+            let i = instructions.binary_search_by_key(&pos, |x| x.0).map_err(|_| VerifyError::BadScript { entity, pos })?;
+            visit(&mut depths, &mut pending, pos, i, (0, 0))?;
+        }
+        for (pos, ins) in instructions.iter() {
+            if let Some(target) = ins.target() {
+                let i = index_of(*pos, target)?;
+                if let Instruction::Call { .. } | Instruction::MakeClosure { .. } = ins {
+                    visit(&mut depths, &mut pending, target, i, (0, 0))?;
+                }
+            }
+        }
+
+        while let Some(i) = pending.pop() {
+            let (pos, ins) = &instructions[i];
+            let pos = *pos;
+            let (depth, handlers) = depths[i].unwrap();
+            let (consumed, produced) = ins.stack_effect().ok_or(VerifyError::BadTokens { pos })?;
+            if depth < consumed { return Err(VerifyError::StackUnderflow { pos, depth, needed: consumed }) }
+            let next_depth = depth - consumed + produced;
+
+            let mut goto = |target: usize, depth: (usize, usize)| visit(&mut depths, &mut pending, target, index_of(pos, target)?, depth);
+            let next = match ins {
+                Instruction::Return | Instruction::Throw => None,
+                Instruction::Jump { to } => {
+                    goto(*to, (next_depth, handlers))?;
+                    None
+                }
+                Instruction::ConditionalJump { to, .. } => {
+                    goto(*to, (next_depth, handlers))?;
+                    Some((next_depth, handlers))
+                }
+                Instruction::ListPopFirstOrElse { goto: to } => {
+                    goto(*to, (depth - 1, handlers))?;
+                    Some((next_depth, handlers))
+                }
+                Instruction::PushHandler { pos: to, .. } => {
+                    goto(*to, (next_depth, handlers + 1))?; // handlers are not popped when triggered
+                    Some((next_depth, handlers + 1))
+                }
+                Instruction::PopHandler => match handlers {
+                    0 => return Err(VerifyError::HandlerUnderflow { pos }),
+                    _ => Some((next_depth, handlers - 1)),
+                }
+                _ => Some((next_depth, handlers)),
+            };
+            if let Some(next) = next {
+                match instructions.get(i + 1) {
+                    Some(x) => visit(&mut depths, &mut pending, x.0, i + 1, next)?,
+                    None => return Err(VerifyError::FallsOffEnd { pos }),
+                }
+            }
+        }
+
+        Ok(instructions.iter().zip(depths).filter(|x| x.1.is_some()).map(|x| x.0.0).collect())
+    }
     /// Generates a hex dump of the stored code, including instructions and addresses.
     #[cfg(feature = "std")]
     pub fn dump_code(&self, f: &mut dyn Write) -> io::Result<()> {
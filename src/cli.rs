@@ -6,32 +6,42 @@
 //! This includes being able to compile and run individual project files locally,
 //! as well as a server mode where a user can connect to the server from the browser
 //! and use the block-based interface to write, upload, and run code on the server.
-//! Note that server mode does not yet support multiple simultaneous.
+//! Server mode supports multiple simultaneous sessions (e.g., one per connected browser),
+//! each of which runs its own independent project.
+//! Sessions are accessed by their (unguessable) id, can only be listed if the server requires an access token,
+//! and are destroyed after a period without any requests.
 
 use alloc::rc::Rc;
 use alloc::vec::Vec;
 use alloc::boxed::Box;
-use alloc::string::String;
-use alloc::collections::VecDeque;
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
+use alloc::collections::{VecDeque, BTreeMap};
 
 use core::time::Duration;
 use core::cell::{Cell, RefCell};
-use core::{mem, fmt};
+use core::{iter, mem, fmt};
 
 use std::fs::File;
-use std::io::{self, Read, Write as IoWrite, stdout};
-use std::sync::{Arc, Mutex};
+use std::io::{self, Read, Write as IoWrite, LineWriter, stdout};
+use std::time::Instant;
+use std::sync::{Arc, Weak, Mutex};
 use std::sync::mpsc::{channel, Sender, TryRecvError};
 use std::sync::atomic::{AtomicBool, Ordering as MemoryOrder};
 use std::thread;
 
-use clap::Subcommand;
-use actix_web::{get, post, web, App, HttpServer, Responder, HttpResponse};
+use clap::{Subcommand, ValueEnum};
+use actix_web::{get, post, delete, web, App, HttpServer, Responder, HttpResponse};
+use actix_web::dev::{Service, ServiceResponse};
 use actix_cors::Cors;
 
+use uuid::Uuid;
+
+use futures::future::{self, Either, TryFutureExt};
+
 use crossterm::{cursor, execute, queue};
 use crossterm::tty::IsTty;
-use crossterm::event::{self, Event, KeyCode as RawKeyCode, KeyModifiers as RawKeyModifiers};
+use crossterm::event::{self, Event, KeyCode as RawKeyCode, KeyModifiers as RawKeyModifiers, KeyEventKind, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags};
 use crossterm::terminal::{self, ClearType};
 use crossterm::style::{ResetColor, SetForegroundColor, Color, Print};
 
@@ -44,13 +54,22 @@ use crate::bytecode::*;
 use crate::runtime::*;
 use crate::process::*;
 use crate::project::*;
+use crate::replay::*;
+use crate::profiler::*;
 use crate::template::*;
+use crate::bundle::*;
 
 const DEFAULT_BASE_URL: &str = "https://cloud.netsblox.org";
-const STEPS_PER_IO_ITER: usize = 64;
+const MAX_STEPS_PER_IO_ITER: usize = 1024;
+const DEFAULT_QUANTUM: usize = 64;
 const MAX_REQUEST_SIZE_BYTES: usize = 1024 * 1024 * 1024;
 const YIELDS_BEFORE_IDLE_SLEEP: usize = 256;
 const IDLE_SLEEP_TIME: Duration = Duration::from_micros(500);
+const RECORDING_MARK_INTERVAL: Duration = Duration::from_secs(1);
+const PROFILE_WRITE_INTERVAL: Duration = Duration::from_secs(1);
+const KEY_HOLD_TIMEOUT_MS: u64 = 750;
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const SESSION_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 macro_rules! crash {
     ($ret:literal : $($tt:tt)*) => {{
@@ -75,15 +94,32 @@ impl<F: FnOnce()> Drop for AtExit<F> {
 struct Env<'gc, C: CustomTypes<StdSystem<C>>> {
                                proj: Gc<'gc, RefLock<Project<'gc, C, StdSystem<C>>>>,
     #[collect(require_static)] locs: Locations,
+                               watchers: Vec<Watcher<'gc, C, StdSystem<C>>>,
 }
 type EnvArena<S> = Arena<Rootable![Env<'_, S>]>;
 
-fn get_env<C: CustomTypes<StdSystem<C>>>(role: &ast::Role, system: Rc<StdSystem<C>>) -> Result<EnvArena<C>, FromAstError> {
+fn get_env<C: CustomTypes<StdSystem<C>>>(role: &ast::Role, settings: Settings, system: Rc<StdSystem<C>>) -> Result<EnvArena<C>, FromAstError> {
     let (bytecode, init_info, locs, _) = ByteCode::compile(role)?;
-    Ok(EnvArena::new(Default::default(), |mc| {
-        let proj = Project::from_init(mc, &init_info, Rc::new(bytecode), Settings::default(), system);
-        Env { proj: Gc::new(mc, RefLock::new(proj)), locs }
-    }))
+    Ok(load_env(bytecode, &init_info, locs, settings, system)?)
+}
+fn load_env<C: CustomTypes<StdSystem<C>>>(bytecode: ByteCode, init_info: &InitInfo, locs: Locations, settings: Settings, system: Rc<StdSystem<C>>) -> Result<EnvArena<C>, VerifyError> {
+    EnvArena::try_new(Default::default(), |mc| {
+        let proj = Project::from_init(mc, init_info, Rc::new(bytecode), settings, system)?;
+        Ok(Env { proj: Gc::new(mc, RefLock::new(proj)), locs, watchers: vec![] })
+    })
+}
+
+/// The process scheduling policies that can be selected from the command line (see [`Scheduling`])
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SchedulingPolicy {
+    /// A process runs until it yields, so a process that never yields starves the others
+    UntilYield,
+    /// Like until-yield, but a process also loses its turn after executing --quantum instructions in a row
+    RoundRobin,
+    /// Like round-robin, but processes run in rounds where processes of higher priority entities take their turns first
+    Priority,
+    /// Runs processes in frames like the browser does, where each running process gets one turn per frame and inputs are handled between frames
+    Frames,
 }
 
 /// Standard NetsBlox VM project actions that can be performed
@@ -96,6 +132,75 @@ pub enum Mode {
         /// The specific role to run, or none if not ambiguous
         #[clap(long)]
         role: Option<String>,
+        /// Runs every role in the project side by side, with messages between them delivered locally
+        #[clap(long, conflicts_with = "role")]
+        all_roles: bool,
+        /// Records the run to the given file so that it can be reproduced with the replay command
+        #[clap(long, conflicts_with = "all_roles")]
+        record: Option<String>,
+        /// Runs without connecting to the NetsBlox server (RPCs are unavailable and messages are only delivered locally)
+        #[clap(long, conflicts_with = "server")]
+        offline: bool,
+        /// The seed for the random number generator (offline mode only)
+        #[clap(long, requires = "offline")]
+        seed: Option<u64>,
+        /// Sets a breakpoint on the block with the given collab id (interactive terminal only, can be repeated)
+        #[clap(long = "break", value_name = "COLLAB_ID")]
+        breakpoints: Vec<String>,
+        /// Profiles the run and writes the time spent in each block to the given file in the folded stack format used by flamegraph tools
+        /// (the sorted profile report is also printed on exit in an interactive terminal)
+        #[clap(long, conflicts_with = "all_roles")]
+        profile: Option<String>,
+        /// The policy used to decide when to switch between processes
+        #[clap(long, value_enum, default_value_t = SchedulingPolicy::UntilYield, conflicts_with = "record")]
+        scheduling: SchedulingPolicy,
+        /// The number of instructions a process may execute in a row before its turn ends (round-robin and priority scheduling only)
+        #[clap(long, default_value_t = DEFAULT_QUANTUM, conflicts_with = "record")]
+        quantum: usize,
+
+        /// Address of the NetsBlox server
+        #[clap(long, default_value_t = String::from(DEFAULT_BASE_URL))]
+        server: String,
+    },
+    /// Replays a recording of a project run made with run --record
+    Replay {
+        /// Path to the (xml) project file that was recorded
+        src: String,
+        /// Path to the recording file
+        recording: String,
+        /// The specific role to run, or none if not ambiguous
+        #[clap(long)]
+        role: Option<String>,
+    },
+    /// Compiles a single project file into a bundle which can be run with the run-bundle command
+    Compile {
+        /// Path to the (xml) project file
+        src: String,
+        /// Path to the bundle file to create
+        output: String,
+        /// The specific role to compile, or none if not ambiguous
+        #[clap(long)]
+        role: Option<String>,
+    },
+    /// Runs a bundle created by the compile command (no project file is needed)
+    RunBundle {
+        /// Path to the bundle file
+        src: String,
+        /// Runs without connecting to the NetsBlox server (RPCs are unavailable and messages are only delivered locally)
+        #[clap(long, conflicts_with = "server")]
+        offline: bool,
+        /// The seed for the random number generator (offline mode only)
+        #[clap(long, requires = "offline")]
+        seed: Option<u64>,
+        /// Sets a breakpoint on the block with the given collab id (interactive terminal only, can be repeated)
+        #[clap(long = "break", value_name = "COLLAB_ID")]
+        breakpoints: Vec<String>,
+        /// The policy used to decide when to switch between processes
+        #[clap(long, value_enum, default_value_t = SchedulingPolicy::UntilYield)]
+        scheduling: SchedulingPolicy,
+        /// The number of instructions a process may execute in a row before its turn ends (round-robin and priority scheduling only)
+        #[clap(long, default_value_t = DEFAULT_QUANTUM)]
+        quantum: usize,
 
         /// Address of the NetsBlox server
         #[clap(long, default_value_t = String::from(DEFAULT_BASE_URL))]
@@ -121,6 +226,27 @@ pub enum Mode {
         /// The port to bind for the web server
         #[clap(long, default_value_t = 6286)]
         port: u16,
+        /// The local address to bind for the web server (use 0.0.0.0 to accept connections from other machines)
+        #[clap(long, default_value_t = String::from("localhost"))]
+        bind: String,
+        /// Requires all requests to present the given access token (it is embedded in the served extension)
+        #[clap(long)]
+        token: Option<String>,
+        /// An origin that is allowed to make cross-origin requests (can be repeated, defaults to allowing any origin)
+        #[clap(long = "cors-origin", value_name = "ORIGIN")]
+        cors_origins: Vec<String>,
+        /// Path to a PEM certificate chain to serve over https instead of http (requires a tls feature flag)
+        #[clap(long, requires = "tls_key")]
+        tls_cert: Option<String>,
+        /// Path to the PEM private key for the certificate given by --tls-cert
+        #[clap(long, requires = "tls_cert")]
+        tls_key: Option<String>,
+        /// The policy used to decide when to switch between processes
+        #[clap(long, value_enum, default_value_t = SchedulingPolicy::UntilYield)]
+        scheduling: SchedulingPolicy,
+        /// The number of instructions a process may execute in a row before its turn ends (round-robin and priority scheduling only)
+        #[clap(long, default_value_t = DEFAULT_QUANTUM)]
+        quantum: usize,
     },
 }
 
@@ -166,11 +292,138 @@ fn open_project<'a>(content: &str, role: Option<&'a str>) -> Result<(String, ast
     };
     Ok((parsed.name, role))
 }
+fn open_project_all_roles(content: &str) -> Result<(String, Vec<ast::Role>), OpenProjectError<'static>> {
+    let parsed = match ast::Parser::default().parse(content) {
+        Ok(x) => x,
+        Err(error) => return Err(OpenProjectError::ParseError { error }),
+    };
+    if parsed.roles.is_empty() { return Err(OpenProjectError::NoRoles) }
+    Ok((parsed.name, parsed.roles))
+}
+
+/// Gets the prefix used to distinguish the output of different roles when running multiple roles at once.
+fn role_prefix(roles: &[Bundle], role: &Bundle) -> String {
+    if roles.len() > 1 { format!("[{}] ", role.info.role) } else { String::new() }
+}
+
+/// The NetsBlox server which systems created by the cli connect to.
+enum Server {
+    Online(String),
+    Offline { seed: Option<u64> },
+}
+impl Server {
+    fn connect<C: CustomTypes<StdSystem<C>>>(&self, project_name: &str, config: Config<C, StdSystem<C>>, utc_offset: UtcOffset) -> StdSystem<C> {
+        match self {
+            Server::Online(base_url) => StdSystem::new_sync(base_url.clone(), Some(project_name), config, utc_offset),
+            Server::Offline { seed } => StdSystem::new_offline(Some(project_name), config, utc_offset, OfflineOptions { seed: *seed, ..Default::default() }),
+        }
+    }
+}
 
-fn run_proj_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: String, role: &ast::Role, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset) {
+/// Creates a [`Recorder`] that writes each event to the given file as a line of json.
+fn enable_profiler<C: CustomTypes<StdSystem<C>>>(env: &EnvArena<C>) {
+    let start = Instant::now();
+    env.mutate(|mc, env| env.proj.borrow().get_global_context().borrow_mut(mc).profiler = Some(Profiler::new(Box::new(move || start.elapsed().as_nanos() as u64))));
+}
+fn write_profile<C: CustomTypes<StdSystem<C>>>(env: &EnvArena<C>, script_info: &ScriptInfo, path: &str) -> ProfileReport {
+    env.mutate(|_, env| {
+        let global_context = env.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+        let profiler = global_context.profiler.as_ref().unwrap();
+        if let Err(e) = std::fs::write(path, profiler.folded(&global_context.bytecode, &env.locs, script_info, ProfileMetric::Time)) {
+            crash!(3: "failed to write profile: {e}");
+        }
+        profiler.report(&global_context.bytecode, &env.locs, script_info)
+    })
+}
+
+fn create_recorder(path: &str) -> io::Result<Rc<Recorder>> {
+    let mut file = LineWriter::new(File::create(path)?);
+    Ok(Rc::new(Recorder::new(Box::new(move |event| {
+        if let Err(e) = writeln!(file, "{event}") {
+            crash!(3: "failed to write recording: {e}");
+        }
+    }))))
+}
+
+/// Converts a terminal key into a [`KeyCode`] for use in key inputs, if it has an equivalent.
+fn convert_key_code(code: RawKeyCode) -> Option<KeyCode> {
+    match code {
+        RawKeyCode::Char(ch) => Some(KeyCode::Char(ch.to_ascii_lowercase())),
+        RawKeyCode::Up => Some(KeyCode::Up),
+        RawKeyCode::Down => Some(KeyCode::Down),
+        RawKeyCode::Left => Some(KeyCode::Left),
+        RawKeyCode::Right => Some(KeyCode::Right),
+        RawKeyCode::Enter => Some(KeyCode::Enter),
+        _ => None,
+    }
+}
+
+/// Formats the state of a process stopped in the debugger for display in the terminal.
+fn format_break(prefix: &str, summary: &DebugSummary) -> String {
+    let join = |vars: &[VarEntry]| vars.iter().map(|x| format!("{} = {}", x.name, x.value)).collect::<Vec<_>>().join(", ");
+    let mut res = format!(">>> {prefix}break at {} in entity {:?}\r\n", summary.location.as_deref().unwrap_or("<unknown>"), summary.entity);
+    for entry in summary.trace.iter().rev() {
+        res += &format!("    in {}: {}\r\n", entry.location, join(&entry.locals));
+    }
+    res += &format!("    fields: {}\r\n", join(&summary.fields));
+    res += &format!("    globals: {}\r\n", join(&summary.globals));
+    res += &format!("    stack: {}\r\n", summary.value_stack.join(", "));
+    res += ">>> [c]ontinue, [s]tep, [n]ext (step over), [o]ut (step out), ctrl+b to break\r\n";
+    res
+}
+
+/// Optional instrumentation for a project run by the cli.
+struct RunTools<'a> {
+    recorder: Option<Rc<Recorder>>,
+    breakpoints: &'a [String],
+    profile: Option<(&'a str, ScriptInfo<'a>)>,
+    scheduling: Scheduling,
+}
+fn get_scheduling(policy: SchedulingPolicy, quantum: usize) -> Scheduling {
+    match policy {
+        SchedulingPolicy::UntilYield => Scheduling::UntilYield,
+        SchedulingPolicy::RoundRobin => Scheduling::RoundRobin { quantum },
+        SchedulingPolicy::Priority => Scheduling::Priority { quantum },
+        SchedulingPolicy::Frames => Scheduling::Frames,
+    }
+}
+
+/// Counts the steps a run loop performs on a project between handling io (inputs, messages, redrawing),
+/// which is once per turn of a process (or once per frame for [`Scheduling::Frames`]) as determined by the [`Scheduling`] policy.
+/// Every io iteration is capped at [`MAX_STEPS_PER_IO_ITER`] steps so that the cli stays responsive even if a process never yields.
+struct IoIter {
+    scheduling: Scheduling,
+    steps: usize,
+}
+impl IoIter {
+    fn new(scheduling: Scheduling) -> Self {
+        Self { scheduling, steps: 0 }
+    }
+    /// Counts a step of the project and checks if it ended the io iteration.
+    fn step<C: CustomTypes<S>, S: System<C>>(&mut self, res: &ProjectStep<'_, C, S>) -> bool {
+        self.steps += 1;
+        let turn_over = matches!(res, ProjectStep::Idle | ProjectStep::Yield | ProjectStep::ProcessTerminated { .. } | ProjectStep::Error { .. });
+        self.steps >= MAX_STEPS_PER_IO_ITER || match self.scheduling {
+            Scheduling::UntilYield => turn_over,
+            Scheduling::RoundRobin { quantum } | Scheduling::Priority { quantum } => turn_over || self.steps >= quantum,
+            Scheduling::Frames => matches!(res, ProjectStep::Idle | ProjectStep::FrameEnd),
+        }
+    }
+}
+
+fn run_proj_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: &Server, roles: Vec<Bundle>, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset, tools: RunTools) {
+    let RunTools { recorder, breakpoints, profile, scheduling } = tools;
     terminal::enable_raw_mode().unwrap();
     execute!(stdout(), cursor::Hide).unwrap();
-    let _tty_mode_guard = AtExit::new(|| {
+    let key_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if key_releases {
+        execute!(stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)).unwrap();
+    }
+    let _tty_mode_guard = AtExit::new(move || {
+        if key_releases {
+            execute!(stdout(), PopKeyboardEnhancementFlags).unwrap();
+        }
         terminal::disable_raw_mode().unwrap();
         execute!(stdout(), cursor::Show).unwrap()
     });
@@ -186,70 +439,103 @@ fn run_proj_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: String
     let mut term_size = terminal::size().unwrap();
     let mut input_value = String::new();
 
-    let config = overrides.fallback(&Config {
-        command: {
-            let update_flag = update_flag.clone();
-            Some(Rc::new(move |_, _, key, command, entity| match command {
-                Command::Print { style: _, value } => {
-                    if let Some(value) = value {
-                        print!("{entity:?} > {value:?}\r\n");
+    let network = Arc::new(LocalNetwork::new(project_name.into()));
+    let prefixes = roles.iter().map(|role| role_prefix(&roles, role)).collect::<Vec<_>>();
+    let mut envs = Vec::with_capacity(roles.len());
+    for (role, prefix) in iter::zip(roles, &prefixes) {
+        let config = overrides.fallback(&Config {
+            command: {
+                let update_flag = update_flag.clone();
+                let prefix = prefix.clone();
+                Some(Rc::new(move |_, _, key, command, entity| match command {
+                    Command::Print { style: _, value } => {
+                        if let Some(value) = value {
+                            print!("{prefix}{entity:?} > {value:?}\r\n");
+                            update_flag.set(true);
+                        }
+                        key.complete(Ok(()));
+                        CommandStatus::Handled
+                    }
+                    _ => CommandStatus::UseDefault { key, command },
+                }))
+            },
+            request: {
+                let update_flag = update_flag.clone();
+                let input_queries = input_queries.clone();
+                let prefix = prefix.clone();
+                Some(Rc::new(move |_, _, key, request, entity| match request {
+                    Request::Input { prompt } => {
+                        input_queries.borrow_mut().push_back((format!("{prefix}{entity:?} {prompt:?} > "), key));
                         update_flag.set(true);
+                        RequestStatus::Handled
                     }
-                    key.complete(Ok(()));
-                    CommandStatus::Handled
-                }
-                _ => CommandStatus::UseDefault { key, command },
-            }))
-        },
-        request: {
-            let update_flag = update_flag.clone();
-            let input_queries = input_queries.clone();
-            Some(Rc::new(move |_, _, key, request, entity| match request {
-                Request::Input { prompt } => {
-                    input_queries.borrow_mut().push_back((format!("{entity:?} {prompt:?} > "), key));
-                    update_flag.set(true);
-                    RequestStatus::Handled
-                }
-                _ => RequestStatus::UseDefault { key, request },
-            }))
-        },
-    });
+                    _ => RequestStatus::UseDefault { key, request },
+                }))
+            },
+        });
 
-    let system = Rc::new(StdSystem::new_sync(server, Some(project_name), config, utc_offset));
-    let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
-    print!("public id: {}\r\n", system.get_public_id());
+        let mut system = server.connect(project_name, config, utc_offset).with_local_network(network.clone(), &role.info.role);
+        if let Some(recorder) = &recorder { system = system.with_recorder(recorder.clone()); }
+        let system = Rc::new(system);
+        print!("{prefix}public id: {}\r\n", system.get_public_id());
 
-    let env = match get_env(role, system) {
-        Ok(x) => x,
-        Err(e) => {
-            print!("error loading project: {e:?}\r\n");
-            return;
+        // without key release events, the only way to tell that a key was released is that it stopped repeating
+        let key_hold_timeout = if key_releases { None } else { Some(KEY_HOLD_TIMEOUT_MS) };
+        match load_env(role.bytecode, &role.init_info, role.locations, Settings { scheduling, key_hold_timeout, ..Default::default() }, system) {
+            Ok(x) => envs.push(x),
+            Err(e) => {
+                print!("{prefix}error loading project: {e:?}\r\n");
+                return;
+            }
         }
-    };
-    env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, Input::Start));
+        envs.last().unwrap().mutate(|mc, env| {
+            let mut proj = env.proj.borrow_mut(mc);
+            for loc in breakpoints {
+                if !proj.add_breakpoint(&env.locs, loc) { print!("{prefix}no block with collab id {loc:?}\r\n") }
+            }
+        });
+    }
+    if profile.is_some() { enable_profiler(&envs[0]); }
+    let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
+    if let Some(recorder) = &recorder { recorder.record_input(&Input::Start); }
+    for env in envs.iter() {
+        env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, Input::Start));
+    }
 
+    let (mut steps, mut last_mark, mut last_profile_write) = (0, Instant::now(), Instant::now());
     let mut input_sequence = Vec::with_capacity(16);
+    let (mut paused, mut debug_action, mut break_requested) = (None, None, false);
     let in_input_mode = || !input_queries.borrow().is_empty();
     'program: loop {
         debug_assert_eq!(input_sequence.len(), 0);
         while event::poll(Duration::from_secs(0)).unwrap() {
             match event::read().unwrap() {
+                Event::Key(key) if key.kind == KeyEventKind::Release => if let Some(key) = convert_key_code(key.code) {
+                    input_sequence.push(Input::KeyUp { key });
+                }
                 Event::Key(key) => match key.code {
                     RawKeyCode::Char('c') if key.modifiers == RawKeyModifiers::CONTROL => break 'program,
+                    RawKeyCode::Char('b') if key.modifiers == RawKeyModifiers::CONTROL => break_requested = true,
+                    RawKeyCode::Char(ch) if paused.is_some() && !in_input_mode() => debug_action = match ch {
+                        'c' => Some(StepMode::Continue),
+                        's' => Some(StepMode::Step),
+                        'n' => Some(StepMode::StepOver),
+                        'o' => Some(StepMode::StepOut),
+                        _ => debug_action,
+                    },
                     RawKeyCode::Esc => input_sequence.push(Input::Stop),
                     RawKeyCode::Char(ch) => match in_input_mode() {
                         true => { input_value.push(ch); update_flag.set(true); }
-                        false => input_sequence.push(Input::KeyDown { key: KeyCode::Char(ch.to_ascii_lowercase()) }),
+                        false => input_sequence.push(Input::KeyDown { key: convert_key_code(key.code).unwrap() }),
                     }
                     RawKeyCode::Backspace => if in_input_mode() && input_value.pop().is_some() { update_flag.set(true) }
                     RawKeyCode::Enter => if let Some((_, res_key)) = input_queries.borrow_mut().pop_front() {
                         res_key.complete(Ok(C::Intermediate::from_json(Json::String(mem::take(&mut input_value)))));
                         update_flag.set(true);
                     }
-                    RawKeyCode::Up => if !in_input_mode() { input_sequence.push(Input::KeyDown { key: KeyCode::Up }) }
-                    RawKeyCode::Down => if !in_input_mode() { input_sequence.push(Input::KeyDown { key: KeyCode::Down }) }
-                    RawKeyCode::Left => if !in_input_mode() { input_sequence.push(Input::KeyDown { key: KeyCode::Left }) }
-                    RawKeyCode::Right => if !in_input_mode() { input_sequence.push(Input::KeyDown { key: KeyCode::Right }) }
+                    RawKeyCode::Up | RawKeyCode::Down | RawKeyCode::Left | RawKeyCode::Right => if !in_input_mode() {
+                        input_sequence.push(Input::KeyDown { key: convert_key_code(key.code).unwrap() });
+                    }
                     _ => (),
                 }
                 Event::Resize(c, r) => {
@@ -260,17 +546,60 @@ fn run_proj_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: String
             }
         }
 
-        env.mutate(|mc, env| {
-            let mut proj = env.proj.borrow_mut(mc);
-            for input in input_sequence.drain(..) { proj.input(mc, input); }
-            for _ in 0..STEPS_PER_IO_ITER {
-                let res = proj.step(mc);
-                if let ProjectStep::Error { error, proc } = &res {
-                    print!("\r\n>>> runtime error in entity {:?}: {:?}\r\n\r\n", proc.get_call_stack().last().unwrap().entity.borrow().name, error.cause);
+        for (i, (prefix, env)) in iter::zip(&prefixes, envs.iter_mut()).enumerate() {
+            env.mutate(|mc, env| {
+                let mut proj = env.proj.borrow_mut(mc);
+                for input in input_sequence.iter() {
+                    if let Some(recorder) = &recorder { recorder.record_input(input); }
+                    proj.input(mc, input.clone());
                 }
-                idle_sleeper.consume(&res);
+                if break_requested && paused.is_none() { proj.request_break(); }
+                if paused == Some(i) {
+                    match debug_action.take() {
+                        Some(mode) => proj.resume(&env.locs, mode),
+                        None => if proj.get_paused_process().is_some() { return },
+                    }
+                    paused = None;
+                }
+                if paused.is_some() { return }
+
+                let mut io_iter = IoIter::new(scheduling);
+                loop {
+                    let res = proj.step(mc);
+                    match &res {
+                        ProjectStep::Error { error, proc } => print!("\r\n>>> {prefix}runtime error in entity {:?}: {:?}\r\n\r\n", proc.get_call_stack().last().unwrap().entity.borrow().name, error.cause),
+                        ProjectStep::Break => {
+                            print!("\r\n{}\r\n", format_break(prefix, &DebugSummary::extract(proj.get_paused_process().unwrap(), &env.locs)));
+                            update_flag.set(true);
+                            paused = Some(i);
+                            break
+                        }
+                        _ => (),
+                    }
+                    idle_sleeper.consume(&res);
+
+                    steps += 1;
+                    if let Some(recorder) = &recorder { recorder.set_step(steps); }
+                    if io_iter.step(&res) { break }
+                }
+            });
+        }
+        input_sequence.clear();
+        break_requested = false;
+        if paused.is_some() { idle_sleeper.trigger(); }
+
+        if let Some(recorder) = &recorder {
+            if last_mark.elapsed() >= RECORDING_MARK_INTERVAL {
+                recorder.record_mark();
+                last_mark = Instant::now();
             }
-        });
+        }
+        if let Some((path, script_info)) = &profile {
+            if last_profile_write.elapsed() >= PROFILE_WRITE_INTERVAL {
+                write_profile(&envs[0], script_info, path);
+                last_profile_write = Instant::now();
+            }
+        }
 
         if update_flag.get() {
             update_flag.set(false);
@@ -292,9 +621,91 @@ fn run_proj_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: String
         }
     }
 
+    if let Some(recorder) = &recorder { recorder.record_mark(); }
     execute!(stdout(), terminal::Clear(ClearType::CurrentLine)).unwrap();
+    if let Some((path, script_info)) = &profile {
+        let report = write_profile(&envs[0], script_info, path);
+        print!("\r\n{}\r\n", report.to_string().replace('\n', "\r\n"));
+    }
 }
-fn run_proj_non_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: String, role: &ast::Role, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset) {
+fn run_proj_non_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: &Server, roles: Vec<Bundle>, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset, tools: RunTools) {
+    let RunTools { recorder, breakpoints, profile, scheduling } = tools;
+    if !breakpoints.is_empty() { crash!(2: "breakpoints require an interactive terminal") }
+
+    let network = Arc::new(LocalNetwork::new(project_name.into()));
+    let prefixes = roles.iter().map(|role| role_prefix(&roles, role)).collect::<Vec<_>>();
+    let mut envs = Vec::with_capacity(roles.len());
+    for (role, prefix) in iter::zip(roles, &prefixes) {
+        let config = overrides.fallback(&Config {
+            request: None,
+            command: {
+                let prefix = prefix.clone();
+                Some(Rc::new(move |_, _, key, command, entity| match command {
+                    Command::Print { style: _, value } => {
+                        if let Some(value) = value { println!("{prefix}{entity:?} > {value:?}") }
+                        key.complete(Ok(()));
+                        CommandStatus::Handled
+                    }
+                    _ => CommandStatus::UseDefault { key, command },
+                }))
+            },
+        });
+
+        let mut system = server.connect(project_name, config, utc_offset).with_local_network(network.clone(), &role.info.role);
+        if let Some(recorder) = &recorder { system = system.with_recorder(recorder.clone()); }
+        let system = Rc::new(system);
+        println!(">>> {prefix}public id: {}\n", system.get_public_id());
+
+        match load_env(role.bytecode, &role.init_info, role.locations, Settings { scheduling, ..Default::default() }, system) {
+            Ok(x) => envs.push(x),
+            Err(e) => {
+                println!(">>> {prefix}error loading project: {e:?}");
+                return;
+            }
+        }
+    }
+    if profile.is_some() { enable_profiler(&envs[0]); }
+    let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
+    if let Some(recorder) = &recorder { recorder.record_input(&Input::Start); }
+    for env in envs.iter() {
+        env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, Input::Start));
+    }
+
+    let (mut steps, mut last_mark, mut last_profile_write) = (0, Instant::now(), Instant::now());
+    loop {
+        for (prefix, env) in iter::zip(&prefixes, envs.iter_mut()) {
+            env.mutate(|mc, env| {
+                let mut proj = env.proj.borrow_mut(mc);
+                let mut io_iter = IoIter::new(scheduling);
+                loop {
+                    let res = proj.step(mc);
+                    if let ProjectStep::Error { error, proc } = &res {
+                        println!("\n>>> {prefix}runtime error in entity {:?}: {:?}\n", proc.get_call_stack().last().unwrap().entity.borrow().name, error.cause);
+                    }
+                    idle_sleeper.consume(&res);
+
+                    steps += 1;
+                    if let Some(recorder) = &recorder { recorder.set_step(steps); }
+                    if io_iter.step(&res) { break }
+                }
+            });
+        }
+
+        if let Some(recorder) = &recorder {
+            if last_mark.elapsed() >= RECORDING_MARK_INTERVAL {
+                recorder.record_mark();
+                last_mark = Instant::now();
+            }
+        }
+        if let Some((path, script_info)) = &profile {
+            if last_profile_write.elapsed() >= PROFILE_WRITE_INTERVAL {
+                write_profile(&envs[0], script_info, path);
+                last_profile_write = Instant::now();
+            }
+        }
+    }
+}
+fn run_proj_replay<C: CustomTypes<StdSystem<C>>>(role: &ast::Role, replayer: Rc<Replayer>, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset) {
     let config = overrides.fallback(&Config {
         request: None,
         command: Some(Rc::new(move |_, _, key, command, entity| match command {
@@ -307,71 +718,161 @@ fn run_proj_non_tty<C: CustomTypes<StdSystem<C>>>(project_name: &str, server: St
         })),
     });
 
-    let system = Rc::new(StdSystem::new_sync(server, Some(project_name), config, utc_offset));
-    let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
-    println!(">>> public id: {}\n", system.get_public_id());
-
-    let env = match get_env(role, system) {
+    let system = Rc::new(StdSystem::new_replay(replayer.clone(), config, utc_offset));
+    let settings = Settings::default();
+    let env = match get_env(role, settings, system) {
         Ok(x) => x,
         Err(e) => {
             println!(">>> error loading project: {e:?}");
             return;
         }
     };
-    env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, Input::Start));
 
-    loop {
+    let total_steps = replayer.steps();
+    let mut steps = 0;
+    while steps < total_steps {
         env.mutate(|mc, env| {
             let mut proj = env.proj.borrow_mut(mc);
-            for _ in 0..STEPS_PER_IO_ITER {
+            let mut io_iter = IoIter::new(settings.scheduling);
+            while steps < total_steps {
+                for input in replayer.take_inputs(steps) { proj.input(mc, input); }
                 let res = proj.step(mc);
                 if let ProjectStep::Error { error, proc } = &res {
                     println!("\n>>> runtime error in entity {:?}: {:?}\n", proc.get_call_stack().last().unwrap().entity.borrow().name, error.cause);
                 }
-                idle_sleeper.consume(&res);
+                steps += 1;
+                if io_iter.step(&res) { break }
             }
         });
     }
+    println!("\n>>> end of recording ({total_steps} steps)");
+}
+#[cfg_attr(not(any(feature = "native-tls", feature = "native-tls-vendored", feature = "rustls-tls-native-roots", feature = "rustls-tls-webpki-roots")), allow(dead_code))]
+struct TlsFiles {
+    cert: String,
+    key: String,
+}
+struct ServerAccess {
+    bind: String,
+    port: u16,
+    token: Option<String>,
+    cors_origins: Vec<String>,
+    tls: Option<TlsFiles>,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+fn is_authorized(req: &actix_web::dev::ServiceRequest, token: Option<&str>) -> bool {
+    fn token_eq(a: &str, b: &str) -> bool {
+        a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    let token = match token {
+        Some(x) => x,
+        None => return true,
+    };
+    let header = req.headers().get("Authorization").and_then(|x| x.to_str().ok()).and_then(|x| x.strip_prefix("Bearer "));
+    if header.map(|x| token_eq(x, token)).unwrap_or(false) {
+        return true;
+    }
+    match web::Query::<TokenQuery>::from_query(req.query_string()) {
+        Ok(query) => query.token.as_deref().map(|x| token_eq(x, token)).unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+fn url_encode(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => res.push(b as char),
+            _ => res.push_str(&format!("%{b:02X}")),
+        }
+    }
+    res
+}
+
+#[cfg(any(feature = "native-tls", feature = "native-tls-vendored"))]
+fn load_openssl_config(tls: &TlsFiles) -> Result<openssl::ssl::SslAcceptorBuilder, openssl::error::ErrorStack> {
+    use openssl::ssl::{SslAcceptor, SslMethod, SslFiletype};
+
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder.set_private_key_file(&tls.key, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(&tls.cert)?;
+    Ok(builder)
 }
-fn run_server<C: CustomTypes<StdSystem<C>>>(nb_server: String, addr: String, port: u16, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset, syscalls: &[SyscallMenu]) {
-    println!(r#"connect from {nb_server}/?extensions=["http://{addr}:{port}/extension.js"]"#);
+
+#[cfg(all(not(any(feature = "native-tls", feature = "native-tls-vendored")), any(feature = "rustls-tls-native-roots", feature = "rustls-tls-webpki-roots")))]
+fn load_rustls_config(tls: &TlsFiles) -> io::Result<rustls::ServerConfig> {
+    use std::io::BufReader;
+    use rustls_pemfile::Item;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&tls.cert)?))?.into_iter().map(rustls::Certificate).collect();
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(&tls.key)?))?.into_iter().find_map(|item| match item {
+        Item::RSAKey(x) | Item::PKCS8Key(x) | Item::ECKey(x) => Some(rustls::PrivateKey(x)),
+        _ => None,
+    }).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+    rustls::ServerConfig::builder().with_safe_defaults().with_no_client_auth().with_single_cert(certs, key).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn run_server<C: CustomTypes<StdSystem<C>>>(nb_server: String, addr: String, access: ServerAccess, scheduling: Scheduling, overrides: Config<C, StdSystem<C>>, utc_offset: UtcOffset, syscalls: &[SyscallMenu]) {
+    let scheme = if access.tls.is_some() { "https" } else { "http" };
+    let token_query = access.token.as_deref().map(|x| format!("?token={}", url_encode(x))).unwrap_or_default();
+    println!(r#"connect from {nb_server}/?extensions=["{scheme}://{addr}:{}/extension.js{token_query}"]"#, access.port);
+    if access.token.is_none() && access.bind != "localhost" {
+        println!(">>> warning: no access token was set, so anyone who can reach this server can run code on it");
+    }
 
     let extension = ExtensionArgs {
-        server: &format!("http://{addr}:{port}"),
+        server: &format!("{scheme}://{addr}:{}", access.port),
+        token: access.token.as_deref(),
         syscalls,
         omitted_elements: &["thumbnail", "pentrails", "history", "replay"],
         pull_interval: Duration::from_millis(250),
     }.render();
 
     enum ServerCommand {
+        CreateSession(Weak<Session>),
+        DestroySession,
         SetProject(String),
         Input(Input),
+        SetBreakpoints(Vec<String>),
+        Resume(StepMode),
+        Break,
     }
 
-    let (proj_sender, proj_receiver) = channel();
+    let (proj_sender, proj_receiver) = channel::<(String, ServerCommand)>();
 
-    struct State {
-        extension: String,
+    struct Session {
         running: AtomicBool,
         current_proj: Mutex<String>,
-        proj_sender: Mutex<Sender<ServerCommand>>,
         output: Mutex<String>,
         errors: Mutex<Vec<ErrorSummary>>,
+        debug: Mutex<Option<DebugSummary>>,
+        watchers: Mutex<Vec<WatcherSummary>>,
+        last_access: Mutex<Instant>,
+    }
+    struct State {
+        extension: String,
+        proj_sender: Mutex<Sender<(String, ServerCommand)>>,
+        sessions: Mutex<BTreeMap<String, Arc<Session>>>,
+        listable: bool, // session ids may only be listed if every request must present the access token
     }
     let state = web::Data::new(State {
         extension,
-        running: AtomicBool::new(true),
-        current_proj: Mutex::new(EMPTY_PROJECT.into()),
         proj_sender: Mutex::new(proj_sender),
-        output: Mutex::new(String::with_capacity(1024)),
-        errors: Mutex::new(Vec::with_capacity(8)),
+        sessions: Mutex::new(Default::default()),
+        listable: access.token.is_some(),
     });
 
     macro_rules! tee_println {
-        ($state:expr => $($t:tt)*) => {{
+        ($session:expr => $($t:tt)*) => {{
             let content = format!($($t)*);
-            if let Some(state) = $state {
-                let mut output = state.output.lock().unwrap();
+            if let Some(session) = $session {
+                let mut output = session.output.lock().unwrap();
                 output.push_str(&content);
                 output.push('\n');
             }
@@ -379,116 +880,296 @@ fn run_server<C: CustomTypes<StdSystem<C>>>(nb_server: String, addr: String, por
         }}
     }
 
-    let weak_state = Arc::downgrade(&state);
-    let config = overrides.fallback(&Config {
-        request: None,
-        command: Some(Rc::new(move |_, _, key, command, entity| match command {
-            Command::Print { style: _, value } => {
-                if let Some(value) = value { tee_println!(weak_state.upgrade() => "{entity:?} > {value:?}") }
-                key.complete(Ok(()));
-                CommandStatus::Handled
-            }
-            _ => CommandStatus::UseDefault { key, command },
-        })),
-    });
-    let system = Rc::new(StdSystem::new_sync(nb_server, Some("native-server"), config, utc_offset));
+    let make_system = |session: Weak<Session>| {
+        let config = overrides.fallback(&Config {
+            request: None,
+            command: Some(Rc::new(move |_, _, key, command, entity| match command {
+                Command::Print { style: _, value } => {
+                    if let Some(value) = value { tee_println!(session.upgrade() => "{entity:?} > {value:?}") }
+                    key.complete(Ok(()));
+                    CommandStatus::Handled
+                }
+                _ => CommandStatus::UseDefault { key, command },
+            })),
+        });
+        Rc::new(StdSystem::new_sync(nb_server.clone(), Some("native-server"), config, utc_offset))
+    };
     let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
-    println!("public id: {}", system.get_public_id());
 
     #[tokio::main(flavor = "multi_thread", worker_threads = 1)]
-    async fn run_http(state: web::Data<State>, port: u16) {
+    async fn run_http(state: web::Data<State>, access: ServerAccess) {
+        fn get_session(state: &State, id: &str) -> Option<Arc<Session>> {
+            let session = state.sessions.lock().unwrap().get(id).cloned()?;
+            *session.last_access.lock().unwrap() = Instant::now();
+            Some(session)
+        }
+        fn unknown_session(id: &str) -> HttpResponse {
+            HttpResponse::NotFound().content_type("text/plain").body(format!("unknown session: {id:?}"))
+        }
+        fn send_command(state: &State, id: String, command: ServerCommand) {
+            state.proj_sender.lock().unwrap().send((id, command)).unwrap();
+        }
+
         #[get("/extension.js")]
         async fn get_extension(state: web::Data<State>) -> impl Responder {
             HttpResponse::Ok().content_type("text/javascript").body(state.extension.clone())
         }
 
-        #[post("/pull")]
-        async fn pull_status(state: web::Data<State>) -> impl Responder {
-            let running = state.running.load(MemoryOrder::Relaxed);
-            let output = mem::take(&mut *state.output.lock().unwrap());
-            let errors = mem::take(&mut *state.errors.lock().unwrap());
+        #[get("/sessions")]
+        async fn list_sessions(state: web::Data<State>) -> impl Responder {
+            if !state.listable {
+                return HttpResponse::Forbidden().content_type("text/plain").body("listing sessions requires the server to be started with an access token (--token)");
+            }
+            let ids = state.sessions.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+            HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&ids).unwrap())
+        }
 
-            HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&Status { running, output, errors }).unwrap())
+        #[post("/sessions")]
+        async fn create_session(state: web::Data<State>) -> impl Responder {
+            let id = Uuid::new_v4().to_string();
+            let session = Arc::new(Session {
+                running: AtomicBool::new(true),
+                current_proj: Mutex::new(EMPTY_PROJECT.into()),
+                output: Mutex::new(String::with_capacity(1024)),
+                errors: Mutex::new(Vec::with_capacity(8)),
+                debug: Mutex::new(None),
+                watchers: Mutex::new(vec![]),
+                last_access: Mutex::new(Instant::now()),
+            });
+            let weak_session = Arc::downgrade(&session);
+            state.sessions.lock().unwrap().insert(id.clone(), session);
+            send_command(&state, id.clone(), ServerCommand::CreateSession(weak_session));
+            HttpResponse::Ok().content_type("text/plain").body(id)
         }
 
-        #[post("/project")]
-        async fn set_project(state: web::Data<State>, body: web::Bytes) -> impl Responder {
+        #[delete("/sessions/{id}")]
+        async fn destroy_session(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            let id = id.into_inner();
+            if state.sessions.lock().unwrap().remove(&id).is_none() { return unknown_session(&id) }
+            send_command(&state, id, ServerCommand::DestroySession);
+            HttpResponse::Ok().content_type("text/plain").body("destroyed session")
+        }
+
+        #[post("/sessions/{id}/pull")]
+        async fn pull_status(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            let session = match get_session(&state, &id) { Some(x) => x, None => return unknown_session(&id) };
+            let running = session.running.load(MemoryOrder::Relaxed);
+            let output = mem::take(&mut *session.output.lock().unwrap());
+            let errors = mem::take(&mut *session.errors.lock().unwrap());
+            let debug = session.debug.lock().unwrap().clone();
+            let watchers = session.watchers.lock().unwrap().clone();
+
+            HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&Status { running, output, errors, debug, watchers }).unwrap())
+        }
+
+        #[post("/sessions/{id}/project")]
+        async fn set_project(state: web::Data<State>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+            if get_session(&state, &id).is_none() { return unknown_session(&id) }
             match String::from_utf8(body.to_vec()) {
                 Ok(content) => {
-                    state.proj_sender.lock().unwrap().send(ServerCommand::SetProject(content)).unwrap();
+                    send_command(&state, id.into_inner(), ServerCommand::SetProject(content));
                     HttpResponse::Ok().content_type("text/plain").body("loaded project")
                 }
                 Err(_) => HttpResponse::BadRequest().content_type("text/plain").body("project was not valid utf8"),
             }
         }
 
-        #[get("/project")]
-        async fn get_project(state: web::Data<State>) -> impl Responder {
-            let proj = state.current_proj.lock().unwrap().clone();
+        #[get("/sessions/{id}/project")]
+        async fn get_project(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            let session = match get_session(&state, &id) { Some(x) => x, None => return unknown_session(&id) };
+            let proj = session.current_proj.lock().unwrap().clone();
             HttpResponse::Ok().content_type("text/xml").append_header(("Content-Disposition", "attachment; filename=\"project.xml\"")).body(proj)
         }
 
-        #[post("/input")]
-        async fn send_input(state: web::Data<State>, input: web::Bytes) -> impl Responder {
+        #[post("/sessions/{id}/input")]
+        async fn send_input(state: web::Data<State>, id: web::Path<String>, input: web::Bytes) -> impl Responder {
+            if get_session(&state, &id).is_none() { return unknown_session(&id) }
             let input = match String::from_utf8(input.to_vec()) {
-                Ok(input) => match input.as_str() {
-                    "start" => Input::Start,
-                    "stop" => Input::Stop,
+                Ok(input) => match (input.as_str(), input.split_once(' ')) {
+                    ("start", _) => Input::Start,
+                    ("stop", _) => Input::Stop,
+                    (_, Some(("keydown", key))) => match KeyCode::from_name(key) {
+                        Some(key) => Input::KeyDown { key },
+                        None => return HttpResponse::BadRequest().content_type("text/plain").body(format!("unknown key: {key:?}")),
+                    }
+                    (_, Some(("keyup", key))) => match KeyCode::from_name(key) {
+                        Some(key) => Input::KeyUp { key },
+                        None => return HttpResponse::BadRequest().content_type("text/plain").body(format!("unknown key: {key:?}")),
+                    }
                     _ => return HttpResponse::BadRequest().content_type("text/plain").body(format!("unknown input: {input:?}")),
                 }
                 Err(_) => return HttpResponse::BadRequest().content_type("text/plain").body("input was not valid utf8")
             };
-            state.proj_sender.lock().unwrap().send(ServerCommand::Input(input)).unwrap();
+            send_command(&state, id.into_inner(), ServerCommand::Input(input));
             HttpResponse::Ok().content_type("text/plain").body("sent input")
         }
 
-        #[post("/toggle-paused")]
-        async fn toggle_paused(state: web::Data<State>) -> impl Responder {
-            state.running.fetch_xor(true, MemoryOrder::Relaxed);
+        #[post("/sessions/{id}/toggle-paused")]
+        async fn toggle_paused(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            let session = match get_session(&state, &id) { Some(x) => x, None => return unknown_session(&id) };
+            session.running.fetch_xor(true, MemoryOrder::Relaxed);
             HttpResponse::Ok().content_type("text/plain").body("toggled pause state")
         }
 
-        HttpServer::new(move || {
+        #[get("/sessions/{id}/debug")]
+        async fn get_debug(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            let session = match get_session(&state, &id) { Some(x) => x, None => return unknown_session(&id) };
+            let debug = session.debug.lock().unwrap().clone();
+            HttpResponse::Ok().content_type("application/json").body(serde_json::to_string(&debug).unwrap())
+        }
+
+        #[post("/sessions/{id}/debug/breakpoints")]
+        async fn set_breakpoints(state: web::Data<State>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+            if get_session(&state, &id).is_none() { return unknown_session(&id) }
+            match serde_json::from_slice::<Vec<String>>(&body) {
+                Ok(breakpoints) => {
+                    send_command(&state, id.into_inner(), ServerCommand::SetBreakpoints(breakpoints));
+                    HttpResponse::Ok().content_type("text/plain").body("set breakpoints")
+                }
+                Err(_) => HttpResponse::BadRequest().content_type("text/plain").body("breakpoints must be a json list of collab ids"),
+            }
+        }
+
+        #[post("/sessions/{id}/debug/resume")]
+        async fn resume(state: web::Data<State>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+            if get_session(&state, &id).is_none() { return unknown_session(&id) }
+            let mode = match body.as_ref() {
+                b"continue" => StepMode::Continue,
+                b"step" => StepMode::Step,
+                b"step-over" => StepMode::StepOver,
+                b"step-out" => StepMode::StepOut,
+                _ => return HttpResponse::BadRequest().content_type("text/plain").body(format!("unknown step mode: {:?}", String::from_utf8_lossy(&body))),
+            };
+            send_command(&state, id.into_inner(), ServerCommand::Resume(mode));
+            HttpResponse::Ok().content_type("text/plain").body("resumed")
+        }
+
+        #[post("/sessions/{id}/debug/break")]
+        async fn request_break(state: web::Data<State>, id: web::Path<String>) -> impl Responder {
+            if get_session(&state, &id).is_none() { return unknown_session(&id) }
+            send_command(&state, id.into_inner(), ServerCommand::Break);
+            HttpResponse::Ok().content_type("text/plain").body("requested break")
+        }
+
+        let ServerAccess { bind, port, token, cors_origins, tls } = access;
+        let server = HttpServer::new(move || {
+            let token = token.clone();
+            let cors = match cors_origins.is_empty() {
+                true => Cors::permissive(),
+                false => cors_origins.iter().fold(Cors::default().allow_any_method().allow_any_header(), |cors, origin| cors.allowed_origin(origin)),
+            };
             App::new()
-                .wrap(Cors::permissive())
+                .wrap_fn(move |req, srv| match is_authorized(&req, token.as_deref()) {
+                    true => Either::Left(srv.call(req).map_ok(ServiceResponse::map_into_left_body)),
+                    false => Either::Right(future::ready(Ok(req.into_response(HttpResponse::Unauthorized().content_type("text/plain").body("missing or invalid access token")).map_into_right_body()))),
+                })
+                .wrap(cors)
                 .app_data(web::PayloadConfig::new(MAX_REQUEST_SIZE_BYTES))
                 .app_data(state.clone())
                 .service(get_extension)
+                .service(list_sessions)
+                .service(create_session)
+                .service(destroy_session)
                 .service(pull_status)
                 .service(set_project)
                 .service(get_project)
                 .service(send_input)
                 .service(toggle_paused)
+                .service(get_debug)
+                .service(set_breakpoints)
+                .service(resume)
+                .service(request_break)
         })
-        .workers(1)
-        .bind(("localhost", port)).unwrap().run().await.unwrap();
+        .workers(1);
+
+        let server = match &tls {
+            None => server.bind((bind.as_str(), port)),
+            #[cfg(any(feature = "native-tls", feature = "native-tls-vendored"))]
+            Some(tls) => {
+                let builder = load_openssl_config(tls).unwrap_or_else(|e| crash!(1: "failed to load tls certificate: {e}"));
+                server.bind_openssl((bind.as_str(), port), builder)
+            }
+            #[cfg(all(not(any(feature = "native-tls", feature = "native-tls-vendored")), any(feature = "rustls-tls-native-roots", feature = "rustls-tls-webpki-roots")))]
+            Some(tls) => {
+                let config = load_rustls_config(tls).unwrap_or_else(|e| crash!(1: "failed to load tls certificate: {e}"));
+                server.bind_rustls_021((bind.as_str(), port), config)
+            }
+            #[cfg(not(any(feature = "native-tls", feature = "native-tls-vendored", feature = "rustls-tls-native-roots", feature = "rustls-tls-webpki-roots")))]
+            Some(_) => crash!(1: "tls requires building with one of the tls feature flags"),
+        };
+        server.unwrap_or_else(|e| crash!(1: "failed to bind {bind}:{port}: {e}")).run().await.unwrap();
+    }
+    let server_state = state.clone();
+    thread::spawn(move || run_http(server_state, access));
+
+    struct SessionEnv<C: CustomTypes<StdSystem<C>>> {
+        state: Weak<Session>,
+        system: Rc<StdSystem<C>>,
+        env: EnvArena<C>,
+        breakpoints: Vec<String>,
     }
-    let weak_state = Arc::downgrade(&state);
-    thread::spawn(move || run_http(state, port));
+    let mut sessions = BTreeMap::<String, SessionEnv<C>>::new();
+    let mut last_expiry_check = Instant::now();
 
     let (_, empty_role) = open_project(EMPTY_PROJECT, None).unwrap_or_else(|_| crash!(666: "default project failed to load"));
-    let mut env = get_env(&empty_role, system.clone()).unwrap();
+    let apply_breakpoints = |env: &EnvArena<C>, breakpoints: &[String]| env.mutate(|mc, env| {
+        let mut proj = env.proj.borrow_mut(mc);
+        proj.clear_breakpoints();
+        for loc in breakpoints {
+            proj.add_breakpoint(&env.locs, loc);
+        }
+    });
 
     'program: loop {
         'input: loop {
-            match proj_receiver.try_recv() {
-                Ok(command) => match command {
-                    ServerCommand::SetProject(content) => match open_project(&content, None) {
-                        Ok((proj_name, role)) => {
-                            let mut state = weak_state.upgrade().unwrap();
-                            tee_println!(Some(&mut state) => "\n>>> loaded project '{proj_name}'\n");
-                            match get_env(&role, system.clone()) {
-                                Ok(x) => {
-                                    env = x;
+            let (id, command) = match proj_receiver.try_recv() {
+                Ok(x) => x,
+                Err(TryRecvError::Disconnected) => break 'program,
+                Err(TryRecvError::Empty) => break 'input,
+            };
+            let session = match command {
+                ServerCommand::CreateSession(state) => {
+                    let system = make_system(state.clone());
+                    println!("\n>>> created session {id} (public id: {})\n", system.get_public_id());
+                    let env = get_env(&empty_role, Settings { scheduling, ..Default::default() }, system.clone()).unwrap();
+                    sessions.insert(id, SessionEnv { state, system, env, breakpoints: vec![] });
+                    continue;
+                }
+                ServerCommand::DestroySession => {
+                    if sessions.remove(&id).is_some() {
+                        println!("\n>>> destroyed session {id}\n");
+                    }
+                    continue;
+                }
+                _ => match sessions.get_mut(&id) {
+                    Some(x) => x,
+                    None => continue,
+                }
+            };
+            match command {
+                ServerCommand::CreateSession(_) | ServerCommand::DestroySession => unreachable!(),
+                ServerCommand::SetProject(content) => match open_project(&content, None) {
+                    Ok((proj_name, role)) => {
+                        let state = session.state.upgrade();
+                        tee_println!(state.as_deref() => "\n>>> loaded project '{proj_name}'\n");
+                        match get_env(&role, Settings { scheduling, ..Default::default() }, session.system.clone()) {
+                            Ok(x) => {
+                                session.env = x;
+                                apply_breakpoints(&session.env, &session.breakpoints);
+                                if let Some(state) = state {
                                     *state.current_proj.lock().unwrap() = content;
+                                    *state.debug.lock().unwrap() = None;
+                                    state.watchers.lock().unwrap().clear();
                                 }
-                                Err(e) => tee_println!(Some(&mut state) => "\n>>> project load error: {e:?}\n>>> keeping previous project...\n"),
                             }
+                            Err(e) => tee_println!(state.as_deref() => "\n>>> project load error: {e:?}\n>>> keeping previous project...\n"),
                         }
-                        Err(e) => match e {
-                            OpenProjectError::ParseError { error } if error.location.collab_id.is_some() => {
-                                let mut state = weak_state.upgrade().unwrap();
-                                let cause = format!("{:?}", error.kind);
+                    }
+                    Err(e) => match e {
+                        OpenProjectError::ParseError { error } if error.location.collab_id.is_some() => {
+                            let state = session.state.upgrade();
+                            let cause = format!("{:?}", error.kind);
+                            if let Some(state) = &state {
                                 state.errors.lock().unwrap().push(ErrorSummary {
                                     cause: cause.clone(),
                                     entity: error.location.entity.unwrap_or_default(),
@@ -496,50 +1177,118 @@ fn run_server<C: CustomTypes<StdSystem<C>>>(nb_server: String, addr: String, por
                                     fields: vec![],
                                     trace: vec![TraceEntry { location: error.location.collab_id.unwrap(), locals: vec![] }], // unwrap safe because of branch guard condition
                                 });
-                                tee_println!(Some(&mut state) => "\n>>> project load error: {cause:?}\n>>> see red error comments...\n>>> keeping previous project...\n");
                             }
-                            _ => tee_println!(weak_state.upgrade() => "\n>>> project load error: {e:?}\n>>> keeping previous project...\n"),
+                            tee_println!(state.as_deref() => "\n>>> project load error: {cause:?}\n>>> see red error comments...\n>>> keeping previous project...\n");
                         }
+                        _ => tee_println!(session.state.upgrade() => "\n>>> project load error: {e:?}\n>>> keeping previous project...\n"),
                     }
-                    ServerCommand::Input(input) => {
-                        if let Input::Start = &input {
-                            if let Some(state) = weak_state.upgrade() {
-                                state.running.store(true, MemoryOrder::Relaxed);
-                            }
+                }
+                ServerCommand::Input(input) => {
+                    if let Input::Start = &input {
+                        if let Some(state) = session.state.upgrade() {
+                            state.running.store(true, MemoryOrder::Relaxed);
+                        }
+                    }
+                    let stopped = matches!(input, Input::Stop);
+                    session.env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, input));
+                    if stopped {
+                        if let Some(state) = session.state.upgrade() {
+                            *state.debug.lock().unwrap() = None;
                         }
-                        env.mutate(|mc, env| env.proj.borrow_mut(mc).input(mc, input));
                     }
                 }
-                Err(TryRecvError::Disconnected) => break 'program,
-                Err(TryRecvError::Empty) => break 'input,
+                ServerCommand::SetBreakpoints(new_breakpoints) => {
+                    session.breakpoints = new_breakpoints;
+                    apply_breakpoints(&session.env, &session.breakpoints);
+                }
+                ServerCommand::Resume(mode) => {
+                    session.env.mutate(|mc, env| env.proj.borrow_mut(mc).resume(&env.locs, mode));
+                    if let Some(state) = session.state.upgrade() {
+                        *state.debug.lock().unwrap() = None;
+                    }
+                }
+                ServerCommand::Break => session.env.mutate(|mc, env| env.proj.borrow_mut(mc).request_break()),
             }
         }
-        if !weak_state.upgrade().map(|state| state.running.load(MemoryOrder::Relaxed)).unwrap_or(true) {
-            idle_sleeper.trigger();
-            continue;
+
+        if last_expiry_check.elapsed() >= SESSION_EXPIRY_CHECK_INTERVAL {
+            let mut expired = vec![];
+            state.sessions.lock().unwrap().retain(|id, session| {
+                let keep = session.last_access.lock().unwrap().elapsed() < SESSION_IDLE_TIMEOUT;
+                if !keep { expired.push(id.clone()) }
+                keep
+            });
+            for id in expired {
+                if sessions.remove(&id).is_some() {
+                    println!("\n>>> destroyed idle session {id}\n");
+                }
+            }
+            last_expiry_check = Instant::now();
         }
 
-        env.mutate(|mc, env| {
-            let mut proj = env.proj.borrow_mut(mc);
-            for _ in 0..STEPS_PER_IO_ITER {
-                let res = proj.step(mc);
-                match &res {
-                    ProjectStep::Error { error, proc } => if let Some(state) = weak_state.upgrade() {
-                        let summary = ErrorSummary::extract(error, proc, &env.locs);
+        let mut any_running = false;
+        for session in sessions.values_mut() {
+            let state = match session.state.upgrade() {
+                Some(x) => x,
+                None => continue,
+            };
+            if !state.running.load(MemoryOrder::Relaxed) { continue }
+            any_running = true;
+
+            session.env.mutate_root(|mc, env| {
+                let mut proj = env.proj.borrow_mut(mc);
+                let mut io_iter = IoIter::new(scheduling);
+                loop {
+                    let res = proj.step(mc);
+                    match &res {
+                        ProjectStep::Watcher { create, watcher } => {
+                            env.watchers.retain(|x| !(GcWeak::ptr_eq(x.entity, watcher.entity) && x.name == watcher.name));
+                            if *create {
+                                env.watchers.push(Watcher { entity: watcher.entity, name: watcher.name.clone(), value: watcher.value });
+                            }
+                        }
+                        ProjectStep::Error { error, proc } => {
+                            let summary = ErrorSummary::extract(error, proc, &env.locs);
 
-                        tee_println!(Some(&state) => "\n>>> runtime error in entity {:?}: {:?}\n>>> see red error comments...\n", summary.entity, summary.cause);
+                            tee_println!(Some(&state) => "\n>>> runtime error in entity {:?}: {:?}\n>>> see red error comments...\n", summary.entity, summary.cause);
 
-                        state.errors.lock().unwrap().push(summary);
-                    }
-                    ProjectStep::Pause => if let Some(state) = weak_state.upgrade() {
-                        state.running.store(false, MemoryOrder::Relaxed);
-                        break
+                            state.errors.lock().unwrap().push(summary);
+                        }
+                        ProjectStep::Pause => {
+                            state.running.store(false, MemoryOrder::Relaxed);
+                            break
+                        }
+                        ProjectStep::Break => {
+                            let mut debug = state.debug.lock().unwrap();
+                            if debug.is_none() {
+                                let summary = DebugSummary::extract(proj.get_paused_process().unwrap(), &env.locs);
+                                tee_println!(Some(&state) => "\n>>> break at {} in entity {:?}\n", summary.location.as_deref().unwrap_or("<unknown>"), summary.entity);
+                                *debug = Some(summary);
+                            }
+                            drop(debug);
+                            idle_sleeper.consume(&res);
+                            break
+                        }
+                        _ => (),
                     }
-                    _ => (),
+                    idle_sleeper.consume(&res);
+                    if io_iter.step(&res) { break }
                 }
-                idle_sleeper.consume(&res);
-            }
-        });
+
+                let mut summaries = Vec::with_capacity(env.watchers.len());
+                env.watchers.retain(|watcher| match WatcherSummary::extract(watcher, mc) {
+                    Some(x) => {
+                        summaries.push(x);
+                        true
+                    }
+                    None => false,
+                });
+                *state.watchers.lock().unwrap() = summaries;
+            });
+        }
+        if !any_running {
+            idle_sleeper.trigger();
+        }
     }
 }
 
@@ -547,16 +1296,57 @@ fn run_server<C: CustomTypes<StdSystem<C>>>(nb_server: String, addr: String, por
 pub fn run<C: CustomTypes<StdSystem<C>>>(mode: Mode, config: Config<C, StdSystem<C>>, syscalls: &[SyscallMenu]) {
     let utc_offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
     match mode {
-        Mode::Run { src, role, server } => {
+        Mode::Run { src, role, all_roles, record, offline, seed, breakpoints, profile, scheduling, quantum, server } => {
+            let content = read_file(&src).unwrap_or_else(|_| crash!(1: "failed to read file '{src}'"));
+            let (project_name, roles) = match all_roles {
+                false => open_project(&content, role.as_deref()).map(|(name, role)| (name, vec![role])),
+                true => open_project_all_roles(&content),
+            }.unwrap_or_else(|e| crash!(2: "{e}"));
+            let bundles = roles.iter().map(|role| Bundle::compile(&project_name, role, Default::default())).collect::<Result<Vec<_>, _>>().unwrap_or_else(|e| crash!(2: "error loading project: {e:?}"));
+            let recorder = record.map(|path| create_recorder(&path).unwrap_or_else(|_| crash!(1: "failed to create file '{path}'")));
+            let server = match offline {
+                false => Server::Online(server),
+                true => Server::Offline { seed },
+            };
+
+            let profile = profile.as_deref().map(|path| (path, ByteCode::compile(&roles[0]).unwrap().3));
+            let tools = RunTools { recorder, breakpoints: &breakpoints, profile, scheduling: get_scheduling(scheduling, quantum) };
+            if stdout().is_tty() {
+                run_proj_tty(&project_name, &server, bundles, config, utc_offset, tools);
+            } else {
+                run_proj_non_tty(&project_name, &server, bundles, config, utc_offset, tools);
+            }
+        }
+        Mode::Compile { src, output, role } => {
             let content = read_file(&src).unwrap_or_else(|_| crash!(1: "failed to read file '{src}'"));
             let (project_name, role) = open_project(&content, role.as_deref()).unwrap_or_else(|e| crash!(2: "{e}"));
+            let bundle = Bundle::compile(&project_name, &role, Default::default()).unwrap_or_else(|e| crash!(2: "error compiling project: {e:?}"));
+            std::fs::write(&output, bundle.save()).unwrap_or_else(|_| crash!(1: "failed to create file '{output}'"));
+        }
+        Mode::RunBundle { src, offline, seed, breakpoints, scheduling, quantum, server } => {
+            let content = std::fs::read(&src).unwrap_or_else(|_| crash!(1: "failed to read file '{src}'"));
+            let bundle = Bundle::load(&content).unwrap_or_else(|e| crash!(2: "failed to load bundle: {e}"));
+            let project_name = bundle.info.project.clone();
+            let server = match offline {
+                false => Server::Online(server),
+                true => Server::Offline { seed },
+            };
 
+            let tools = RunTools { recorder: None, breakpoints: &breakpoints, profile: None, scheduling: get_scheduling(scheduling, quantum) };
             if stdout().is_tty() {
-                run_proj_tty(&project_name, server, &role, config, utc_offset);
+                run_proj_tty(&project_name, &server, vec![bundle], config, utc_offset, tools);
             } else {
-                run_proj_non_tty(&project_name, server, &role, config, utc_offset);
+                run_proj_non_tty(&project_name, &server, vec![bundle], config, utc_offset, tools);
             }
         }
+        Mode::Replay { src, recording, role } => {
+            let content = read_file(&src).unwrap_or_else(|_| crash!(1: "failed to read file '{src}'"));
+            let (_, role) = open_project(&content, role.as_deref()).unwrap_or_else(|e| crash!(2: "{e}"));
+            let recording = read_file(&recording).unwrap_or_else(|_| crash!(1: "failed to read file '{recording}'"));
+            let recording = Recording::parse(&recording).unwrap_or_else(|e| crash!(2: "failed to load recording: {e:?}"));
+
+            run_proj_replay(&role, Rc::new(Replayer::new(recording)), config, utc_offset);
+        }
         Mode::Dump { src, role } => {
             let content = read_file(&src).unwrap_or_else(|_| crash!(1: "failed to read file '{src}'"));
             let (_, role) = open_project(&content, role.as_deref()).unwrap_or_else(|e| crash!(2: "{e}"));
@@ -568,8 +1358,10 @@ pub fn run<C: CustomTypes<StdSystem<C>>>(mode: Mode, config: Config<C, StdSystem
             bytecode.dump_data(&mut std::io::stdout().lock()).unwrap();
             println!("\ntotal size: {}", bytecode.total_size());
         }
-        Mode::Start { server, addr, port } => {
-            run_server(server, addr, port, config, utc_offset, syscalls);
+        Mode::Start { server, addr, port, bind, token, cors_origins, tls_cert, tls_key, scheduling, quantum } => {
+            let tls = tls_cert.zip(tls_key).map(|(cert, key)| TlsFiles { cert, key });
+            let access = ServerAccess { bind, port, token, cors_origins, tls };
+            run_server(server, addr, access, get_scheduling(scheduling, quantum), config, utc_offset, syscalls);
         }
     }
 }
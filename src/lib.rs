@@ -36,6 +36,9 @@ pub mod runtime;
 pub mod process;
 pub mod project;
 pub mod template;
+pub mod replay;
+pub mod snapshot;
+pub mod profiler;
 mod util;
 
 mod meta {
@@ -43,6 +46,7 @@ mod meta {
 }
 
 #[cfg(feature = "std")] pub mod std_system;
+#[cfg(feature = "serde")] pub mod bundle;
 #[cfg(feature = "cli")] pub mod cli;
 
 #[cfg(test)] mod test;
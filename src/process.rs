@@ -25,6 +25,7 @@ use crate::gc::*;
 use crate::json::*;
 use crate::runtime::*;
 use crate::bytecode::*;
+use crate::snapshot::*;
 use crate::util::*;
 
 fn empty_string() -> Rc<String> {
@@ -71,29 +72,80 @@ impl ErrorSummary {
         let entity = raw_entity.borrow().name.as_str().to_owned();
         let cause = format!("{:?}", error.cause);
 
-        fn summarize_symbols<C: CustomTypes<S>, S: System<C>>(symbols: &SymbolTable<'_, C, S>) -> Vec<VarEntry> {
-            let mut res = Vec::with_capacity(symbols.len());
-            for (k, v) in symbols {
-                res.push(VarEntry { name: k.clone(), value: format!("{:?}", &*v.get()) });
-            }
-            res
-        }
         let globals = summarize_symbols(&process.get_global_context().borrow().globals);
         let fields = summarize_symbols(&raw_entity.borrow().fields);
-
-        let call_stack = process.get_call_stack();
-        let mut trace = Vec::with_capacity(call_stack.len());
-        for (pos, locals) in iter::zip(call_stack[1..].iter().map(|x| x.called_from).chain(iter::once(error.pos)), call_stack.iter().map(|x| &x.locals)) {
-            if let Some(loc) = locations.lookup(pos) {
-                trace.push(TraceEntry { location: loc.clone(), locals: summarize_symbols(locals) });
-            }
-        }
-        debug_assert_eq!(trace.len(), call_stack.len());
+        let trace = summarize_trace(process, error.pos, locations);
+        debug_assert_eq!(trace.len(), process.get_call_stack().len());
 
         Self { entity, cause, globals, fields, trace }
     }
 }
 
+/// The state of a process stopped by the debugger in the structure expected by the standard js extension (see [`Project::get_paused_process`](crate::project::Project::get_paused_process)).
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct DebugSummary {
+    pub location: Option<String>,
+    pub entity: String,
+    pub globals: Vec<VarEntry>,
+    pub fields: Vec<VarEntry>,
+    pub trace: Vec<TraceEntry>,
+    pub value_stack: Vec<String>,
+}
+impl DebugSummary {
+    pub fn extract<C: CustomTypes<S>, S: System<C>>(process: &Process<C, S>, locations: &Locations) -> Self {
+        let raw_entity = process.call_stack.last().unwrap().entity;
+        let entity = raw_entity.borrow().name.as_str().to_owned();
+
+        let globals = summarize_symbols(&process.get_global_context().borrow().globals);
+        let fields = summarize_symbols(&raw_entity.borrow().fields);
+        let trace = summarize_trace(process, process.pos, locations);
+        let value_stack = process.value_stack.iter().map(|x| format!("{x:?}")).collect();
+
+        Self { location: locations.lookup(process.pos), entity, globals, fields, trace, value_stack }
+    }
+}
+
+/// The current state of a variable watcher in the structure expected by the standard js extension (see [`Watcher`]).
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct WatcherSummary {
+    pub entity: String,
+    pub name: String,
+    pub value: String,
+}
+impl WatcherSummary {
+    /// Samples the current value of a watcher, or returns [`None`] if its entity or variable no longer exists.
+    pub fn extract<'gc, C: CustomTypes<S>, S: System<C>>(watcher: &Watcher<'gc, C, S>, mc: &Mutation<'gc>) -> Option<Self> {
+        let entity = watcher.entity.upgrade(mc)?.borrow().name.as_str().to_owned();
+        let value = watcher.value.upgrade(mc)?;
+        let value = value.borrow();
+        let value = match Value::to_string(&value) {
+            Ok(x) => x.into_owned(),
+            Err(_) => format!("{:?}", &*value),
+        };
+        Some(Self { entity, name: watcher.name.clone(), value })
+    }
+}
+
+fn summarize_symbols<C: CustomTypes<S>, S: System<C>>(symbols: &SymbolTable<'_, C, S>) -> Vec<VarEntry> {
+    let mut res = Vec::with_capacity(symbols.len());
+    for (k, v) in symbols {
+        res.push(VarEntry { name: k.clone(), value: format!("{:?}", &*v.get()) });
+    }
+    res
+}
+fn summarize_trace<C: CustomTypes<S>, S: System<C>>(process: &Process<C, S>, pos: usize, locations: &Locations) -> Vec<TraceEntry> {
+    let call_stack = process.get_call_stack();
+    let mut trace = Vec::with_capacity(call_stack.len());
+    for (pos, locals) in iter::zip(call_stack[1..].iter().map(|x| x.called_from).chain(iter::once(pos)), call_stack.iter().map(|x| &x.locals)) {
+        if let Some(loc) = locations.lookup(pos) {
+            trace.push(TraceEntry { location: loc.clone(), locals: summarize_symbols(locals) });
+        }
+    }
+    trace
+}
+
 /// An execution error from a [`Process`] (see [`Process::step`]).
 ///
 /// This consists of an [`ErrorCause`] value describing the cause, as well as the bytecode location of the error.
@@ -135,9 +187,20 @@ pub enum ProcessStep<'gc, C: CustomTypes<S>, S: System<C>> {
     /// The clone has already been created, so this is just an informational flag for any logging or other initialization logic.
     /// Projects use this event to bind new scripts to the clone, which is an aspect of projects but not processes or entities.
     CreatedClone { new_entity: Gc<'gc, RefLock<Entity<'gc, C, S>>> },
+    /// The process has deleted a clone, which has already been marked as dead.
+    /// Projects use this event to remove the clone's scripts and stop all processes associated with it.
+    /// If the current process belongs to the deleted clone, it should also be terminated.
+    DeletedClone { entity: Gc<'gc, RefLock<Entity<'gc, C, S>>> },
     /// The process has requested to pause execution of the (entire) project.
     /// This can be useful for student debugging (similar to breakpoints), but can be ignored by the executor if desired.
     Pause,
+    /// The process has requested to stop all processes, including itself.
+    /// The process is already terminated, so executors should treat this as [`ProcessStep::Terminate`] (with no return value)
+    /// in addition to stopping all other processes.
+    StopAll,
+    /// The process has requested to stop all other processes, but not itself.
+    /// If `entity` is [`Some`], only processes associated with that entity (or any of its clones) should be stopped.
+    StopOthers { entity: Option<Gc<'gc, RefLock<Entity<'gc, C, S>>>> },
 }
 
 /// An entry in the call stack of a [`Process`].
@@ -170,10 +233,35 @@ enum Defer<C: CustomTypes<S>, S: System<C>> {
     MessageReply { key: S::ExternReplyKey, aft_pos: usize },
     Barrier { condition: BarrierCondition, aft_pos: usize },
     Sleep { until: u64, aft_pos: usize },
+    Interrupted { op: InterruptedOp, aft_pos: usize }, // an async operation that was in progress when restored from a snapshot
 }
+#[derive(Clone, Copy)]
 enum RequestAction {
     Rpc, Syscall, Input, Push,
 }
+enum InterruptedOp {
+    Request(RequestAction), Command, MessageReply,
+}
+impl From<RequestAction> for RequestKind {
+    fn from(action: RequestAction) -> Self {
+        match action {
+            RequestAction::Rpc => RequestKind::Rpc,
+            RequestAction::Syscall => RequestKind::Syscall,
+            RequestAction::Input => RequestKind::Input,
+            RequestAction::Push => RequestKind::Push,
+        }
+    }
+}
+impl From<RequestKind> for RequestAction {
+    fn from(kind: RequestKind) -> Self {
+        match kind {
+            RequestKind::Rpc => RequestAction::Rpc,
+            RequestKind::Syscall => RequestAction::Syscall,
+            RequestKind::Input => RequestAction::Input,
+            RequestKind::Push => RequestAction::Push,
+        }
+    }
+}
 
 /// A collection of context info for starting a new [`Process`].
 #[derive(Collect, Educe)]
@@ -200,6 +288,8 @@ pub struct Process<'gc, C: CustomTypes<S>, S: System<C>> {
     #[collect(require_static)] barrier: Option<Barrier>,
     #[collect(require_static)] reply_key: Option<S::InternReplyKey>,
     #[collect(require_static)] warp_counter: usize,
+    #[collect(require_static)] warp_start: Option<u64>,
+    #[collect(require_static)] steps: u64,
                                call_stack: Vec<CallStackEntry<'gc, C, S>>,
                                value_stack: Vec<Value<'gc, C, S>>,
     #[collect(require_static)] handler_stack: Vec<Handler>,
@@ -221,6 +311,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             reply_key: None,
             pos: 0,
             warp_counter: 0,
+            warp_start: None,
+            steps: 0,
             call_stack: vec![CallStackEntry {
                 called_from: usize::MAX,
                 return_to: usize::MAX,
@@ -255,6 +347,16 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
     pub fn get_call_stack(&self) -> &[CallStackEntry<'gc, C, S>] {
         &self.call_stack
     }
+    /// Gets the bytecode position of the next instruction to execute.
+    /// This can be converted into a block location with [`Locations::lookup`].
+    pub fn get_pos(&self) -> usize {
+        self.pos
+    }
+    /// Gets a reference to the current value stack, which holds temporary values such as evaluated block inputs.
+    /// The most recently pushed value is at the end.
+    pub fn get_value_stack(&self) -> &[Value<'gc, C, S>] {
+        &self.value_stack
+    }
     /// Prepares the process to execute starting at the main entry point (see [`Process::new`]) with the provided input local variables.
     /// A [`Barrier`] may also be set, which will be destroyed upon termination, either due to completion or an error.
     /// 
@@ -265,6 +367,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
         self.barrier = context.barrier;
         self.reply_key = context.reply_key;
         self.warp_counter = 0;
+        self.warp_start = None;
+        self.steps = 0;
         self.call_stack.drain(1..);
         self.call_stack[0].locals = context.locals;
         self.value_stack.clear();
@@ -277,15 +381,133 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
 
         debug_assert_eq!(self.call_stack.len(), 1);
     }
+    /// Creates a snapshot of the process, adding any referenced values to `saver`.
+    pub(crate) fn save(&self, saver: &mut SnapshotSaver<'gc, C, S>) -> Result<ProcessSnapshot, SnapshotError> where C::EntityState: Clone {
+        let mut save_opt = |x: &Option<Value<'gc, C, S>>| x.as_ref().map(|x| saver.save_value(x)).transpose();
+        let (last_syscall_error, last_rpc_error, last_answer, last_message) = (save_opt(&self.last_syscall_error)?, save_opt(&self.last_rpc_error)?, save_opt(&self.last_answer)?, save_opt(&self.last_message)?);
+
+        let call_stack = self.call_stack.iter().map(|x| Ok(CallStackEntrySnapshot {
+            called_from: x.called_from,
+            return_to: x.return_to,
+            entity: saver.save_entity(x.entity)?,
+            locals: saver.save_symbols(&x.locals)?,
+            warp_counter: x.warp_counter,
+            value_stack_size: x.value_stack_size,
+            handler_stack_size: x.handler_stack_size,
+        })).collect::<Result<_,_>>()?;
+        let value_stack = self.value_stack.iter().map(|x| saver.save_value(x)).collect::<Result<_,_>>()?;
+        let handler_stack = self.handler_stack.iter().map(|x| HandlerSnapshot {
+            pos: x.pos,
+            var: x.var.clone(),
+            warp_counter: x.warp_counter,
+            call_stack_size: x.call_stack_size,
+            value_stack_size: x.value_stack_size,
+        }).collect();
+        let defer = self.defer.as_ref().map(|x| match x {
+            Defer::Request { aft_pos, action, .. } | Defer::Interrupted { op: InterruptedOp::Request(action), aft_pos } => DeferSnapshot::Request { kind: (*action).into(), aft_pos: *aft_pos },
+            Defer::Command { aft_pos, .. } | Defer::Interrupted { op: InterruptedOp::Command, aft_pos } => DeferSnapshot::Command { aft_pos: *aft_pos },
+            Defer::MessageReply { aft_pos, .. } | Defer::Interrupted { op: InterruptedOp::MessageReply, aft_pos } => DeferSnapshot::MessageReply { aft_pos: *aft_pos },
+            Defer::Barrier { condition, aft_pos } => DeferSnapshot::Barrier { condition: saver.save_condition(condition), aft_pos: *aft_pos },
+            Defer::Sleep { until, aft_pos } => DeferSnapshot::Sleep { until: *until, aft_pos: *aft_pos },
+        });
+
+        Ok(ProcessSnapshot {
+            start_pos: self.start_pos,
+            pos: self.pos,
+            running: self.running,
+            barrier: self.barrier.as_ref().map(|x| saver.save_barrier(x)),
+            warp_counter: self.warp_counter,
+            call_stack, value_stack, handler_stack, defer,
+            last_syscall_error, last_rpc_error, last_answer, last_message,
+        })
+    }
+    /// Restores a process from a snapshot created by [`Process::save`].
+    pub(crate) fn load(global_context: Gc<'gc, RefLock<GlobalContext<'gc, C, S>>>, snapshot: &ProcessSnapshot, loader: &SnapshotLoader<'gc, C, S>) -> Result<Self, SnapshotError> where C::EntityState: Clone {
+        let load_opt = |x: &Option<ValueSnapshot>| x.as_ref().map(|x| loader.load_value(x)).transpose();
+
+        let call_stack = snapshot.call_stack.iter().map(|x| Ok(CallStackEntry {
+            called_from: x.called_from,
+            return_to: x.return_to,
+            entity: loader.load_entity(x.entity)?,
+            locals: loader.load_symbols(&x.locals)?,
+            warp_counter: x.warp_counter,
+            value_stack_size: x.value_stack_size,
+            handler_stack_size: x.handler_stack_size,
+        })).collect::<Result<Vec<_>,_>>()?;
+        if call_stack.is_empty() { return Err(SnapshotError::EmptyCallStack) }
+
+        let handler_stack = snapshot.handler_stack.iter().map(|x| Handler {
+            pos: x.pos,
+            var: x.var.clone(),
+            warp_counter: x.warp_counter,
+            call_stack_size: x.call_stack_size,
+            value_stack_size: x.value_stack_size,
+        }).collect();
+        let defer = snapshot.defer.as_ref().map(|x| Ok(match x {
+            DeferSnapshot::Request { kind, aft_pos } => Defer::Interrupted { op: InterruptedOp::Request((*kind).into()), aft_pos: *aft_pos },
+            DeferSnapshot::Command { aft_pos } => Defer::Interrupted { op: InterruptedOp::Command, aft_pos: *aft_pos },
+            DeferSnapshot::MessageReply { aft_pos } => Defer::Interrupted { op: InterruptedOp::MessageReply, aft_pos: *aft_pos },
+            DeferSnapshot::Barrier { condition, aft_pos } => Defer::Barrier { condition: loader.load_condition(*condition)?, aft_pos: *aft_pos },
+            DeferSnapshot::Sleep { until, aft_pos } => Defer::Sleep { until: *until, aft_pos: *aft_pos },
+        })).transpose()?;
+
+        Ok(Self {
+            global_context, call_stack, handler_stack, defer,
+            start_pos: snapshot.start_pos,
+            pos: snapshot.pos,
+            running: snapshot.running,
+            barrier: snapshot.barrier.map(|x| loader.load_barrier(x)).transpose()?,
+            reply_key: None,
+            warp_counter: snapshot.warp_counter,
+            warp_start: None,
+            steps: 0,
+            value_stack: snapshot.value_stack.iter().map(|x| loader.load_value(x)).collect::<Result<_,_>>()?,
+            last_syscall_error: load_opt(&snapshot.last_syscall_error)?,
+            last_rpc_error: load_opt(&snapshot.last_rpc_error)?,
+            last_answer: load_opt(&snapshot.last_answer)?,
+            last_message: load_opt(&snapshot.last_message)?,
+        })
+    }
     /// Executes a single bytecode instruction.
     /// The return value can be used to determine what additional effects the script has requested,
     /// as well as to retrieve the return value or execution error in the event that the process terminates.
     /// 
     /// The process transitions to the idle state (see [`Process::is_running`]) upon failing with [`Err`] or succeeding with [`ProcessStep::Terminate`].
+    /// Errors from exceeding the resource limits in [`Settings`] cannot be caught by error handlers in the program, so they always terminate the process.
     pub fn step(&mut self, mc: &Mutation<'gc>) -> Result<ProcessStep<'gc, C, S>, ExecError<C, S>> {
-        let mut res = self.step_impl(mc);
+        let profiling = self.global_context.borrow().profiler.is_some();
+        if profiling {
+            if let Some(profiler) = self.global_context.borrow_mut(mc).profiler.as_mut() {
+                profiler.begin(iter::once(self.start_pos).chain(self.call_stack[1..].iter().map(|x| x.called_from)).chain(iter::once(self.pos)));
+            }
+        }
+        let (max_process_steps, max_project_steps) = {
+            let settings = &self.global_context.borrow().settings;
+            (settings.max_process_steps, settings.max_project_steps)
+        };
+        let project_steps = match max_project_steps {
+            Some(_) => {
+                let mut global_context = self.global_context.borrow_mut(mc);
+                global_context.steps += 1;
+                global_context.steps
+            }
+            None => 0,
+        };
+        self.steps += 1;
+
+        let mut res = match (max_process_steps, max_project_steps) {
+            (Some(limit), _) if self.steps > limit => Err(ErrorCause::ProcessStepLimit { limit }),
+            (_, Some(limit)) if project_steps > limit => Err(ErrorCause::ProjectStepLimit { limit }),
+            _ => self.step_impl(mc),
+        };
+        if profiling {
+            if let Some(profiler) = self.global_context.borrow_mut(mc).profiler.as_mut() {
+                profiler.end();
+            }
+        }
         if let Err(err) = &res {
-            if let Some(Handler { pos, var, warp_counter, call_stack_size, value_stack_size }) = self.handler_stack.last() {
+            let catchable = !matches!(err, ErrorCause::ProcessStepLimit { .. } | ErrorCause::ProjectStepLimit { .. } | ErrorCause::WarpTimeLimit { .. } | ErrorCause::ListSizeLimit { .. });
+            if let Some(Handler { pos, var, warp_counter, call_stack_size, value_stack_size }) = self.handler_stack.last().filter(|_| catchable) {
                 self.warp_counter = *warp_counter;
                 self.call_stack.drain(*call_stack_size..);
                 self.value_stack.drain(*value_stack_size..);
@@ -302,13 +524,40 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             }
         }
 
-        if let Ok(ProcessStep::Terminate { .. }) | Err(_) = &res {
+        if let Ok(ProcessStep::Terminate { .. } | ProcessStep::StopAll) | Err(_) = &res {
             self.running = false;
             self.barrier = None;
             self.reply_key = None;
         }
         res.map_err(|cause| ExecError { cause, pos: self.pos })
     }
+    /// Returns from the current call frame with the given value.
+    /// If this is the last call frame, the process terminates and the resulting step is returned.
+    fn return_from_call(&mut self, return_value: Value<'gc, C, S>) -> Option<ProcessStep<'gc, C, S>> {
+        let CallStackEntry { called_from, return_to, warp_counter, value_stack_size, handler_stack_size, .. } = self.call_stack.last().unwrap();
+
+        self.pos = *return_to;
+        self.warp_counter = *warp_counter;
+        self.value_stack.drain(value_stack_size..);
+        self.handler_stack.drain(handler_stack_size..);
+        debug_assert_eq!(self.value_stack.len(), *value_stack_size);
+        debug_assert_eq!(self.handler_stack.len(), *handler_stack_size);
+
+        self.value_stack.push(return_value);
+
+        if self.call_stack.len() > 1 {
+            self.call_stack.pop();
+            None
+        } else {
+            debug_assert_eq!(self.value_stack.len(), 1);
+            debug_assert_eq!(*called_from, usize::MAX);
+            debug_assert_eq!(*return_to, usize::MAX);
+            debug_assert_eq!(*warp_counter, 0);
+            debug_assert_eq!(*value_stack_size, 0);
+            debug_assert_eq!(*handler_stack_size, 0);
+            Some(ProcessStep::Terminate { result: Some(self.value_stack.pop().unwrap()) })
+        }
+    }
     fn step_impl(&mut self, mc: &Mutation<'gc>) -> Result<ProcessStep<'gc, C, S>, ErrorCause<C, S>> {
         let mut global_context = self.global_context.borrow_mut(mc);
         let global_context = &mut *global_context;
@@ -416,6 +665,20 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 }
                 false => return Ok(ProcessStep::Yield),
             }
+            Some(Defer::Interrupted { op, aft_pos }) => {
+                match op {
+                    InterruptedOp::Request(action) => {
+                        let x: Result<Value<'gc, C, S>, String> = Err("request was interrupted".into());
+                        process_request!(x, action, *aft_pos);
+                    }
+                    InterruptedOp::Command => self.pos = *aft_pos,
+                    InterruptedOp::MessageReply => {
+                        self.value_stack.push(empty_string().into());
+                        self.pos = *aft_pos;
+                    }
+                }
+                self.defer = None;
+            }
         }
 
         let mut entity = context_entity.borrow_mut(mc);
@@ -456,6 +719,34 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             }}
         }
 
+        macro_rules! pop_entity {
+            () => {
+                match self.value_stack.pop().unwrap() {
+                    Value::Entity(x) => x,
+                    Value::String(x) => match global_context.entities.get(x.as_str()) {
+                        Some(x) => *x,
+                        None => return Err(ErrorCause::UndefinedEntity { name: x.as_str().into() }),
+                    }
+                    x => return Err(ErrorCause::ConversionError { got: x.get_type(), expected: Type::Entity }),
+                }
+            }
+        }
+        fn costume_index<C: CustomTypes<S>, S: System<C>>(entity: &Entity<'_, C, S>) -> Option<usize> {
+            entity.costume.as_ref().and_then(|x| entity.costume_list.iter().position(|c| Rc::ptr_eq(x, &c.1)))
+        }
+        fn check_list_growth<C: CustomTypes<S>, S: System<C>>(settings: &Settings, new_len: usize) -> Result<(), ErrorCause<C, S>> {
+            match settings.max_list_size {
+                Some(limit) if new_len > limit => Err(ErrorCause::ListSizeLimit { limit }),
+                _ => Ok(()),
+            }
+        }
+        fn check_list_size<C: CustomTypes<S>, S: System<C>>(settings: &Settings, value: &Value<'_, C, S>) -> Result<(), ErrorCause<C, S>> {
+            match value {
+                Value::List(x) => check_list_growth(settings, x.borrow().len()),
+                _ => Ok(()),
+            }
+        }
+
         fn prep_call_closure<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, value_stack: &mut Vec<Value<'gc, C, S>>, args: usize) -> Result<(usize, SymbolTable<'gc, C, S>), ErrorCause<C, S>> {
             let mut values = value_stack.drain(value_stack.len() - (args + 1)..);
             let closure = values.next().unwrap().as_closure()?;
@@ -480,8 +771,17 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             Instruction::Yield => {
                 self.pos = aft_pos;
                 if self.warp_counter == 0 { return Ok(ProcessStep::Yield) }
+
+                if let Some(limit) = global_context.settings.max_warp_time {
+                    if let Ok(now) = global_context.system.time().to_arbitrary_ms::<C, S>() {
+                        if now.saturating_sub(*self.warp_start.get_or_insert(now)) > limit {
+                            return Err(ErrorCause::WarpTimeLimit { limit });
+                        }
+                    }
+                }
             }
             Instruction::WarpStart => {
+                if self.warp_counter == 0 { self.warp_start = None }
                 self.warp_counter += 1;
                 self.pos = aft_pos;
             }
@@ -560,6 +860,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
 
             Instruction::ListCons => {
                 let mut res = self.value_stack.pop().unwrap().as_list()?.borrow().clone();
+                check_list_growth(&global_context.settings, res.len() + 1)?;
                 res.push_front(self.value_stack.pop().unwrap());
                 self.value_stack.push(Gc::new(mc, RefLock::new(res)).into());
                 self.pos = aft_pos;
@@ -598,7 +899,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             }
             Instruction::ListDims => {
                 let list = self.value_stack.pop().unwrap();
-                self.value_stack.push(Gc::new(mc, RefLock::new(ops::dimensions(&list)?.into_iter().map(|x| Ok(Number::new(x as f64)?.into())).collect::<Result<VecDeque<_>, NumberError>>()?)).into());
+                self.value_stack.push(Gc::new(mc, RefLock::new(ops::dimensions(&list)?.into_iter().map(|x| Ok(Number::new(x as f64)?.into())).collect::<Result<List<_, _>, NumberError>>()?)).into());
                 self.pos = aft_pos;
             }
             Instruction::ListRank => {
@@ -609,12 +910,23 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
 
             Instruction::ListRev => {
                 let list = self.value_stack.pop().unwrap().as_list()?;
-                self.value_stack.push(Gc::new(mc, RefLock::new(list.borrow().iter().rev().cloned().collect::<VecDeque<_>>())).into());
+                self.value_stack.push(Gc::new(mc, RefLock::new(list.borrow().iter().rev().cloned().collect::<List<_, _>>())).into());
                 self.pos = aft_pos;
             }
             Instruction::ListFlatten => {
                 let list = self.value_stack.pop().unwrap();
-                self.value_stack.push(Gc::new(mc, RefLock::new(ops::flatten(&list)?)).into());
+                let res = ops::flatten(&list)?;
+                check_list_growth(&global_context.settings, res.len())?;
+                self.value_stack.push(Gc::new(mc, RefLock::new(List::from(res))).into());
+                self.pos = aft_pos;
+            }
+            Instruction::ListSort { keyed } => {
+                let keys = match keyed {
+                    true => Some(self.value_stack.pop().unwrap().as_list()?.borrow().clone().into_items()),
+                    false => None,
+                };
+                let list = self.value_stack.pop().unwrap().as_list()?.borrow().clone().into_items();
+                self.value_stack.push(Gc::new(mc, RefLock::new(List::from(ops::sort(list, keys)?))).into());
                 self.pos = aft_pos;
             }
             Instruction::ListReshape { len } => {
@@ -635,6 +947,9 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                     if int_dim as f64 != dim { return Err(ErrorCause::InvalidSize { value: dim }) }
                     dims.push(int_dim);
                 }
+                if !dims.contains(&0) {
+                    check_list_growth(&global_context.settings, dims.iter().copied().max().unwrap_or(0))?; // check before allocating
+                }
 
                 self.value_stack.push(ops::reshape(mc, &src, &dims)?);
                 self.pos = aft_pos;
@@ -647,12 +962,13 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                     }
                     VariadicLen::Dynamic => self.value_stack.pop().unwrap().as_list()?.borrow().iter().map(|x| x.as_list()).collect::<Result<_,_>>()?,
                 };
-                self.value_stack.push(Gc::new(mc, RefLock::new(ops::cartesian_product(mc, &sources))).into());
+                check_list_growth(&global_context.settings, sources.iter().fold(1usize, |acc, x| acc.saturating_mul(x.borrow().len())))?; // check before allocating
+                self.value_stack.push(Gc::new(mc, RefLock::new(List::from(ops::cartesian_product(mc, &sources)))).into());
                 self.pos = aft_pos;
             }
 
             Instruction::ListJson => {
-                let value = self.value_stack.pop().unwrap().to_json()?;
+                let value = self.value_stack.pop().unwrap().to_json_with(global_context.settings.json_mode)?;
                 self.value_stack.push(Rc::new(value.to_string()).into());
                 self.pos = aft_pos;
             }
@@ -663,7 +979,9 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             }
             Instruction::ListColumns => {
                 let value = self.value_stack.pop().unwrap();
-                self.value_stack.push(ops::columns(mc, &value)?);
+                let res = ops::columns(mc, &value)?;
+                check_list_size(&global_context.settings, &res)?;
+                self.value_stack.push(res);
                 self.pos = aft_pos;
             }
             Instruction::ListLines => {
@@ -692,6 +1010,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 let index = self.value_stack.pop().unwrap();
                 let val = self.value_stack.pop().unwrap();
                 let mut list = list.borrow_mut(mc);
+                check_list_growth(&global_context.settings, list.len() + 1)?;
 
                 let index = ops::prep_index(&index, list.len() + 1)?;
                 list.insert(index, val);
@@ -700,13 +1019,16 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             Instruction::ListInsertLast => {
                 let list = self.value_stack.pop().unwrap().as_list()?;
                 let val = self.value_stack.pop().unwrap();
-                list.borrow_mut(mc).push_back(val);
+                let mut list = list.borrow_mut(mc);
+                check_list_growth(&global_context.settings, list.len() + 1)?;
+                list.push_back(val);
                 self.pos = aft_pos;
             }
             Instruction::ListInsertRandom => {
                 let list = self.value_stack.pop().unwrap().as_list()?;
                 let val = self.value_stack.pop().unwrap();
                 let mut list = list.borrow_mut(mc);
+                check_list_growth(&global_context.settings, list.len() + 1)?;
 
                 let index = ops::prep_rand_index(&*global_context.system, list.len() + 1)?;
                 list.insert(index, val);
@@ -734,6 +1056,16 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 self.value_stack.push(list[index].clone());
                 self.pos = aft_pos;
             }
+            Instruction::ListGetKey => {
+                let list = self.value_stack.pop().unwrap().as_list()?;
+                let key = self.value_stack.pop().unwrap();
+                let value = match global_context.key_index.find(list, &key.to_string()?) {
+                    Some(index) => list.borrow()[index].as_list()?.borrow()[1].clone(),
+                    None => empty_string().into(),
+                };
+                self.value_stack.push(value);
+                self.pos = aft_pos;
+            }
 
             Instruction::ListAssign => {
                 let value = self.value_stack.pop().unwrap();
@@ -762,6 +1094,29 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 list[index] = value;
                 self.pos = aft_pos;
             }
+            Instruction::ListAssignKey => {
+                let value = self.value_stack.pop().unwrap();
+                let list = self.value_stack.pop().unwrap().as_list()?;
+                let key = self.value_stack.pop().unwrap();
+                let key = key.to_string()?;
+
+                match global_context.key_index.find(list, &key) {
+                    Some(index) => {
+                        let pair = list.borrow()[index].as_list()?;
+                        pair.borrow_mut(mc)[1] = value;
+                    }
+                    None => {
+                        let mut items = list.borrow_mut(mc);
+                        check_list_growth(&global_context.settings, items.len() + 1)?;
+                        let prev_version = items.version();
+                        let key = Rc::new(key.into_owned());
+                        let pair = List::from(VecDeque::from([Value::String(key.clone()), value]));
+                        items.push_back(Gc::new(mc, RefLock::new(pair)).into());
+                        global_context.key_index.appended(list, &items, &key, prev_version);
+                    }
+                }
+                self.pos = aft_pos;
+            }
 
             Instruction::ListRemove => {
                 let list = self.value_stack.pop().unwrap().as_list()?;
@@ -779,72 +1134,78 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 self.pos = aft_pos;
             }
             Instruction::ListRemoveAll => {
-                self.value_stack.pop().unwrap().as_list()?.borrow_mut(mc).clear();
+                let list = self.value_stack.pop().unwrap().as_list()?;
+                list.borrow_mut(mc).clear();
                 self.pos = aft_pos;
             }
 
-            Instruction::ListPopFirstOrElse { goto } => match self.value_stack.pop().unwrap().as_list()?.borrow_mut(mc).pop_front() {
-                Some(value) => {
-                    self.value_stack.push(value);
-                    self.pos = aft_pos;
+            Instruction::ListPopFirstOrElse { goto } => {
+                let list = self.value_stack.pop().unwrap().as_list()?;
+                match list.borrow_mut(mc).pop_front() {
+                    Some(value) => {
+                        self.value_stack.push(value);
+                        self.pos = aft_pos;
+                    }
+                    None => self.pos = goto,
                 }
-                None => self.pos = goto,
             }
 
             Instruction::BinaryOp { op } => {
                 let b = self.value_stack.pop().unwrap();
                 let a = self.value_stack.pop().unwrap();
-                self.value_stack.push(ops::binary_op(mc, &*global_context.system, &a, &b, op)?);
+                if let (BinaryOp::Range, Ok(x), Ok(y)) = (op, a.to_number(), b.to_number()) {
+                    check_list_growth(&global_context.settings, libm::fabs(x.get() - y.get()).min(usize::MAX as f64) as usize + 1)?; // check before allocating
+                }
+                let res = ops::binary_op(mc, &*global_context.system, &a, &b, op)?;
+                check_list_size(&global_context.settings, &res)?;
+                self.value_stack.push(res);
                 self.pos = aft_pos;
             }
             Instruction::VariadicOp { op, len } => {
                 type CombineEmpty<'gc, C, S> = fn(&Mutation<'gc>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>;
-                fn combine_as_binary<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, values: &mut dyn Iterator<Item = &Value<'gc, C, S>>, combine_op: BinaryOp, singleton_op: UnaryOp, empty_case: CombineEmpty<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+                type CombineSingle<'gc, C, S> = fn(&Mutation<'gc>, &S, &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>;
+                type CombinePair<'gc, C, S> = fn(&Mutation<'gc>, &S, &Value<'gc, C, S>, &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>;
+                fn combine_as_binary<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, values: &mut dyn Iterator<Item = &Value<'gc, C, S>>, combine_op: CombinePair<'gc, C, S>, singleton_op: CombineSingle<'gc, C, S>, empty_case: CombineEmpty<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
                     match values.next() {
                         Some(first) => match values.next() {
                             Some(second) => {
-                                let mut acc = ops::binary_op(mc, system, first, second, combine_op)?;
+                                let mut acc = combine_op(mc, system, first, second)?;
                                 for item in values {
-                                    acc = ops::binary_op(mc, system, &acc, item, combine_op)?;
+                                    acc = combine_op(mc, system, &acc, item)?;
                                 }
                                 Ok(acc)
                             }
-                            None => ops::unary_op(mc, system, first, singleton_op),
+                            None => singleton_op(mc, system, first),
                         }
                         None => empty_case(mc),
                     }
                 }
-                fn combine_by_relation<'gc, C: CustomTypes<S>, S: System<C>>(values: &mut dyn Iterator<Item = &Value<'gc, C, S>>, relation: Relation) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
-                    let mut res = match values.next() {
-                        None => return Err(ErrorCause::EmptyList),
-                        Some(x) => x,
-                    };
-                    for other in values {
-                        if ops::check_relation(other, res, relation)? {
-                            res = other;
-                        }
-                    }
-                    Ok(res.clone())
-                }
 
                 type Combine<'gc, C, S, I> = fn(&Mutation<'gc>, &S, I) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>;
                 let combine: Combine<'gc, C, S, &mut dyn Iterator<Item = &Value<'gc, C, S>>> = match op {
-                    VariadicOp::Add => |mc, system, values| combine_as_binary(mc, system, values, BinaryOp::Add, UnaryOp::ToNumber, |_| Ok(Number::new(0.0)?.into())),
-                    VariadicOp::Mul => |mc, system, values| combine_as_binary(mc, system, values, BinaryOp::Mul, UnaryOp::ToNumber, |_| Ok(Number::new(1.0)?.into())),
-                    VariadicOp::Min => |_, _, values| combine_by_relation(values, Relation::Less),
-                    VariadicOp::Max => |_, _, values| combine_by_relation(values, Relation::Greater),
-                    VariadicOp::StrCat => |_, _, values| {
+                    VariadicOp::Add => |mc, system, values| combine_as_binary(mc, system, values, |mc, system, a, b| ops::binary_op(mc, system, a, b, BinaryOp::Add), |mc, system, x| ops::unary_op(mc, system, x, UnaryOp::ToNumber), |_| Ok(Number::new(0.0)?.into())),
+                    VariadicOp::Mul => |mc, system, values| combine_as_binary(mc, system, values, |mc, system, a, b| ops::binary_op(mc, system, a, b, BinaryOp::Mul), |mc, system, x| ops::unary_op(mc, system, x, UnaryOp::ToNumber), |_| Ok(Number::new(1.0)?.into())),
+                    VariadicOp::Min => |mc, system, values| combine_as_binary(mc, system, values, |mc, system, a, b| ops::extremum(mc, system, a, b, Relation::Less), |_, _, x| Ok(x.clone()), |_| Err(ErrorCause::EmptyList)),
+                    VariadicOp::Max => |mc, system, values| combine_as_binary(mc, system, values, |mc, system, a, b| ops::extremum(mc, system, a, b, Relation::Greater), |_, _, x| Ok(x.clone()), |_| Err(ErrorCause::EmptyList)),
+                    VariadicOp::StrCat => |mc, system, values| {
                         let mut acc = String::new();
-                        for item in values {
+                        while let Some(item) = values.next() {
+                            if let Value::List(_) = item { // only hyperize once we see a list
+                                let mut res = ops::str_cat(mc, system, &Rc::new(acc).into(), item)?;
+                                for item in values {
+                                    res = ops::str_cat(mc, system, &res, item)?;
+                                }
+                                return Ok(res);
+                            }
                             core::fmt::write(&mut acc, format_args!("{item}")).unwrap();
                         }
                         Ok(Rc::new(acc).into())
                     },
                     VariadicOp::MakeList => |mc, _, values| {
-                        Ok(Gc::new(mc, RefLock::new(values.cloned().collect::<VecDeque<_>>())).into())
+                        Ok(Gc::new(mc, RefLock::new(values.cloned().collect::<List<_, _>>())).into())
                     },
                     VariadicOp::ListCat => |mc, _, values| {
-                        let mut acc = VecDeque::new();
+                        let mut acc = List::new();
                         for item in values {
                             acc.extend(item.as_list()?.borrow().iter().cloned());
                         }
@@ -865,13 +1226,14 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                         combine(mc, &*global_context.system, &mut src.iter())?
                     }
                 };
+                check_list_size(&global_context.settings, &res)?;
                 self.value_stack.push(res);
                 self.pos = aft_pos;
             }
             Instruction::Cmp { relation } => {
                 let b = self.value_stack.pop().unwrap();
                 let a = self.value_stack.pop().unwrap();
-                self.value_stack.push(ops::check_relation(&a, &b, relation)?.into());
+                self.value_stack.push(ops::relation_op(mc, &*global_context.system, &a, &b, relation)?);
                 self.pos = aft_pos;
             }
             Instruction::Identical => {
@@ -882,7 +1244,18 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             }
             Instruction::UnaryOp { op } => {
                 let x = self.value_stack.pop().unwrap();
-                self.value_stack.push(ops::unary_op(mc, &*global_context.system, &x, op)?);
+                let res = ops::unary_op(mc, &*global_context.system, &x, op)?;
+                check_list_size(&global_context.settings, &res)?;
+                self.value_stack.push(res);
+                self.pos = aft_pos;
+            }
+            Instruction::StrReplace => {
+                let replacement = self.value_stack.pop().unwrap();
+                let pattern = self.value_stack.pop().unwrap();
+                let text = self.value_stack.pop().unwrap();
+                let res = ops::str_replace(mc, &*global_context.system, &text, &pattern, &replacement)?;
+                check_list_size(&global_context.settings, &res)?;
+                self.value_stack.push(res);
                 self.pos = aft_pos;
             }
 
@@ -926,6 +1299,20 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 self.pos = aft_pos;
                 return Ok(ProcessStep::Pause);
             }
+            Instruction::Stop { mode } => {
+                if let (StopMode::ThisBlock, true) = (mode, self.call_stack.len() > 1) {
+                    // inside a custom block or closure, this is the same as reporting nothing
+                    self.return_from_call(empty_string().into());
+                    return Ok(ProcessStep::Normal);
+                }
+                self.pos = aft_pos;
+                return Ok(match mode {
+                    StopMode::All => ProcessStep::StopAll,
+                    StopMode::AllButThisScript => ProcessStep::StopOthers { entity: None },
+                    StopMode::ThisScript | StopMode::ThisBlock => ProcessStep::Terminate { result: None },
+                    StopMode::OtherScriptsInSprite => ProcessStep::StopOthers { entity: Some(context_entity) },
+                });
+            }
 
             Instruction::Jump { to } => self.pos = to,
             Instruction::ConditionalJump { to, when } => {
@@ -993,29 +1380,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 return Ok(ProcessStep::Fork { pos: closure_pos, locals, entity: context_entity });
             }
             Instruction::Return => {
-                let CallStackEntry { called_from, return_to, warp_counter, value_stack_size, handler_stack_size, .. } = self.call_stack.last().unwrap();
                 let return_value = self.value_stack.pop().unwrap();
-
-                self.pos = *return_to;
-                self.warp_counter = *warp_counter;
-                self.value_stack.drain(value_stack_size..);
-                self.handler_stack.drain(handler_stack_size..);
-                debug_assert_eq!(self.value_stack.len(), *value_stack_size);
-                debug_assert_eq!(self.handler_stack.len(), *handler_stack_size);
-
-                self.value_stack.push(return_value);
-
-                if self.call_stack.len() > 1 {
-                    self.call_stack.pop();
-                } else {
-                    debug_assert_eq!(self.value_stack.len(), 1);
-                    debug_assert_eq!(*called_from, usize::MAX);
-                    debug_assert_eq!(*return_to, usize::MAX);
-                    debug_assert_eq!(*warp_counter, 0);
-                    debug_assert_eq!(*value_stack_size, 0);
-                    debug_assert_eq!(*handler_stack_size, 0);
-                    return Ok(ProcessStep::Terminate { result: Some(self.value_stack.pop().unwrap()) });
-                }
+                if let Some(res) = self.return_from_call(return_value) { return Ok(res) }
             }
             Instruction::PushHandler { pos, var } => {
                 self.handler_stack.push(Handler {
@@ -1044,7 +1410,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 let arg_count = arg_names.len();
                 let args = iter::zip(arg_names, self.value_stack.drain(self.value_stack.len() - arg_count..)).collect();
 
-                perform_request!(Request::Rpc { service, rpc, args }, RequestAction::Rpc, aft_pos);
+                perform_request!(Request::Rpc { service, rpc, args, json_mode: global_context.settings.json_mode }, RequestAction::Rpc, aft_pos);
             }
             Instruction::PushRpcError => {
                 self.value_stack.push(self.last_rpc_error.clone().unwrap_or_else(|| empty_string().into()));
@@ -1121,6 +1487,10 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 }
                 self.defer = Some(Defer::Sleep { until: global_context.system.time().to_arbitrary_ms()? + ms as u64, aft_pos });
             }
+            Instruction::KeyPressed { key } => {
+                self.value_stack.push(global_context.is_key_held(key).into());
+                self.pos = aft_pos;
+            }
             Instruction::PushRealTime { query } => {
                 let t = global_context.system.time().to_real_local()?;
                 let v = match query {
@@ -1159,7 +1529,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 let values = {
                     let field_names = tokens.map(ToOwned::to_owned).collect::<Vec<_>>();
                     let field_count = field_names.len();
-                    iter::zip(field_names.into_iter(), self.value_stack.drain(self.value_stack.len() - field_count..)).map(|(k, v)| v.to_json().map(|x| (k, x))).collect::<Result<_,_>>()?
+                    iter::zip(field_names.into_iter(), self.value_stack.drain(self.value_stack.len() - field_count..)).map(|(k, v)| v.to_json_with(global_context.settings.json_mode).map(|x| (k, x))).collect::<Result<_,_>>()?
                 };
 
                 match global_context.system.send_message(msg_type.into(), values, targets, expect_reply)? {
@@ -1168,7 +1538,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 }
             }
             Instruction::SendNetworkReply => {
-                let value = self.value_stack.pop().unwrap().to_json()?;
+                let value = self.value_stack.pop().unwrap().to_json_with(global_context.settings.json_mode)?;
                 if let Some(key) = self.reply_key.take() {
                     global_context.system.send_reply(key, value)?;
                 }
@@ -1190,7 +1560,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 self.pos = aft_pos;
             }
             Instruction::PushCostumeNumber => {
-                let res = entity.costume.as_ref().and_then(|x| entity.costume_list.iter().enumerate().find(|c| Rc::ptr_eq(x, &c.1.1))).map(|x| x.0 + 1).unwrap_or(0);
+                let res = costume_index(&entity).map(|x| x + 1).unwrap_or(0);
                 self.value_stack.push(Value::Number(Number::new(res as f64)?));
                 self.pos = aft_pos;
             }
@@ -1234,20 +1604,38 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
             Instruction::Clone => {
                 drop(entity); // drop our mutable borrow from earlier (in case target is self)
                 let target_cell = self.value_stack.pop().unwrap().as_entity()?;
+                let root_cell = target_cell.borrow().root.unwrap_or(target_cell);
+                {
+                    let mut root = root_cell.borrow_mut(mc);
+                    let limit = global_context.settings.max_clones;
+                    if root.clone_count >= limit { return Err(ErrorCause::CloneLimit { limit }) }
+                    root.clone_count += 1;
+                }
                 let target = target_cell.borrow();
                 let new_entity = Gc::new(mc, RefLock::new(Entity {
                     name: target.name.clone(),
                     costume_list: target.costume_list.clone(),
                     costume: target.costume.clone(),
+                    sound_list: target.sound_list.clone(),
                     state: C::EntityState::from(EntityKind::Clone { parent: &*target }),
                     alive: true,
-                    root: Some(target.root.unwrap_or(target_cell)),
+                    clone_count: 0,
+                    root: Some(root_cell),
                     fields: target.fields.clone(),
                 }));
                 self.value_stack.push(new_entity.into());
                 self.pos = aft_pos;
                 return Ok(ProcessStep::CreatedClone { new_entity });
             }
+            Instruction::DeleteClone => {
+                self.pos = aft_pos;
+                if let (Some(root), true) = (entity.root, entity.alive) {
+                    entity.alive = false;
+                    drop(entity);
+                    root.borrow_mut(mc).clone_count -= 1;
+                    return Ok(ProcessStep::DeletedClone { entity: context_entity });
+                }
+            }
             Instruction::ClearEffects => {
                 perform_command!(Command::ClearEffects, aft_pos);
             }
@@ -1294,6 +1682,104 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
                 let distance = self.value_stack.pop().unwrap().to_number()?;
                 perform_command!(Command::Forward { distance }, aft_pos);
             }
+            Instruction::PlaySound { blocking } => {
+                let sound = match self.value_stack.pop().unwrap() {
+                    Value::Audio(x) => Some(x),
+                    Value::String(x) => match x.as_str() {
+                        "" => None,
+                        x => match entity.sound_list.iter().find(|s| s.0 == x) {
+                            Some(s) => Some(s.1.clone()),
+                            None => return Err(ErrorCause::UndefinedSound { name: x.into() }),
+                        }
+                    }
+                    x => return Err(ErrorCause::ConversionError { got: x.get_type(), expected: Type::Audio }),
+                };
+
+                match sound {
+                    Some(sound) => perform_command!(Command::PlaySound { sound, blocking }, aft_pos),
+                    None => self.pos = aft_pos,
+                }
+            }
+            Instruction::PlayNote => {
+                let beats = self.value_stack.pop().unwrap().to_number()?;
+                let notes = match self.value_stack.pop().unwrap() {
+                    Value::List(notes) => notes.borrow().iter().map(Value::to_number).collect::<Result<_,_>>()?,
+                    note => vec![note.to_number()?],
+                };
+                perform_command!(Command::PlayNote { notes, beats }, aft_pos);
+            }
+            Instruction::StopSounds => perform_command!(Command::StopSounds, aft_pos),
+            Instruction::IsTouchingEntity => {
+                let target = pop_entity!();
+                if target.as_ptr() == context_entity.as_ptr() {
+                    self.value_stack.push(false.into());
+                    self.pos = aft_pos;
+                } else {
+                    perform_request!(Request::IsTouching { target: TouchTarget::Entity(target) }, RequestAction::Push, aft_pos);
+                }
+            }
+            Instruction::IsTouchingMouse => perform_request!(Request::IsTouching { target: TouchTarget::Mouse }, RequestAction::Push, aft_pos),
+            Instruction::IsTouchingEdge => perform_request!(Request::IsTouching { target: TouchTarget::Edge }, RequestAction::Push, aft_pos),
+            Instruction::IsTouchingDrawings => perform_request!(Request::IsTouching { target: TouchTarget::Drawings }, RequestAction::Push, aft_pos),
+            Instruction::DistanceTo | Instruction::DirectionTo => {
+                let target = pop_entity!();
+                if target.as_ptr() == context_entity.as_ptr() {
+                    self.value_stack.push(Number::new(0.0)?.into());
+                    self.pos = aft_pos;
+                } else if let Instruction::DistanceTo = ins {
+                    perform_request!(Request::DistanceTo { target }, RequestAction::Push, aft_pos);
+                } else {
+                    perform_request!(Request::DirectionTo { target }, RequestAction::Push, aft_pos);
+                }
+            }
+            Instruction::PushAttributeOf => {
+                let target = pop_entity!();
+                let attr = self.value_stack.pop().unwrap().to_string()?.into_owned();
+                let is_self = target.as_ptr() == context_entity.as_ptr();
+
+                let prop = match attr.as_str() {
+                    "x position" => Some(Property::XPos),
+                    "y position" => Some(Property::YPos),
+                    "direction" => Some(Property::Heading),
+                    "size" => Some(Property::Size),
+                    "volume" => Some(Property::Volume),
+                    "balance" => Some(Property::Balance),
+                    _ => None,
+                };
+                match prop {
+                    Some(prop) => {
+                        // properties are requested on behalf of the target entity rather than the current one
+                        let res = match is_self {
+                            true => global_context.system.perform_request(mc, Request::Property { prop }, &mut *entity)?,
+                            false => global_context.system.perform_request(mc, Request::Property { prop }, &mut *target.borrow_mut(mc))?,
+                        };
+                        match res {
+                            MaybeAsync::Async(key) => self.defer = Some(Defer::Request { key, aft_pos, action: RequestAction::Push }),
+                            MaybeAsync::Sync(res) => process_request!(res, RequestAction::Push, aft_pos),
+                        }
+                    }
+                    None => {
+                        let target_ref;
+                        let target = match is_self {
+                            true => &*entity,
+                            false => {
+                                target_ref = target.borrow();
+                                &*target_ref
+                            }
+                        };
+                        let value = match attr.as_str() {
+                            "costume #" => Number::new(costume_index(target).map(|x| x + 1).unwrap_or(0) as f64)?.into(),
+                            "costume name" => costume_index(target).map(|x| Rc::new(target.costume_list[x].0.clone())).unwrap_or_else(empty_string).into(),
+                            _ => match target.fields.lookup(&attr) {
+                                Some(x) => x.get().clone(),
+                                None => return Err(ErrorCause::UndefinedVariable { name: attr }),
+                            }
+                        };
+                        self.value_stack.push(value);
+                        self.pos = aft_pos;
+                    }
+                }
+            }
             Instruction::UnknownBlock { name, args } => {
                 let args = self.value_stack.drain(self.value_stack.len() - args..).collect();
                 perform_request!(Request::UnknownBlock { name: name.into(), args }, RequestAction::Push, aft_pos);
@@ -1304,13 +1790,13 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Process<'gc, C, S> {
     }
 }
 
-mod ops {
+pub(crate) mod ops {
     use super::*;
 
-    fn as_list<'gc, C: CustomTypes<S>, S: System<C>>(v: &Value<'gc, C, S>) -> Option<Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>> {
+    fn as_list<'gc, C: CustomTypes<S>, S: System<C>>(v: &Value<'gc, C, S>) -> Option<Gc<'gc, RefLock<List<'gc, C, S>>>> {
         v.as_list().ok()
     }
-    fn as_matrix<'gc, C: CustomTypes<S>, S: System<C>>(v: &Value<'gc, C, S>) -> Option<Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>> {
+    fn as_matrix<'gc, C: CustomTypes<S>, S: System<C>>(v: &Value<'gc, C, S>) -> Option<Gc<'gc, RefLock<List<'gc, C, S>>>> {
         let vals = as_list(v)?;
         let good = match vals.borrow().front() {
             None => false,
@@ -1380,7 +1866,7 @@ mod ops {
     }
     pub(super) fn reshape<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, src: &Value<'gc, C, S>, dims: &[usize]) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
         if dims.iter().any(|&x| x == 0) {
-            return Ok(Gc::new(mc, RefLock::new(VecDeque::default())).into())
+            return Ok(Gc::new(mc, RefLock::new(List::new())).into())
         }
 
         let mut src = ops::flatten(src)?;
@@ -1391,7 +1877,7 @@ mod ops {
         fn reshape_impl<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, src: &mut Cycle<VecDequeIter<Value<'gc, C, S>>>, dims: &[usize]) -> Value<'gc, C, S> {
             match dims {
                 [] => src.next().unwrap().clone(),
-                [first, rest @ ..] => Gc::new(mc, RefLock::new((0..*first).map(|_| reshape_impl(mc, src, rest)).collect::<VecDeque<_>>())).into(),
+                [first, rest @ ..] => Gc::new(mc, RefLock::new((0..*first).map(|_| reshape_impl(mc, src, rest)).collect::<List<_, _>>())).into(),
             }
         }
         Ok(reshape_impl(mc, &mut src.iter().cycle(), dims))
@@ -1405,9 +1891,9 @@ mod ops {
             _ => 1,
         }).max().unwrap_or(0);
 
-        let mut res = VecDeque::with_capacity(columns);
+        let mut res = List::with_capacity(columns);
         for column in 0..columns {
-            let mut inner = VecDeque::with_capacity(src.len());
+            let mut inner = List::with_capacity(src.len());
             for row in src.iter() {
                 inner.push_back(match row {
                     Value::List(x) => x.borrow().get(column).cloned().unwrap_or_else(|| Value::String(empty_string())),
@@ -1418,12 +1904,12 @@ mod ops {
         }
         Ok(Gc::new(mc, RefLock::new(res)).into())
     }
-    pub(super) fn cartesian_product<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, sources: &[Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>]) -> VecDeque<Value<'gc, C, S>> {
+    pub(super) fn cartesian_product<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, sources: &[Gc<'gc, RefLock<List<'gc, C, S>>>]) -> VecDeque<Value<'gc, C, S>> {
         if sources.is_empty() { return Default::default() }
 
-        fn cartesian_product_impl<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, res: &mut VecDeque<Value<'gc, C, S>>, partial: &mut VecDeque<Value<'gc, C, S>>, sources: &[Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>]) {
+        fn cartesian_product_impl<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, res: &mut VecDeque<Value<'gc, C, S>>, partial: &mut VecDeque<Value<'gc, C, S>>, sources: &[Gc<'gc, RefLock<List<'gc, C, S>>>]) {
             match sources {
-                [] => res.push_back(Gc::new(mc, RefLock::new(partial.clone())).into()),
+                [] => res.push_back(Gc::new(mc, RefLock::new(List::from(partial.clone()))).into()),
                 [first, rest @ ..] => for item in first.borrow().iter() {
                     partial.push_back(item.clone());
                     cartesian_product_impl(mc, res, partial, rest);
@@ -1443,7 +1929,7 @@ mod ops {
         if value.is_empty() { return Ok(table); }
 
         'next_vector: loop {
-            let mut vector = VecDeque::new();
+            let mut vector = List::new();
 
             'next_scalar: loop {
                 let mut scalar = String::new();
@@ -1529,7 +2015,7 @@ mod ops {
         Ok(res)
     }
 
-    fn binary_op_impl<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, matrix_mode: bool, cache: &mut BTreeMap<(Identity<'gc, C, S>, Identity<'gc, C, S>, bool), Value<'gc, C, S>>, scalar_op: fn(&Mutation<'gc>, &S, &Value<'gc, C, S>, &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+    fn binary_op_impl<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, matrix_mode: bool, cache: &mut BTreeMap<(Identity<'gc, C, S>, Identity<'gc, C, S>, bool), Value<'gc, C, S>>, scalar_op: &dyn Fn(&Mutation<'gc>, &S, &Value<'gc, C, S>, &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
         let cache_key = (a.identity(), b.identity(), matrix_mode);
         Ok(match cache.get(&cache_key) {
             Some(x) => x.clone(),
@@ -1538,7 +2024,7 @@ mod ops {
                 match (checker(a), checker(b)) {
                     (Some(a), Some(b)) => {
                         let (a, b) = (a.borrow(), b.borrow());
-                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(VecDeque::with_capacity(a.len().min(b.len())))).into();
+                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(List::with_capacity(a.len().min(b.len())))).into();
                         cache.insert(cache_key, real_res.clone());
                         let res = as_list(&real_res).unwrap();
                         let mut res = res.borrow_mut(mc);
@@ -1549,7 +2035,7 @@ mod ops {
                     }
                     (Some(a), None) => {
                         let a = a.borrow();
-                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(VecDeque::with_capacity(a.len()))).into();
+                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(List::with_capacity(a.len()))).into();
                         cache.insert(cache_key, real_res.clone());
                         let res = as_list(&real_res).unwrap();
                         let mut res = res.borrow_mut(mc);
@@ -1560,7 +2046,7 @@ mod ops {
                     }
                     (None, Some(b)) => {
                         let b = b.borrow();
-                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(VecDeque::with_capacity(b.len()))).into();
+                        let real_res: Value<C, S> = Gc::new(mc, RefLock::new(List::with_capacity(b.len()))).into();
                         cache.insert(cache_key, real_res.clone());
                         let res = as_list(&real_res).unwrap();
                         let mut res = res.borrow_mut(mc);
@@ -1574,35 +2060,58 @@ mod ops {
             }
         })
     }
+    /// Evaluates a purely numeric binary operator, or returns [`None`] if `op` is not one.
+    /// This is the scalar case of [`binary_op`], and is also used for constant folding in the compiler.
+    pub(crate) fn num_binary_op(op: BinaryOp, a: Number, b: Number) -> Option<Result<Number, NumberError>> {
+        Some(match op {
+            BinaryOp::Add => a.add(b),
+            BinaryOp::Sub => a.sub(b),
+            BinaryOp::Mul => a.mul(b),
+            BinaryOp::Div => a.div(b),
+            BinaryOp::Pow => a.powf(b),
+            BinaryOp::Log => b.log(a),
+            BinaryOp::Atan2 => a.atan2(b).and_then(|x| x.to_degrees()),
+            BinaryOp::Mod => {
+                let (a, b) = (a.get(), b.get());
+                Number::new(if a.is_sign_positive() == b.is_sign_positive() { a % b } else { b + (a % -b) })
+            }
+            _ => return None,
+        })
+    }
     pub(super) fn binary_op<'gc, 'a, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &'a Value<'gc, C, S>, b: &'a Value<'gc, C, S>, op: BinaryOp) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
         let mut cache = Default::default();
         match op {
-            BinaryOp::Add       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.add(b.to_number()?)?.into())),
-            BinaryOp::Sub       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.sub(b.to_number()?)?.into())),
-            BinaryOp::Mul       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.mul(b.to_number()?)?.into())),
-            BinaryOp::Div       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.div(b.to_number()?)?.into())),
-            BinaryOp::Pow       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.powf(b.to_number()?)?.into())),
-            BinaryOp::Log       => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(b.to_number()?.log(a.to_number()?)?.into())),
-            BinaryOp::Atan2     => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| Ok(a.to_number()?.atan2(b.to_number()?)?.to_degrees()?.into())),
-
-            BinaryOp::StrGet => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| {
+            BinaryOp::Add       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Add, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Sub       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Sub, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Mul       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Mul, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Div       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Div, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Pow       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Pow, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Log       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Log, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Atan2     => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Atan2, a.to_number()?, b.to_number()?).unwrap()?.into())),
+            BinaryOp::Mod       => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(num_binary_op(BinaryOp::Mod, a.to_number()?, b.to_number()?).unwrap()?.into())),
+
+            BinaryOp::StrGet => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| {
                 let string = b.to_string()?;
                 let index = prep_index(a, string.chars().count())?;
                 Ok(Rc::new(string.chars().nth(index).unwrap().to_string()).into())
             }),
 
-            BinaryOp::Mod => binary_op_impl(mc, system, a, b, true, &mut cache, |_, _, a, b| {
-                let (a, b) = (a.to_number()?.get(), b.to_number()?.get());
-                Ok(Number::new(if a.is_sign_positive() == b.is_sign_positive() { a % b } else { b + (a % -b) })?.into())
+            BinaryOp::StrContains => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| {
+                Ok(a.to_string()?.contains(b.to_string()?.as_ref()).into())
+            }),
+            BinaryOp::StrIndexOf => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| {
+                let (text, pattern) = (a.to_string()?, b.to_string()?);
+                let res = text.find(pattern.as_ref()).map(|i| text[..i].chars().count() + 1).unwrap_or(0);
+                Ok(Number::new(res as f64)?.into())
             }),
-            BinaryOp::SplitBy => binary_op_impl(mc, system, a, b, true, &mut cache, |mc, _, a, b| {
+            BinaryOp::SplitBy => binary_op_impl(mc, system, a, b, true, &mut cache, &|mc, _, a, b| {
                 let (text, pattern) = (a.to_string()?, b.to_string()?);
-                Ok(Gc::new(mc, RefLock::new(text.split(pattern.as_ref()).map(|x| Rc::new(x.to_owned()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(text.split(pattern.as_ref()).map(|x| Rc::new(x.to_owned()).into()).collect::<List<_, _>>())).into())
             }),
 
-            BinaryOp::Range => binary_op_impl(mc, system, a, b, true, &mut cache, |mc, _, a, b| {
+            BinaryOp::Range => binary_op_impl(mc, system, a, b, true, &mut cache, &|mc, _, a, b| {
                 let (mut a, b) = (a.to_number()?.get(), b.to_number()?.get());
-                let mut res = VecDeque::new();
+                let mut res = List::new();
                 if a.is_finite() && b.is_finite() {
                     if a <= b {
                         while a <= b {
@@ -1618,7 +2127,7 @@ mod ops {
                 }
                 Ok(Gc::new(mc, RefLock::new(res)).into())
             }),
-            BinaryOp::Random => binary_op_impl(mc, system, a, b, true, &mut cache, |_, system, a, b| {
+            BinaryOp::Random => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, system, a, b| {
                 let (mut a, mut b) = (a.to_number()?.get(), b.to_number()?.get());
                 if a > b { (a, b) = (b, a); }
                 let res = if a == libm::round(a) && b == libm::round(b) {
@@ -1639,7 +2148,7 @@ mod ops {
             None => match as_list(x) {
                 Some(x) => {
                     let x = x.borrow();
-                    let real_res: Value<C, S> = Gc::new(mc, RefLock::new(VecDeque::with_capacity(x.len()))).into();
+                    let real_res: Value<C, S> = Gc::new(mc, RefLock::new(List::with_capacity(x.len()))).into();
                     cache.insert(cache_key, real_res.clone());
                     let res = as_list(&real_res).unwrap();
                     let mut res = res.borrow_mut(mc);
@@ -1653,23 +2162,34 @@ mod ops {
             }
         })
     }
+    /// Evaluates a purely numeric unary operator, or returns [`None`] if `op` is not one.
+    /// This is the scalar case of [`unary_op`], and is also used for constant folding in the compiler.
+    pub(crate) fn num_unary_op(op: UnaryOp, x: Number) -> Option<Result<Number, NumberError>> {
+        Some(match op {
+            UnaryOp::ToNumber => Ok(x),
+            UnaryOp::Abs => x.abs(),
+            UnaryOp::Neg => x.neg(),
+            UnaryOp::Sqrt => x.sqrt(),
+            UnaryOp::Round => x.round(),
+            UnaryOp::Floor => x.floor(),
+            UnaryOp::Ceil => x.ceil(),
+            UnaryOp::Sin => Number::new(libm::sin(x.get().to_radians())),
+            UnaryOp::Cos => Number::new(libm::cos(x.get().to_radians())),
+            UnaryOp::Tan => Number::new(libm::tan(x.get().to_radians())),
+            UnaryOp::Asin => Number::new(libm::asin(x.get()).to_degrees()),
+            UnaryOp::Acos => Number::new(libm::acos(x.get()).to_degrees()),
+            UnaryOp::Atan => Number::new(libm::atan(x.get()).to_degrees()),
+            _ => return None,
+        })
+    }
     pub(super) fn unary_op<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, x: &Value<'gc, C, S>, op: UnaryOp) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
         let mut cache = Default::default();
         match op {
-            UnaryOp::ToNumber => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.into())),
+            UnaryOp::ToNumber | UnaryOp::Abs | UnaryOp::Neg | UnaryOp::Sqrt | UnaryOp::Round | UnaryOp::Floor | UnaryOp::Ceil
+            | UnaryOp::Sin | UnaryOp::Cos | UnaryOp::Tan | UnaryOp::Asin | UnaryOp::Acos | UnaryOp::Atan => {
+                unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(num_unary_op(op, x.to_number()?).unwrap()?.into()))
+            }
             UnaryOp::Not      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok((!x.to_bool()?).into())),
-            UnaryOp::Abs      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.abs()?.into())),
-            UnaryOp::Neg      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.neg()?.into())),
-            UnaryOp::Sqrt     => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.sqrt()?.into())),
-            UnaryOp::Round    => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.round()?.into())),
-            UnaryOp::Floor    => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.floor()?.into())),
-            UnaryOp::Ceil     => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(x.to_number()?.ceil()?.into())),
-            UnaryOp::Sin      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::sin(x.to_number()?.get().to_radians()))?.into())),
-            UnaryOp::Cos      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::cos(x.to_number()?.get().to_radians()))?.into())),
-            UnaryOp::Tan      => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::tan(x.to_number()?.get().to_radians()))?.into())),
-            UnaryOp::Asin     => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::asin(x.to_number()?.get()).to_degrees())?.into())),
-            UnaryOp::Acos     => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::acos(x.to_number()?.get()).to_degrees())?.into())),
-            UnaryOp::Atan     => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(libm::atan(x.to_number()?.get()).to_degrees())?.into())),
             UnaryOp::StrLen   => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Number::new(x.to_string()?.chars().count() as f64)?.into())),
 
             UnaryOp::StrGetLast => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| match x.to_string()?.chars().next_back() {
@@ -1682,24 +2202,28 @@ mod ops {
                 Ok(Rc::new(x.chars().nth(i).unwrap().to_string()).into())
             }),
 
+            UnaryOp::StrUpper => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Rc::new(x.to_string()?.to_uppercase()).into())),
+            UnaryOp::StrLower => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Rc::new(x.to_string()?.to_lowercase()).into())),
+            UnaryOp::StrTrim  => unary_op_impl(mc, system, x, &mut cache, &|_, _, x| Ok(Rc::new(x.to_string()?.trim().to_owned()).into())),
+
             UnaryOp::SplitLetter => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
-                Ok(Gc::new(mc, RefLock::new(x.to_string()?.chars().map(|x| Rc::new(x.to_string()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(x.to_string()?.chars().map(|x| Rc::new(x.to_string()).into()).collect::<List<_, _>>())).into())
             }),
             UnaryOp::SplitWord => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
-                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split_whitespace().map(|x| Rc::new(x.to_owned()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split_whitespace().map(|x| Rc::new(x.to_owned()).into()).collect::<List<_, _>>())).into())
             }),
             UnaryOp::SplitTab => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
-                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split('\t').map(|x| Rc::new(x.to_owned()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split('\t').map(|x| Rc::new(x.to_owned()).into()).collect::<List<_, _>>())).into())
             }),
             UnaryOp::SplitCR => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
-                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split('\r').map(|x| Rc::new(x.to_owned()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(x.to_string()?.split('\r').map(|x| Rc::new(x.to_owned()).into()).collect::<List<_, _>>())).into())
             }),
             UnaryOp::SplitLF => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
-                Ok(Gc::new(mc, RefLock::new(x.to_string()?.lines().map(|x| Rc::new(x.to_owned()).into()).collect::<VecDeque<_>>())).into())
+                Ok(Gc::new(mc, RefLock::new(x.to_string()?.lines().map(|x| Rc::new(x.to_owned()).into()).collect::<List<_, _>>())).into())
             }),
             UnaryOp::SplitCsv => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
                 let value = from_csv(mc, x.to_string()?.as_ref())?;
-                Ok(Gc::new(mc, RefLock::new(value)).into())
+                Ok(Gc::new(mc, RefLock::new(List::from(value))).into())
             }),
             UnaryOp::SplitJson => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
                 let value = x.to_string()?;
@@ -1721,9 +2245,9 @@ mod ops {
             }),
             UnaryOp::CharToUnicode => unary_op_impl(mc, system, x, &mut cache, &|mc, _, x| {
                 let src = x.to_string()?;
-                let values: VecDeque<_> = src.chars().map(|ch| Ok(Number::new(ch as u32 as f64)?.into())).collect::<Result<_, NumberError>>()?;
+                let values: List<_, _> = src.chars().map(|ch| Ok(Number::new(ch as u32 as f64)?.into())).collect::<Result<_, NumberError>>()?;
                 Ok(match values.len() {
-                    1 => values.into_iter().next().unwrap(),
+                    1 => values.into_items().pop_front().unwrap(),
                     _ => Gc::new(mc, RefLock::new(values)).into(),
                 })
             }),
@@ -1735,6 +2259,9 @@ mod ops {
         unary_op_impl(mc, system, index, &mut Default::default(), &|_, _, x| Ok(list[prep_index(x, list.len())?].clone()))
     }
 
+    fn parse_num(x: &str) -> Option<Number> {
+        x.parse::<f64>().ok().and_then(|x| Number::new(x).ok())
+    }
     fn cmp_impl<'gc, C: CustomTypes<S>, S: System<C>>(a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, cache: &mut BTreeMap<(Identity<'gc, C, S>, Identity<'gc, C, S>), Option<Option<Ordering>>>) -> Result<Option<Ordering>, ErrorCause<C, S>> {
         let key = (a.identity(), b.identity());
         match cache.get(&key) {
@@ -1743,10 +2270,6 @@ mod ops {
             None => { cache.insert(key, None); }
         }
 
-        fn parse_num(x: &str) -> Option<Number> {
-            x.parse::<f64>().ok().and_then(|x| Number::new(x).ok())
-        }
-
         let res = match (a, b) {
             (Value::Bool(a), Value::Bool(b)) => a.cmp(b).into(),
             (Value::Bool(_), _) | (_, Value::Bool(_)) => None,
@@ -1807,6 +2330,67 @@ mod ops {
         cmp_impl(a, b, &mut Default::default())
     }
 
+    /// A total ordering of values used by [`sort`], since [`cmp`] is not transitive on mixed number/text values.
+    /// Numbers (and numeric text) come before other text, which is compared case-insensitively.
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    enum SortKey {
+        Bool(bool),
+        Number(Number),
+        Text(UniCase<String>),
+        List(Vec<SortKey>),
+        Ref(u8, usize), // (type, address) since other values are only comparable to themselves
+    }
+    fn sort_key<'gc, C: CustomTypes<S>, S: System<C>>(value: &Value<'gc, C, S>, path: &mut Vec<*const ()>) -> Result<SortKey, ErrorCause<C, S>> {
+        Ok(match value {
+            Value::Bool(x) => SortKey::Bool(*x),
+            Value::Number(x) => SortKey::Number(*x),
+            Value::String(x) => match parse_num(x) {
+                Some(x) => SortKey::Number(x),
+                None => SortKey::Text(UniCase::new(x.as_str().to_owned())),
+            }
+            Value::List(x) => {
+                let ptr = Gc::as_ptr(*x) as *const ();
+                if path.contains(&ptr) { return Err(ErrorCause::CyclicValue) }
+                path.push(ptr);
+                let res = x.borrow().iter().map(|x| sort_key(x, path)).collect::<Result<_, _>>()?;
+                path.pop();
+                SortKey::List(res)
+            }
+            Value::Image(x) => SortKey::Ref(0, Rc::as_ptr(x) as *const () as usize),
+            Value::Audio(x) => SortKey::Ref(1, Rc::as_ptr(x) as *const () as usize),
+            Value::Closure(x) => SortKey::Ref(2, Gc::as_ptr(*x) as *const () as usize),
+            Value::Entity(x) => SortKey::Ref(3, Gc::as_ptr(*x) as *const () as usize),
+            Value::Native(x) => SortKey::Ref(4, Rc::as_ptr(x) as *const () as usize),
+        })
+    }
+    pub(super) fn sort<'gc, C: CustomTypes<S>, S: System<C>>(values: VecDeque<Value<'gc, C, S>>, keys: Option<VecDeque<Value<'gc, C, S>>>) -> Result<VecDeque<Value<'gc, C, S>>, ErrorCause<C, S>> {
+        let entries: Vec<_> = match keys {
+            Some(keys) => keys.into_iter().zip(values).collect(),
+            None => values.into_iter().map(|x| (x.clone(), x)).collect(),
+        };
+        let mut entries = entries.into_iter().map(|(k, v)| Ok((sort_key(&k, &mut vec![])?, k, v))).collect::<Result<Vec<_>, ErrorCause<C, S>>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // values of different kinds end up next to each other in the total order, so checking neighbors finds any incomparable pair
+        for pair in entries.windows(2) {
+            if cmp(&pair[0].1, &pair[1].1)?.is_none() {
+                return Err(ErrorCause::Incomparable { left: pair[0].1.get_type(), right: pair[1].1.get_type() });
+            }
+        }
+        Ok(entries.into_iter().map(|x| x.2).collect())
+    }
+    /// Replaces all occurrences of `pattern` in `text`, hyperized over all arguments.
+    /// Following Snap, an empty pattern leaves the text unchanged.
+    pub(super) fn str_replace<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, text: &Value<'gc, C, S>, pattern: &Value<'gc, C, S>, replacement: &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+        binary_op_impl(mc, system, text, pattern, true, &mut Default::default(), &|mc, system, text, pattern| {
+            let (text, pattern) = (text.to_string()?, pattern.to_string()?);
+            unary_op_impl(mc, system, replacement, &mut Default::default(), &|_, _, replacement| Ok(match pattern.is_empty() {
+                true => Rc::new(text.clone().into_owned()).into(),
+                false => Rc::new(text.replace(pattern.as_ref(), replacement.to_string()?.as_ref())).into(),
+            }))
+        })
+    }
+
     pub(super) fn check_relation<'gc, C: CustomTypes<S>, S: System<C>>(a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, relation: Relation) -> Result<bool, ErrorCause<C, S>> {
         let ord = cmp(a, b)?;
         Ok(match relation {
@@ -1825,6 +2409,49 @@ mod ops {
         })
     }
 
+    /// Hyperized version of [`check_relation`].
+    /// Following Snap, only the ordering relations are applied element-wise; equality always compares entire values.
+    pub(super) fn relation_op<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, relation: Relation) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+        let mut cache = Default::default();
+        match relation {
+            Relation::Equal | Relation::NotEqual => Ok(check_relation(a, b, relation)?.into()),
+            Relation::Less      => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(check_relation(a, b, Relation::Less)?.into())),
+            Relation::LessEq    => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(check_relation(a, b, Relation::LessEq)?.into())),
+            Relation::Greater   => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(check_relation(a, b, Relation::Greater)?.into())),
+            Relation::GreaterEq => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| Ok(check_relation(a, b, Relation::GreaterEq)?.into())),
+        }
+    }
+    /// Checks if `y` is strictly better than `x` as a minimum (for [`Relation::Less`]) or maximum (for [`Relation::Greater`]).
+    /// This is the numeric case of [`extremum`], and is also used for constant folding in the compiler.
+    pub(crate) fn num_is_better(x: Number, y: Number, relation: Relation) -> bool {
+        match relation {
+            Relation::Less => y < x,
+            Relation::Greater => y > x,
+            _ => unreachable!(),
+        }
+    }
+    /// Hyperized minimum (for [`Relation::Less`]) or maximum (for [`Relation::Greater`]) of two values.
+    /// Values are compared as numbers if possible (and as text otherwise), but the result is always one of the (non-list) inputs.
+    pub(super) fn extremum<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, relation: Relation) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+        fn pick<'gc, C: CustomTypes<S>, S: System<C>>(a: &Value<'gc, C, S>, b: &Value<'gc, C, S>, relation: Relation) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+            let better = match (a.to_number(), b.to_number()) {
+                (Ok(x), Ok(y)) => num_is_better(x, y, relation),
+                _ => check_relation(b, a, relation)?,
+            };
+            Ok(if better { b } else { a }.clone())
+        }
+        let mut cache = Default::default();
+        match relation {
+            Relation::Less => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| pick(a, b, Relation::Less)),
+            Relation::Greater => binary_op_impl(mc, system, a, b, true, &mut cache, &|_, _, a, b| pick(a, b, Relation::Greater)),
+            _ => unreachable!(),
+        }
+    }
+    /// Hyperized text concatenation of two values.
+    pub(super) fn str_cat<'gc, C: CustomTypes<S>, S: System<C>>(mc: &Mutation<'gc>, system: &S, a: &Value<'gc, C, S>, b: &Value<'gc, C, S>) -> Result<Value<'gc, C, S>, ErrorCause<C, S>> {
+        binary_op_impl(mc, system, a, b, true, &mut Default::default(), &|_, _, a, b| Ok(Rc::new(format!("{a}{b}")).into()))
+    }
+
     pub(super) fn identical<'gc, C: CustomTypes<S>, S: System<C>>(a: &Value<'gc, C, S>, b: &Value<'gc, C, S>) -> bool {
         match (a, b) {
             (Value::Bool(a), Value::Bool(b)) => a == b,
@@ -1855,7 +2482,7 @@ mod ops {
         }
     }
 
-    pub(super) fn find<'gc, C: CustomTypes<S>, S: System<C>>(list: Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>, value: &Value<'gc, C, S>) -> Result<Option<usize>, ErrorCause<C, S>> {
+    pub(super) fn find<'gc, C: CustomTypes<S>, S: System<C>>(list: Gc<'gc, RefLock<List<'gc, C, S>>>, value: &Value<'gc, C, S>) -> Result<Option<usize>, ErrorCause<C, S>> {
         let list = list.borrow();
         for (i, x) in list.iter().enumerate() {
             if cmp(x, value)? == Some(Ordering::Equal) {
@@ -0,0 +1,218 @@
+//! Execution profiling of [`ByteCode`] programs.
+//!
+//! A [`Profiler`] can be attached to a [`GlobalContext`](crate::runtime::GlobalContext) (see [`GlobalContext::profiler`](crate::runtime::GlobalContext::profiler)), after which every call to
+//! [`Process::step`](crate::process::Process::step) on that context records the instruction it executed and the time it took.
+//! Samples are keyed by the call stack of the process, so the raw data can be aggregated in several ways after the fact.
+//!
+//! [`Profiler::report`] produces a [`ProfileReport`] which attributes costs to individual blocks (by collab id, via [`Locations`])
+//! and to custom block definitions (via [`ScriptInfo`]), while [`Profiler::folded`] produces the folded stack format used by
+//! flamegraph tools such as `inferno` and `flamegraph.pl`.
+//!
+//! Profiling requires a monotonic clock, which is supplied by the embedder.
+//! The unit of the clock is arbitrary, but all times in reports are given in that unit (typically nanoseconds).
+
+use alloc::string::{String, ToString};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::fmt;
+
+use crate::*;
+use crate::bytecode::*;
+
+/// The cost measure to use as the sample weight of [`Profiler::folded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileMetric {
+    /// The number of executed instructions.
+    Instructions,
+    /// The total elapsed time as measured by the profiler's clock.
+    Time,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Sample {
+    instructions: u64,
+    time: u64,
+}
+
+/// The accumulated cost of a block or custom block in a [`ProfileReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub instructions: u64,
+    pub time: u64,
+}
+
+/// A summary of the samples collected by a [`Profiler`] (see [`Profiler::report`]).
+///
+/// All entries are sorted by descending time, then by descending instruction count.
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    /// The total number of instructions executed and the total time taken.
+    pub total: ProfileEntry,
+    /// The cost of each block (by collab id), not counting the cost of any custom blocks it calls.
+    pub blocks: Vec<ProfileEntry>,
+    /// The cost of each custom block definition, including the cost of any blocks it calls (recursive calls are only counted once).
+    pub custom_blocks: Vec<ProfileEntry>,
+}
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_section(f: &mut fmt::Formatter, title: &str, total: &ProfileEntry, entries: &[ProfileEntry]) -> fmt::Result {
+            let percent = |part: u64, whole: u64| if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 };
+            writeln!(f, "{title}:")?;
+            writeln!(f, "  {:>7}  {:>14}  {:>7}  {:>12}  name", "time %", "time", "ins %", "instructions")?;
+            for entry in entries {
+                writeln!(f, "  {:>6.2}%  {:>14}  {:>6.2}%  {:>12}  {}", percent(entry.time, total.time), entry.time, percent(entry.instructions, total.instructions), entry.instructions, entry.name)?;
+            }
+            Ok(())
+        }
+
+        writeln!(f, "total: {} instructions in {} time units", self.total.instructions, self.total.time)?;
+        write_section(f, "blocks (self)", &self.total, &self.blocks)?;
+        write_section(f, "custom blocks (inclusive)", &self.total, &self.custom_blocks)
+    }
+}
+
+/// An instruction-level execution profiler (see the [module-level documentation](crate::profiler)).
+pub struct Profiler {
+    clock: Box<dyn Fn() -> u64>,
+    samples: BTreeMap<Vec<usize>, Sample>,
+    stack: Vec<usize>,
+    start_time: u64,
+}
+impl Profiler {
+    /// Creates a new profiler with no samples that measures time with the given monotonic `clock`.
+    pub fn new(clock: Box<dyn Fn() -> u64>) -> Self {
+        Self { clock, samples: Default::default(), stack: vec![], start_time: 0 }
+    }
+    /// Discards all samples collected so far.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+    /// Checks if no samples have been collected.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    /// Begins a sample for an instruction executed with the given stack, which consists of the start position of the process,
+    /// the [`CallStackEntry::called_from`](crate::process::CallStackEntry::called_from) position of each call, and the position of the instruction.
+    pub(crate) fn begin<I: Iterator<Item = usize>>(&mut self, stack: I) {
+        self.stack.clear();
+        self.stack.extend(stack);
+        self.start_time = (self.clock)();
+    }
+    /// Ends the sample started by [`Profiler::begin`].
+    pub(crate) fn end(&mut self) {
+        let time = (self.clock)().saturating_sub(self.start_time);
+        let sample = match self.samples.get_mut(self.stack.as_slice()) {
+            Some(x) => x,
+            None => self.samples.entry(self.stack.clone()).or_default(),
+        };
+        sample.instructions += 1;
+        sample.time += time;
+    }
+
+    /// Aggregates the collected samples into a [`ProfileReport`].
+    /// The `bytecode`, `locations`, and `script_info` must be the values returned by [`ByteCode::compile`] for the profiled project.
+    pub fn report(&self, bytecode: &ByteCode, locations: &Locations, script_info: &ScriptInfo) -> ProfileReport {
+        let funcs = func_names(script_info);
+
+        let mut total = Sample::default();
+        let mut blocks = BTreeMap::<String, Sample>::new();
+        let mut custom_blocks = BTreeMap::<&str, Sample>::new();
+        for (stack, sample) in self.samples.iter() {
+            let (pos, callers) = stack[1..].split_last().unwrap();
+            add_sample(&mut total, sample);
+            add_sample(blocks.entry(locations.lookup(*pos).unwrap_or_else(|| "<unknown>".into())).or_default(), sample);
+
+            let callees = callers.iter().filter_map(|&x| match Instruction::read(&bytecode.code, &bytecode.data, x).0 {
+                Instruction::Call { pos, .. } => funcs.get(&pos).map(String::as_str),
+                _ => None,
+            }).collect::<BTreeSet<_>>();
+            for callee in callees {
+                add_sample(custom_blocks.entry(callee).or_default(), sample);
+            }
+        }
+
+        fn finish<K: ToString>(samples: BTreeMap<K, Sample>) -> Vec<ProfileEntry> {
+            let mut res = samples.into_iter().map(|(name, x)| ProfileEntry { name: name.to_string(), instructions: x.instructions, time: x.time }).collect::<Vec<_>>();
+            res.sort_by(|a, b| b.time.cmp(&a.time).then(b.instructions.cmp(&a.instructions)).then(a.name.cmp(&b.name)));
+            res
+        }
+
+        ProfileReport {
+            total: ProfileEntry { name: "total".into(), instructions: total.instructions, time: total.time },
+            blocks: finish(blocks),
+            custom_blocks: finish(custom_blocks),
+        }
+    }
+    /// Renders the collected samples in the folded stack format, where each line is a semicolon-separated list of frames followed by a sample weight.
+    /// Frames alternate between the block that made a call and the script, custom block, or closure that was called, and end with the executing block.
+    /// The `bytecode`, `locations`, and `script_info` must be the values returned by [`ByteCode::compile`] for the profiled project.
+    pub fn folded(&self, bytecode: &ByteCode, locations: &Locations, script_info: &ScriptInfo, metric: ProfileMetric) -> String {
+        let mut units = func_names(script_info);
+        for (entity, info) in script_info.entities.iter() {
+            for (script, pos) in info.scripts.iter() {
+                units.insert(*pos, script_name(&entity.name, script, ""));
+            }
+            for (script, pos) in info.conditions.iter() {
+                units.insert(*pos, script_name(&entity.name, script, " condition"));
+            }
+        }
+        let block = |pos: usize| locations.lookup(pos).unwrap_or_else(|| "<unknown>".into());
+
+        let mut lines = BTreeMap::<String, u64>::new();
+        for (stack, sample) in self.samples.iter() {
+            let (start, rest) = stack.split_first().unwrap();
+            let (pos, callers) = rest.split_last().unwrap();
+
+            let mut frames = vec![units.get(start).cloned().unwrap_or_else(|| "<closure>".into())];
+            for &caller in callers {
+                frames.push(block(caller));
+                frames.push(match Instruction::read(&bytecode.code, &bytecode.data, caller).0 {
+                    Instruction::Call { pos, .. } => units.get(&pos).cloned().unwrap_or_else(|| "<unknown>".into()),
+                    _ => "<closure>".into(),
+                });
+            }
+            frames.push(block(*pos));
+
+            let line = frames.iter().map(|x| x.replace(';', ":")).collect::<Vec<_>>().join(";");
+            *lines.entry(line).or_default() += match metric {
+                ProfileMetric::Instructions => sample.instructions,
+                ProfileMetric::Time => sample.time,
+            };
+        }
+
+        let mut res = String::new();
+        for (line, weight) in lines {
+            res += &format!("{line} {weight}\n");
+        }
+        res
+    }
+}
+
+fn add_sample(dest: &mut Sample, src: &Sample) {
+    dest.instructions += src.instructions;
+    dest.time += src.time;
+}
+fn func_names(script_info: &ScriptInfo) -> BTreeMap<usize, String> {
+    let mut res = BTreeMap::new();
+    for (func, pos) in script_info.funcs.iter() {
+        res.insert(*pos, func_name(func));
+    }
+    for (entity, info) in script_info.entities.iter() {
+        for (func, pos) in info.funcs.iter() {
+            res.insert(*pos, format!("{}::{}", entity.name, func_name(func)));
+        }
+    }
+    res
+}
+fn func_name(func: &ast::Function) -> String {
+    func.name.replace('\t', "_") // tabs mark the positions of inputs
+}
+fn script_name(entity: &str, script: &ast::Script, suffix: &str) -> String {
+    match script.hat.as_ref().and_then(|x| x.info.location.as_deref()) {
+        Some(loc) => format!("{entity} script {loc}{suffix}"),
+        None => format!("{entity} script{suffix}"),
+    }
+}
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 use alloc::boxed::Box;
 use alloc::string::String;
-use alloc::collections::{VecDeque, BTreeMap};
+use alloc::collections::{VecDeque, BTreeMap, BTreeSet};
 use alloc::rc::Rc;
 
 use crate::*;
@@ -11,6 +11,7 @@ use crate::slotmap::*;
 use crate::runtime::*;
 use crate::bytecode::*;
 use crate::process::*;
+use crate::snapshot::*;
 
 new_key! {
     struct ProcessKey;
@@ -33,7 +34,7 @@ impl IdleAction {
     /// If the step resulting in an idle action, this may trigger the idle action to fire and reset the state machine.
     pub fn consume<C: CustomTypes<S>, S: System<C>>(&mut self, res: &ProjectStep<'_, C, S>) {
         match res {
-            ProjectStep::Idle | ProjectStep::Yield | ProjectStep::Pause => {
+            ProjectStep::Idle | ProjectStep::Yield | ProjectStep::FrameEnd | ProjectStep::Pause | ProjectStep::Break => {
                 self.count += 1;
                 if self.count >= self.thresh {
                     self.trigger();
@@ -50,14 +51,16 @@ impl IdleAction {
 }
 
 /// Simulates input from the user.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Input {
     /// Simulate pressing the start (green flag) button.
-    /// This has the effect of interrupting any running "on start" scripts and restarting them (with an empty context).
-    /// Any other running processes are not affected.
+    /// This has the effect of removing all clones and interrupting any running "on start" scripts and restarting them (with an empty context),
+    /// and resets the step budget of [`Settings::max_project_steps`](crate::runtime::Settings::max_project_steps).
+    /// Any other running processes (not owned by a clone) are not affected.
     Start,
     /// Simulate pressing the stop button.
-    /// This has the effect of stopping all currently-running processes.
+    /// This has the effect of stopping all currently-running processes, removing all clones,
+    /// and resetting the step budget of [`Settings::max_project_steps`](crate::runtime::Settings::max_project_steps).
     /// Note that some hat blocks could cause new processes to spin up after this operation.
     Stop,
     /// Simulates a key down hat from the keyboard.
@@ -94,6 +97,83 @@ pub enum ProjectStep<'gc, C: CustomTypes<S>, S: System<C>> {
     /// The project had a running process that requested to pause execution of the (entire) project.
     /// See [`ProcessStep::Pause`] for more details.
     Pause,
+    /// The project is stopped in the debugger, either due to reaching a breakpoint or finishing a debugger step (see [`StepMode`]).
+    /// The stopped process can be inspected with [`Project::get_paused_process`].
+    /// No code is executed until [`Project::resume`] is called, and until then all calls to [`Project::step`] return this value.
+    Break,
+    /// Every process that was running at the start of the current frame has had its turn (only used by [`Scheduling::Frames`]).
+    /// No code was executed, and the next step starts a new frame.
+    FrameEnd,
+}
+
+/// The ways in which the debugger can resume execution of a [`Project`] which is stopped at a break (see [`Project::resume`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    /// Run normally until a breakpoint is reached.
+    Continue,
+    /// Run the stopped process until it moves on to a different block, including entering a custom block or closure.
+    Step,
+    /// Like [`StepMode::Step`], but calls to custom blocks and closures are executed in their entirety as a single step.
+    StepOver,
+    /// Run the stopped process until it returns from the current custom block or closure.
+    StepOut,
+}
+
+struct StepRequest {
+    proc: ProcessKey,
+    mode: StepMode,
+    depth: usize, // call stack depth when the step started
+    block_starts: Vec<usize>, // sorted bytecode positions where a block begins
+    fresh: bool, // true until the process has executed since resuming
+}
+impl StepRequest {
+    fn is_done<C: CustomTypes<S>, S: System<C>>(&mut self, proc: &Process<'_, C, S>) -> bool {
+        if core::mem::replace(&mut self.fresh, false) { return false }
+
+        let depth = proc.get_call_stack().len();
+        let at_block = self.block_starts.binary_search(&proc.get_pos()).is_ok();
+        match self.mode {
+            StepMode::Continue => false,
+            StepMode::Step => at_block,
+            StepMode::StepOver => at_block && depth <= self.depth,
+            StepMode::StepOut => depth < self.depth,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    parked: BTreeMap<ProcessKey, usize>, // processes that already stopped at a breakpoint and have not moved since
+    paused: Option<ProcessKey>,
+    stepping: Option<StepRequest>,
+    break_requested: bool,
+}
+impl Debugger {
+    fn should_break<C: CustomTypes<S>, S: System<C>>(&mut self, key: ProcessKey, proc: &Process<'_, C, S>) -> bool {
+        let pos = proc.get_pos();
+        if self.parked.get(&key).map(|&x| x != pos).unwrap_or(false) {
+            self.parked.remove(&key);
+        }
+
+        let step_done = self.stepping.as_mut().map(|x| x.proc == key && x.is_done(proc)).unwrap_or(false);
+        let breakpoint = self.breakpoints.contains(&pos) && !self.parked.contains_key(&key);
+        if !step_done && !breakpoint && !self.break_requested { return false }
+
+        if self.breakpoints.contains(&pos) { self.parked.insert(key, pos); }
+        self.paused = Some(key);
+        self.stepping = None;
+        self.break_requested = false;
+        true
+    }
+}
+
+#[derive(Default)]
+struct Scheduler {
+    turn: Option<(ProcessKey, usize)>, // the process whose turn it is and the number of instructions it has executed during the turn
+    frame: Option<BTreeSet<ProcessKey>>, // processes that have not yet had a turn in the current frame/round (frame-based policies only)
+    priorities: BTreeMap<String, i32>, // entity name to priority (see [`Scheduling::Priority`])
+    process_priorities: BTreeMap<ProcessKey, i32>, // cached priority of each scheduled process (cleared when priorities change)
 }
 
 #[derive(Collect)]
@@ -103,6 +183,8 @@ struct Script<'gc, C: CustomTypes<S>, S: System<C>> {
                                entity: Gc<'gc, RefLock<Entity<'gc, C, S>>>,
     #[collect(require_static)] process: Option<ProcessKey>,
                                context_queue: VecDeque<ProcContext<'gc, C, S>>,
+                               condition_proc: Option<Process<'gc, C, S>>, // in-progress evaluation of the condition for [`Event::When`]
+    #[collect(require_static)] condition_state: bool, // result of the last condition evaluation (for edge triggering)
 }
 impl<'gc, C: CustomTypes<S>, S: System<C>> Script<'gc, C, S> {
     fn consume_context(&mut self, state: &mut State<'gc, C, S>) {
@@ -172,11 +254,18 @@ struct State<'gc, C: CustomTypes<S>, S: System<C>> {
 #[derive(Collect)]
 #[collect(no_drop, bound = "")]
 pub struct Project<'gc, C: CustomTypes<S>, S: System<C>> {
-    state: State<'gc, C, S>,
-    scripts: Vec<Script<'gc, C, S>>,
+                               state: State<'gc, C, S>,
+                               scripts: Vec<Script<'gc, C, S>>,
+                               clones: Vec<Gc<'gc, RefLock<Entity<'gc, C, S>>>>,
+    #[collect(require_static)] steps_till_poll: usize,
+    #[collect(require_static)] debugger: Debugger,
+    #[collect(require_static)] scheduler: Scheduler,
 }
 impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
-    pub fn from_init(mc: &Mutation<'gc>, init_info: &InitInfo, bytecode: Rc<ByteCode>, settings: Settings, system: Rc<S>) -> Self {
+    /// Creates a new project from the output of [`ByteCode::compile`] (or any other source, such as deserialization).
+    /// The code is checked with [`ByteCode::verify`] before anything is loaded.
+    pub fn from_init(mc: &Mutation<'gc>, init_info: &InitInfo, bytecode: Rc<ByteCode>, settings: Settings, system: Rc<S>) -> Result<Self, VerifyError> {
+        bytecode.verify(init_info)?;
         let global_context = GlobalContext::from_init(mc, init_info, bytecode, settings, system);
         let mut project = Self::new(Gc::new(mc, RefLock::new(global_context)));
 
@@ -187,7 +276,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
             }
         }
 
-        project
+        Ok(project)
     }
     pub fn new(global_context: Gc<'gc, RefLock<GlobalContext<'gc, C, S>>>) -> Self {
         Self {
@@ -197,7 +286,116 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                 process_queue: Default::default(),
             },
             scripts: Default::default(),
+            clones: Default::default(),
+            steps_till_poll: 0,
+            debugger: Default::default(),
+            scheduler: Default::default(),
+        }
+    }
+    /// Restores a project from a snapshot previously taken with [`Project::snapshot`].
+    /// The `bytecode` must be the same bytecode that the original project was running, and is checked against the snapshot before anything is loaded.
+    pub fn from_snapshot(mc: &Mutation<'gc>, snapshot: &ProjectSnapshot<C::EntityState>, bytecode: Rc<ByteCode>, settings: Settings, system: Rc<S>) -> Result<Self, SnapshotError> where C::EntityState: Clone {
+        snapshot.verify(&bytecode).map_err(|error| SnapshotError::VerifyError { error })?;
+        let loader = SnapshotLoader::new(mc, snapshot)?;
+
+        let global_context = GlobalContext {
+            bytecode, settings, system,
+            timer_start: snapshot.timer_start,
+            proj_name: snapshot.proj_name.clone(),
+            held_keys: snapshot.held_keys.iter().copied().collect(),
+            profiler: None,
+            steps: 0,
+            key_index: Default::default(),
+            globals: loader.load_symbols(&snapshot.globals)?,
+            entities: snapshot.entities.iter().map(|(name, idx)| Ok((name.clone(), loader.load_entity(*idx)?))).collect::<Result<_,_>>()?,
+        };
+        let mut project = Self::new(Gc::new(mc, RefLock::new(global_context)));
+
+        let mut process_keys = Vec::with_capacity(snapshot.processes.len());
+        for process in snapshot.processes.iter() {
+            process_keys.push(project.state.processes.insert(Process::load(project.state.global_context, process, &loader)?));
+        }
+        let process_key = |idx: usize| process_keys.get(idx).copied().ok_or(SnapshotError::BadReference { index: idx });
+
+        for idx in snapshot.process_queue.iter() {
+            project.state.process_queue.push_back(process_key(*idx)?);
+        }
+        for script in snapshot.scripts.iter() {
+            let mut context_queue = VecDeque::with_capacity(script.context_queue.len());
+            for context in script.context_queue.iter() {
+                context_queue.push_back(ProcContext {
+                    locals: loader.load_symbols(&context.locals)?,
+                    barrier: context.barrier.map(|x| loader.load_barrier(x)).transpose()?,
+                    reply_key: None,
+                    local_message: context.local_message.clone(),
+                });
+            }
+            project.scripts.push(Script {
+                event: Rc::new((script.event.clone(), script.start_pos)),
+                entity: loader.load_entity(script.entity)?,
+                process: script.process.map(process_key).transpose()?,
+                context_queue,
+                condition_proc: script.condition_proc.as_ref().map(|x| Process::load(project.state.global_context, x, &loader)).transpose()?,
+                condition_state: script.condition_state,
+            });
+        }
+        project.clones = snapshot.clones.iter().map(|x| loader.load_entity(*x)).collect::<Result<_,_>>()?;
+        project.steps_till_poll = snapshot.steps_till_poll;
+
+        Ok(project)
+    }
+    /// Captures the full execution state of the project (global/entity state, processes, and queued script contexts).
+    /// The result can later be restored with [`Project::from_snapshot`], even in a different [`Arena`](crate::gc::Arena).
+    /// 
+    /// Pending asynchronous operations (RPCs, syscalls, etc.) cannot be resumed and are restored as failed/interrupted,
+    /// and message reply keys are discarded.
+    pub fn snapshot(&self) -> Result<ProjectSnapshot<C::EntityState>, SnapshotError> where C::EntityState: Clone {
+        let mut saver = SnapshotSaver::new();
+        let global_context = self.state.global_context.borrow();
+
+        let globals = saver.save_symbols(&global_context.globals)?;
+        let entities = global_context.entities.iter().map(|(name, entity)| Ok((name.clone(), saver.save_entity(*entity)?))).collect::<Result<_,_>>()?;
+
+        let mut process_indices = BTreeMap::new();
+        let mut processes = Vec::with_capacity(self.state.processes.len());
+        for (key, process) in self.state.processes.iter() {
+            process_indices.insert(key, processes.len());
+            processes.push(process.save(&mut saver)?);
+        }
+        let process_queue = self.state.process_queue.iter().filter_map(|key| process_indices.get(key).copied()).collect();
+
+        let mut scripts = Vec::with_capacity(self.scripts.len());
+        for script in self.scripts.iter() {
+            let mut context_queue = Vec::with_capacity(script.context_queue.len());
+            for context in script.context_queue.iter() {
+                context_queue.push(ProcContextSnapshot {
+                    locals: saver.save_symbols(&context.locals)?,
+                    barrier: context.barrier.as_ref().map(|x| saver.save_barrier(x)),
+                    local_message: context.local_message.clone(),
+                });
+            }
+            scripts.push(ScriptSnapshot {
+                event: script.event.0.clone(),
+                start_pos: script.event.1,
+                entity: saver.save_entity(script.entity)?,
+                process: script.process.and_then(|key| process_indices.get(&key).copied()),
+                context_queue,
+                condition_proc: script.condition_proc.as_ref().map(|x| x.save(&mut saver)).transpose()?,
+                condition_state: script.condition_state,
+            });
         }
+
+        let clones = self.clones.iter().map(|x| saver.save_entity(*x)).collect::<Result<_,_>>()?;
+
+        let (refs, blobs, barriers) = saver.finish();
+        Ok(ProjectSnapshot {
+            refs, blobs, barriers,
+            proj_name: global_context.proj_name.clone(),
+            timer_start: global_context.timer_start,
+            held_keys: global_context.held_keys.iter().map(|(k, v)| (*k, *v)).collect(),
+            globals, entities, processes, process_queue, scripts, clones,
+            steps_till_poll: self.steps_till_poll,
+        })
     }
     pub fn add_script(&mut self, start_pos: usize, entity: Gc<'gc, RefLock<Entity<'gc, C, S>>>, event: Option<Event>) {
         let mut all_contexts_consumer = AllContextsConsumer::new();
@@ -207,6 +405,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                 entity,
                 process: None,
                 context_queue: Default::default(),
+                condition_proc: None,
+                condition_state: false,
             }),
             None => {
                 let process = Process::new(self.state.global_context, entity, start_pos);
@@ -221,6 +421,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
         let mut all_contexts_consumer = AllContextsConsumer::new();
         match input {
             Input::Start => {
+                self.state.global_context.borrow_mut(mc).steps = 0;
+                self.remove_clones(mc);
                 for i in 0..self.scripts.len() {
                     if let Event::OnFlag = &self.scripts[i].event.0 {
                         all_contexts_consumer.do_once(self); // need to consume all contexts before scheduling things in the future
@@ -248,13 +450,15 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                 }
             }
             Input::Stop => {
-                for script in self.scripts.iter_mut() {
-                    script.stop_all(&mut self.state);
-                }
-                self.state.processes.clear();
-                self.state.process_queue.clear();
+                self.state.global_context.borrow_mut(mc).steps = 0;
+                self.stop_all(mc);
             }
             Input::KeyDown { key: input_key } => {
+                let mut global_context = self.state.global_context.borrow_mut(mc);
+                let now = global_context.system.time().to_arbitrary_ms::<C, S>().unwrap_or(0);
+                global_context.held_keys.insert(input_key, now);
+                drop(global_context);
+
                 for i in 0..self.scripts.len() {
                     if let Event::OnKey { key_filter } = &self.scripts[i].event.0 {
                         if key_filter.map(|x| x == input_key).unwrap_or(true) {
@@ -264,10 +468,51 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                     }
                 }
             }
-            Input::KeyUp { .. } => unimplemented!(),
+            Input::KeyUp { key } => {
+                self.state.global_context.borrow_mut(mc).held_keys.remove(&key);
+            }
+        }
+    }
+    /// Stops all running processes, clears all queued script contexts, removes all clones, and resets the debugger.
+    /// This is the shared implementation of [`Input::Stop`] and the stop all block.
+    fn stop_all(&mut self, mc: &Mutation<'gc>) {
+        self.debugger.paused = None;
+        self.debugger.stepping = None;
+        for script in self.scripts.iter_mut() {
+            script.stop_all(&mut self.state);
+            script.condition_proc = None;
         }
+        self.state.processes.clear();
+        self.state.process_queue.clear();
+        self.remove_clones(mc);
+    }
+    /// Removes all clones along with their scripts and processes, and resets the clone count of every original entity.
+    fn remove_clones(&mut self, mc: &Mutation<'gc>) {
+        for clone in self.clones.drain(..) {
+            clone.borrow_mut(mc).alive = false;
+        }
+        for entity in self.state.global_context.borrow().entities.values() {
+            entity.borrow_mut(mc).clone_count = 0;
+        }
+
+        let is_clone = |entity: Gc<'gc, RefLock<Entity<'gc, C, S>>>| entity.borrow().root.is_some();
+        for script in self.scripts.iter_mut() {
+            if is_clone(script.entity) { script.stop_all(&mut self.state); }
+        }
+        self.scripts.retain(|script| !is_clone(script.entity));
+
+        let stopped: Vec<_> = self.state.processes.iter().filter(|x| x.1.get_call_stack().first().map(|x| is_clone(x.entity)).unwrap_or(false)).map(|x| x.0).collect();
+        for key in stopped {
+            self.state.processes.remove(key);
+        }
+        self.state.process_queue.retain(|&key| self.state.processes.get(key).is_some());
     }
     pub fn step(&mut self, mc: &Mutation<'gc>) -> ProjectStep<'gc, C, S> {
+        if let Some(key) = self.debugger.paused {
+            if self.state.processes.get(key).is_some() { return ProjectStep::Break }
+            self.debugger.paused = None;
+        }
+
         let mut all_contexts_consumer = AllContextsConsumer::new();
 
         let msg = self.state.global_context.borrow().system.receive_message();
@@ -290,17 +535,40 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
             }
         }
 
-        let (proc_key, proc) = loop {
-            match self.state.process_queue.pop_front() {
-                None => {
+        if self.steps_till_poll == 0 {
+            self.steps_till_poll = self.state.global_context.borrow().settings.condition_poll_interval.saturating_sub(1);
+            if let Some(res) = self.poll_conditions(mc, &mut all_contexts_consumer) {
+                return res;
+            }
+        } else {
+            self.steps_till_poll -= 1;
+        }
+
+        let scheduling = self.state.global_context.borrow().settings.scheduling;
+        let proc_key = match self.next_process(scheduling) {
+            Some(x) => x,
+            None => {
+                if self.state.process_queue.is_empty() {
                     debug_assert!(self.scripts.iter().all(|x| x.context_queue.is_empty()));
                     return ProjectStep::Idle;
                 }
-                Some(proc_key) => if let Some(proc) = self.state.processes.get_mut(proc_key) { break (proc_key, proc) }
+                return ProjectStep::FrameEnd;
             }
         };
+        let proc = self.state.processes.get_mut(proc_key).unwrap();
+
+        if self.debugger.should_break(proc_key, proc) {
+            self.state.process_queue.push_front(proc_key);
+            return ProjectStep::Break;
+        }
 
-        match proc.step(mc) {
+        let turn_steps = match self.scheduler.turn {
+            Some((key, steps)) if key == proc_key => steps + 1,
+            _ => 1,
+        };
+        self.scheduler.turn = Some((proc_key, turn_steps));
+
+        let mut res = match proc.step(mc) {
             Ok(x) => match x {
                 ProcessStep::Normal => {
                     self.state.process_queue.push_front(proc_key);
@@ -339,6 +607,8 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                                 entity: new_entity,
                                 process: None,
                                 context_queue: Default::default(),
+                                condition_proc: None,
+                                condition_state: false,
                             });
                         }
                     }
@@ -349,9 +619,42 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                         }
                     }
                     self.scripts.extend(new_scripts);
+                    self.clones.push(new_entity);
                     self.state.process_queue.push_front(proc_key); // keep executing the same process as before
                     ProjectStep::Normal
                 }
+                ProcessStep::DeletedClone { entity } => {
+                    let is_owned = |proc: &Process<'gc, C, S>| proc.get_call_stack().first().map(|x| Gc::ptr_eq(x.entity, entity)).unwrap_or(false);
+                    let proc = match self.state.processes.get(proc_key).map(is_owned).unwrap_or(false) {
+                        true => self.state.processes.remove(proc_key),
+                        false => None,
+                    };
+
+                    for script in self.scripts.iter_mut() {
+                        if Gc::ptr_eq(script.entity, entity) {
+                            script.stop_all(&mut self.state);
+                        }
+                    }
+                    self.scripts.retain(|script| !Gc::ptr_eq(script.entity, entity));
+                    self.clones.retain(|clone| !Gc::ptr_eq(*clone, entity));
+
+                    let stopped: Vec<_> = self.state.processes.iter().filter(|x| is_owned(x.1)).map(|x| x.0).collect();
+                    for key in stopped {
+                        self.state.processes.remove(key);
+                    }
+                    self.state.process_queue.retain(|&key| self.state.processes.get(key).is_some());
+
+                    match proc {
+                        Some(proc) => {
+                            all_contexts_consumer.do_once(self); // need to consume all contexts after dropping a process
+                            ProjectStep::ProcessTerminated { result: None, proc }
+                        }
+                        None => {
+                            self.state.process_queue.push_front(proc_key); // keep executing the same process as before
+                            ProjectStep::Normal
+                        }
+                    }
+                }
                 ProcessStep::Broadcast { msg_type, barrier, targets } => {
                     for i in 0..self.scripts.len() {
                         if let Event::LocalMessage { msg_type: recv_type } = &self.scripts[i].event.0 {
@@ -371,6 +674,30 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                     self.state.process_queue.push_front(proc_key); // keep executing same process, if it was a wait, it'll yield next step
                     ProjectStep::Normal
                 }
+                ProcessStep::StopAll => {
+                    let proc = self.state.processes.remove(proc_key).unwrap();
+                    self.stop_all(mc);
+                    all_contexts_consumer.do_once(self); // need to consume all contexts after dropping a process
+                    ProjectStep::ProcessTerminated { result: None, proc }
+                }
+                ProcessStep::StopOthers { entity } => {
+                    let is_target = |target: Gc<'gc, RefLock<Entity<'gc, C, S>>>| match entity {
+                        Some(entity) => Gc::ptr_eq(target, entity) || target.borrow().root.map(|root| Gc::ptr_eq(root, entity)).unwrap_or(false),
+                        None => true,
+                    };
+                    for script in self.scripts.iter_mut() {
+                        if script.process != Some(proc_key) && is_target(script.entity) {
+                            script.stop_all(&mut self.state);
+                        }
+                    }
+                    let stopped: Vec<_> = self.state.processes.iter().filter(|(key, proc)| *key != proc_key && proc.get_call_stack().first().map(|x| is_target(x.entity)).unwrap_or(false)).map(|x| x.0).collect();
+                    for key in stopped {
+                        self.state.processes.remove(key);
+                    }
+                    self.state.process_queue.retain(|&key| key != proc_key && self.state.processes.get(key).is_some());
+                    self.state.process_queue.push_front(proc_key); // keep executing the same process as before
+                    ProjectStep::Normal
+                }
                 ProcessStep::Terminate { result } => {
                     let proc = self.state.processes.remove(proc_key).unwrap();
                     all_contexts_consumer.do_once(self); // need to consume all contexts after dropping a process
@@ -383,9 +710,196 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Project<'gc, C, S> {
                 all_contexts_consumer.do_once(self); // need to consume all contexts after dropping a process
                 ProjectStep::Error { error, proc }
             }
+        };
+
+        let quantum = match scheduling {
+            Scheduling::RoundRobin { quantum } | Scheduling::Priority { quantum } => Some(quantum),
+            Scheduling::UntilYield | Scheduling::Frames => None,
+        };
+        let mut turn_over = matches!(res, ProjectStep::Yield | ProjectStep::ProcessTerminated { .. } | ProjectStep::Error { .. });
+        if !turn_over && quantum.map(|x| turn_steps >= x).unwrap_or(false) && self.state.process_queue.front() == Some(&proc_key) {
+            all_contexts_consumer.do_once(self); // need to consume all contexts before scheduling things in the future
+            self.state.process_queue.pop_front();
+            self.state.process_queue.push_back(proc_key);
+            if let ProjectStep::Normal = res { res = ProjectStep::Yield }
+            turn_over = true;
         }
+        if turn_over {
+            self.scheduler.turn = None;
+            if let Some(frame) = &mut self.scheduler.frame { frame.remove(&proc_key); }
+        }
+        res
+    }
+    /// Removes the next process to run from the process queue, according to the given scheduling policy.
+    /// Returns [`None`] if there are no processes, or if the current frame has ended (see [`ProjectStep::FrameEnd`]).
+    fn next_process(&mut self, scheduling: Scheduling) -> Option<ProcessKey> {
+        let state = &mut self.state;
+        match scheduling {
+            Scheduling::UntilYield | Scheduling::RoundRobin { .. } => loop {
+                let key = state.process_queue.pop_front()?;
+                if state.processes.get(key).is_some() { return Some(key) }
+            }
+            Scheduling::Priority { .. } | Scheduling::Frames => {
+                if let Some((key, _)) = self.scheduler.turn {
+                    if state.process_queue.front() == Some(&key) && state.processes.get(key).is_some() {
+                        return state.process_queue.pop_front();
+                    }
+                }
+
+                if state.process_queue.is_empty() {
+                    self.scheduler.frame = None;
+                    return None;
+                }
+
+                let (priorities, process_priorities) = (&self.scheduler.priorities, &mut self.scheduler.process_priorities);
+                let frame = self.scheduler.frame.get_or_insert_with(|| {
+                    process_priorities.retain(|&key, _| state.processes.get(key).is_some());
+                    state.process_queue.iter().copied().collect()
+                });
+
+                let mut next: Option<(usize, i32)> = None;
+                let mut dead = false;
+                for (i, &key) in state.process_queue.iter().enumerate() {
+                    let proc = match state.processes.get(key) {
+                        Some(x) => x,
+                        None => { dead = true; continue }
+                    };
+                    if !frame.contains(&key) { continue }
+
+                    let priority = match scheduling {
+                        Scheduling::Priority { .. } => *process_priorities.entry(key).or_insert_with(|| proc.get_call_stack().first().map(|entry| {
+                            let entity = entry.entity.borrow();
+                            let root = entity.root.map(|x| x.borrow());
+                            priorities.get(root.as_ref().map(|x| &x.name).unwrap_or(&entity.name).as_str()).copied().unwrap_or(0)
+                        }).unwrap_or(0)),
+                        _ => 0,
+                    };
+                    if next.map(|x| priority > x.1).unwrap_or(true) { next = Some((i, priority)); }
+                }
+                if dead {
+                    state.process_queue.retain(|&key| state.processes.get(key).is_some());
+                    return self.next_process(scheduling);
+                }
+                match next {
+                    Some((i, _)) => state.process_queue.remove(i),
+                    None => {
+                        self.scheduler.frame = None;
+                        match scheduling {
+                            Scheduling::Frames => None,
+                            _ => self.next_process(scheduling),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Sets the priority of the processes of an entity (and its clones) for [`Scheduling::Priority`] (default `0`).
+    /// Processes of entities with higher priority take their turns first in each round.
+    pub fn set_priority(&mut self, entity: &str, priority: i32) {
+        self.scheduler.priorities.insert(entity.into(), priority);
+        self.scheduler.process_priorities.clear();
+    }
+    /// Evaluates the conditions of all [`Event::When`] scripts and schedules any whose condition has just become true.
+    /// Condition evaluations that need to wait (e.g., for an async request) or that exceed [`Settings::condition_poll_steps`] are resumed the next time conditions are polled.
+    /// Watchers and pauses requested by condition code are returned, while other requests (e.g., broadcasts or forks) fail with [`ErrorCause::ConditionSideEffect`].
+    /// If a condition fails with an error, evaluation stops and the error is returned.
+    fn poll_conditions(&mut self, mc: &Mutation<'gc>, all_contexts_consumer: &mut AllContextsConsumer) -> Option<ProjectStep<'gc, C, S>> {
+        let global_context = self.state.global_context;
+        for i in 0..self.scripts.len() {
+            let script = &mut self.scripts[i];
+            let condition = match &script.event.0 {
+                Event::When { condition } => *condition,
+                _ => continue,
+            };
+
+            let proc = script.condition_proc.get_or_insert_with(|| {
+                let mut proc = Process::new(global_context, script.entity, condition);
+                proc.initialize(Default::default());
+                proc
+            });
+            let max_steps = global_context.borrow().settings.condition_poll_steps;
+            let mut steps = 0;
+            let state = loop {
+                if steps >= max_steps { break None }
+                steps += 1;
+
+                let error = match proc.step(mc) {
+                    Ok(ProcessStep::Normal) => continue,
+                    Ok(ProcessStep::Yield) => break None,
+                    Ok(ProcessStep::Terminate { result }) => break Some(matches!(result, Some(Value::Bool(true)))),
+                    Ok(ProcessStep::StopAll) => break Some(false),
+                    Ok(ProcessStep::Watcher { create, watcher }) => return Some(ProjectStep::Watcher { create, watcher }), // evaluation resumes next poll
+                    Ok(ProcessStep::Pause) => return Some(ProjectStep::Pause), // evaluation resumes next poll
+                    Ok(ProcessStep::Idle) => unreachable!(),
+                    Ok(ProcessStep::Broadcast { .. } | ProcessStep::Fork { .. } | ProcessStep::CreatedClone { .. } | ProcessStep::DeletedClone { .. } | ProcessStep::StopOthers { .. }) => {
+                        ExecError { cause: ErrorCause::ConditionSideEffect, pos: proc.get_pos() }
+                    }
+                    Err(error) => error,
+                };
+                script.condition_state = false;
+                return Some(ProjectStep::Error { error, proc: script.condition_proc.take().unwrap() });
+            };
+            let state = match state {
+                Some(x) => x,
+                None => continue, // not done evaluating yet, so check again next time
+            };
+
+            script.condition_proc = None;
+            let fire = state && !script.condition_state;
+            script.condition_state = state;
+            if fire {
+                all_contexts_consumer.do_once(self); // need to consume all contexts before scheduling things in the future
+                self.scripts[i].schedule(&mut self.state, Default::default(), 0);
+            }
+        }
+        None
     }
     pub fn get_global_context(&self) -> Gc<'gc, RefLock<GlobalContext<'gc, C, S>>> {
         self.state.global_context
     }
+    /// Sets a breakpoint on the block with the given location (e.g., collab id) from the ast.
+    /// Any process that reaches the block will stop (see [`ProjectStep::Break`]) before executing it.
+    /// Returns `false` if there was no code for the given location.
+    pub fn add_breakpoint(&mut self, locations: &Locations, loc: &str) -> bool {
+        let positions = locations.find(loc);
+        self.debugger.breakpoints.extend(positions.iter().copied());
+        !positions.is_empty()
+    }
+    /// Removes a breakpoint previously set by [`Project::add_breakpoint`].
+    pub fn remove_breakpoint(&mut self, locations: &Locations, loc: &str) {
+        for pos in locations.find(loc) {
+            self.debugger.breakpoints.remove(&pos);
+        }
+    }
+    /// Removes all breakpoints.
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.breakpoints.clear();
+    }
+    /// Requests that the debugger stop (see [`ProjectStep::Break`]) before the next block executed by any process.
+    pub fn request_break(&mut self) {
+        self.debugger.break_requested = true;
+    }
+    /// Gets the process which is stopped in the debugger, if any (see [`ProjectStep::Break`]).
+    /// This can be used to inspect the current position, value stack, and local variables of the process.
+    pub fn get_paused_process(&self) -> Option<&Process<'gc, C, S>> {
+        self.state.processes.get(self.debugger.paused?)
+    }
+    /// Resumes execution of a project which is stopped in the debugger.
+    /// This has no effect if the project is not stopped.
+    pub fn resume(&mut self, locations: &Locations, mode: StepMode) {
+        let key = match self.debugger.paused.take() {
+            Some(x) => x,
+            None => return,
+        };
+        let proc = match self.state.processes.get(key) {
+            Some(x) => x,
+            None => return,
+        };
+
+        self.debugger.stepping = match mode {
+            StepMode::Continue => None,
+            _ => Some(StepRequest { proc: key, mode, depth: proc.get_call_stack().len(), block_starts: locations.block_starts().collect(), fresh: true }),
+        };
+        self.debugger.parked.retain(|&key, _| self.state.processes.get(key).is_some());
+    }
 }
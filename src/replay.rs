@@ -0,0 +1,565 @@
+//! Deterministic recording and replay of project executions.
+//!
+//! Running a project involves several sources of nondeterminism: user inputs, the results (and timing) of asynchronous requests such as RPCs,
+//! random numbers, the system time, and incoming messages.
+//! A [`Recorder`] logs each of these as a stream of [`ReplayEvent`] values, which can later be loaded as a [`Recording`]
+//! and fed back into the project through a [`Replayer`] to reproduce the exact same execution.
+//!
+//! Nondeterminism enters the runtime through a [`System`], so [`System`] implementations are responsible for calling into
+//! a [`Recorder`] or [`Replayer`] at each nondeterministic operation.
+//! The simplest way to do this is to hold a [`ReplayMode`] and pass each operation through it, as
+//! [`StdSystem`](crate::std_system::StdSystem) does to support both recording and replay out of the box.
+//!
+//! This cannot be done by a generic wrapper type around an existing [`System`]:
+//! every runtime type that crosses the [`System`] interface ([`Value`], [`Entity`], [`Request`], [`Command`], [`ErrorCause`], ...)
+//! is parameterized by the concrete [`System`] type itself, so a wrapper would receive values typed for the wrapper
+//! which it has no way of handing to the wrapped system (and vice versa for the results).
+//! Additionally, [`System::rand`] is generic over the sampled type, so random numbers can only be reproduced by recording
+//! the seed of the system's random number generator ([`ReplayEvent::Seed`]) rather than the generated values.
+//! Project inputs do not go through the [`System`], so the driver of the project must record them with [`Recorder::record_input`]
+//! and feed them back with [`Replayer::take_inputs`].
+//!
+//! Events are tagged with the number of calls to [`Project::step`](crate::project::Project::step) that had been made when they occurred,
+//! which the driver must keep up to date with [`Recorder::set_step`].
+//! Replay is only guaranteed to reproduce the original execution if the same project is run with the same [`Settings`].
+
+use alloc::string::{String, ToString};
+use alloc::collections::VecDeque;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::rc::Rc;
+
+use core::cell::{Cell, RefCell};
+
+use crate::*;
+use crate::gc::*;
+use crate::json::*;
+use crate::real_time::*;
+use crate::runtime::*;
+use crate::project::*;
+use crate::util::{to_hex, from_hex};
+
+/// An error from loading a [`Recording`].
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The given line of the recording was not valid json.
+    BadJson { line: usize },
+    /// The given line of the recording was not a valid [`ReplayEvent`].
+    BadEvent { line: usize },
+}
+
+/// A recorded value of [`SysTime`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTime {
+    Timeless,
+    Arbitrary { ms: u64 },
+    Real { unix_nanos: i128, offset_secs: i32 },
+}
+impl From<&SysTime> for ReplayTime {
+    fn from(time: &SysTime) -> Self {
+        match time {
+            SysTime::Timeless => ReplayTime::Timeless,
+            SysTime::Arbitrary { ms } => ReplayTime::Arbitrary { ms: *ms },
+            SysTime::Real { local } => ReplayTime::Real { unix_nanos: local.unix_timestamp_nanos(), offset_secs: local.offset().whole_seconds() },
+        }
+    }
+}
+impl ReplayTime {
+    /// Converts the recorded time back into a [`SysTime`].
+    pub fn to_sys_time(&self) -> SysTime {
+        match *self {
+            ReplayTime::Timeless => SysTime::Timeless,
+            ReplayTime::Arbitrary { ms } => SysTime::Arbitrary { ms },
+            ReplayTime::Real { unix_nanos, offset_secs } => match (OffsetDateTime::from_unix_timestamp_nanos(unix_nanos), UtcOffset::from_whole_seconds(offset_secs)) {
+                (Ok(time), Ok(offset)) => SysTime::Real { local: time.to_offset(offset) },
+                _ => SysTime::Timeless,
+            }
+        }
+    }
+    fn to_json(self) -> Json {
+        match self {
+            ReplayTime::Timeless => Json::Null,
+            ReplayTime::Arbitrary { ms } => json!({ "ms": ms }),
+            ReplayTime::Real { unix_nanos, offset_secs } => json!({ "nanos": unix_nanos.to_string(), "offset": offset_secs }),
+        }
+    }
+    fn from_json(value: &Json) -> Option<Self> {
+        Some(match value {
+            Json::Null => ReplayTime::Timeless,
+            _ => match value.get("ms") {
+                Some(ms) => ReplayTime::Arbitrary { ms: ms.as_u64()? },
+                None => ReplayTime::Real { unix_nanos: value.get("nanos")?.as_str()?.parse().ok()?, offset_secs: value.get("offset")?.as_i64()?.try_into().ok()? },
+            }
+        })
+    }
+}
+
+/// A recorded value returned by a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayValue {
+    Json(Json),
+    Image(Vec<u8>),
+    Audio(Vec<u8>),
+}
+impl ReplayValue {
+    /// Records a value, or returns [`None`] if the value cannot be recorded (e.g., native values or entities).
+    pub fn from_value<C: CustomTypes<S>, S: System<C>>(value: &Value<'_, C, S>) -> Option<Self> {
+        Some(match value {
+            Value::Image(x) => ReplayValue::Image((**x).clone()),
+            Value::Audio(x) => ReplayValue::Audio((**x).clone()),
+            x => ReplayValue::Json(x.to_json().ok()?),
+        })
+    }
+    /// Converts the recorded value back into a [`Value`].
+    pub fn to_value<'gc, C: CustomTypes<S>, S: System<C>>(&self, mc: &Mutation<'gc>) -> Result<Value<'gc, C, S>, FromJsonError> {
+        Ok(match self {
+            ReplayValue::Json(x) => Value::from_json(mc, x.clone())?,
+            ReplayValue::Image(x) => Value::Image(Rc::new(x.clone())),
+            ReplayValue::Audio(x) => Value::Audio(Rc::new(x.clone())),
+        })
+    }
+    fn to_json(&self) -> Json {
+        match self {
+            ReplayValue::Json(x) => json!({ "json": x }),
+            ReplayValue::Image(x) => json!({ "image": to_hex(x) }),
+            ReplayValue::Audio(x) => json!({ "audio": to_hex(x) }),
+        }
+    }
+    fn from_json(value: &Json) -> Option<Self> {
+        if let Some(x) = value.get("json") { return Some(ReplayValue::Json(x.clone())) }
+        if let Some(x) = value.get("image") { return Some(ReplayValue::Image(from_hex(x.as_str()?)?)) }
+        if let Some(x) = value.get("audio") { return Some(ReplayValue::Audio(from_hex(x.as_str()?)?)) }
+        None
+    }
+}
+
+fn input_to_json(input: &Input) -> Json {
+    match input {
+        Input::Start => json!("start"),
+        Input::Stop => json!("stop"),
+        Input::KeyDown { key } => json!({ "keydown": key.name() }),
+        Input::KeyUp { key } => json!({ "keyup": key.name() }),
+        Input::CustomEvent { name, args, interrupt, max_queue } => json!({ "custom": { "name": name, "args": args, "interrupt": interrupt, "max_queue": max_queue } }),
+    }
+}
+fn input_from_json(value: &Json) -> Option<Input> {
+    Some(match value {
+        Json::String(x) if x == "start" => Input::Start,
+        Json::String(x) if x == "stop" => Input::Stop,
+        _ => {
+            if let Some(key) = value.get("keydown") { return Some(Input::KeyDown { key: KeyCode::from_name(key.as_str()?)? }) }
+            if let Some(key) = value.get("keyup") { return Some(Input::KeyUp { key: KeyCode::from_name(key.as_str()?)? }) }
+            let custom = value.get("custom")?;
+            Input::CustomEvent {
+                name: custom.get("name")?.as_str()?.into(),
+                args: custom.get("args")?.as_object()?.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                interrupt: custom.get("interrupt")?.as_bool()?,
+                max_queue: custom.get("max_queue")?.as_u64()?.try_into().ok()?,
+            }
+        }
+    })
+}
+
+fn result_to_json<T>(result: &Result<T, String>, f: impl FnOnce(&T) -> Json) -> Json {
+    match result {
+        Ok(x) => json!({ "ok": f(x) }),
+        Err(x) => json!({ "err": x }),
+    }
+}
+fn result_from_json<T>(value: &Json, f: impl FnOnce(&Json) -> Option<T>) -> Option<Result<T, String>> {
+    match (value.get("ok"), value.get("err")) {
+        (Some(x), None) => Some(Ok(f(x)?)),
+        (None, Some(x)) => Some(Err(x.as_str()?.into())),
+        _ => None,
+    }
+}
+
+/// A recorded incoming message, consisting of the message type, the field values, and whether or not a reply is expected.
+pub type ReplayMessage = (String, Vec<(String, Json)>, bool);
+
+/// A single nondeterministic event that occurred while running a project.
+///
+/// The `poll` field of the result events is the (zero-based) index of the call to the corresponding [`System`] polling function which observed the result.
+/// Calls that did not observe anything (e.g., a still-pending request) are not recorded.
+#[derive(Debug, Clone)]
+pub enum ReplayEvent {
+    /// The seed used for the random number generator, which must be recorded before any random numbers are generated.
+    Seed { seed: [u8; 32] },
+    /// An [`Input`] given to the project.
+    Input { input: Input },
+    /// A result of [`System::time`].
+    Time { time: ReplayTime },
+    /// A call to [`System::perform_request`] and whether or not the request was handled (i.e., supported).
+    Request { handled: bool },
+    /// A completed result of [`System::poll_request`].
+    RequestResult { poll: u64, result: Result<ReplayValue, String> },
+    /// A completed result of [`System::poll_command`].
+    CommandResult { poll: u64, result: Result<(), String> },
+    /// A message received from [`System::receive_message`].
+    Message { poll: u64, msg_type: String, values: Vec<(String, Json)>, expect_reply: bool },
+    /// A completed result of [`System::poll_reply`].
+    Reply { poll: u64, value: Option<Json> },
+    /// A marker with no effect, which can be recorded periodically so that replays know how many steps the original run performed.
+    Mark,
+}
+impl ReplayEvent {
+    /// Encodes the event (which occurred at the given step) as json.
+    pub fn to_json(&self, step: u64) -> Json {
+        match self {
+            ReplayEvent::Seed { seed } => json!({ "step": step, "type": "seed", "seed": to_hex(seed) }),
+            ReplayEvent::Input { input } => json!({ "step": step, "type": "input", "input": input_to_json(input) }),
+            ReplayEvent::Time { time } => json!({ "step": step, "type": "time", "time": time.to_json() }),
+            ReplayEvent::Request { handled } => json!({ "step": step, "type": "request", "handled": handled }),
+            ReplayEvent::RequestResult { poll, result } => json!({ "step": step, "type": "request-result", "poll": poll, "result": result_to_json(result, ReplayValue::to_json) }),
+            ReplayEvent::CommandResult { poll, result } => json!({ "step": step, "type": "command-result", "poll": poll, "result": result_to_json(result, |_| Json::Null) }),
+            ReplayEvent::Message { poll, msg_type, values, expect_reply } => json!({ "step": step, "type": "message", "poll": poll, "msg_type": msg_type, "values": values, "expect_reply": expect_reply }),
+            ReplayEvent::Reply { poll, value } => json!({ "step": step, "type": "reply", "poll": poll, "value": value }),
+            ReplayEvent::Mark => json!({ "step": step, "type": "mark" }),
+        }
+    }
+    /// Decodes an event encoded by [`ReplayEvent::to_json`], along with the step at which it occurred.
+    pub fn from_json(value: &Json) -> Option<(u64, Self)> {
+        let step = value.get("step")?.as_u64()?;
+        let poll = || value.get("poll")?.as_u64();
+        let event = match value.get("type")?.as_str()? {
+            "seed" => ReplayEvent::Seed { seed: from_hex(value.get("seed")?.as_str()?)?.try_into().ok()? },
+            "input" => ReplayEvent::Input { input: input_from_json(value.get("input")?)? },
+            "time" => ReplayEvent::Time { time: ReplayTime::from_json(value.get("time")?)? },
+            "request" => ReplayEvent::Request { handled: value.get("handled")?.as_bool()? },
+            "request-result" => ReplayEvent::RequestResult { poll: poll()?, result: result_from_json(value.get("result")?, ReplayValue::from_json)? },
+            "command-result" => ReplayEvent::CommandResult { poll: poll()?, result: result_from_json(value.get("result")?, |_| Some(()))? },
+            "message" => ReplayEvent::Message {
+                poll: poll()?,
+                msg_type: value.get("msg_type")?.as_str()?.into(),
+                values: value.get("values")?.as_array()?.iter().map(|x| Some((x.get(0)?.as_str()?.to_string(), x.get(1)?.clone()))).collect::<Option<_>>()?,
+                expect_reply: value.get("expect_reply")?.as_bool()?,
+            },
+            "reply" => ReplayEvent::Reply { poll: poll()?, value: value.get("value").filter(|x| !x.is_null()).cloned() },
+            "mark" => ReplayEvent::Mark,
+            _ => return None,
+        };
+        Some((step, event))
+    }
+}
+
+/// A sequence of recorded events, each tagged with the step at which it occurred.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    pub events: Vec<(u64, ReplayEvent)>,
+}
+impl Recording {
+    /// Parses a recording consisting of one json-encoded event per line, as produced by [`Recorder`].
+    /// Blank lines are ignored.
+    pub fn parse(src: &str) -> Result<Self, ReplayError> {
+        let mut events = vec![];
+        for (line, content) in src.lines().enumerate() {
+            let line = line + 1;
+            if content.trim().is_empty() { continue }
+            let value = parse_json::<Json>(content).map_err(|_| ReplayError::BadJson { line })?;
+            events.push(ReplayEvent::from_json(&value).ok_or(ReplayError::BadEvent { line })?);
+        }
+        Ok(Self { events })
+    }
+}
+
+#[derive(Default)]
+struct PollCounters {
+    requests: Cell<u64>,
+    commands: Cell<u64>,
+    messages: Cell<u64>,
+    replies: Cell<u64>,
+}
+impl PollCounters {
+    fn next(counter: &Cell<u64>) -> u64 {
+        let res = counter.get();
+        counter.set(res + 1);
+        res
+    }
+}
+
+/// Records the nondeterministic events of a running project.
+///
+/// Each event is encoded as json (see [`ReplayEvent::to_json`]) and passed to a sink function as soon as it occurs,
+/// so that the recording is not lost if the program is terminated abruptly.
+/// Writing each value on its own line produces a file which can be loaded by [`Recording::parse`].
+pub struct Recorder {
+    sink: RefCell<Box<dyn FnMut(Json)>>,
+    step: Cell<u64>,
+    polls: PollCounters,
+}
+impl Recorder {
+    /// Creates a new recorder which sends each recorded event to the given sink.
+    pub fn new(sink: Box<dyn FnMut(Json)>) -> Self {
+        Self { sink: RefCell::new(sink), step: Cell::new(0), polls: Default::default() }
+    }
+    /// Sets the number of project steps that have been performed, which is used to tag all subsequent events.
+    /// This should be updated after every call to [`Project::step`](crate::project::Project::step).
+    pub fn set_step(&self, step: u64) {
+        self.step.set(step);
+    }
+    /// Records an arbitrary event.
+    pub fn record(&self, event: ReplayEvent) {
+        let value = event.to_json(self.step.get());
+        self.sink.borrow_mut()(value);
+    }
+    /// Records a [`ReplayEvent::Mark`] at the current step.
+    pub fn record_mark(&self) {
+        self.record(ReplayEvent::Mark);
+    }
+    /// Records an [`Input`] that is about to be given to the project.
+    pub fn record_input(&self, input: &Input) {
+        self.record(ReplayEvent::Input { input: input.clone() });
+    }
+    /// Records a result of [`System::time`].
+    pub fn record_time(&self, time: &SysTime) {
+        self.record(ReplayEvent::Time { time: time.into() });
+    }
+    /// Records a call to [`System::perform_request`].
+    pub fn record_request(&self, handled: bool) {
+        self.record(ReplayEvent::Request { handled });
+    }
+    /// Records a call to [`System::poll_request`].
+    /// Values which cannot be recorded (see [`ReplayValue::from_value`]) are recorded as errors.
+    pub fn record_request_poll<C: CustomTypes<S>, S: System<C>>(&self, result: &AsyncResult<Result<Value<'_, C, S>, String>>) {
+        let poll = PollCounters::next(&self.polls.requests);
+        if let AsyncResult::Completed(result) = result {
+            let result = match result {
+                Ok(x) => ReplayValue::from_value(x).ok_or_else(|| format!("failed to record value of type {:?}", x.get_type())),
+                Err(x) => Err(x.clone()),
+            };
+            self.record(ReplayEvent::RequestResult { poll, result });
+        }
+    }
+    /// Records a call to [`System::poll_command`].
+    pub fn record_command_poll(&self, result: &AsyncResult<Result<(), String>>) {
+        let poll = PollCounters::next(&self.polls.commands);
+        if let AsyncResult::Completed(result) = result {
+            self.record(ReplayEvent::CommandResult { poll, result: result.clone() });
+        }
+    }
+    /// Records a call to [`System::receive_message`].
+    pub fn record_message_poll<C: CustomTypes<S>, S: System<C>>(&self, message: &Option<IncomingMessage<C, S>>) {
+        let poll = PollCounters::next(&self.polls.messages);
+        if let Some(IncomingMessage { msg_type, values, reply_key }) = message {
+            self.record(ReplayEvent::Message { poll, msg_type: msg_type.clone(), values: values.clone(), expect_reply: reply_key.is_some() });
+        }
+    }
+    /// Records a call to [`System::poll_reply`].
+    pub fn record_reply_poll(&self, result: &AsyncResult<Option<Json>>) {
+        let poll = PollCounters::next(&self.polls.replies);
+        if let AsyncResult::Completed(value) = result {
+            self.record(ReplayEvent::Reply { poll, value: value.clone() });
+        }
+    }
+}
+
+/// Feeds the events of a [`Recording`] back into a project.
+///
+/// Each polling function should be called exactly when the corresponding [`System`] function is called, in the same order as during recording.
+/// If the execution diverges from the recording (e.g., because the project was modified), the results are unspecified (but safe),
+/// and once the recording is exhausted, all requests remain pending forever and no further messages are received.
+pub struct Replayer {
+    seed: Option<[u8; 32]>,
+    steps: u64,
+    inputs: RefCell<VecDeque<(u64, Input)>>,
+    times: RefCell<VecDeque<ReplayTime>>,
+    requests: RefCell<VecDeque<bool>>,
+    request_results: RefCell<VecDeque<(u64, Result<ReplayValue, String>)>>,
+    command_results: RefCell<VecDeque<(u64, Result<(), String>)>>,
+    messages: RefCell<VecDeque<(u64, ReplayMessage)>>,
+    replies: RefCell<VecDeque<(u64, Option<Json>)>>,
+    polls: PollCounters,
+}
+impl Replayer {
+    /// Creates a new replayer for the given recording.
+    pub fn new(recording: Recording) -> Self {
+        let mut res = Self {
+            seed: None,
+            steps: recording.events.iter().map(|(step, event)| if let ReplayEvent::Mark = event { *step } else { step + 1 }).max().unwrap_or(0),
+            inputs: Default::default(),
+            times: Default::default(),
+            requests: Default::default(),
+            request_results: Default::default(),
+            command_results: Default::default(),
+            messages: Default::default(),
+            replies: Default::default(),
+            polls: Default::default(),
+        };
+        for (step, event) in recording.events {
+            match event {
+                ReplayEvent::Seed { seed } => res.seed = Some(seed),
+                ReplayEvent::Input { input } => res.inputs.get_mut().push_back((step, input)),
+                ReplayEvent::Time { time } => res.times.get_mut().push_back(time),
+                ReplayEvent::Request { handled } => res.requests.get_mut().push_back(handled),
+                ReplayEvent::RequestResult { poll, result } => res.request_results.get_mut().push_back((poll, result)),
+                ReplayEvent::CommandResult { poll, result } => res.command_results.get_mut().push_back((poll, result)),
+                ReplayEvent::Message { poll, msg_type, values, expect_reply } => res.messages.get_mut().push_back((poll, (msg_type, values, expect_reply))),
+                ReplayEvent::Reply { poll, value } => res.replies.get_mut().push_back((poll, value)),
+                ReplayEvent::Mark => (),
+            }
+        }
+        res
+    }
+    /// Gets the recorded random number generator seed, if any.
+    pub fn seed(&self) -> Option<[u8; 32]> {
+        self.seed
+    }
+    /// Gets the number of steps needed to replay every recorded event.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    /// Takes all recorded inputs that should be given to the project before performing the step with the given (zero-based) index.
+    pub fn take_inputs(&self, step: u64) -> Vec<Input> {
+        let mut inputs = self.inputs.borrow_mut();
+        let mut res = vec![];
+        while inputs.front().map(|x| x.0 <= step).unwrap_or(false) {
+            res.push(inputs.pop_front().unwrap().1);
+        }
+        res
+    }
+    /// Replays a call to [`System::time`], or returns [`None`] if the recording is exhausted.
+    pub fn time(&self) -> Option<SysTime> {
+        self.times.borrow_mut().pop_front().map(|x| x.to_sys_time())
+    }
+    /// Replays a call to [`System::perform_request`], returning whether or not the request was handled.
+    pub fn request(&self) -> bool {
+        self.requests.borrow_mut().pop_front().unwrap_or(true)
+    }
+    /// Replays a call to [`System::poll_request`].
+    pub fn poll_request<'gc, C: CustomTypes<S>, S: System<C>>(&self, mc: &Mutation<'gc>) -> AsyncResult<Result<Value<'gc, C, S>, String>> {
+        let poll = PollCounters::next(&self.polls.requests);
+        let mut results = self.request_results.borrow_mut();
+        match results.front() {
+            Some((x, _)) if *x == poll => AsyncResult::Completed(results.pop_front().unwrap().1.and_then(|x| x.to_value(mc).map_err(|e| format!("failed to replay value: {e:?}")))),
+            _ => AsyncResult::Pending,
+        }
+    }
+    /// Replays a call to [`System::poll_command`].
+    pub fn poll_command(&self) -> AsyncResult<Result<(), String>> {
+        let poll = PollCounters::next(&self.polls.commands);
+        let mut results = self.command_results.borrow_mut();
+        match results.front() {
+            Some((x, _)) if *x == poll => AsyncResult::Completed(results.pop_front().unwrap().1),
+            _ => AsyncResult::Pending,
+        }
+    }
+    /// Replays a call to [`System::receive_message`].
+    /// On success, returns the message type, values, and whether or not the sender expects a reply.
+    pub fn receive_message(&self) -> Option<ReplayMessage> {
+        let poll = PollCounters::next(&self.polls.messages);
+        let mut messages = self.messages.borrow_mut();
+        match messages.front() {
+            Some(x) if x.0 == poll => messages.pop_front().map(|x| x.1),
+            _ => None,
+        }
+    }
+    /// Replays a call to [`System::poll_reply`].
+    pub fn poll_reply(&self) -> AsyncResult<Option<Json>> {
+        let poll = PollCounters::next(&self.polls.replies);
+        let mut replies = self.replies.borrow_mut();
+        match replies.front() {
+            Some((x, _)) if *x == poll => AsyncResult::Completed(replies.pop_front().unwrap().1),
+            _ => AsyncResult::Pending,
+        }
+    }
+}
+
+type RequestPoll<'gc, C, S> = Result<AsyncResult<Result<Value<'gc, C, S>, String>>, ErrorCause<C, S>>;
+
+/// The replay state of a [`System`], which can be used to record or replay each of its nondeterministic operations.
+///
+/// Each method takes the live implementation of the operation, which is only invoked if the system is not replaying.
+/// Operations with external side effects (e.g., [`System::send_message`] and [`System::send_reply`]) should be skipped
+/// entirely while [`ReplayMode::is_replaying`] is true.
+pub enum ReplayMode {
+    /// The system is running normally.
+    Off,
+    /// The system is running normally and recording all nondeterministic events.
+    Recording(Rc<Recorder>),
+    /// The system is reproducing a previous execution from a recording.
+    Replaying(Rc<Replayer>),
+}
+impl ReplayMode {
+    /// Checks if the system is replaying a recording.
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, ReplayMode::Replaying(_))
+    }
+    /// Wraps a call to [`System::time`].
+    /// Once the recording is exhausted, replaying systems fall back to the live time.
+    pub fn time(&self, live: impl FnOnce() -> SysTime) -> SysTime {
+        if let ReplayMode::Replaying(replayer) = self {
+            if let Some(time) = replayer.time() { return time }
+        }
+        let time = live();
+        if let ReplayMode::Recording(recorder) = self {
+            recorder.record_time(&time);
+        }
+        time
+    }
+    /// Wraps a call to [`System::perform_request`].
+    /// When replaying, `replay` is invoked instead of `live` with whether or not the request was originally handled,
+    /// and should produce a result that is awaited with [`ReplayMode::poll_request`].
+    pub fn perform_request<T, E>(&self, live: impl FnOnce() -> Result<T, E>, replay: impl FnOnce(bool) -> Result<T, E>) -> Result<T, E> {
+        match self {
+            ReplayMode::Replaying(replayer) => replay(replayer.request()),
+            ReplayMode::Recording(recorder) => {
+                let res = live();
+                recorder.record_request(res.is_ok());
+                res
+            }
+            ReplayMode::Off => live(),
+        }
+    }
+    /// Wraps a call to [`System::poll_request`].
+    pub fn poll_request<'gc, C: CustomTypes<S>, S: System<C>>(&self, mc: &Mutation<'gc>, live: impl FnOnce() -> RequestPoll<'gc, C, S>) -> RequestPoll<'gc, C, S> {
+        match self {
+            ReplayMode::Replaying(replayer) => Ok(replayer.poll_request(mc)),
+            ReplayMode::Recording(recorder) => {
+                let res = live()?;
+                recorder.record_request_poll(&res);
+                Ok(res)
+            }
+            ReplayMode::Off => live(),
+        }
+    }
+    /// Wraps a call to [`System::poll_command`].
+    pub fn poll_command(&self, live: impl FnOnce() -> AsyncResult<Result<(), String>>) -> AsyncResult<Result<(), String>> {
+        match self {
+            ReplayMode::Replaying(replayer) => replayer.poll_command(),
+            ReplayMode::Recording(recorder) => {
+                let res = live();
+                recorder.record_command_poll(&res);
+                res
+            }
+            ReplayMode::Off => live(),
+        }
+    }
+    /// Wraps a call to [`System::poll_reply`].
+    pub fn poll_reply(&self, live: impl FnOnce() -> AsyncResult<Option<Json>>) -> AsyncResult<Option<Json>> {
+        match self {
+            ReplayMode::Replaying(replayer) => replayer.poll_reply(),
+            ReplayMode::Recording(recorder) => {
+                let res = live();
+                recorder.record_reply_poll(&res);
+                res
+            }
+            ReplayMode::Off => live(),
+        }
+    }
+    /// Wraps a call to [`System::receive_message`].
+    /// When replaying, `reply_key` is used to produce the reply key of replayed messages that expect a reply;
+    /// since replies are not sent during replay, this can be any placeholder value.
+    pub fn receive_message<C: CustomTypes<S>, S: System<C>>(&self, live: impl FnOnce() -> Option<IncomingMessage<C, S>>, reply_key: impl FnOnce() -> S::InternReplyKey) -> Option<IncomingMessage<C, S>> {
+        match self {
+            ReplayMode::Replaying(replayer) => replayer.receive_message().map(|(msg_type, values, expect_reply)| {
+                IncomingMessage { msg_type, values, reply_key: expect_reply.then(reply_key) }
+            }),
+            ReplayMode::Recording(recorder) => {
+                let res = live();
+                recorder.record_message_poll(&res);
+                res
+            }
+            ReplayMode::Off => live(),
+        }
+    }
+}
@@ -6,7 +6,7 @@ use alloc::vec::Vec;
 
 use core::marker::PhantomData;
 use core::{iter, fmt, mem};
-use core::ops::Deref;
+use core::ops::{Deref, DerefMut};
 use core::cell::Ref;
 
 use rand::distributions::uniform::{SampleUniform, SampleRange};
@@ -20,6 +20,7 @@ use crate::gc::*;
 use crate::json::*;
 use crate::real_time::*;
 use crate::bytecode::*;
+use crate::profiler::*;
 
 /// Error type used by [`NumberChecker`].
 #[derive(Debug)]
@@ -40,15 +41,28 @@ impl FloatChecker<f64> for NumberChecker {
 /// The type used to represent numbers in the runtime.
 pub type Number = CheckedFloat<f64, NumberChecker>;
 
+/// Parses a string into a [`Number`] using the same rules as [`Value::to_number`].
+pub(crate) fn parse_number(x: &str) -> Option<Number> {
+    let parsed = match x.get(..2) {
+        Some("0x" | "0X") => i64::from_str_radix(&x[2..], 16).ok().map(|x| x as f64),
+        Some("0o" | "0O") => i64::from_str_radix(&x[2..], 8).ok().map(|x| x as f64),
+        Some("0b" | "0B") => i64::from_str_radix(&x[2..], 2).ok().map(|x| x as f64),
+        _ => x.parse::<f64>().ok(),
+    };
+    parsed.and_then(|x| Number::new(x).ok())
+}
+
 #[derive(Debug)]
 pub enum FromAstError<'a> {
     BadNumber { error: NumberError },
     BadKeycode { key: String },
     UnsupportedEvent { kind: &'a ast::HatKind },
     CompileError { error: CompileError<'a> },
+    VerifyError { error: VerifyError },
 }
 impl From<NumberError> for FromAstError<'_> { fn from(error: NumberError) -> Self { Self::BadNumber { error } } }
 impl<'a> From<CompileError<'a>> for FromAstError<'a> { fn from(error: CompileError<'a>) -> Self { Self::CompileError { error } } }
+impl From<VerifyError> for FromAstError<'_> { fn from(error: VerifyError) -> Self { Self::VerifyError { error } } }
 
 #[derive(Debug)]
 pub enum FromJsonError {
@@ -89,6 +103,8 @@ pub enum ErrorCause<C: CustomTypes<S>, S: System<C>> {
     UndefinedVariable { name: String },
     /// A name-based costume lookup operation failed.
     UndefinedCostume { name: String },
+    /// A name-based sound lookup operation failed.
+    UndefinedSound { name: String },
     /// A name-based entity lookup operation failed.
     UndefinedEntity { name: String },
     /// An upvar was created at the root scope, which is not allowed (it has nothing to refer up to).
@@ -113,6 +129,18 @@ pub enum ErrorCause<C: CustomTypes<S>, S: System<C>> {
     InvalidUnicode { value: f64 },
     /// Exceeded the maximum call depth.
     CallDepthLimit { limit: usize },
+    /// A single process exceeded the maximum number of instructions it may execute (see [`Settings::max_process_steps`]).
+    ProcessStepLimit { limit: u64 },
+    /// All processes in the project combined exceeded the maximum number of instructions they may execute (see [`Settings::max_project_steps`]).
+    ProjectStepLimit { limit: u64 },
+    /// A process spent longer than the maximum time (in milliseconds) in warp mode without exiting (see [`Settings::max_warp_time`]).
+    WarpTimeLimit { limit: u64 },
+    /// A list grew beyond the maximum list size (see [`Settings::max_list_size`]).
+    ListSizeLimit { limit: usize },
+    /// Exceeded the maximum number of live clones of a single entity.
+    CloneLimit { limit: usize },
+    /// The condition of a [`Event::When`] script did something other than compute a value (e.g., broadcast, fork, or clone).
+    ConditionSideEffect,
     /// Attempt to call a closure which required `expected` arguments, but `got` arguments were supplied.
     ClosureArgCount { expected: usize, got: usize },
     /// An acyclic operation received a cyclic input value.
@@ -331,6 +359,7 @@ impl Property {
 }
 
 /// A collection of graphical effects related to an entity
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Copy)]
 pub struct Effects {
     pub color_h: Number,
@@ -363,6 +392,7 @@ impl Default for Effects {
 }
 
 /// A collection of properties related to an entity.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Copy)]
 pub struct Properties {
     pub pos: (Number, Number),
@@ -574,11 +604,24 @@ impl Properties {
         self.with_value::<C, S, _>(key, Number::new(x).map_err(Into::into).and_then(|x| Number::new(y).map(|y| (x, y)).map_err(Into::into)), |props, pos| props.pos = pos);
         CommandStatus::Handled
     }
+
+    pub fn perform_distance_to<'gc, C: CustomTypes<S>, S: System<C>>(&self, key: S::RequestKey, target: &Properties) -> RequestStatus<'gc, C, S> {
+        let (dx, dy) = (target.pos.0.get() - self.pos.0.get(), target.pos.1.get() - self.pos.1.get());
+        key.complete(Ok(C::Intermediate::from_json(dx.hypot(dy).into())));
+        RequestStatus::Handled
+    }
+
+    pub fn perform_direction_to<'gc, C: CustomTypes<S>, S: System<C>>(&self, key: S::RequestKey, target: &Properties) -> RequestStatus<'gc, C, S> {
+        let (dx, dy) = (target.pos.0.get() - self.pos.0.get(), target.pos.1.get() - self.pos.1.get());
+        let dir = dx.atan2(dy).to_degrees();
+        key.complete(Ok(C::Intermediate::from_json((if dir <= -180.0 { dir + 360.0 } else { dir }).into()))); // same range as headings in snap: (-180, 180]
+        RequestStatus::Handled
+    }
 }
 
 /// A key from the keyboard.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum KeyCode {
     /// A normal character key, such as a letter, number, or special symbol.
     Char(char),
@@ -593,10 +636,42 @@ pub enum KeyCode {
     /// Either enter/return key.
     Enter,
 }
+impl KeyCode {
+    /// Parses a key from its Snap!/NetsBlox name (e.g., `"space"` or `"up arrow"`).
+    /// Single-character keys are normalized to lowercase.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "up arrow" => KeyCode::Up,
+            "down arrow" => KeyCode::Down,
+            "left arrow" => KeyCode::Left,
+            "right arrow" => KeyCode::Right,
+            "enter" => KeyCode::Enter,
+            "space" => KeyCode::Char(' '),
+            _ => {
+                let mut chars = name.chars();
+                let res = KeyCode::Char(chars.next()?.to_ascii_lowercase());
+                if chars.next().is_some() { return None }
+                res
+            }
+        })
+    }
+    /// Gets the Snap!/NetsBlox name of this key, which is the inverse of [`KeyCode::from_name`].
+    pub fn name(&self) -> String {
+        match self {
+            KeyCode::Up => "up arrow".into(),
+            KeyCode::Down => "down arrow".into(),
+            KeyCode::Left => "left arrow".into(),
+            KeyCode::Right => "right arrow".into(),
+            KeyCode::Enter => "enter".into(),
+            KeyCode::Char(' ') => "space".into(),
+            KeyCode::Char(x) => x.to_string(),
+        }
+    }
+}
 
 /// An event type which can be set to trigger the execution of a script.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Event {
     /// Fire when a green flag click event is issued.
     OnFlag,
@@ -610,6 +685,9 @@ pub enum Event {
     OnKey { key_filter: Option<KeyCode> },
     /// Fire when explicitly requested from an input command.
     Custom { name: String, fields: Vec<String> },
+    /// Fire when a condition changes from false to true (edge-triggered).
+    /// The condition is a separate piece of code starting at `condition` which returns the value to test.
+    When { condition: usize },
 }
 
 #[derive(Debug, Clone, Copy, FromPrimitive)]
@@ -652,13 +730,78 @@ pub enum Value<'gc, C: CustomTypes<S>, S: System<C>> {
     /// A reference to a native object handle produced by [`System`].
     Native(#[collect(require_static)] Rc<C::NativeValue>),
     /// A primitive list type, which is a mutable reference type.
-    List(Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>),
+    /// The items are stored in a [`List`], which replaced the bare [`VecDeque`] used before version `0.3.0`.
+    List(Gc<'gc, RefLock<List<'gc, C, S>>>),
     /// A closure/lambda function. This contains information about the closure's bytecode location, parameters, and captures from the parent scope.
     Closure(Gc<'gc, RefLock<Closure<'gc, C, S>>>),
     /// A reference to an [`Entity`] in the environment.
     Entity(Gc<'gc, RefLock<Entity<'gc, C, S>>>),
 }
 
+/// The contents of a [`Value::List`], which dereferences to the items of the list.
+/// Every mutable access to the items changes the version of the list (see [`List::version`]).
+#[derive(Educe, Collect)]
+#[educe(Clone, Default)]
+#[collect(no_drop, bound = "")]
+pub struct List<'gc, C: CustomTypes<S>, S: System<C>> {
+                               items: VecDeque<Value<'gc, C, S>>,
+    #[collect(require_static)] version: u64,
+    /// Whether this list was made from a json object by [`Value::from_json`].
+    /// Only such lists are converted back into json objects by [`JsonMode::Objects`].
+    #[collect(require_static)] pub json_object: bool,
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> List<'gc, C, S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_capacity(capacity: usize) -> Self {
+        VecDeque::with_capacity(capacity).into()
+    }
+    /// Gets a counter which changes whenever the items of the list are (potentially) modified.
+    /// This is used to detect when cached information about the list (e.g., a [`KeyIndexCache`] entry) is out of date.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+    /// Takes the items out of the list.
+    pub fn into_items(self) -> VecDeque<Value<'gc, C, S>> {
+        self.items
+    }
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> Deref for List<'gc, C, S> {
+    type Target = VecDeque<Value<'gc, C, S>>;
+    fn deref(&self) -> &Self::Target {
+        &self.items
+    }
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> DerefMut for List<'gc, C, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.version = self.version.wrapping_add(1);
+        &mut self.items
+    }
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> From<VecDeque<Value<'gc, C, S>>> for List<'gc, C, S> {
+    fn from(items: VecDeque<Value<'gc, C, S>>) -> Self {
+        Self { items, version: 0, json_object: false }
+    }
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> FromIterator<Value<'gc, C, S>> for List<'gc, C, S> {
+    fn from_iter<I: IntoIterator<Item = Value<'gc, C, S>>>(iter: I) -> Self {
+        iter.into_iter().collect::<VecDeque<_>>().into()
+    }
+}
+impl<C: CustomTypes<S>, S: System<C>> fmt::Debug for List<'_, C, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.items.fmt(f)
+    }
+}
+impl<'a, 'gc, C: CustomTypes<S>, S: System<C>> IntoIterator for &'a List<'gc, C, S> {
+    type Item = &'a Value<'gc, C, S>;
+    type IntoIter = alloc::collections::vec_deque::Iter<'a, Value<'gc, C, S>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
 impl<'gc, C: CustomTypes<S>, S: System<C>> GetType for Value<'gc, C, S> {
     type Output = Type<C, S>;
     fn get_type(&self) -> Self::Output {
@@ -731,7 +874,7 @@ fn format_value<C: CustomTypes<S>, S: System<C>>(value: &Value<'_, C, S>, f: &mu
 impl<'gc, C: CustomTypes<S>, S: System<C>> From<bool> for Value<'gc, C, S> { fn from(v: bool) -> Self { Value::Bool(v) } }
 impl<'gc, C: CustomTypes<S>, S: System<C>> From<Number> for Value<'gc, C, S> { fn from(v: Number) -> Self { Value::Number(v) } }
 impl<'gc, C: CustomTypes<S>, S: System<C>> From<Rc<String>> for Value<'gc, C, S> { fn from(v: Rc<String>) -> Self { Value::String(v) } }
-impl<'gc, C: CustomTypes<S>, S: System<C>> From<Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>> for Value<'gc, C, S> { fn from(v: Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>) -> Self { Value::List(v) } }
+impl<'gc, C: CustomTypes<S>, S: System<C>> From<Gc<'gc, RefLock<List<'gc, C, S>>>> for Value<'gc, C, S> { fn from(v: Gc<'gc, RefLock<List<'gc, C, S>>>) -> Self { Value::List(v) } }
 impl<'gc, C: CustomTypes<S>, S: System<C>> From<Gc<'gc, RefLock<Closure<'gc, C, S>>>> for Value<'gc, C, S> { fn from(v: Gc<'gc, RefLock<Closure<'gc, C, S>>>) -> Self { Value::Closure(v) } }
 impl<'gc, C: CustomTypes<S>, S: System<C>> From<Gc<'gc, RefLock<Entity<'gc, C, S>>>> for Value<'gc, C, S> { fn from(v: Gc<'gc, RefLock<Entity<'gc, C, S>>>) -> Self { Value::Entity(v) } }
 impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
@@ -743,17 +886,37 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
             Json::Number(x) => Value::Number(x.as_f64().and_then(|x| Number::new(x).ok()).ok_or(FromJsonError::HadBadNumber)?),
             Json::String(x) => Value::String(Rc::new(x)),
             Json::Array(x) => Value::List(Gc::new(mc, RefLock::new(x.into_iter().map(|x| Value::from_json(mc, x)).collect::<Result<_,_>>()?))),
-            Json::Object(x) => Value::List(Gc::new(mc, RefLock::new(x.into_iter().map(|(k, v)| {
-                let mut entry = VecDeque::with_capacity(2);
-                entry.push_back(Value::String(Rc::new(k)));
-                entry.push_back(Value::from_json(mc, v)?);
-                Ok(Value::List(Gc::new(mc, RefLock::new(entry))))
-            }).collect::<Result<_,_>>()?))),
+            Json::Object(x) => {
+                let mut list = x.into_iter().map(|(k, v)| {
+                    let mut entry = List::with_capacity(2);
+                    entry.push_back(Value::String(Rc::new(k)));
+                    entry.push_back(Value::from_json(mc, v)?);
+                    Ok(Value::List(Gc::new(mc, RefLock::new(entry))))
+                }).collect::<Result<List<_, _>,_>>()?;
+                list.json_object = true;
+                Value::List(Gc::new(mc, RefLock::new(list)))
+            }
         })
     }
     /// Converts a [`Value`] into [`Json`]. Note that not all values can be converted to json (e.g., cyclic lists or complex types).
+    /// This is equivalent to [`Value::to_json_with`] using [`JsonMode::Arrays`].
     pub fn to_json(&self) -> Result<Json, ToJsonError<C, S>> {
-        fn simplify<'gc, C: CustomTypes<S>, S: System<C>>(value: &Value<'gc, C, S>, cache: &mut BTreeSet<Identity<'gc, C, S>>) -> Result<Json, ToJsonError<C, S>> {
+        self.to_json_with(JsonMode::Arrays)
+    }
+    /// Converts a [`Value`] into [`Json`], using the given mode to determine how lists are converted.
+    /// Note that not all values can be converted to json (e.g., cyclic lists or complex types).
+    pub fn to_json_with(&self, mode: JsonMode) -> Result<Json, ToJsonError<C, S>> {
+        fn is_object<C: CustomTypes<S>, S: System<C>>(list: &List<'_, C, S>) -> bool {
+            let mut keys = BTreeSet::new();
+            list.json_object && list.iter().all(|item| match item {
+                Value::List(pair) => match &*pair.borrow() {
+                    pair if pair.len() == 2 => matches!(&pair[0], Value::String(key) if keys.insert(key.clone())),
+                    _ => false,
+                }
+                _ => false,
+            })
+        }
+        fn simplify<'gc, C: CustomTypes<S>, S: System<C>>(value: &Value<'gc, C, S>, mode: JsonMode, cache: &mut BTreeSet<Identity<'gc, C, S>>) -> Result<Json, ToJsonError<C, S>> {
             Ok(match value {
                 Value::Bool(x) => Json::Bool(*x),
                 Value::Number(x) => Json::Number(JsonNumber::from_f64(x.get()).ok_or_else(|| ToJsonError::BadNumber(x.get()))?),
@@ -762,7 +925,15 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
                 Value::List(x) => {
                     let identity = value.identity();
                     if !cache.insert(identity) { return Err(ToJsonError::Cyclic) }
-                    let res = Json::Array(x.borrow().iter().map(|x| simplify(x, cache)).collect::<Result<_,_>>()?);
+                    let x = x.borrow();
+                    let res = match mode == JsonMode::Objects && is_object(&x) {
+                        true => Json::Object(x.iter().map(|pair| {
+                            let pair = pair.as_list().unwrap();
+                            let pair = pair.borrow();
+                            Ok((pair[0].to_string().unwrap().into_owned(), simplify(&pair[1], mode, cache)?))
+                        }).collect::<Result<_,_>>()?),
+                        false => Json::Array(x.iter().map(|x| simplify(x, mode, cache)).collect::<Result<_,_>>()?),
+                    };
                     debug_assert!(cache.contains(&identity));
                     cache.remove(&identity);
                     res
@@ -770,7 +941,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
             })
         }
         let mut cache = Default::default();
-        let res = simplify(self, &mut cache);
+        let res = simplify(self, mode, &mut cache);
         if res.is_ok() { debug_assert_eq!(cache.len(), 0); }
         res
     }
@@ -801,15 +972,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
     pub fn to_number(&self) -> Result<Number, ConversionError<C, S>> {
         match self {
             Value::Number(x) => Ok(*x),
-            Value::String(x) => {
-                let parsed = match x.get(..2) {
-                    Some("0x" | "0X") => i64::from_str_radix(&x[2..], 16).ok().map(|x| x as f64),
-                    Some("0o" | "0O") => i64::from_str_radix(&x[2..], 8).ok().map(|x| x as f64),
-                    Some("0b" | "0B") => i64::from_str_radix(&x[2..], 2).ok().map(|x| x as f64),
-                    _ => x.parse::<f64>().ok(),
-                };
-                parsed.and_then(|x| Number::new(x).ok()).ok_or(ConversionError { got: Type::String, expected: Type::Number })
-            }
+            Value::String(x) => parse_number(x).ok_or(ConversionError { got: Type::String, expected: Type::Number }),
             x => Err(ConversionError { got: x.get_type(), expected: Type::Number }),
         }
     }
@@ -829,7 +992,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Value<'gc, C, S> {
         }
     }
     /// Attempts to interpret this value as a list.
-    pub fn as_list(&self) -> Result<Gc<'gc, RefLock<VecDeque<Value<'gc, C, S>>>>, ConversionError<C, S>> {
+    pub fn as_list(&self) -> Result<Gc<'gc, RefLock<List<'gc, C, S>>>, ConversionError<C, S>> {
         match self {
             Value::List(x) => Ok(*x),
             x => Err(ConversionError { got: x.get_type(), expected: Type::List }),
@@ -879,8 +1042,10 @@ pub struct Entity<'gc, C: CustomTypes<S>, S: System<C>> {
     #[collect(require_static)] pub name: Rc<String>,
     #[collect(require_static)] pub costume_list: Rc<Vec<(String, Rc<Vec<u8>>)>>,
     #[collect(require_static)] pub costume: Option<Rc<Vec<u8>>>,
+    #[collect(require_static)] pub sound_list: Rc<Vec<(String, Rc<Vec<u8>>)>>,
     #[collect(require_static)] pub state: C::EntityState,
     #[collect(require_static)] pub alive: bool,
+    #[collect(require_static)] pub clone_count: usize,
                                pub root: Option<Gc<'gc, RefLock<Entity<'gc, C, S>>>>,
                                pub fields: SymbolTable<'gc, C, S>,
 }
@@ -895,7 +1060,7 @@ impl<C: CustomTypes<S>, S: System<C>> fmt::Debug for Entity<'_, C, S> {
 /// Users can "show" or "hide" variables to create or remove watchers.
 /// These can be used by students for a number of purposes, including debugging.
 #[derive(Collect)]
-#[collect(no_drop)]
+#[collect(no_drop, bound = "")]
 pub struct Watcher<'gc, C: CustomTypes<S>, S: System<C>> {
     /// The entity associated with the variable being watched.
     pub entity: GcWeak<'gc, RefLock<Entity<'gc, C, S>>>,
@@ -1101,22 +1266,187 @@ pub enum ErrorScheme {
     Hard,
 }
 
+/// The way in which lists are converted into json by [`Value::to_json_with`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonMode {
+    /// Every list is converted into a json array.
+    /// Because [`Value::from_json`] represents json objects as lists of key/value pairs, objects do not survive a round trip.
+    Arrays,
+    /// Lists that were made from json objects by [`Value::from_json`] (see [`List::json_object`]) are converted back into json objects,
+    /// as long as they still consist of key/value pairs with distinct text keys, and all other lists are converted into json arrays.
+    /// This allows json objects (including empty objects) to survive a round trip through the runtime unchanged.
+    Objects,
+}
+
+/// The policy used by [`Project::step`](crate::project::Project::step) to decide when to switch between processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduling {
+    /// A process runs until it yields (e.g., at the end of each loop iteration outside of warp mode), and then moves to the back of the queue.
+    /// A process that never yields prevents all other processes from running.
+    UntilYield,
+    /// Like [`Scheduling::UntilYield`], but a process also moves to the back of the queue after executing `quantum` instructions in a row,
+    /// so that a process which does not yield cannot starve the others.
+    RoundRobin { quantum: usize },
+    /// Processes run in rounds where each process gets one turn, which lasts until it yields or executes `quantum` instructions.
+    /// Within each round, processes of entities with higher priority (see [`Project::set_priority`](crate::project::Project::set_priority))
+    /// take their turns first, and processes of entities with equal priority take turns in queue order.
+    Priority { quantum: usize },
+    /// Emulates Snap!, where processes run in frames: each process that was running at the start of a frame runs until it yields,
+    /// while processes started during the frame wait until the next one.
+    /// The end of each frame is reported by [`ProjectStep::FrameEnd`](crate::project::ProjectStep::FrameEnd),
+    /// which is when the browser would redraw the stage and handle inputs.
+    Frames,
+}
+
 /// Settings to use for a [`Process`](crate::process::Process).
 #[derive(Clone, Copy)]
 pub struct Settings {
     /// The maximum depth of the call stack (default `1024`).
     pub max_call_depth: usize,
+    /// The maximum number of live clones of any single (original) entity (default `5000`).
+    pub max_clones: usize,
     /// The error pattern to use for rpc errors (default [`ErrorScheme::Hard`]).
     pub rpc_error_scheme: ErrorScheme,
     /// The error pattern to use for syscall errors (default [`ErrorScheme::Hard`]).
     pub syscall_error_scheme: ErrorScheme,
+    /// The time (in milliseconds) after the most recent [`Input::KeyDown`](crate::project::Input::KeyDown) for a key after which it is considered released,
+    /// even if no [`Input::KeyUp`](crate::project::Input::KeyUp) was received (default [`None`], meaning keys are only released explicitly).
+    /// This should only be set for platforms that cannot report key releases, which instead rely on key repeat events to keep a key held.
+    pub key_hold_timeout: Option<u64>,
+    /// The number of calls to [`Project::step`](crate::project::Project::step) between re-evaluations of the conditions of [`Event::When`] scripts (default `16`).
+    /// Lower values make "when" hat blocks more responsive at the cost of more overhead (`0` and `1` both evaluate conditions every step).
+    pub condition_poll_interval: usize,
+    /// The maximum number of instructions that the condition of a single [`Event::When`] script may execute each time conditions are polled (default `1024`).
+    /// Conditions that take longer are resumed the next time conditions are polled.
+    pub condition_poll_steps: usize,
+    /// The maximum number of instructions that a single [`Process`](crate::process::Process) may execute after it is initialized,
+    /// or [`None`] for no limit (default [`None`]).
+    pub max_process_steps: Option<u64>,
+    /// The maximum number of instructions that all processes sharing a [`GlobalContext`] may execute combined,
+    /// or [`None`] for no limit (default [`None`]).
+    /// The count is reset by [`Input::Start`](crate::project::Input::Start) and [`Input::Stop`](crate::project::Input::Stop).
+    pub max_project_steps: Option<u64>,
+    /// The maximum time (in milliseconds) that a process may spend continuously in warp mode, or [`None`] for no limit (default [`None`]).
+    /// This is checked at each point where the process would have yielded if it were not in warp mode, and requires a [`System`] that reports time.
+    pub max_warp_time: Option<u64>,
+    /// The maximum length of any list created or grown by a process, or [`None`] for no limit (default [`None`]).
+    pub max_list_size: Option<usize>,
+    /// The way in which values are converted into json when they are sent in messages or as rpc arguments,
+    /// or converted by the "json of" block (default [`JsonMode::Arrays`]).
+    pub json_mode: JsonMode,
+    /// The policy used by [`Project::step`](crate::project::Project::step) to decide when to switch between processes (default [`Scheduling::UntilYield`]).
+    pub scheduling: Scheduling,
 }
 impl Default for Settings {
     fn default() -> Self {
         Self {
             max_call_depth: 1024,
+            max_clones: 5000,
             rpc_error_scheme: ErrorScheme::Hard,
             syscall_error_scheme: ErrorScheme::Hard,
+            key_hold_timeout: None,
+            condition_poll_interval: 16,
+            condition_poll_steps: 1024,
+            max_process_steps: None,
+            max_project_steps: None,
+            max_warp_time: None,
+            max_list_size: None,
+            json_mode: JsonMode::Arrays,
+            scheduling: Scheduling::UntilYield,
+        }
+    }
+}
+
+/// The minimum length of a list for which a [`KeyIndexCache`] builds an index (shorter lists are searched directly).
+const KEY_INDEX_MIN_LEN: usize = 64;
+/// The maximum number of lists that a [`KeyIndexCache`] holds indices for at once.
+const KEY_INDEX_CAPACITY: usize = 16;
+
+/// Checks if a value is a key/value pair (a list of two items) whose key is the given text.
+fn is_pair_with_key<C: CustomTypes<S>, S: System<C>>(value: &Value<'_, C, S>, key: &str) -> bool {
+    match value {
+        Value::List(pair) => {
+            let pair = pair.borrow();
+            pair.len() == 2 && pair[0].to_string().map(|x| x == key).unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Collect)]
+#[collect(no_drop, bound = "")]
+struct KeyIndex<'gc, C: CustomTypes<S>, S: System<C>> {
+                               list: GcWeak<'gc, RefLock<List<'gc, C, S>>>,
+    #[collect(require_static)] version: u64,
+    #[collect(require_static)] positions: BTreeMap<String, usize>,
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> KeyIndex<'gc, C, S> {
+    fn build(list: Gc<'gc, RefLock<List<'gc, C, S>>>, items: &List<'gc, C, S>) -> Self {
+        let mut positions = BTreeMap::new();
+        for (i, item) in items.iter().enumerate() {
+            if let Value::List(pair) = item {
+                let pair = pair.borrow();
+                if pair.len() != 2 { continue }
+                if let Ok(key) = pair[0].to_string() {
+                    positions.entry(key.into_owned()).or_insert(i);
+                }
+            }
+        }
+        Self { list: Gc::downgrade(list), version: items.version(), positions }
+    }
+    fn is_for(&self, list: Gc<'gc, RefLock<List<'gc, C, S>>>) -> bool {
+        !self.list.is_dropped() && GcWeak::as_ptr(self.list) == Gc::as_ptr(list)
+    }
+}
+
+/// A cache of indices which map keys to positions in big lists of key/value pairs (dictionaries),
+/// so that processes can look up keys without searching the entire list.
+///
+/// Each index records the [`List::version`] it was built for, and is rebuilt after the list is modified.
+/// Changing the key of a pair in place does not change the version of the list containing it,
+/// so a found key is checked against the pair at its recorded position, and a missing key is confirmed by searching the list.
+#[derive(Collect)]
+#[collect(no_drop, bound = "")]
+pub struct KeyIndexCache<'gc, C: CustomTypes<S>, S: System<C>> {
+    entries: Vec<KeyIndex<'gc, C, S>>,
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> Default for KeyIndexCache<'gc, C, S> {
+    fn default() -> Self {
+        Self { entries: Vec::new() }
+    }
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> KeyIndexCache<'gc, C, S> {
+    /// Gets the position of the first key/value pair in `list` whose key is the given text, if any.
+    pub fn find(&mut self, list: Gc<'gc, RefLock<List<'gc, C, S>>>, key: &str) -> Option<usize> {
+        let items = list.borrow();
+        if items.len() < KEY_INDEX_MIN_LEN {
+            return items.iter().position(|x| is_pair_with_key(x, key));
+        }
+
+        let mut entry = match self.entries.iter().position(|x| x.is_for(list) && x.version == items.version()) {
+            Some(i) => self.entries.remove(i),
+            None => KeyIndex::build(list, &items),
+        };
+        let res = match entry.positions.get(key) {
+            Some(&i) if is_pair_with_key(&items[i], key) => Some(i),
+            indexed => {
+                let res = items.iter().position(|x| is_pair_with_key(x, key));
+                if res.is_some() || indexed.is_some() { entry = KeyIndex::build(list, &items); }
+                res
+            }
+        };
+
+        self.entries.retain(|x| !x.list.is_dropped() && !x.is_for(list));
+        if self.entries.len() >= KEY_INDEX_CAPACITY { self.entries.remove(0); }
+        self.entries.push(entry);
+
+        res
+    }
+    /// Records that a new key/value pair with the given key was appended to `list` (which was at version `prev_version` before appending).
+    pub fn appended(&mut self, list: Gc<'gc, RefLock<List<'gc, C, S>>>, items: &List<'gc, C, S>, key: &str, prev_version: u64) {
+        if let Some(entry) = self.entries.iter_mut().find(|x| x.is_for(list) && x.version == prev_version) {
+            entry.version = items.version();
+            entry.positions.entry(key.to_owned()).or_insert(items.len() - 1);
         }
     }
 }
@@ -1130,6 +1460,14 @@ pub struct GlobalContext<'gc, C: CustomTypes<S>, S: System<C>> {
     #[collect(require_static)] pub system: Rc<S>,
     #[collect(require_static)] pub timer_start: u64,
     #[collect(require_static)] pub proj_name: String,
+    #[collect(require_static)] pub held_keys: BTreeMap<KeyCode, u64>,
+    /// The profiler which records the instructions executed by processes using this context, if profiling is enabled.
+    #[collect(require_static)] pub profiler: Option<Profiler>,
+    /// The total number of instructions executed by processes using this context since the project was last started or stopped.
+    /// This is only counted while [`Settings::max_project_steps`] is set.
+    #[collect(require_static)] pub steps: u64,
+    /// The key indices used by processes using this context to look up keys in big lists of key/value pairs.
+                               pub key_index: KeyIndexCache<'gc, C, S>,
                                pub globals: SymbolTable<'gc, C, S>,
                                pub entities: BTreeMap<String, Gc<'gc, RefLock<Entity<'gc, C, S>>>>,
 }
@@ -1138,6 +1476,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> GlobalContext<'gc, C, S> {
         let allocated_refs = init_info.ref_values.iter().map(|ref_value| match ref_value {
             RefValue::String(value) => Value::String(Rc::new(value.clone())),
             RefValue::Image(content) => Value::Image(Rc::new(content.clone())),
+            RefValue::Audio(content) => Value::Audio(Rc::new(content.clone())),
             RefValue::List(_) => Value::List(Gc::new(mc, Default::default())),
         }).collect::<Vec<_>>();
 
@@ -1151,7 +1490,7 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> GlobalContext<'gc, C, S> {
 
         for (allocated_ref, ref_value) in iter::zip(&allocated_refs, &init_info.ref_values) {
             match ref_value {
-                RefValue::String(_) | RefValue::Image(_) => continue, // we already populated these values in the first pass
+                RefValue::String(_) | RefValue::Image(_) | RefValue::Audio(_) => continue, // we already populated these values in the first pass
                 RefValue::List(values) => {
                     let allocated_ref = match allocated_ref {
                         Value::List(x) => x,
@@ -1191,6 +1530,18 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> GlobalContext<'gc, C, S> {
 
             let costume = entity_info.active_costume.and_then(|x| costume_list.get(x)).map(|x| x.1.clone());
 
+            let sound_list = {
+                let mut res = Vec::with_capacity(entity_info.sounds.len());
+                for (name, value) in entity_info.sounds.iter() {
+                    let sound = match get_value(value, &allocated_refs) {
+                        Value::Audio(x) => x.clone(),
+                        _ => unreachable!(),
+                    };
+                    res.push((name.clone(), sound));
+                }
+                Rc::new(res)
+            };
+
             let mut props = Properties::default();
             props.visible = entity_info.visible;
             props.size = entity_info.size;
@@ -1208,13 +1559,26 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> GlobalContext<'gc, C, S> {
             let name = Rc::new(entity_info.name.clone());
             let state = kind.into();
 
-            entities.insert(entity_info.name.clone(), Gc::new(mc, RefLock::new(Entity { alive: true, root: None, name, fields, costume_list, costume, state })));
+            entities.insert(entity_info.name.clone(), Gc::new(mc, RefLock::new(Entity { alive: true, clone_count: 0, root: None, name, fields, costume_list, costume, sound_list, state })));
         }
 
         let proj_name = init_info.proj_name.clone();
         let timer_start = system.time().to_arbitrary_ms::<C, S>().unwrap_or(0);
 
-        Self { proj_name, globals, entities, timer_start, system, settings, bytecode }
+        Self { proj_name, globals, entities, timer_start, system, settings, bytecode, held_keys: Default::default(), profiler: None, steps: 0, key_index: Default::default() }
+    }
+    /// Checks if the given key is currently held down, or any key if `key` is [`None`].
+    /// Keys are held from [`Input::KeyDown`](crate::project::Input::KeyDown) until [`Input::KeyUp`](crate::project::Input::KeyUp) or [`Settings::key_hold_timeout`] elapses, whichever comes first.
+    pub fn is_key_held(&self, key: Option<KeyCode>) -> bool {
+        let now = self.system.time().to_arbitrary_ms::<C, S>().ok();
+        let is_held = |&pressed: &u64| match (now, self.settings.key_hold_timeout) {
+            (Some(now), Some(timeout)) => now.saturating_sub(pressed) < timeout,
+            _ => true,
+        };
+        match key {
+            Some(key) => self.held_keys.get(&key).map(is_held).unwrap_or(false),
+            None => self.held_keys.values().any(is_held),
+        }
     }
 }
 
@@ -1258,12 +1622,22 @@ impl Barrier {
     pub fn get_condition(&self) -> BarrierCondition {
         BarrierCondition(Rc::downgrade(&self.0))
     }
+    pub(crate) fn id(&self) -> *const () {
+        Rc::as_ptr(&self.0)
+    }
 }
 impl BarrierCondition {
     /// Checks if the condition has been completed, i.e., that all the associated barriers have been destroyed.
     pub fn is_completed(&self) -> bool {
         self.0.strong_count() == 0
     }
+    /// Constructs a [`BarrierCondition`] which is already completed.
+    pub(crate) fn completed() -> Self {
+        BarrierCondition(Weak::new())
+    }
+    pub(crate) fn id(&self) -> *const () {
+        Weak::as_ptr(&self.0)
+    }
 }
 
 /// The result of an operation that might be synchronous or asynchronous.
@@ -1356,10 +1730,36 @@ pub enum Feature {
     /// The ability of an entity to move forward or backwards by a distance.
     Forward,
 
+    /// The ability of an entity to play a sound.
+    PlaySound,
+    /// The ability of an entity to play musical notes.
+    PlayNote,
+    /// The ability to stop all sounds that are currently playing.
+    StopSounds,
+
+    /// The ability of an entity to check if it is touching another entity, the mouse pointer, the edge of the stage, or drawings.
+    IsTouching,
+    /// The ability of an entity to get its distance to another entity.
+    DistanceTo,
+    /// The ability of an entity to get the direction from itself to another entity.
+    DirectionTo,
+
     /// The ability of an entity to execute a specific block that was not built in to the ast parser or bytecode compiler (e.g., extension blocks).
     UnknownBlock { name: String },
 }
 
+/// The target of a [`Request::IsTouching`] query.
+pub enum TouchTarget<'gc, C: CustomTypes<S>, S: System<C>> {
+    /// Another entity.
+    Entity(Gc<'gc, RefLock<Entity<'gc, C, S>>>),
+    /// The mouse pointer.
+    Mouse,
+    /// The edge of the stage.
+    Edge,
+    /// Any drawings made by the pen of any sprite.
+    Drawings,
+}
+
 /// A value-returning request issued from the runtime.
 pub enum Request<'gc, C: CustomTypes<S>, S: System<C>> {
     /// Request input from the user. The `prompt` argument is either [`Some`] prompt to display, or [`None`] for no prompt.
@@ -1367,9 +1767,19 @@ pub enum Request<'gc, C: CustomTypes<S>, S: System<C>> {
     /// Performs a system call on the local hardware to access device resources.
     Syscall { name: String, args: Vec<Value<'gc, C, S>> },
     /// Requests the system to execute the given RPC.
-    Rpc { service: String, rpc: String, args: Vec<(String, Value<'gc, C, S>)> },
+    /// The `json_mode` is the process's [`Settings::json_mode`], which should be used to convert the `args` into json.
+    Rpc { service: String, rpc: String, args: Vec<(String, Value<'gc, C, S>)>, json_mode: JsonMode },
     /// Request to get the current value of an entity property.
     Property { prop: Property },
+    /// Request to check if the entity is touching the given target.
+    /// The target is never the requesting entity itself (the runtime answers that case directly).
+    IsTouching { target: TouchTarget<'gc, C, S> },
+    /// Request to get the distance from the entity to the target entity.
+    /// The target is never the requesting entity itself (the runtime answers that case directly).
+    DistanceTo { target: Gc<'gc, RefLock<Entity<'gc, C, S>>> },
+    /// Request to get the direction (as a heading in degrees, in the range `(-180, 180]`) from the entity to the target entity.
+    /// The target is never the requesting entity itself (the runtime answers that case directly).
+    DirectionTo { target: Gc<'gc, RefLock<Entity<'gc, C, S>>> },
     /// Request to run a block which was not known by the ast parser or bytecode compiler.
     /// This is typically used for implementing extension blocks in the VM, which cannot be handled otherwise.
     UnknownBlock { name: String, args: Vec<Value<'gc, C, S>> },
@@ -1382,6 +1792,9 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Request<'gc, C, S> {
             Request::Syscall { name, .. } => Feature::Syscall { name: name.clone() },
             Request::Rpc { service, rpc, .. } => Feature::Rpc { service: service.clone(), rpc: rpc.clone() },
             Request::Property { prop } => Feature::GetProperty { prop: *prop },
+            Request::IsTouching { .. } => Feature::IsTouching,
+            Request::DistanceTo { .. } => Feature::DistanceTo,
+            Request::DirectionTo { .. } => Feature::DirectionTo,
             Request::UnknownBlock { name, .. } => Feature::UnknownBlock { name: name.clone() },
         }
     }
@@ -1418,6 +1831,15 @@ pub enum Command<'gc, 'a, C: CustomTypes<S>, S: System<C>> {
 
     /// Move forward by a given distance. If the distance is negative, move backwards instead.
     Forward { distance: Number },
+
+    /// Play a sound. If `blocking` is true, the command should not be completed until the sound has finished playing.
+    PlaySound { sound: Rc<Vec<u8>>, blocking: bool },
+    /// Play the given notes (as MIDI note numbers) simultaneously for a number of beats at the entity's tempo (see [`Property::Tempo`]).
+    /// If `notes` is empty, this is a rest.
+    /// In either case, the command should not be completed until the given number of beats has elapsed.
+    PlayNote { notes: Vec<Number>, beats: Number },
+    /// Stop all sounds that are currently playing.
+    StopSounds,
 }
 impl<'gc, C: CustomTypes<S>, S: System<C>> Command<'gc, '_, C, S> {
     /// Gets the [`Feature`] associated with this command.
@@ -1434,6 +1856,9 @@ impl<'gc, C: CustomTypes<S>, S: System<C>> Command<'gc, '_, C, S> {
             Command::PointTowardsXY { .. } => Feature::PointTowardsXY,
             Command::PointTowardsEntity { .. } => Feature::PointTowardsEntity,
             Command::Forward { .. } => Feature::Forward,
+            Command::PlaySound { .. } => Feature::PlaySound,
+            Command::PlayNote { .. } => Feature::PlayNote,
+            Command::StopSounds => Feature::StopSounds,
         }
     }
 }
@@ -0,0 +1,492 @@
+//! Snapshots of the complete execution state of a running project.
+//!
+//! All program-accessible objects live inside a garbage collected arena (see [`process`](crate::process)),
+//! so they cannot be stored directly.
+//! Instead, [`Project::snapshot`](crate::project::Project::snapshot) creates a [`ProjectSnapshot`], which is a plain copy of the global context,
+//! every entity, and every process (including call stacks, exception handlers, and pending waits),
+//! and [`Project::from_snapshot`](crate::project::Project::from_snapshot) restores it into a (possibly new) arena.
+//! Reference types (lists, closures, entities, and aliased variables) are stored in a single table which is referenced by index,
+//! so sharing and cycles are preserved across a save and restore.
+//!
+//! The bytecode, settings, and system are not part of the snapshot and must be provided again when restoring.
+//! The bytecode must be the same as the one used by the project when the snapshot was taken.
+//!
+//! Some state cannot be captured:
+//! - [`Value::Native`] values are owned by the [`System`], so attempting to snapshot one results in [`SnapshotError::NativeValue`].
+//! - Asynchronous operations (requests, commands, and message replies) which were in progress when the snapshot was taken cannot be resumed.
+//!   Upon restoring, interrupted requests fail with an error (subject to the usual [`ErrorScheme`]),
+//!   interrupted commands are treated as completed, and interrupted message replies are treated as timed out.
+//! - Reply keys for received messages are owned by the [`System`], so replies to messages received before the snapshot are not sent.
+//!
+//! The types in this module support serde serialization if the `serde` feature flag is enabled.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::rc::Rc;
+
+use core::marker::PhantomData;
+use core::iter;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::*;
+use crate::gc::*;
+use crate::runtime::*;
+use crate::bytecode::*;
+
+/// An error produced when creating or restoring a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The project state contained a [`Value::Native`], which cannot be captured.
+    NativeValue,
+    /// The snapshot referenced a missing table entry, or an entry of the wrong kind.
+    BadReference { index: usize },
+    /// The snapshot contained a process with an empty call stack.
+    EmptyCallStack,
+    /// The snapshot is not consistent with the provided bytecode.
+    VerifyError { error: VerifyError },
+}
+
+/// A snapshot of a [`Value`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum ValueSnapshot {
+    Bool(bool),
+    Number(Number),
+    String(String),
+    /// An image, given as an index into [`ProjectSnapshot::blobs`].
+    Image(usize),
+    /// An audio clip, given as an index into [`ProjectSnapshot::blobs`].
+    Audio(usize),
+    /// A list, closure, or entity, given as an index into [`ProjectSnapshot::refs`].
+    Ref(usize),
+}
+
+/// A snapshot of a variable.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum VarSnapshot {
+    /// A variable with a single handle (see [`Shared::Unique`]).
+    Unique(ValueSnapshot),
+    /// A handle to a variable shared by several owners (see [`Shared::Aliased`]), given as an index into [`ProjectSnapshot::refs`].
+    Aliased(usize),
+}
+
+/// A snapshot of an [`Entity`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct EntitySnapshot<E> {
+    pub name: String,
+    /// The names of the costumes and their images, given as indices into [`ProjectSnapshot::blobs`].
+    pub costume_list: Vec<(String, usize)>,
+    /// The current costume, given as an index into [`ProjectSnapshot::blobs`].
+    pub costume: Option<usize>,
+    /// The names of the sounds and their audio, given as indices into [`ProjectSnapshot::blobs`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sound_list: Vec<(String, usize)>,
+    pub state: E,
+    pub alive: bool,
+    pub clone_count: usize,
+    /// The original entity for a clone, given as an index into [`ProjectSnapshot::refs`].
+    pub root: Option<usize>,
+    pub fields: Vec<(String, VarSnapshot)>,
+}
+
+/// A snapshot of a reference type, which may be referenced any number of times.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum RefSnapshot<E> {
+    List(Vec<ValueSnapshot>),
+    /// A list that was made from a json object (see [`List::json_object`]).
+    JsonObject(Vec<ValueSnapshot>),
+    Closure { pos: usize, params: Vec<String>, captures: Vec<(String, VarSnapshot)> },
+    Entity(EntitySnapshot<E>),
+    /// The value of a variable shared by several owners (see [`VarSnapshot::Aliased`]).
+    Cell(ValueSnapshot),
+}
+
+/// The kind of an interrupted request.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy)]
+pub enum RequestKind {
+    Rpc, Syscall, Input, Push,
+}
+
+/// A snapshot of an operation which a [`Process`](crate::process::Process) is waiting on.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum DeferSnapshot {
+    /// An in-progress request, which will fail upon being restored.
+    Request { kind: RequestKind, aft_pos: usize },
+    /// An in-progress command, which will be considered complete upon being restored.
+    Command { aft_pos: usize },
+    /// An in-progress wait for a message reply, which will be considered timed out upon being restored.
+    MessageReply { aft_pos: usize },
+    /// A wait for a [`BarrierCondition`], which is an index into the barriers of the snapshot, or [`None`] if already completed.
+    Barrier { condition: Option<usize>, aft_pos: usize },
+    /// A wait until the given time (in the same units as [`SysTime::to_arbitrary_ms`]).
+    Sleep { until: u64, aft_pos: usize },
+}
+
+/// A snapshot of an entry in the call stack of a [`Process`](crate::process::Process).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct CallStackEntrySnapshot {
+    pub called_from: usize,
+    pub return_to: usize,
+    /// The entity, given as an index into [`ProjectSnapshot::refs`].
+    pub entity: usize,
+    pub locals: Vec<(String, VarSnapshot)>,
+    pub warp_counter: usize,
+    pub value_stack_size: usize,
+    pub handler_stack_size: usize,
+}
+
+/// A snapshot of an exception handler of a [`Process`](crate::process::Process).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HandlerSnapshot {
+    pub pos: usize,
+    pub var: String,
+    pub warp_counter: usize,
+    pub call_stack_size: usize,
+    pub value_stack_size: usize,
+}
+
+/// A snapshot of a [`Process`](crate::process::Process).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ProcessSnapshot {
+    pub start_pos: usize,
+    pub pos: usize,
+    pub running: bool,
+    /// The barrier held by the process, given as an index into the barriers of the snapshot.
+    pub barrier: Option<usize>,
+    pub warp_counter: usize,
+    pub call_stack: Vec<CallStackEntrySnapshot>,
+    pub value_stack: Vec<ValueSnapshot>,
+    pub handler_stack: Vec<HandlerSnapshot>,
+    pub defer: Option<DeferSnapshot>,
+    pub last_syscall_error: Option<ValueSnapshot>,
+    pub last_rpc_error: Option<ValueSnapshot>,
+    pub last_answer: Option<ValueSnapshot>,
+    pub last_message: Option<ValueSnapshot>,
+}
+
+/// A snapshot of a [`ProcContext`](crate::process::ProcContext) which is queued for execution.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ProcContextSnapshot {
+    pub locals: Vec<(String, VarSnapshot)>,
+    /// The barrier held by the context, given as an index into the barriers of the snapshot.
+    pub barrier: Option<usize>,
+    pub local_message: Option<String>,
+}
+
+/// A snapshot of a script of a [`Project`](crate::project::Project).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ScriptSnapshot {
+    pub event: Event,
+    pub start_pos: usize,
+    /// The entity, given as an index into [`ProjectSnapshot::refs`].
+    pub entity: usize,
+    /// The process running the script, given as an index into [`ProjectSnapshot::processes`].
+    pub process: Option<usize>,
+    pub context_queue: Vec<ProcContextSnapshot>,
+    pub condition_proc: Option<ProcessSnapshot>,
+    pub condition_state: bool,
+}
+
+/// A snapshot of the complete execution state of a [`Project`](crate::project::Project).
+///
+/// The type parameter `E` is the [`CustomTypes::EntityState`] type of the project.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ProjectSnapshot<E> {
+    /// The table of reference types, which are referenced by index from the rest of the snapshot.
+    pub refs: Vec<RefSnapshot<E>>,
+    /// The table of binary data (images and audio), which are referenced by index from the rest of the snapshot.
+    pub blobs: Vec<Vec<u8>>,
+    /// The number of distinct barriers (used by broadcast and wait), which are referenced by index from the rest of the snapshot.
+    pub barriers: usize,
+
+    pub proj_name: String,
+    pub timer_start: u64,
+    pub held_keys: Vec<(KeyCode, u64)>,
+    pub globals: Vec<(String, VarSnapshot)>,
+    /// The (original) entities in the project, given as indices into [`ProjectSnapshot::refs`].
+    pub entities: Vec<(String, usize)>,
+
+    pub processes: Vec<ProcessSnapshot>,
+    /// The queue of processes to run, given as indices into [`ProjectSnapshot::processes`].
+    pub process_queue: Vec<usize>,
+    pub scripts: Vec<ScriptSnapshot>,
+    /// The clones which have not been deleted, given as indices into [`ProjectSnapshot::refs`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub clones: Vec<usize>,
+    pub steps_till_poll: usize,
+}
+impl<E> ProjectSnapshot<E> {
+    /// Checks that every code address held by the snapshot is valid for the given bytecode (see [`ByteCode::verify`]).
+    pub(crate) fn verify(&self, bytecode: &ByteCode) -> Result<(), VerifyError> {
+        fn process_positions(process: &ProcessSnapshot, res: &mut Vec<usize>) {
+            res.extend([process.start_pos, process.pos]);
+            for entry in process.call_stack.iter().skip(1) { res.extend([entry.called_from, entry.return_to]); }
+            res.extend(process.handler_stack.iter().map(|x| x.pos));
+            if let Some(defer) = &process.defer {
+                res.push(match defer {
+                    DeferSnapshot::Request { aft_pos, .. } | DeferSnapshot::Command { aft_pos } | DeferSnapshot::MessageReply { aft_pos }
+                    | DeferSnapshot::Barrier { aft_pos, .. } | DeferSnapshot::Sleep { aft_pos, .. } => *aft_pos,
+                });
+            }
+        }
+
+        let mut entries = vec![];
+        let mut positions = vec![];
+        for script in self.scripts.iter() {
+            entries.push((script.entity, script.start_pos));
+            if let Event::When { condition } = &script.event { entries.push((script.entity, *condition)); }
+            if let Some(process) = &script.condition_proc { process_positions(process, &mut positions); }
+        }
+        for process in self.processes.iter() { process_positions(process, &mut positions); }
+        for value in self.refs.iter() {
+            if let RefSnapshot::Closure { pos, .. } = value { positions.push(*pos); }
+        }
+        bytecode.verify_snapshot(&entries, &positions)
+    }
+}
+
+/// The reference table, binary data table, and barrier count of a [`ProjectSnapshot`].
+pub(crate) type SnapshotTables<E> = (Vec<RefSnapshot<E>>, Vec<Vec<u8>>, usize);
+
+/// Converts reference types into entries in the tables of a [`ProjectSnapshot`], keeping track of identity.
+pub(crate) struct SnapshotSaver<'gc, C: CustomTypes<S>, S: System<C>> {
+    refs: Vec<Option<RefSnapshot<C::EntityState>>>,
+    ref_ids: BTreeMap<*const (), usize>,
+    blobs: Vec<Vec<u8>>,
+    blob_ids: BTreeMap<*const Vec<u8>, usize>,
+    barrier_ids: BTreeMap<*const (), usize>,
+    _phantom: PhantomData<Value<'gc, C, S>>,
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> SnapshotSaver<'gc, C, S> where C::EntityState: Clone {
+    pub(crate) fn new() -> Self {
+        Self { refs: vec![], ref_ids: Default::default(), blobs: vec![], blob_ids: Default::default(), barrier_ids: Default::default(), _phantom: PhantomData }
+    }
+    /// Returns the tables of reference types and binary data, as well as the number of barriers.
+    pub(crate) fn finish(self) -> SnapshotTables<C::EntityState> {
+        (self.refs.into_iter().map(Option::unwrap).collect(), self.blobs, self.barrier_ids.len())
+    }
+    /// Reserves a new entry in the reference table for the given identity,
+    /// or returns [`Err`] with the existing index if the identity was already visited.
+    fn reserve(&mut self, id: *const ()) -> Result<usize, usize> {
+        if let Some(&idx) = self.ref_ids.get(&id) { return Err(idx) }
+        let idx = self.refs.len();
+        self.refs.push(None);
+        self.ref_ids.insert(id, idx);
+        Ok(idx)
+    }
+    fn save_blob(&mut self, blob: &Rc<Vec<u8>>) -> usize {
+        let blobs = &mut self.blobs;
+        *self.blob_ids.entry(Rc::as_ptr(blob)).or_insert_with(|| {
+            blobs.push((**blob).clone());
+            blobs.len() - 1
+        })
+    }
+    pub(crate) fn save_barrier(&mut self, barrier: &Barrier) -> usize {
+        let count = self.barrier_ids.len();
+        *self.barrier_ids.entry(barrier.id()).or_insert(count)
+    }
+    pub(crate) fn save_condition(&mut self, condition: &BarrierCondition) -> Option<usize> {
+        if condition.is_completed() { return None }
+        let count = self.barrier_ids.len();
+        Some(*self.barrier_ids.entry(condition.id()).or_insert(count))
+    }
+    pub(crate) fn save_value(&mut self, value: &Value<'gc, C, S>) -> Result<ValueSnapshot, SnapshotError> {
+        Ok(match value {
+            Value::Bool(x) => ValueSnapshot::Bool(*x),
+            Value::Number(x) => ValueSnapshot::Number(*x),
+            Value::String(x) => ValueSnapshot::String((**x).clone()),
+            Value::Image(x) => ValueSnapshot::Image(self.save_blob(x)),
+            Value::Audio(x) => ValueSnapshot::Audio(self.save_blob(x)),
+            Value::Native(_) => return Err(SnapshotError::NativeValue),
+            Value::Entity(x) => ValueSnapshot::Ref(self.save_entity(*x)?),
+            Value::List(x) => ValueSnapshot::Ref(match self.reserve(Gc::as_ptr(*x) as *const ()) {
+                Err(idx) => idx,
+                Ok(idx) => {
+                    let list = x.borrow();
+                    let values = list.iter().map(|x| self.save_value(x)).collect::<Result<_,_>>()?;
+                    self.refs[idx] = Some(match list.json_object {
+                        true => RefSnapshot::JsonObject(values),
+                        false => RefSnapshot::List(values),
+                    });
+                    idx
+                }
+            }),
+            Value::Closure(x) => ValueSnapshot::Ref(match self.reserve(Gc::as_ptr(*x) as *const ()) {
+                Err(idx) => idx,
+                Ok(idx) => {
+                    let closure = x.borrow();
+                    let captures = self.save_symbols(&closure.captures)?;
+                    self.refs[idx] = Some(RefSnapshot::Closure { pos: closure.pos, params: closure.params.clone(), captures });
+                    idx
+                }
+            }),
+        })
+    }
+    pub(crate) fn save_var(&mut self, var: &Shared<'gc, Value<'gc, C, S>>) -> Result<VarSnapshot, SnapshotError> {
+        Ok(match var {
+            Shared::Unique(x) => VarSnapshot::Unique(self.save_value(x)?),
+            Shared::Aliased(x) => VarSnapshot::Aliased(match self.reserve(Gc::as_ptr(*x) as *const ()) {
+                Err(idx) => idx,
+                Ok(idx) => {
+                    let value = self.save_value(&x.borrow())?;
+                    self.refs[idx] = Some(RefSnapshot::Cell(value));
+                    idx
+                }
+            }),
+        })
+    }
+    pub(crate) fn save_symbols(&mut self, symbols: &SymbolTable<'gc, C, S>) -> Result<Vec<(String, VarSnapshot)>, SnapshotError> {
+        symbols.iter().map(|(k, v)| Ok((k.clone(), self.save_var(v)?))).collect()
+    }
+    pub(crate) fn save_entity(&mut self, entity: Gc<'gc, RefLock<Entity<'gc, C, S>>>) -> Result<usize, SnapshotError> {
+        let idx = match self.reserve(Gc::as_ptr(entity) as *const ()) {
+            Err(idx) => return Ok(idx),
+            Ok(idx) => idx,
+        };
+
+        let entity = entity.borrow();
+        let costume_list = entity.costume_list.iter().map(|(name, image)| (name.clone(), self.save_blob(image))).collect();
+        let costume = entity.costume.as_ref().map(|x| self.save_blob(x));
+        let sound_list = entity.sound_list.iter().map(|(name, sound)| (name.clone(), self.save_blob(sound))).collect();
+        let root = entity.root.map(|x| self.save_entity(x)).transpose()?;
+        let fields = self.save_symbols(&entity.fields)?;
+
+        self.refs[idx] = Some(RefSnapshot::Entity(EntitySnapshot {
+            name: (*entity.name).clone(),
+            state: entity.state.clone(),
+            alive: entity.alive,
+            clone_count: entity.clone_count,
+            costume_list, costume, sound_list, root, fields,
+        }));
+        Ok(idx)
+    }
+}
+
+enum LoadedRef<'gc, C: CustomTypes<S>, S: System<C>> {
+    List(Gc<'gc, RefLock<List<'gc, C, S>>>),
+    Closure(Gc<'gc, RefLock<Closure<'gc, C, S>>>),
+    Entity(Gc<'gc, RefLock<Entity<'gc, C, S>>>),
+    Cell(Gc<'gc, RefLock<Value<'gc, C, S>>>),
+}
+
+/// Converts the tables of a [`ProjectSnapshot`] back into reference types, preserving identity.
+pub(crate) struct SnapshotLoader<'gc, C: CustomTypes<S>, S: System<C>> {
+    refs: Vec<LoadedRef<'gc, C, S>>,
+    blobs: Vec<Rc<Vec<u8>>>,
+    barriers: Vec<Barrier>,
+}
+impl<'gc, C: CustomTypes<S>, S: System<C>> SnapshotLoader<'gc, C, S> where C::EntityState: Clone {
+    pub(crate) fn new(mc: &Mutation<'gc>, snapshot: &ProjectSnapshot<C::EntityState>) -> Result<Self, SnapshotError> {
+        let blobs: Vec<_> = snapshot.blobs.iter().map(|x| Rc::new(x.clone())).collect();
+        let blob = |idx: usize| blobs.get(idx).cloned().ok_or(SnapshotError::BadReference { index: idx });
+
+        // first pass: allocate every reference type so that they can be referenced (possibly cyclically) in the second pass
+        let mut refs = Vec::with_capacity(snapshot.refs.len());
+        for value in snapshot.refs.iter() {
+            refs.push(match value {
+                RefSnapshot::List(_) | RefSnapshot::JsonObject(_) => LoadedRef::List(Gc::new(mc, Default::default())),
+                RefSnapshot::Cell(_) => LoadedRef::Cell(Gc::new(mc, RefLock::new(Value::Bool(false)))),
+                RefSnapshot::Closure { pos, params, captures: _ } => LoadedRef::Closure(Gc::new(mc, RefLock::new(Closure { pos: *pos, params: params.clone(), captures: Default::default() }))),
+                RefSnapshot::Entity(entity) => LoadedRef::Entity(Gc::new(mc, RefLock::new(Entity {
+                    name: Rc::new(entity.name.clone()),
+                    costume_list: Rc::new(entity.costume_list.iter().map(|(name, image)| Ok((name.clone(), blob(*image)?))).collect::<Result<_,_>>()?),
+                    costume: entity.costume.map(blob).transpose()?,
+                    sound_list: Rc::new(entity.sound_list.iter().map(|(name, sound)| Ok((name.clone(), blob(*sound)?))).collect::<Result<_,_>>()?),
+                    state: entity.state.clone(),
+                    alive: entity.alive,
+                    clone_count: entity.clone_count,
+                    root: None,
+                    fields: Default::default(),
+                }))),
+            });
+        }
+
+        let loader = Self { refs, blobs, barriers: (0..snapshot.barriers).map(|_| Barrier::new()).collect() };
+        for (value, loaded) in iter::zip(&snapshot.refs, &loader.refs) {
+            match (value, loaded) {
+                (RefSnapshot::List(values), LoadedRef::List(list)) | (RefSnapshot::JsonObject(values), LoadedRef::List(list)) => {
+                    let mut list = list.borrow_mut(mc);
+                    list.json_object = matches!(value, RefSnapshot::JsonObject(_));
+                    for value in values {
+                        list.push_back(loader.load_value(value)?);
+                    }
+                }
+                (RefSnapshot::Cell(value), LoadedRef::Cell(cell)) => *cell.borrow_mut(mc) = loader.load_value(value)?,
+                (RefSnapshot::Closure { captures, .. }, LoadedRef::Closure(closure)) => closure.borrow_mut(mc).captures = loader.load_symbols(captures)?,
+                (RefSnapshot::Entity(src), LoadedRef::Entity(entity)) => {
+                    let root = src.root.map(|x| loader.load_entity(x)).transpose()?;
+                    let fields = loader.load_symbols(&src.fields)?;
+
+                    let mut entity = entity.borrow_mut(mc);
+                    entity.root = root;
+                    entity.fields = fields;
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(loader)
+    }
+    fn load_blob(&self, idx: usize) -> Result<Rc<Vec<u8>>, SnapshotError> {
+        self.blobs.get(idx).cloned().ok_or(SnapshotError::BadReference { index: idx })
+    }
+    pub(crate) fn load_barrier(&self, idx: usize) -> Result<Barrier, SnapshotError> {
+        self.barriers.get(idx).cloned().ok_or(SnapshotError::BadReference { index: idx })
+    }
+    pub(crate) fn load_condition(&self, idx: Option<usize>) -> Result<BarrierCondition, SnapshotError> {
+        Ok(match idx {
+            Some(idx) => self.load_barrier(idx)?.get_condition(),
+            None => BarrierCondition::completed(),
+        })
+    }
+    pub(crate) fn load_value(&self, value: &ValueSnapshot) -> Result<Value<'gc, C, S>, SnapshotError> {
+        Ok(match value {
+            ValueSnapshot::Bool(x) => Value::Bool(*x),
+            ValueSnapshot::Number(x) => Value::Number(*x),
+            ValueSnapshot::String(x) => Value::String(Rc::new(x.clone())),
+            ValueSnapshot::Image(x) => Value::Image(self.load_blob(*x)?),
+            ValueSnapshot::Audio(x) => Value::Audio(self.load_blob(*x)?),
+            ValueSnapshot::Ref(x) => match self.refs.get(*x) {
+                Some(LoadedRef::List(x)) => Value::List(*x),
+                Some(LoadedRef::Closure(x)) => Value::Closure(*x),
+                Some(LoadedRef::Entity(x)) => Value::Entity(*x),
+                Some(LoadedRef::Cell(_)) | None => return Err(SnapshotError::BadReference { index: *x }),
+            }
+        })
+    }
+    pub(crate) fn load_var(&self, var: &VarSnapshot) -> Result<Shared<'gc, Value<'gc, C, S>>, SnapshotError> {
+        Ok(match var {
+            VarSnapshot::Unique(x) => Shared::Unique(self.load_value(x)?),
+            VarSnapshot::Aliased(x) => match self.refs.get(*x) {
+                Some(LoadedRef::Cell(x)) => Shared::Aliased(*x),
+                _ => return Err(SnapshotError::BadReference { index: *x }),
+            }
+        })
+    }
+    pub(crate) fn load_symbols(&self, symbols: &[(String, VarSnapshot)]) -> Result<SymbolTable<'gc, C, S>, SnapshotError> {
+        let mut res = SymbolTable::default();
+        for (var, value) in symbols {
+            res.define_or_redefine(var, self.load_var(value)?);
+        }
+        Ok(res)
+    }
+    pub(crate) fn load_entity(&self, idx: usize) -> Result<Gc<'gc, RefLock<Entity<'gc, C, S>>>, SnapshotError> {
+        match self.refs.get(idx) {
+            Some(LoadedRef::Entity(x)) => Ok(*x),
+            _ => Err(SnapshotError::BadReference { index: idx }),
+        }
+    }
+}
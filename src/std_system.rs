@@ -8,12 +8,13 @@
 //! as well as overriding default behavior (e.g., rpc intercepting).
 
 use alloc::string::{String, ToString};
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::borrow::ToOwned;
 use alloc::vec::Vec;
+use alloc::boxed::Box;
 use alloc::rc::Rc;
 
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{Sender, Receiver, channel};
 use std::thread;
@@ -23,15 +24,18 @@ use rand_chacha::ChaChaRng;
 use rand::{Rng, SeedableRng};
 use tokio_tungstenite::tungstenite::Message;
 use futures::{StreamExt, SinkExt};
+use futures::future::BoxFuture;
 use uuid::Uuid;
 
 use crate::real_time::*;
 use crate::runtime::*;
+use crate::replay::*;
 use crate::json::*;
 use crate::gc::*;
 use crate::*;
 
 const MESSAGE_REPLY_TIMEOUT_MS: u32 = 1500;
+const OFFLINE_ROLE_NAME: &str = "myRole";
 
 /// A [`StdSystem`] key type used to await a reply message from an external source.
 #[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
@@ -43,6 +47,7 @@ pub struct ExternReplyKey {
 pub struct InternReplyKey {
     src_id: String,
     request_id: String,
+    local: bool,
 }
 
 struct Context {
@@ -60,6 +65,18 @@ struct RpcRequest<C: CustomTypes<StdSystem<C>>> {
     rpc: String,
     args: Vec<(String, Json)>,
     key: RequestKey<C>,
+    handler: Option<AsyncRpcHandler<C>>,
+}
+
+type SyncRpcHandler<C> = Box<dyn Fn(&[(String, Json)]) -> Result<<C as CustomTypes<StdSystem<C>>>::Intermediate, String>>;
+type AsyncRpcHandler<C> = Arc<dyn Fn(Vec<(String, Json)>) -> BoxFuture<'static, Result<<C as CustomTypes<StdSystem<C>>>::Intermediate, String>> + Send + Sync>;
+
+/// A local implementation of an RPC, which can be registered with [`StdSystem::with_rpc`].
+pub enum LocalRpc<C: CustomTypes<StdSystem<C>>> {
+    /// An RPC which computes its result immediately (on the thread running the project).
+    Sync(SyncRpcHandler<C>),
+    /// An RPC which computes its result asynchronously, on the same runtime used to call remote RPCs.
+    Async(AsyncRpcHandler<C>),
 }
 struct ReplyEntry {
     timestamp: Instant,
@@ -99,6 +116,49 @@ impl Key<Result<(), String>> for CommandKey {
 
 type MessageReplies = BTreeMap<ExternReplyKey, ReplyEntry>;
 
+struct LocalPeer<C: CustomTypes<StdSystem<C>>> {
+    message_injector: Sender<IncomingMessage<C, StdSystem<C>>>,
+    message_replies: Arc<Mutex<MessageReplies>>,
+}
+
+/// A group of [`StdSystem`] instances running in the same process (e.g., the roles of a multi-role project)
+/// which deliver network messages to one another directly rather than through the NetsBlox server.
+///
+/// Systems are added to the network by [`StdSystem::with_local_network`] and are addressed by role name.
+/// Message targets of the form `role`, `role@project` (where `project` is the name of the network),
+/// `everyone in room`, and `others in room` are delivered locally;
+/// any other targets are sent to the NetsBlox server as usual.
+pub struct LocalNetwork<C: CustomTypes<StdSystem<C>>> {
+    project_name: String,
+    peers: Mutex<BTreeMap<String, LocalPeer<C>>>,
+}
+impl<C: CustomTypes<StdSystem<C>>> LocalNetwork<C> {
+    /// Creates a new, empty local network for the given project.
+    pub fn new(project_name: String) -> Self {
+        Self { project_name, peers: Default::default() }
+    }
+    /// Gets the (sorted) role names of all the systems in the network.
+    pub fn roles(&self) -> Vec<String> {
+        self.peers.lock().unwrap().keys().cloned().collect()
+    }
+    /// Resolves a message target into a list of local roles, or [`None`] if the target is not local.
+    fn resolve(&self, target: &str, src_role: &str) -> Option<Vec<String>> {
+        let peers = self.peers.lock().unwrap();
+        match target {
+            "everyone in room" => Some(peers.keys().cloned().collect()),
+            "others in room" => Some(peers.keys().filter(|x| *x != src_role).cloned().collect()),
+            _ => {
+                let role = match target.split_once('@') {
+                    Some((role, project)) if project.split('@').next() == Some(self.project_name.as_str()) => role, // role@project or role@project@owner
+                    Some(_) => return None,
+                    None => target,
+                };
+                peers.contains_key(role).then(|| vec![role.to_owned()])
+            }
+        }
+    }
+}
+
 async fn call_rpc_async<C: CustomTypes<StdSystem<C>>>(context: &Context, client: &reqwest::Client, service: &str, rpc: &str, args: &[(&str, &Json)]) -> Result<C::Intermediate, String> {
     let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
     let url = format!("{services_url}/{service}/{rpc}?clientId={client_id}&t={time}",
@@ -135,6 +195,59 @@ async fn call_rpc_async<C: CustomTypes<StdSystem<C>>>(context: &Context, client:
     }
 }
 
+/// A clock which only advances when explicitly told to, which can be used by an offline [`StdSystem`] in place of the real time.
+pub struct ManualClock {
+    now: Mutex<OffsetDateTime>,
+}
+impl ManualClock {
+    /// Creates a new clock which starts at the given time.
+    pub fn new(start: OffsetDateTime) -> Self {
+        Self { now: Mutex::new(start) }
+    }
+    /// Gets the current time of the clock.
+    pub fn now(&self) -> OffsetDateTime {
+        *self.now.lock().unwrap()
+    }
+    /// Sets the current time of the clock.
+    pub fn set(&self, time: OffsetDateTime) {
+        *self.now.lock().unwrap() = time;
+    }
+    /// Advances the clock by the given amount of time.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+/// Options for an offline [`StdSystem`] created by [`StdSystem::new_offline`].
+#[derive(Default)]
+pub struct OfflineOptions {
+    /// The seed for the random number generator, or [`None`] (default) to use a random seed.
+    pub seed: Option<u64>,
+    /// A clock to use in place of the real time, or [`None`] (default) to use the real time.
+    pub clock: Option<Arc<ManualClock>>,
+}
+
+fn spawn_rpc_handler<C: CustomTypes<StdSystem<C>>>(client: Arc<reqwest::Client>, context: Arc<Context>) -> Sender<RpcRequest<C>> {
+    let (sender, receiver) = channel();
+
+    #[tokio::main(flavor = "multi_thread", worker_threads = 1)]
+    async fn handler<C: CustomTypes<StdSystem<C>>>(client: Arc<reqwest::Client>, context: Arc<Context>, receiver: Receiver<RpcRequest<C>>) {
+        while let Ok(request) = receiver.recv() {
+            let (client, context) = (client.clone(), context.clone());
+            tokio::spawn(async move {
+                let res = match request.handler {
+                    Some(handler) => handler(request.args).await,
+                    None => call_rpc_async::<C>(&context, &client, &request.service, &request.rpc, &request.args.iter().map(|x| (x.0.as_str(), &x.1)).collect::<Vec<_>>()).await,
+                };
+                request.key.complete(res);
+            });
+        }
+    }
+    thread::spawn(move || handler(client, context, receiver));
+
+    sender
+}
+
 /// A type implementing the [`System`] trait which supports all features.
 pub struct StdSystem<C: CustomTypes<StdSystem<C>>> {
     config: Config<C, Self>,
@@ -149,6 +262,12 @@ pub struct StdSystem<C: CustomTypes<StdSystem<C>>> {
     message_sender: Sender<OutgoingMessage<C, Self>>,
     message_injector: Sender<IncomingMessage<C, Self>>,
     message_receiver: Receiver<IncomingMessage<C, Self>>,
+
+    local_network: Option<(Arc<LocalNetwork<C>>, String)>,
+    replay: ReplayMode,
+    offline: bool,
+    clock: Option<Arc<ManualClock>>,
+    local_rpcs: BTreeMap<(String, String), LocalRpc<C>>,
 }
 impl<C: CustomTypes<StdSystem<C>>> StdSystem<C> {
     /// Equivalent to [`StdSystem::new_async`] except that it can be executed outside of async context.
@@ -226,7 +345,7 @@ impl<C: CustomTypes<StdSystem<C>>> StdSystem<C> {
                                     } else {
                                         let reply_key = match msg.contains_key("requestId") {
                                             true => match (msg.remove("srcId"), msg.remove("requestId")) {
-                                                (Some(Json::String(src_id)), Some(Json::String(request_id))) => Some(InternReplyKey { src_id, request_id }),
+                                                (Some(Json::String(src_id)), Some(Json::String(request_id))) => Some(InternReplyKey { src_id, request_id, local: false }),
                                                 _ => return,
                                             }
                                             false => None,
@@ -290,34 +409,65 @@ impl<C: CustomTypes<StdSystem<C>>> StdSystem<C> {
         context.role_name = first_role_meta.get("name").unwrap().as_str().unwrap().to_owned();
 
         let context = Arc::new(context);
-        let rpc_request_pipe = {
-            let (client, context) = (client.clone(), context.clone());
-            let (sender, receiver) = channel();
+        let rpc_request_pipe = spawn_rpc_handler(client.clone(), context.clone());
 
-            #[tokio::main(flavor = "multi_thread", worker_threads = 1)]
-            async fn handler<C: CustomTypes<StdSystem<C>>>(client: Arc<reqwest::Client>, context: Arc<Context>, receiver: Receiver<RpcRequest<C>>) {
-                while let Ok(request) = receiver.recv() {
-                    let (client, context) = (client.clone(), context.clone());
-                    tokio::spawn(async move {
-                        let res = call_rpc_async::<C>(&context, &client, &request.service, &request.rpc, &request.args.iter().map(|x| (x.0.as_str(), &x.1)).collect::<Vec<_>>()).await;
-                        request.key.complete(res);
-                    });
-                }
-            }
-            thread::spawn(move || handler(client, context, receiver));
+        let mut seed: <ChaChaRng as SeedableRng>::Seed = Default::default();
+        getrandom::getrandom(&mut seed).expect("failed to generate random seed");
 
-            sender
+        let config = config.fallback(&Self::default_config());
+
+        Self {
+            config, context, client, utc_offset,
+            rng: Mutex::new(ChaChaRng::from_seed(seed)),
+            rpc_request_pipe,
+            message_replies, message_sender, message_receiver, message_injector,
+            local_network: None,
+            replay: ReplayMode::Off,
+            offline: false,
+            clock: None,
+            local_rpcs: Default::default(),
+        }
+    }
+    /// Creates a new instance of [`StdSystem`] which does not connect to the NetsBlox server.
+    ///
+    /// The system is a member of a [`LocalNetwork`] containing only itself (under the role name `myRole`), so messages sent to itself
+    /// (e.g., to `everyone in room`) are delivered as usual; this can be replaced by [`StdSystem::with_local_network`].
+    /// Messages to any other targets are discarded, and RPCs fail with an error unless handled by the `config` or registered with [`StdSystem::with_rpc`].
+    pub fn new_offline(project_name: Option<&str>, config: Config<C, Self>, utc_offset: UtcOffset, options: OfflineOptions) -> Self {
+        let project_name = project_name.unwrap_or("untitled");
+        let seed = match options.seed {
+            Some(seed) => seed,
+            None => {
+                let mut seed = [0; 8];
+                getrandom::getrandom(&mut seed).expect("failed to generate random seed");
+                u64::from_le_bytes(seed)
+            }
         };
 
-        let mut seed: <ChaChaRng as SeedableRng>::Seed = Default::default();
-        getrandom::getrandom(&mut seed).expect("failed to generate random seed");
+        let config = config.fallback(&Self::default_config());
 
-        let config = config.fallback(&Config {
+        let mut res = Self::new_disconnected(project_name, config, utc_offset, ChaChaRng::seed_from_u64(seed), ReplayMode::Off);
+        res.rpc_request_pipe = spawn_rpc_handler(res.client.clone(), res.context.clone());
+        res.clock = options.clock;
+        res.with_local_network(Arc::new(LocalNetwork::new(project_name.to_owned())), OFFLINE_ROLE_NAME)
+    }
+    /// Creates a new instance of [`StdSystem`] which replays a recording made by a system with [`StdSystem::with_recorder`].
+    /// The replaying system does not connect to the NetsBlox server; instead, all nondeterministic events
+    /// (request results, random numbers, the system time, and messages) are taken from the recording.
+    /// Outgoing messages are discarded, but commands are still performed by the `config` so that their effects (e.g., printing) are visible.
+    ///
+    /// Project inputs are not handled by the system, so the caller is responsible for giving the project the inputs from [`Replayer::take_inputs`].
+    pub fn new_replay(replayer: Rc<Replayer>, config: Config<C, Self>, utc_offset: UtcOffset) -> Self {
+        let rng = ChaChaRng::from_seed(replayer.seed().unwrap_or_default());
+        Self::new_disconnected("replay", config, utc_offset, rng, ReplayMode::Replaying(replayer))
+    }
+    fn default_config() -> Config<C, Self> {
+        Config {
             request: Some(Rc::new(|system, _, key, request, _| {
                 match request {
-                    Request::Rpc { service, rpc, args } => {
-                        match args.into_iter().map(|(k, v)| Ok((k, v.to_json()?))).collect::<Result<_,ToJsonError<_,_>>>() {
-                            Ok(args) => system.rpc_request_pipe.send(RpcRequest { service, rpc, args, key }).unwrap(),
+                    Request::Rpc { service, rpc, args, json_mode } => {
+                        match args.into_iter().map(|(k, v)| Ok((k, v.to_json_with(json_mode)?))).collect::<Result<_,ToJsonError<_,_>>>() {
+                            Ok(args) => system.dispatch_rpc(service, rpc, args, key),
                             Err(err) => key.complete(Err(format!("failed to convert RPC args to json: {err:?}"))),
                         }
                         RequestStatus::Handled
@@ -326,19 +476,86 @@ impl<C: CustomTypes<StdSystem<C>>> StdSystem<C> {
                 }
             })),
             command: None,
+        }
+    }
+    fn new_disconnected(project_name: &str, config: Config<C, Self>, utc_offset: UtcOffset, rng: ChaChaRng, replay: ReplayMode) -> Self {
+        let context = Arc::new(Context {
+            base_url: String::new(),
+            client_id: "_offline".into(),
+            services_url: String::new(),
+
+            project_name: project_name.to_owned(),
+            project_id: String::new(),
+            role_name: OFFLINE_ROLE_NAME.into(),
+            role_id: String::new(),
         });
+        let (message_injector, message_receiver) = channel();
 
         Self {
-            config, context, client, utc_offset,
-            rng: Mutex::new(ChaChaRng::from_seed(seed)),
-            rpc_request_pipe,
-            message_replies, message_sender, message_receiver, message_injector,
+            config, context, utc_offset, replay,
+            client: Arc::new(reqwest::Client::builder().build().unwrap()),
+            rng: Mutex::new(rng),
+            rpc_request_pipe: channel().0,
+            message_replies: Default::default(),
+            message_sender: channel().0,
+            message_injector, message_receiver,
+            local_network: None,
+            offline: true,
+            clock: None,
+            local_rpcs: Default::default(),
+        }
+    }
+
+    /// Records all nondeterministic events of this system so that the execution can later be reproduced with [`StdSystem::new_replay`].
+    /// This reseeds the random number generator so that the seed can be recorded.
+    ///
+    /// Project inputs are not handled by the system, so the caller is responsible for recording them (see [`Recorder::record_input`]).
+    pub fn with_recorder(mut self, recorder: Rc<Recorder>) -> Self {
+        let mut seed: <ChaChaRng as SeedableRng>::Seed = Default::default();
+        getrandom::getrandom(&mut seed).expect("failed to generate random seed");
+        recorder.record(ReplayEvent::Seed { seed });
+
+        self.rng = Mutex::new(ChaChaRng::from_seed(seed));
+        self.replay = ReplayMode::Recording(recorder);
+        self
+    }
+
+    /// Adds this system to a [`LocalNetwork`] under the given role name.
+    /// Network messages between members of the same local network are delivered directly, without going through the NetsBlox server.
+    pub fn with_local_network(mut self, network: Arc<LocalNetwork<C>>, role: &str) -> Self {
+        network.peers.lock().unwrap().insert(role.to_owned(), LocalPeer { message_injector: self.message_injector.clone(), message_replies: self.message_replies.clone() });
+        self.local_network = Some((network, role.to_owned()));
+        self
+    }
+
+    /// Registers a local implementation of an RPC, which is used in place of calling the NetsBlox server.
+    /// Any RPCs which are not registered are sent to the NetsBlox server as usual (or fail if the system is offline).
+    /// If an RPC is already registered with the same service and name, it is replaced.
+    ///
+    /// Note that RPCs handled by the [`Config`] given to the system take precedence over registered RPCs.
+    pub fn with_rpc(mut self, service: &str, rpc: &str, handler: LocalRpc<C>) -> Self {
+        self.local_rpcs.insert((service.to_owned(), rpc.to_owned()), handler);
+        self
+    }
+
+    fn dispatch_rpc(&self, service: String, rpc: String, args: Vec<(String, Json)>, key: RequestKey<C>) {
+        let id = (service, rpc);
+        let handler = match self.local_rpcs.get(&id) {
+            Some(LocalRpc::Sync(handler)) => return key.complete(handler(&args)),
+            Some(LocalRpc::Async(handler)) => Some(handler.clone()),
+            None => None,
+        };
+        let (service, rpc) = id;
+        match handler.is_none() && self.offline {
+            true => key.complete(Err(format!("{service}.{rpc} is not available offline"))),
+            false => self.rpc_request_pipe.send(RpcRequest { service, rpc, args, key, handler }).unwrap(),
         }
     }
 
     /// Asynchronously calls an RPC and returns the result.
     /// This function directly makes requests to NetsBlox, bypassing any RPC hook defined by [`Config`].
     pub async fn call_rpc_async(&self, service: &str, rpc: &str, args: &[(&str, &Json)]) -> Result<C::Intermediate, String> {
+        if self.offline { return Err(format!("{service}.{rpc} is not available offline")) }
         call_rpc_async::<C>(&self.context, &self.client, service, rpc, args).await
     }
 
@@ -351,6 +568,19 @@ impl<C: CustomTypes<StdSystem<C>>> StdSystem<C> {
     pub fn inject_message(&self, msg_type: String, values: Vec<(String, Json)>) {
         self.message_injector.send(IncomingMessage { msg_type, values, reply_key: None }).unwrap();
     }
+
+    fn poll_reply_impl(&self, key: &ExternReplyKey) -> AsyncResult<Option<Json>> {
+        let mut message_replies = self.message_replies.lock().unwrap();
+        let entry = message_replies.get(key).unwrap();
+        if entry.value.is_some() {
+            return AsyncResult::Completed(message_replies.remove(key).unwrap().value);
+        }
+        if entry.timestamp.elapsed().as_millis() as u32 >= MESSAGE_REPLY_TIMEOUT_MS {
+            message_replies.remove(key).unwrap();
+            return AsyncResult::Completed(None);
+        }
+        AsyncResult::Pending
+    }
 }
 impl<C: CustomTypes<StdSystem<C>>> System<C> for StdSystem<C> {
     type RequestKey = RequestKey<C>;
@@ -364,28 +594,38 @@ impl<C: CustomTypes<StdSystem<C>>> System<C> for StdSystem<C> {
     }
 
     fn time(&self) -> SysTime {
-        SysTime::Real { local: OffsetDateTime::now_utc().to_offset(self.utc_offset) }
+        self.replay.time(|| {
+            let now = match &self.clock {
+                Some(clock) => clock.now(),
+                None => OffsetDateTime::now_utc(),
+            };
+            SysTime::Real { local: now.to_offset(self.utc_offset) }
+        })
     }
 
     fn perform_request<'gc>(&self, mc: &Mutation<'gc>, request: Request<'gc, C, Self>, entity: &mut Entity<'gc, C, Self>) -> Result<MaybeAsync<Result<Value<'gc, C, Self>, String>, Self::RequestKey>, ErrorCause<C, Self>> {
-        Ok(match self.config.request.as_ref() {
+        let feature = request.feature();
+        self.replay.perform_request(|| match self.config.request.as_ref() {
             Some(handler) => {
                 let key = RequestKey(Arc::new(Mutex::new(AsyncResult::new())));
                 match handler(self, mc, RequestKey(key.0.clone()), request, entity) {
-                    RequestStatus::Handled => MaybeAsync::Async(key),
-                    RequestStatus::UseDefault { key: _, request } => return Err(ErrorCause::NotSupported { feature: request.feature() }),
+                    RequestStatus::Handled => Ok(MaybeAsync::Async(key)),
+                    RequestStatus::UseDefault { key: _, request } => Err(ErrorCause::NotSupported { feature: request.feature() }),
                 }
             }
-            None => return Err(ErrorCause::NotSupported { feature: request.feature() }),
+            None => Err(ErrorCause::NotSupported { feature: request.feature() }),
+        }, |handled| match handled {
+            true => Ok(MaybeAsync::Async(RequestKey(Arc::new(Mutex::new(AsyncResult::new()))))),
+            false => Err(ErrorCause::NotSupported { feature }),
         })
     }
     fn poll_request<'gc>(&self, mc: &Mutation<'gc>, key: &Self::RequestKey, _: &mut Entity<'gc, C, Self>) -> Result<AsyncResult<Result<Value<'gc, C, Self>, String>>, ErrorCause<C, Self>> {
-        Ok(match key.poll() {
+        self.replay.poll_request(mc, || Ok(match key.poll() {
             AsyncResult::Completed(Ok(x)) => AsyncResult::Completed(Ok(C::from_intermediate(mc, x)?)),
             AsyncResult::Completed(Err(x)) => AsyncResult::Completed(Err(x)),
             AsyncResult::Pending => AsyncResult::Pending,
             AsyncResult::Consumed => AsyncResult::Consumed,
-        })
+        }))
     }
 
     fn perform_command<'gc>(&self, mc: &Mutation<'gc>, command: Command<'gc, '_, C, Self>, entity: &mut Entity<'gc, C, Self>) -> Result<MaybeAsync<Result<(), String>, Self::CommandKey>, ErrorCause<C, Self>> {
@@ -401,10 +641,43 @@ impl<C: CustomTypes<StdSystem<C>>> System<C> for StdSystem<C> {
         })
     }
     fn poll_command<'gc>(&self, _: &Mutation<'gc>, key: &Self::CommandKey, _: &mut Entity<'gc, C, Self>) -> Result<AsyncResult<Result<(), String>>, ErrorCause<C, Self>> {
-        Ok(key.poll())
+        Ok(self.replay.poll_command(|| key.poll()))
     }
 
-    fn send_message(&self, msg_type: String, values: Vec<(String, Json)>, targets: Vec<String>, expect_reply: bool) -> Result<Option<Self::ExternReplyKey>, ErrorCause<C, StdSystem<C>>> {
+    fn send_message(&self, msg_type: String, values: Vec<(String, Json)>, mut targets: Vec<String>, expect_reply: bool) -> Result<Option<Self::ExternReplyKey>, ErrorCause<C, StdSystem<C>>> {
+        if self.replay.is_replaying() {
+            return Ok(expect_reply.then(|| ExternReplyKey { request_id: String::new() }));
+        }
+        if let Some((network, src_role)) = &self.local_network {
+            let mut local_targets = BTreeSet::new();
+            targets.retain(|target| match network.resolve(target, src_role) {
+                Some(roles) => { local_targets.extend(roles); false }
+                None => true,
+            });
+
+            if !local_targets.is_empty() {
+                let reply_key = expect_reply.then(|| {
+                    let reply_key = ExternReplyKey { request_id: Uuid::new_v4().to_string() };
+                    self.message_replies.lock().unwrap().insert(reply_key.clone(), ReplyEntry { timestamp: Instant::now(), value: None });
+                    reply_key
+                });
+
+                let peers = network.peers.lock().unwrap();
+                for role in local_targets {
+                    let reply_key = reply_key.as_ref().map(|x| InternReplyKey { src_id: src_role.clone(), request_id: x.request_id.clone(), local: true });
+                    peers[&role].message_injector.send(IncomingMessage { msg_type: msg_type.clone(), values: values.clone(), reply_key }).unwrap();
+                }
+
+                // if every target was local (or there is no server), there's no need to go through the server
+                if targets.is_empty() || self.offline { return Ok(reply_key) }
+                if let Some(reply_key) = reply_key {
+                    let msg = OutgoingMessage::Blocking { msg_type, values, targets, reply_key: reply_key.clone() };
+                    self.message_sender.send(msg).unwrap();
+                    return Ok(Some(reply_key));
+                }
+            }
+        }
+
         let (msg, reply_key) = match expect_reply {
             false => (OutgoingMessage::Normal { msg_type, values, targets }, None),
             true => {
@@ -413,26 +686,34 @@ impl<C: CustomTypes<StdSystem<C>>> System<C> for StdSystem<C> {
                 (OutgoingMessage::Blocking { msg_type, values, targets, reply_key: reply_key.clone() }, Some(reply_key))
             }
         };
-        self.message_sender.send(msg).unwrap();
+        if !self.offline {
+            self.message_sender.send(msg).unwrap();
+        }
         Ok(reply_key)
     }
     fn poll_reply(&self, key: &Self::ExternReplyKey) -> AsyncResult<Option<Json>> {
-        let mut message_replies = self.message_replies.lock().unwrap();
-        let entry = message_replies.get(key).unwrap();
-        if entry.value.is_some() {
-            return AsyncResult::Completed(message_replies.remove(key).unwrap().value);
-        }
-        if entry.timestamp.elapsed().as_millis() as u32 >= MESSAGE_REPLY_TIMEOUT_MS {
-            message_replies.remove(key).unwrap();
-            return AsyncResult::Completed(None);
-        }
-        AsyncResult::Pending
+        self.replay.poll_reply(|| self.poll_reply_impl(key))
     }
     fn send_reply(&self, key: Self::InternReplyKey, value: Json) -> Result<(), ErrorCause<C, Self>> {
+        if self.replay.is_replaying() {
+            return Ok(());
+        }
+        if key.local {
+            if let Some((network, _)) = &self.local_network {
+                if let Some(peer) = network.peers.lock().unwrap().get(&key.src_id) {
+                    if let Some(entry) = peer.message_replies.lock().unwrap().get_mut(&ExternReplyKey { request_id: key.request_id }) {
+                        if entry.value.is_none() {
+                            entry.value = Some(value);
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
         self.message_sender.send(OutgoingMessage::Reply { value, reply_key: key }).unwrap();
         Ok(())
     }
     fn receive_message(&self) -> Option<IncomingMessage<C, Self>> {
-        self.message_receiver.try_recv().ok()
+        self.replay.receive_message(|| self.message_receiver.try_recv().ok(), || InternReplyKey { src_id: String::new(), request_id: String::new(), local: true })
     }
 }
@@ -8,7 +8,7 @@ use core::fmt::Write;
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
-use crate::process::ErrorSummary;
+use crate::process::{ErrorSummary, DebugSummary, WatcherSummary};
 
 /// A status update in the structure expected by the standard js extension.
 #[cfg_attr(feature = "serde", derive(Serialize))]
@@ -16,6 +16,8 @@ pub struct Status {
     pub running: bool,
     pub output: String,
     pub errors: Vec<ErrorSummary>,
+    pub debug: Option<DebugSummary>,
+    pub watchers: Vec<WatcherSummary>,
 }
 
 /// An empty project.
@@ -76,6 +78,8 @@ fn test_syscall_menu_format() {
 pub struct ExtensionArgs<'a> {
     /// The NetsBlox VM server to connect to.
     pub server: &'a str,
+    /// The access token to present on every request to the VM server, if any.
+    pub token: Option<&'a str>,
     /// The syscall menu structure to generate for syscall blocks.
     pub syscalls: &'a [SyscallMenu],
     /// A list of XML element names to omit from the XML sent to the VM server.
@@ -88,6 +92,10 @@ impl ExtensionArgs<'_> {
     pub fn render(&self) -> String {
         format!(include_str!("assets/extension.js"),
             server = self.server,
+            token = match self.token {
+                Some(token) => format!("{token:?}"),
+                None => "null".into(),
+            },
             syscalls = SyscallMenu::format(self.syscalls),
             omitted_elements = self.omitted_elements,
             pull_interval_ms = self.pull_interval.as_millis(),
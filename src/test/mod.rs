@@ -1,4 +1,8 @@
 use alloc::rc::Rc;
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use core::iter;
@@ -12,8 +16,6 @@ use crate::gc::*;
 mod process;
 mod project;
 
-const BASE_URL: &'static str = "https://cloud.netsblox.org";
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum NativeType {}
 
@@ -26,6 +28,8 @@ impl GetType for NativeValue {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
 struct EntityState {
     props: Properties,
 }
@@ -42,6 +46,8 @@ fn default_properties_config() -> Config<C, StdSystem<C>> {
     Config {
         request: Some(Rc::new(|_, _, key, request, entity| match request {
             Request::Property { prop } => entity.state.props.perform_get_property(key, prop),
+            Request::DistanceTo { target } => entity.state.props.perform_distance_to(key, &target.borrow().state.props),
+            Request::DirectionTo { target } => entity.state.props.perform_direction_to(key, &target.borrow().state.props),
             _ => RequestStatus::UseDefault { key, request },
         })),
         command: Some(Rc::new(|_, _, key, command, entity| match command {
@@ -56,6 +62,37 @@ fn default_properties_config() -> Config<C, StdSystem<C>> {
     }
 }
 
+/// Registers local stand-ins for the NetsBlox services used by the tests, so that they can run offline.
+fn with_mock_services(system: StdSystem<C>) -> StdSystem<C> {
+    fn arg(args: &[(String, Json)], name: &str) -> Option<f64> {
+        match args.iter().find(|x| x.0 == name).map(|x| &x.1) {
+            Some(Json::Number(x)) => x.as_f64(),
+            Some(Json::String(x)) => x.parse().ok(),
+            _ => None,
+        }
+    }
+
+    system
+        .with_rpc("Geolocation", "city", LocalRpc::Sync(Box::new(|args| match (arg(args, "latitude"), arg(args, "longitude")) {
+            (None, _) => Err("latitude is required.".to_owned()),
+            (_, None) => Err("longitude is required.".to_owned()),
+            (Some(lat), Some(long)) => {
+                let cities = [(36.1627, -86.7816, "Nashville"), (40.8136, -96.7026, "Lincoln"), (40.7608, -111.8910, "Salt Lake City")];
+                let dist = |x: &(f64, f64, &str)| (x.0 - lat).powi(2) + (x.1 - long).powi(2);
+                Ok(Intermediate::Json(json!(cities.iter().min_by(|a, b| dist(a).total_cmp(&dist(b))).unwrap().2)))
+            }
+        })))
+        .with_rpc("PhoneIoT", "getColor", LocalRpc::Async(Arc::new(|args| Box::pin(async move {
+            let channel = |name: &str| arg(&args, name).map(|x| x as u32);
+            match (channel("red"), channel("green"), channel("blue"), channel("alpha").unwrap_or(255)) {
+                (Some(r), Some(g), Some(b), a) => Ok(Intermediate::Json(json!(((a << 24) | (r << 16) | (g << 8) | b) as i32))),
+                _ => Err("red, green, and blue are required.".to_owned()),
+            }
+        }))))
+        .with_rpc("MaunaLoaCO2Data", "getRawCO2", LocalRpc::Sync(Box::new(|_| Ok(Intermediate::Json(json!([[1958.2, 315.71], [1958.29, 317.45], [1958.37, 317.5]]))))))
+        .with_rpc("Chart", "draw", LocalRpc::Sync(Box::new(|_| Ok(Intermediate::Image(b"\x89PNG mock chart".to_vec())))))
+}
+
 enum Intermediate {
     Json(Json),
     Image(Vec<u8>),
@@ -59,11 +59,12 @@ fn run_till_term<F>(env: &mut EnvArena, and_then: F) where F: for<'gc> FnOnce(&M
                 Ok(ProcessStep::Normal) => (),
                 Ok(ProcessStep::Yield) => yields += 1,
                 Ok(ProcessStep::Terminate { result }) => break result,
-                Ok(ProcessStep::CreatedClone { .. }) => panic!("proc tests should not clone"),
+                Ok(ProcessStep::CreatedClone { .. } | ProcessStep::DeletedClone { .. }) => panic!("proc tests should not clone"),
                 Ok(ProcessStep::Broadcast { .. }) => panic!("proc tests should not broadcast"),
                 Ok(ProcessStep::Watcher { .. }) => panic!("proc tests should not use watchers"),
                 Ok(ProcessStep::Fork { .. }) => panic!("proc tests should not fork"),
                 Ok(ProcessStep::Pause) => panic!("proc tests should not pause"),
+                Ok(ProcessStep::StopAll | ProcessStep::StopOthers { .. }) => panic!("proc tests should not stop other processes"),
                 Err(e) => {
                     drop(proc); // so handler can borrow the proc if needed
                     return and_then(mc, env, Err(e));
@@ -79,7 +80,7 @@ fn run_till_term<F>(env: &mut EnvArena, and_then: F) where F: for<'gc> FnOnce(&M
 
 #[test]
 fn test_proc_ret() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -95,7 +96,7 @@ fn test_proc_ret() {
 
 #[test]
 fn test_proc_sum_123n() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -118,7 +119,7 @@ fn test_proc_sum_123n() {
 
 #[test]
 fn test_proc_recursive_factorial() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -141,7 +142,7 @@ fn test_proc_recursive_factorial() {
 
 #[test]
 fn test_proc_loops_lists_basic() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -181,7 +182,7 @@ fn test_proc_loops_lists_basic() {
 
 #[test]
 fn test_proc_recursively_self_containing_lists() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -226,7 +227,7 @@ fn test_proc_recursively_self_containing_lists() {
 
 #[test]
 fn test_proc_sieve_of_eratosthenes() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -252,7 +253,7 @@ fn test_proc_sieve_of_eratosthenes() {
 
 #[test]
 fn test_proc_early_return() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -268,7 +269,7 @@ fn test_proc_early_return() {
 
 #[test]
 fn test_proc_short_circuit() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -296,7 +297,7 @@ fn test_proc_short_circuit() {
 
 #[test]
 fn test_proc_all_arithmetic() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -340,7 +341,7 @@ fn test_proc_all_arithmetic() {
 
 #[test]
 fn test_proc_lambda_local_shadow_capture() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -354,9 +355,25 @@ fn test_proc_lambda_local_shadow_capture() {
     });
 }
 
+#[test]
+fn test_proc_stop_this_block() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+        globals = "",
+        fields = "",
+        funcs = include_str!("blocks/stop-this-block.xml"),
+        methods = "",
+    ), Settings::default(), system);
+
+    run_till_term(&mut env, |mc, _, res| {
+        let expect = Value::from_json(mc, json!(["1", "3", ""])).unwrap();
+        assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-20, "stop this block");
+    });
+}
+
 #[test]
 fn test_proc_upvars() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -415,7 +432,7 @@ fn test_proc_upvars() {
 
 #[test]
 fn test_proc_generators_nested() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -431,7 +448,7 @@ fn test_proc_generators_nested() {
 
 #[test]
 fn test_proc_call_in_closure() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -450,7 +467,7 @@ fn test_proc_call_in_closure() {
 
 #[test]
 fn test_proc_warp_yields() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="counter"><l>0</l></variable>"#,
         fields = "",
@@ -477,7 +494,7 @@ fn test_proc_warp_yields() {
 
 #[test]
 fn test_proc_string_ops() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -538,7 +555,7 @@ fn test_proc_string_ops() {
 
 #[test]
 fn test_proc_str_cmp_case_insensitive() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -560,7 +577,7 @@ fn test_proc_str_cmp_case_insensitive() {
 
 #[test]
 fn test_proc_rpc_call_basic() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(with_mock_services(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default())));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -584,7 +601,7 @@ fn test_proc_rpc_call_basic() {
 
 #[test]
 fn test_proc_list_index_blocks() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -617,7 +634,7 @@ fn test_proc_list_index_blocks() {
 
 #[test]
 fn test_proc_literal_types() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -646,7 +663,7 @@ fn test_proc_say() {
             _ => CommandStatus::UseDefault { key, command },
         })),
     };
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, config, UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, config, UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -690,7 +707,7 @@ fn test_proc_syscall() {
         })),
         ..Default::default()
     };
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, config, UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, config, UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -712,7 +729,7 @@ fn test_proc_syscall() {
 
 #[test]
 fn test_proc_timer_wait() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -731,7 +748,7 @@ fn test_proc_timer_wait() {
 
 #[test]
 fn test_proc_cons_cdr() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -758,7 +775,7 @@ fn test_proc_cons_cdr() {
 
 #[test]
 fn test_proc_list_find_contains() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -786,7 +803,7 @@ fn test_proc_list_find_contains() {
 
 #[test]
 fn test_proc_append() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -809,7 +826,7 @@ fn test_proc_append() {
 
 #[test]
 fn test_proc_foreach_mutate() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -829,7 +846,7 @@ fn test_proc_foreach_mutate() {
 
 #[test]
 fn test_proc_map() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="foo"><l>0</l></variable>"#,
         fields = "",
@@ -849,7 +866,7 @@ fn test_proc_map() {
 
 #[test]
 fn test_proc_keep_find() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="foo"><l>0</l></variable>"#,
         fields = "",
@@ -871,7 +888,7 @@ fn test_proc_keep_find() {
 
 #[test]
 fn test_proc_numeric_bases() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -893,7 +910,7 @@ fn test_proc_numeric_bases() {
 
 #[test]
 fn test_proc_combine() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="foo"><l>0</l></variable>"#,
         fields = "",
@@ -920,7 +937,7 @@ fn test_proc_combine() {
 
 #[test]
 fn test_proc_autofill_closure_params() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="foo"><l>0</l></variable>"#,
         fields = "",
@@ -942,7 +959,7 @@ fn test_proc_autofill_closure_params() {
 
 #[test]
 fn test_proc_pick_random() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1003,7 +1020,7 @@ fn test_proc_pick_random() {
 
 #[test]
 fn test_proc_rand_list_ops() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1057,7 +1074,7 @@ fn test_proc_rand_list_ops() {
 
 #[test]
 fn test_proc_variadic_sum_product() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1084,7 +1101,7 @@ fn test_proc_variadic_sum_product() {
 
 #[test]
 fn test_proc_variadic_min_max() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1100,7 +1117,7 @@ fn test_proc_variadic_min_max() {
 
 #[test]
 fn test_proc_atan2_new_cmp() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1115,8 +1132,8 @@ fn test_proc_atan2_new_cmp() {
             [false, true, true],
             [true, true, false],
             [true, false, true],
-            true,
-            false,
+            [true, false, true],
+            [false, true, true],
             [false, true],
         ])).unwrap();
         assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-5, "atan2 and new cmp");
@@ -1125,7 +1142,7 @@ fn test_proc_atan2_new_cmp() {
 
 #[test]
 fn test_proc_list_columns() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1164,7 +1181,7 @@ fn test_proc_list_columns() {
 
 #[test]
 fn test_proc_compare_str() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1209,23 +1226,23 @@ fn test_proc_compare_str() {
             [false, false, false, true, true, true],
             [false, false, false, true, true, true],
 
-            [false, true, true, false, false, true],
-            [true, true, false, true, false, false],
-            [false, false, false, true, true, true],
-            [false, true, true, false, false, true],
+            [[], [], true, false, [], []],
+            [[], [], false, true, [], []],
+            [[], [], false, true, [], []],
+            [[false], [true], true, false, [false], [true]],
 
-            [false, true, true, false, false, true],
-            [false, true, true, false, false, true],
-            [true, true, false, true, false, false],
-            [false, false, false, true, true, true],
+            [[false], [true], true, false, [false], [true]],
+            [[false], [true], true, false, [false], [true]],
+            [[false, false, true], [true, true, true], false, true, [false, false, false], [true, true, false]],
+            [[false, false, false], [true, true, false], false, true, [false, false, true], [true, true, true]],
 
-            [false, true, true, false, false, true],
-            [true, true, false, true, false, false],
-            [false, false, false, true, true, true],
+            [[false, false, false], [true, true, true], true, false, [false, false, false], [true, true, true]],
+            [[false, false, false], [true, true, true], false, true, [false, false, false], [true, true, true]],
+            [[false, false, false], [true, true, true], false, true, [false, false, false], [true, true, true]],
 
-            [true, true, false, true, false, false],
-            [true, true, false, true, false, false],
-            [true, true, false, true, false, false],
+            [[true, false, false], [true, false, false], false, true, [false, true, true], [false, true, true]],
+            [[true, false, false], [true, false, false], false, true, [false, true, true], [false, true, true]],
+            [[true], [true], false, true, [false], [false]],
         ])).unwrap();
         assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-5, "compare str");
     });
@@ -1233,7 +1250,7 @@ fn test_proc_compare_str() {
 
 #[test]
 fn test_proc_new_min_max() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1251,10 +1268,10 @@ fn test_proc_new_min_max() {
             [12, 12, 23, 23],
             ["hello", "hello", "world", "world"],
             ["abc", "ABC", "abc", "ABC"],
-            [[], [], ["4"], ["4"]],
+            [[], [], [], []],
             [["4"], ["4"], ["7"], ["7"]],
-            [["4"], ["4"], ["4", "1", "2"], ["4", "1", "2"]],
-            [["4", "1", "2"], ["4", "1", "2"], ["4", "2"], ["4", "2"]],
+            [["4"], ["4"], ["4"], ["4"]],
+            [["4", "1"], ["4", "1"], ["4", "2"], ["4", "2"]],
         ])).unwrap();
         assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-5, "new min max");
     });
@@ -1262,7 +1279,7 @@ fn test_proc_new_min_max() {
 
 #[test]
 fn test_proc_flatten() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1283,7 +1300,7 @@ fn test_proc_flatten() {
 
 #[test]
 fn test_proc_list_len_rank_dims() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1309,7 +1326,7 @@ fn test_proc_list_len_rank_dims() {
 
 #[test]
 fn test_proc_string_index() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1332,7 +1349,7 @@ fn test_proc_string_index() {
 
 #[test]
 fn test_proc_type_query() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1361,7 +1378,7 @@ fn test_proc_type_query() {
 
 #[test]
 fn test_proc_variadic_strcat() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1380,9 +1397,41 @@ fn test_proc_variadic_strcat() {
     });
 }
 
+#[test]
+fn test_proc_hyper_ops() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+        globals = "",
+        fields = "",
+        funcs = include_str!("blocks/hyper-ops.xml"),
+        methods = "",
+    ), Settings::default(), system);
+
+    run_till_term(&mut env, |mc, _, res| {
+        let expect = Value::from_json(mc, json!([
+            [true, false, false],
+            [false, true, true],
+            [[false, false], [true, true]],
+            [false, true],
+            [[true], [false]],
+            [true, false, false],
+            true,
+            true,
+            ["3", "2"],
+            [["5", "9"], ["7", "5"]],
+            ["4", "2"],
+            ["x1-a", "x2-b"],
+            [["a!", "b!"], ["c!"]],
+            [11, 22],
+            [[2, 6], [6, 12]],
+        ])).unwrap();
+        assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-5, "hyper ops");
+    });
+}
+
 #[test]
 fn test_proc_list_lines() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1403,7 +1452,7 @@ fn test_proc_list_lines() {
 
 #[test]
 fn test_proc_binary_make_range() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1438,7 +1487,7 @@ fn test_proc_binary_make_range() {
 
 #[test]
 fn test_proc_identical_to() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1459,7 +1508,7 @@ fn test_proc_identical_to() {
 
 #[test]
 fn test_proc_variadic_list_ctors() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1487,7 +1536,7 @@ fn test_proc_variadic_list_ctors() {
 
 #[test]
 fn test_proc_list_rev() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1507,7 +1556,7 @@ fn test_proc_list_rev() {
 
 #[test]
 fn test_proc_list_reshape() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1542,7 +1591,7 @@ fn test_proc_list_reshape() {
 
 #[test]
 fn test_proc_list_json() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1565,7 +1614,7 @@ fn test_proc_list_json() {
 
 #[test]
 fn test_proc_explicit_to_string_cvt() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1580,10 +1629,10 @@ fn test_proc_explicit_to_string_cvt() {
             "hello 69 3",
             "hello true 4",
             "hello false 5",
-            "hello [] 6",
-            "hello [test] 7",
-            "hello [test,more] 8",
-            "hello [1,2,3,4] 9",
+            [],
+            ["hello test 7"],
+            ["hello test 8", "hello more 8"],
+            ["hello 1 9", "hello 2 9", "hello 3 9", "hello 4 9"],
         ])).unwrap();
         assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-5, "explicit tostr");
     });
@@ -1591,7 +1640,7 @@ fn test_proc_explicit_to_string_cvt() {
 
 #[test]
 fn test_proc_empty_variadic_no_auto_insert() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1613,7 +1662,7 @@ fn test_proc_empty_variadic_no_auto_insert() {
 
 #[test]
 fn test_proc_c_ring_no_auto_insert() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1635,7 +1684,7 @@ fn test_proc_c_ring_no_auto_insert() {
 
 #[test]
 fn test_proc_signed_zero() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1660,7 +1709,7 @@ fn test_proc_signed_zero() {
 
 #[test]
 fn test_proc_singleton_sum_product() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1679,7 +1728,7 @@ fn test_proc_singleton_sum_product() {
 
 #[test]
 fn test_proc_list_combinations() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1722,7 +1771,7 @@ fn test_proc_list_combinations() {
 
 #[test]
 fn test_proc_index_over_bounds() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, ins_locs) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1745,7 +1794,7 @@ fn test_proc_index_over_bounds() {
 
 #[test]
 fn test_proc_neg_collab_ids() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, ins_locs) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1766,6 +1815,90 @@ fn test_proc_neg_collab_ids() {
     });
 }
 
+#[test]
+fn test_proc_sounds() {
+    #[derive(PartialEq, Eq, Debug)]
+    enum Action {
+        PlaySound(Vec<u8>, bool),
+        PlayNote(Vec<i32>, String),
+        StopSounds,
+        Set(String, String),
+        Change(String, String),
+        Get(String),
+    }
+
+    let sequence = Rc::new(RefCell::new(Vec::with_capacity(16)));
+    let config = Config::<C, StdSystem<C>> {
+        command: {
+            let sequence = sequence.clone();
+            Some(Rc::new(move |_, _, key, command, _| {
+                match command {
+                    Command::PlaySound { sound, blocking } => sequence.borrow_mut().push(Action::PlaySound((*sound).clone(), blocking)),
+                    Command::PlayNote { notes, beats } => sequence.borrow_mut().push(Action::PlayNote(notes.iter().map(|x| x.get() as i32).collect(), beats.to_string())),
+                    Command::StopSounds => sequence.borrow_mut().push(Action::StopSounds),
+                    Command::SetProperty { prop, value } => sequence.borrow_mut().push(Action::Set(format!("{prop:?}"), value.to_string().unwrap().into_owned())),
+                    Command::ChangeProperty { prop, delta } => sequence.borrow_mut().push(Action::Change(format!("{prop:?}"), delta.to_string().unwrap().into_owned())),
+                    _ => return CommandStatus::UseDefault { key, command },
+                }
+                key.complete(Ok(()));
+                CommandStatus::Handled
+            }))
+        },
+        request: {
+            let sequence = sequence.clone();
+            Some(Rc::new(move |_, _, key, request, _| {
+                match request {
+                    Request::Property { prop } => {
+                        sequence.borrow_mut().push(Action::Get(format!("{prop:?}")));
+                        key.complete(Ok(Intermediate::from_json(json!(format!("{prop:?}")))));
+                    }
+                    _ => return RequestStatus::UseDefault { key, request },
+                }
+                RequestStatus::Handled
+            }))
+        },
+    };
+
+    let system = Rc::new(StdSystem::new_offline(None, config, UtcOffset::UTC, Default::default()));
+    let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+        globals = "",
+        fields = "",
+        funcs = include_str!("blocks/sounds.xml"),
+        methods = "",
+    ), Settings::default(), system);
+
+    env.mutate(|mc, env| {
+        let mut locals = SymbolTable::default();
+        locals.define_or_redefine("snd", Shared::Unique(Value::Audio(Rc::new(vec![1, 2, 3]))));
+        env.proc.borrow_mut(mc).initialize(ProcContext { locals, barrier: None, reply_key: None, local_message: None });
+
+        let entity = *env.glob.borrow().entities.iter().next().unwrap().1;
+        entity.borrow_mut(mc).sound_list = Rc::new(vec![("beep".into(), Rc::new(vec![4, 5, 6]))]);
+    });
+
+    run_till_term(&mut env, |mc, _, res| {
+        let expected = Value::from_json(mc, json!([ "Tempo", "Volume", "Balance" ])).unwrap();
+        assert_values_eq(&res.unwrap().0.unwrap(), &expected, 1e-4, "sounds test")
+    });
+
+    let expected = [
+        Action::PlaySound(vec![1, 2, 3], false),
+        Action::PlaySound(vec![1, 2, 3], true),
+        Action::PlaySound(vec![4, 5, 6], false),
+        Action::PlayNote(vec![60], "0.5".into()),
+        Action::PlayNote(vec![], "2".into()),
+        Action::PlayNote(vec![60, 64, 67], "1".into()),
+        Action::Set("Tempo".into(), "90".into()),
+        Action::Change("Volume".into(), "-10".into()),
+        Action::Set("Balance".into(), "25".into()),
+        Action::StopSounds,
+        Action::Get("Tempo".into()),
+        Action::Get("Volume".into()),
+        Action::Get("Balance".into()),
+    ];
+    assert_eq!(*sequence.borrow(), expected);
+}
+
 #[test]
 fn test_proc_basic_motion() {
     #[derive(PartialEq, Eq, Debug)]
@@ -1819,7 +1952,7 @@ fn test_proc_basic_motion() {
         },
     };
 
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, config, UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, config, UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1848,7 +1981,7 @@ fn test_proc_basic_motion() {
 
 #[test]
 fn test_proc_string_cmp() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1869,7 +2002,7 @@ fn test_proc_string_cmp() {
 
 #[test]
 fn test_proc_stack_overflow() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, locs) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = r#"<variable name="g"><l>0</l></variable>"#,
         fields = r#"<variable name="f"><l>0</l></variable>"#,
@@ -1908,7 +2041,7 @@ fn test_proc_stack_overflow() {
 
 #[test]
 fn test_proc_variadic_params() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1939,7 +2072,7 @@ fn test_proc_variadic_params() {
 
 #[test]
 fn test_proc_rand_str_char_cache() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1966,7 +2099,7 @@ fn test_proc_rand_str_char_cache() {
 
 #[test]
 fn test_proc_noop_upvars() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1982,7 +2115,7 @@ fn test_proc_noop_upvars() {
 
 #[test]
 fn test_proc_try_catch_throw() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -1998,7 +2131,7 @@ fn test_proc_try_catch_throw() {
 
 #[test]
 fn test_proc_exception_unregister() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2014,7 +2147,7 @@ fn test_proc_exception_unregister() {
 
 #[test]
 fn test_proc_exception_rethrow() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2030,7 +2163,7 @@ fn test_proc_exception_rethrow() {
 
 #[test]
 fn test_proc_rpc_error() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(with_mock_services(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default())));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2052,7 +2185,7 @@ fn test_proc_rpc_error() {
 
 #[test]
 fn test_proc_c_rings() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2076,7 +2209,7 @@ fn test_proc_c_rings() {
 #[test]
 fn test_proc_wall_time() {
     let utc_offset = UtcOffset::from_hms(5, 14, 20).unwrap();
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), utc_offset));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), utc_offset, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2103,7 +2236,7 @@ fn test_proc_wall_time() {
 
 #[test]
 fn test_proc_to_csv() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2129,7 +2262,7 @@ fn test_proc_to_csv() {
 
 #[test]
 fn test_proc_from_csv() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2163,7 +2296,7 @@ fn test_proc_from_csv() {
 
 #[test]
 fn test_proc_extra_cmp_tests() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2256,7 +2389,7 @@ fn test_proc_extra_blocks() {
         command: None,
     };
 
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, config, UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, config, UtcOffset::UTC, Default::default()));
     let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
         globals = "",
         fields = "",
@@ -2283,3 +2416,109 @@ fn test_proc_extra_blocks() {
     ];
     assert_eq!(&*actions.borrow(), &expected);
 }
+
+#[test]
+fn test_proc_limits() {
+    let limits = [
+        Settings { max_warp_time: Some(20), ..Default::default() },
+        Settings { max_list_size: Some(100), ..Default::default() },
+        Settings { max_list_size: Some(100), ..Default::default() },
+        Settings { max_process_steps: Some(1000), ..Default::default() },
+        Settings { max_project_steps: Some(1000), ..Default::default() },
+        Settings { max_list_size: Some(100), ..Default::default() },
+        Settings { max_list_size: Some(100), ..Default::default() },
+    ];
+    for (i, settings) in limits.into_iter().enumerate() {
+        let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+        let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+            globals = r#"<variable name="counter"><l>0</l></variable>"#,
+            fields = "",
+            funcs = include_str!("blocks/limits.xml"),
+            methods = "",
+        ), settings, system);
+
+        let mode = if i == 4 { 3 } else { i };
+        env.mutate(|mc, env| {
+            let mut locals = SymbolTable::default();
+            locals.define_or_redefine("mode", Shared::Unique(Number::new(mode as f64).unwrap().into()));
+            env.proc.borrow_mut(mc).initialize(ProcContext { locals, barrier: None, reply_key: None, local_message: None });
+        });
+
+        run_till_term(&mut env, |_, env, res| {
+            let cause = res.unwrap_err().cause;
+            let counter = env.glob.borrow().globals.lookup("counter").unwrap().get().to_number().unwrap().get();
+            match i {
+                0 => assert!(matches!(cause, ErrorCause::WarpTimeLimit { limit: 20 }), "{cause:?}"),
+                1 => assert!(matches!(cause, ErrorCause::ListSizeLimit { limit: 100 }), "{cause:?}"),
+                2 => {
+                    assert!(matches!(cause, ErrorCause::ListSizeLimit { limit: 100 }), "{cause:?}");
+                    assert_eq!(counter, 100.0);
+                }
+                3 => assert!(matches!(cause, ErrorCause::ProcessStepLimit { limit: 1000 }), "{cause:?}"),
+                4 => assert!(matches!(cause, ErrorCause::ProjectStepLimit { limit: 1000 }), "{cause:?}"),
+                5 | 6 => assert!(matches!(cause, ErrorCause::ListSizeLimit { limit: 100 }), "{cause:?}"),
+                _ => unreachable!(),
+            }
+            if i == 3 || i == 4 { assert!(counter > 0.0) }
+        });
+    }
+}
+
+#[test]
+fn test_proc_list_sort_text_ops() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+        globals = "",
+        fields = "",
+        funcs = include_str!("blocks/list-sort-text-ops.xml"),
+        methods = "",
+    ), Settings::default(), system);
+
+    run_till_term(&mut env, |mc, _, res| {
+        let mut texts = (1..=150).map(|i| format!("{}a", i * 53 % 151)).collect::<Vec<_>>();
+        texts.sort();
+        let mut mixed = (1..=150).map(|i| json!(i * 37 % 151)).collect::<Vec<_>>();
+        mixed.sort_by_key(|x| x.as_u64().unwrap());
+        mixed.extend(texts.into_iter().map(|x| json!(x)));
+
+        let expect = Value::from_json(mc, json!([
+            ["-7", "-2", "3", "5", "10"],
+            ["-2", "3", "5", "-7", "10"],
+            ["apple", "fig", "pear"],
+            true, false,
+            7, 0,
+            "a+b+c",
+            "HELLO", "hello", "Hello",
+            ["a+b", "c+d"],
+            "abc",
+            mixed,
+        ])).unwrap();
+        assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-20, "sort text ops");
+    });
+}
+
+#[test]
+fn test_proc_list_key_ops() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let (mut env, _) = get_running_proc(&format!(include_str!("templates/generic-static.xml"),
+        globals = "",
+        fields = "",
+        funcs = include_str!("blocks/list-key-ops.xml"),
+        methods = "",
+    ), Settings { json_mode: JsonMode::Objects, ..Default::default() }, system);
+
+    run_till_term(&mut env, |mc, _, res| {
+        let expect = Value::from_json(mc, json!([
+            7, "",
+            r#"{"age":"8","city":"paris","name":"bob","tags":["a","b"]}"#,
+            r#"[["a","1"],["a","2"]]"#,
+            22500, "x", 40000, 200,
+            "", "y",
+            "z",
+            36, "",
+            r#"[["a","1"],["b","2"]]"#,
+            r#"{"x":{},"y":[]}"#,
+        ])).unwrap();
+        assert_values_eq(&res.unwrap().0.unwrap(), &expect, 1e-20, "list key ops");
+    });
+}
@@ -1,6 +1,13 @@
 use alloc::collections::BTreeSet;
 use alloc::borrow::ToOwned;
 use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::string::ToString;
+
+use core::iter;
+use core::cell::{Cell, RefCell};
 
 use crate::*;
 use crate::gc::*;
@@ -9,6 +16,9 @@ use crate::real_time::*;
 use crate::bytecode::*;
 use crate::project::*;
 use crate::std_system::*;
+use crate::replay::*;
+use crate::snapshot::*;
+use crate::profiler::*;
 
 use super::*;
 
@@ -20,14 +30,16 @@ struct Env<'gc> {
 type EnvArena = Arena<Rootable![Env<'_>]>;
 
 fn get_running_project(xml: &str, system: Rc<StdSystem<C>>) -> EnvArena {
+    let parser = ast::Parser::default();
+    let ast = parser.parse(xml).unwrap();
+    assert_eq!(ast.roles.len(), 1);
+    get_running_role(&ast.roles[0], system)
+}
+fn get_running_role(role: &ast::Role, system: Rc<StdSystem<C>>) -> EnvArena {
     EnvArena::new(Default::default(), |mc| {
-        let parser = ast::Parser::default();
-        let ast = parser.parse(xml).unwrap();
-        assert_eq!(ast.roles.len(), 1);
-
-        let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+        let (bytecode, init_info, _, _) = ByteCode::compile(role).unwrap();
 
-        let mut proj = Project::from_init(mc, &init_info, Rc::new(bytecode), Settings::default(), system);
+        let mut proj = Project::from_init(mc, &init_info, Rc::new(bytecode), Settings::default(), system).unwrap();
         proj.input(mc, Input::Start);
         Env { proj: Gc::new(mc, RefLock::new(proj)) }
     })
@@ -38,6 +50,7 @@ fn get_running_project(xml: &str, system: Rc<StdSystem<C>>) -> EnvArena {
 enum SpecialEvent<'gc, C: CustomTypes<S>, S: System<C>> {
     Watcher { create: bool, watcher: Watcher<'gc, C, S> },
     Pause,
+    Break,
 }
 
 fn run_till_term<'gc>(mc: &Mutation<'gc>, proj: &mut Project<'gc, C, StdSystem<C>>) -> Result<Vec<SpecialEvent<'gc, C, StdSystem<C>>>, ExecError<C, StdSystem<C>>> {
@@ -46,19 +59,23 @@ fn run_till_term<'gc>(mc: &Mutation<'gc>, proj: &mut Project<'gc, C, StdSystem<C
         match proj.step(mc) {
             ProjectStep::Idle => return Ok(special_events),
             ProjectStep::Error { error, .. } => return Err(error),
-            ProjectStep::Normal | ProjectStep::ProcessTerminated { .. } | ProjectStep::Yield => (),
+            ProjectStep::Normal | ProjectStep::ProcessTerminated { .. } | ProjectStep::Yield | ProjectStep::FrameEnd => (),
             ProjectStep::Watcher { create, watcher } => special_events.push(SpecialEvent::Watcher { create, watcher }),
             ProjectStep::Pause => {
                 special_events.push(SpecialEvent::Pause);
                 return Ok(special_events); // simulate actually pausing
             }
+            ProjectStep::Break => {
+                special_events.push(SpecialEvent::Break);
+                return Ok(special_events);
+            }
         }
     }
 }
 
 #[test]
 fn test_proj_counting() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/counting.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -75,7 +92,7 @@ fn test_proj_counting() {
 
 #[test]
 fn test_proj_effects() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/effects.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -98,7 +115,7 @@ fn test_proj_effects() {
 
 #[test]
 fn test_proj_size_visible() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/size-visible.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -125,7 +142,7 @@ fn test_proj_size_visible() {
 
 #[test]
 fn test_proj_motion() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/motion.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -166,7 +183,7 @@ fn test_proj_motion() {
 
 #[test]
 fn test_proj_pen_basic() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/pen-basic.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -222,7 +239,7 @@ fn test_proj_pen_basic() {
 
 #[test]
 fn test_proj_watchers() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/watchers.xml"), system);
     proj.mutate(|mc, proj| {
         let events = run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -243,12 +260,59 @@ fn test_proj_watchers() {
         ];
 
         assert_eq!(mapped, expected);
+
+        let summaries = events.iter().filter_map(|x| match x {
+            SpecialEvent::Watcher { create: true, watcher } => WatcherSummary::extract(watcher, mc).map(|x| (x.entity, x.name, x.value)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(summaries, [
+            ("Sprite".to_owned(), "my global".to_owned(), "0".to_owned()),
+            ("Sprite".to_owned(), "my field".to_owned(), "0".to_owned()),
+            ("Sprite".to_owned(), "my local".to_owned(), "0".to_owned()),
+        ]);
+    });
+}
+
+#[test]
+fn test_proj_sensing() {
+    let touching = Config::<C, StdSystem<C>> {
+        request: Some(Rc::new(|_, _, key, request, entity| match request {
+            Request::IsTouching { target } => {
+                let (x, y) = (entity.state.props.pos.0.get(), entity.state.props.pos.1.get());
+                let res = match target {
+                    TouchTarget::Entity(target) => {
+                        let other = target.borrow().state.props.pos;
+                        (other.0.get() - x).hypot(other.1.get() - y) < 60.0
+                    }
+                    TouchTarget::Edge => x.abs() > 240.0 || y.abs() > 180.0,
+                    TouchTarget::Mouse | TouchTarget::Drawings => false,
+                };
+                key.complete(Ok(Intermediate::Json(json!(res))));
+                RequestStatus::Handled
+            }
+            _ => RequestStatus::UseDefault { key, request },
+        })),
+        command: None,
+    };
+    let system = Rc::new(StdSystem::new_offline(None, touching.fallback(&default_properties_config()), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/sensing.xml"), system);
+    proj.mutate(|mc, proj| {
+        run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let expected = Value::from_json(mc, json!([
+            50, 36.86989764584402, 0, true, false, "7", 30, 90, 0,
+            [false, true, 300, -81.57303097851933],
+        ])).unwrap();
+        let got = global_context.globals.lookup("res").unwrap().get().clone();
+        assert_values_eq(&got, &expected, 1e-10, "sensing");
     });
 }
 
 #[test]
 fn test_proj_costumes() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(with_mock_services(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default())));
     let proj = get_running_project(include_str!("projects/costumes.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -411,7 +475,7 @@ fn test_proj_costumes() {
 
 #[test]
 fn test_proj_broadcast() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/broadcast.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -434,7 +498,7 @@ fn test_proj_broadcast() {
 
 #[test]
 fn test_proj_broadcast_to() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/broadcast-to.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -459,7 +523,7 @@ fn test_proj_broadcast_to() {
 
 #[test]
 fn test_proj_any_msg() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/any-msg.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -504,7 +568,7 @@ fn test_proj_any_msg() {
 
 #[test]
 fn test_proj_launch() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/launch.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -542,7 +606,7 @@ fn test_proj_launch() {
 
 #[test]
 fn test_proj_cloning() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/cloning.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -556,9 +620,71 @@ fn test_proj_cloning() {
     });
 }
 
+#[test]
+fn test_proj_cloning_restart() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let ast = ast::Parser::default().parse(include_str!("projects/cloning.xml")).unwrap();
+    let proj = EnvArena::new(Default::default(), |mc| {
+        let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+        let settings = Settings { max_clones: 8, ..Default::default() };
+        let proj = Project::from_init(mc, &init_info, Rc::new(bytecode), settings, system).unwrap();
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    for input in [Input::Start, Input::Stop, Input::Start, Input::Start, Input::Stop] {
+        let stop = matches!(input, Input::Stop);
+        proj.mutate(|mc, proj| {
+            let mut proj = proj.proj.borrow_mut(mc);
+            proj.input(mc, input);
+            run_till_term(mc, &mut proj).unwrap();
+
+            let snapshot = proj.snapshot().unwrap();
+            assert_eq!(snapshot.clones.len(), if stop { 0 } else { 8 });
+            assert_eq!(snapshot.scripts.len(), if stop { 3 } else { 3 * 9 });
+
+            let global_context = proj.get_global_context();
+            let global_context = global_context.borrow();
+            assert_eq!(global_context.entities["Sprite"].borrow().clone_count, if stop { 0 } else { 8 });
+            if !stop {
+                let expected = Value::from_json(mc, json!(["0", 1, 2, 3, 4, 5, 6, 7, 8])).unwrap();
+                assert_values_eq(&global_context.globals.lookup("foo").unwrap().get().clone(), &expected, 0.005, "foo");
+            }
+        });
+    }
+}
+
+#[test]
+fn test_proj_step_budget_restart() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let ast = ast::Parser::default().parse(include_str!("projects/cloning.xml")).unwrap();
+    let proj = EnvArena::new(Default::default(), |mc| {
+        let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+        let settings = Settings { max_clones: 8, max_project_steps: Some(u64::MAX), ..Default::default() };
+        let proj = Project::from_init(mc, &init_info, Rc::new(bytecode), settings, system).unwrap();
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    proj.mutate(|mc, proj| {
+        let mut proj = proj.proj.borrow_mut(mc);
+        proj.input(mc, Input::Start);
+        run_till_term(mc, &mut proj).unwrap();
+
+        let global_context = proj.get_global_context();
+        let steps = global_context.borrow().steps;
+        assert!(steps > 0);
+        global_context.borrow_mut(mc).settings.max_project_steps = Some(steps + steps / 2);
+
+        for input in [Input::Stop, Input::Start, Input::Start] {
+            proj.input(mc, input);
+            assert_eq!(global_context.borrow().steps, 0);
+            run_till_term(mc, &mut proj).unwrap();
+        }
+    });
+}
+
 #[test]
 fn test_proj_pause() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/pause.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -572,7 +698,7 @@ fn test_proj_pause() {
 
 #[test]
 fn test_proj_loop_yields() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/loop-yields.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -590,7 +716,7 @@ fn test_proj_loop_yields() {
 
 #[test]
 fn test_proj_run_call_ask_tell() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/run-call-ask-tell.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -615,7 +741,7 @@ fn test_proj_run_call_ask_tell() {
 
 #[test]
 fn test_proj_custom_events() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, default_properties_config(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, default_properties_config(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/custom-events.xml"), system);
     proj.mutate(|mc, proj| {
         let mut proj = proj.proj.borrow_mut(mc);
@@ -648,7 +774,7 @@ fn test_proj_custom_events() {
 
 #[test]
 fn test_proj_parallel_rpcs() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(with_mock_services(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default())));
     let proj = get_running_project(include_str!("projects/parallel-rpcs.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -690,7 +816,7 @@ fn test_proj_parallel_rpcs() {
 
 #[test]
 fn test_proj_wait_until() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/wait-until.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -704,7 +830,7 @@ fn test_proj_wait_until() {
 
 #[test]
 fn test_proj_nested_lists_consts() {
-    let system = Rc::new(StdSystem::new_sync(BASE_URL.to_owned(), None, Config::default(), UtcOffset::UTC));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
     let proj = get_running_project(include_str!("projects/nested-lists-consts.xml"), system);
     proj.mutate(|mc, proj| {
         run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
@@ -715,3 +841,507 @@ fn test_proj_nested_lists_consts() {
         assert_values_eq(&global_context.globals.lookup("abc").unwrap().get(), &expected, 1e-20, "nested lists consts");
     });
 }
+
+#[test]
+fn test_proj_multi_role() {
+    let ast = ast::Parser::default().parse(include_str!("projects/multi-role.xml")).unwrap();
+    assert_eq!(ast.roles.len(), 2);
+
+    let network = Arc::new(LocalNetwork::new(ast.name.clone()));
+    let mut projs: Vec<_> = ast.roles.iter().map(|role| {
+        let system = Rc::new(StdSystem::new_offline(Some(&ast.name), Config::default(), UtcOffset::UTC, Default::default()).with_local_network(network.clone(), &role.name));
+        get_running_role(role, system)
+    }).collect();
+    assert_eq!(network.roles(), ["alice", "bob"]);
+
+    let mut idle_rounds = 0;
+    for _ in 0..100000 {
+        let mut all_idle = true;
+        for proj in projs.iter_mut() {
+            proj.mutate(|mc, proj| match proj.proj.borrow_mut(mc).step(mc) {
+                ProjectStep::Idle => (),
+                ProjectStep::Error { error, .. } => panic!("{:?}", error.cause),
+                _ => all_idle = false,
+            });
+        }
+        idle_rounds = if all_idle { idle_rounds + 1 } else { 0 };
+        if idle_rounds >= 2 { break }
+    }
+    assert!(idle_rounds >= 2);
+
+    projs[0].mutate(|mc, proj| {
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+        let expected = Value::from_json(mc, json!("re: ping")).unwrap();
+        assert_values_eq(&global_context.globals.lookup("reply").unwrap().get(), &expected, 1e-20, "reply");
+    });
+    projs[1].mutate(|mc, proj| {
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+        let expected = Value::from_json(mc, json!(["hello", "ping"])).unwrap();
+        assert_values_eq(&global_context.globals.lookup("got").unwrap().get(), &expected, 1e-20, "got");
+    });
+}
+
+#[test]
+fn test_proj_stop() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/stop.xml"), system);
+    proj.mutate(|mc, proj| {
+        run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let get = |name: &str| global_context.globals.lookup(name).unwrap().get().to_number().unwrap().get();
+        assert_eq!(get("t"), 20.0);
+        assert!(get("a") > 0.0 && get("b") > 0.0 && get("s") > 0.0);
+        assert_eq!(get("a"), get("a_snap"));
+        assert_eq!(get("b"), get("b_snap"));
+    });
+}
+
+#[test]
+fn test_proj_delete_clone() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/delete-clone.xml"), system);
+    proj.mutate(|mc, proj| {
+        run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let get = |name: &str| global_context.globals.lookup(name).unwrap().get().to_number().unwrap().get();
+        assert_eq!(get("n"), 8.0);
+        assert_eq!(get("t"), 40.0);
+        assert_eq!(get("bad"), 0.0);
+        assert!(get("loops") > 0.0);
+        assert_values_eq(&global_context.globals.lookup("root").unwrap().get(), &Value::from_json(mc, json!("alive")).unwrap(), 1e-20, "root");
+        assert_eq!(global_context.entities["Sprite"].borrow().clone_count, 0);
+    });
+}
+
+#[test]
+fn test_proj_clone_limit() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let parser = ast::Parser::default();
+    let ast = parser.parse(include_str!("projects/delete-clone.xml")).unwrap();
+    let proj = EnvArena::new(Default::default(), |mc| {
+        let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+        let settings = Settings { max_clones: 4, ..Default::default() };
+        let mut proj = Project::from_init(mc, &init_info, Rc::new(bytecode), settings, system).unwrap();
+        proj.input(mc, Input::Start);
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+    proj.mutate(|mc, proj| match run_till_term(mc, &mut *proj.proj.borrow_mut(mc)) {
+        Err(e) => assert!(matches!(e.cause, ErrorCause::CloneLimit { limit: 4 })),
+        Ok(_) => panic!("expected clone limit error"),
+    });
+}
+
+#[test]
+fn test_proj_when_condition() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/when-condition.xml"), system);
+    proj.mutate(|mc, proj| {
+        run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let get = |name: &str| global_context.globals.lookup(name).unwrap().get().to_number().unwrap().get();
+        assert_eq!(get("t"), 200.0);
+        assert_eq!(get("fired"), 1.0);
+        assert_eq!(get("init"), 1.0);
+    });
+}
+
+#[test]
+fn test_proj_when_condition_limits() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/when-condition-limits.xml"), system);
+    proj.mutate(|mc, proj| {
+        match run_till_term(mc, &mut proj.proj.borrow_mut(mc)) {
+            Err(e) => assert!(matches!(e.cause, ErrorCause::ConditionSideEffect), "{:?}", e.cause),
+            Ok(_) => panic!("expected condition side effect error"),
+        }
+        let global_context = proj.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let get = |name: &str| global_context.globals.lookup(name).unwrap().get().to_number().unwrap().get();
+        assert!(get("t") > 40.0 && get("t") < 50.0);
+        assert!(get("spins") > 0.0); // the non-yielding condition is paused between polls instead of hanging the project
+        assert_eq!(get("fired"), 0.0);
+    });
+}
+
+#[test]
+fn test_proj_key_pressed() {
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = get_running_project(include_str!("projects/key-pressed.xml"), system);
+
+    let input = |input: Input| proj.mutate(|mc, proj| proj.proj.borrow_mut(mc).input(mc, input));
+    let check = |expected: [bool; 3]| proj.mutate(|mc, proj| {
+        let mut proj = proj.proj.borrow_mut(mc);
+        for _ in 0..64 {
+            if let ProjectStep::Error { error, .. } = proj.step(mc) { panic!("{:?}", error.cause) }
+        }
+        let global_context = proj.get_global_context();
+        let global_context = global_context.borrow();
+        for (var, expected) in iter::zip(["a", "up", "any"], expected) {
+            assert_values_eq(&global_context.globals.lookup(var).unwrap().get(), &expected.into(), 1e-20, var);
+        }
+    });
+
+    check([false, false, false]);
+    input(Input::KeyDown { key: KeyCode::Char('a') });
+    check([true, false, true]);
+    input(Input::KeyDown { key: KeyCode::Up });
+    check([true, true, true]);
+    input(Input::KeyUp { key: KeyCode::Char('a') });
+    check([false, true, true]);
+    input(Input::KeyUp { key: KeyCode::Up });
+    check([false, false, false]);
+
+    proj.mutate(|mc, proj| proj.proj.borrow().get_global_context().borrow_mut(mc).settings.key_hold_timeout = Some(0));
+    input(Input::KeyDown { key: KeyCode::Char('a') });
+    check([false, false, false]);
+}
+
+#[test]
+fn test_proj_record_replay() {
+    fn run<'gc>(mc: &Mutation<'gc>, proj: &mut Project<'gc, C, StdSystem<C>>, recorder: Option<&Recorder>, replayer: Option<&Replayer>) -> Json {
+        for step in 0..1024 {
+            if let Some(replayer) = replayer {
+                for input in replayer.take_inputs(step) { proj.input(mc, input); }
+            }
+            if let ProjectStep::Error { error, .. } = proj.step(mc) { panic!("{:?}", error.cause) }
+            if let Some(recorder) = recorder { recorder.set_step(step + 1); }
+        }
+        let global_context = proj.get_global_context();
+        let global_context = global_context.borrow();
+        let res = json!([
+            global_context.globals.lookup("vals").unwrap().get().to_json().unwrap(),
+            global_context.globals.lookup("timer").unwrap().get().to_json().unwrap(),
+        ]);
+        res
+    }
+
+    let events = Rc::new(RefCell::new(vec![]));
+    let recorder = Rc::new(Recorder::new(Box::new({ let events = events.clone(); move |event| events.borrow_mut().push(event) })));
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()).with_recorder(recorder.clone()));
+    let proj = get_running_project(include_str!("projects/replay.xml"), system);
+    let recorded = proj.mutate(|mc, proj| run(mc, &mut *proj.proj.borrow_mut(mc), Some(&recorder), None));
+    recorder.record_mark();
+
+    let recording = events.borrow().iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+    let replayer = Rc::new(Replayer::new(Recording::parse(&recording).unwrap()));
+    assert_eq!(replayer.steps(), 1024);
+    let system = Rc::new(StdSystem::new_replay(replayer.clone(), Config::default(), UtcOffset::UTC));
+    let proj = get_running_project(include_str!("projects/replay.xml"), system);
+    let replayed = proj.mutate(|mc, proj| run(mc, &mut *proj.proj.borrow_mut(mc), None, Some(&replayer)));
+
+    assert_eq!(recorded[0].as_array().unwrap().len(), 20);
+    assert_eq!(recorded, replayed);
+}
+
+#[test]
+fn test_proj_offline_determinism() {
+    let run = |seed: u64| {
+        let clock = Arc::new(ManualClock::new(OffsetDateTime::UNIX_EPOCH));
+        let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, OfflineOptions { seed: Some(seed), clock: Some(clock.clone()) }));
+        let proj = get_running_project(include_str!("projects/replay.xml"), system);
+        clock.advance(core::time::Duration::from_millis(1500));
+        proj.mutate(|mc, proj| {
+            run_till_term(mc, &mut *proj.proj.borrow_mut(mc)).unwrap();
+            let global_context = proj.proj.borrow().get_global_context();
+            let global_context = global_context.borrow();
+            let res = json!([
+                global_context.globals.lookup("vals").unwrap().get().to_json().unwrap(),
+                global_context.globals.lookup("timer").unwrap().get().to_json().unwrap(),
+            ]);
+            res
+        })
+    };
+
+    let (a, b, c) = (run(1), run(1), run(2));
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(a[1], json!(1.5));
+}
+
+#[test]
+fn test_proj_snapshot_restore() {
+    let ast = ast::Parser::default().parse(include_str!("projects/snapshot.xml")).unwrap();
+    let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+    let bytecode = Rc::new(bytecode);
+
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let original = EnvArena::new(Default::default(), |mc| {
+        let mut proj = Project::from_init(mc, &init_info, bytecode.clone(), Settings::default(), system).unwrap();
+        proj.input(mc, Input::Start);
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    let snapshot = original.mutate(|mc, env| {
+        let mut proj = env.proj.borrow_mut(mc);
+        for _ in 0..200 {
+            assert!(!matches!(proj.step(mc), ProjectStep::Idle | ProjectStep::Error { .. }));
+        }
+        proj.snapshot().unwrap()
+    });
+    assert_eq!(snapshot.process_queue.len(), 1);
+
+    #[cfg(feature = "serde")]
+    let snapshot: ProjectSnapshot<EntityState> = serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let restored = EnvArena::new(Default::default(), |mc| {
+        let mut bad_snapshot = snapshot.clone();
+        bad_snapshot.processes[0].pos = usize::MAX;
+        assert!(matches!(Project::from_snapshot(mc, &bad_snapshot, bytecode.clone(), Settings::default(), system.clone()), Err(SnapshotError::VerifyError { error: VerifyError::BadPosition { pos: usize::MAX } })));
+
+        let proj = Project::from_snapshot(mc, &snapshot, bytecode.clone(), Settings::default(), system).unwrap();
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    let results = [original, restored].map(|mut arena| arena.mutate_root(|mc, env| {
+        run_till_term(mc, &mut *env.proj.borrow_mut(mc)).unwrap();
+        let global_context = env.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+
+        let (a, b) = match (global_context.globals.lookup("a").unwrap().get().clone(), global_context.globals.lookup("b").unwrap().get().clone()) {
+            (Value::List(a), Value::List(b)) => (a, b),
+            x => panic!("{x:?}"),
+        };
+        assert!(Gc::ptr_eq(a, b));
+        match &a.borrow()[2] {
+            Value::List(inner) => assert!(Gc::ptr_eq(*inner, a)),
+            x => panic!("{x:?}"),
+        }
+
+        let res = json!([
+            global_context.globals.lookup("total").unwrap().get().to_json().unwrap(),
+            global_context.globals.lookup("res").unwrap().get().to_json().unwrap(),
+        ]);
+        res
+    }));
+    assert_eq!(results[0], json!([500.0, 600.0]));
+    assert_eq!(results[0], results[1]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_proj_bundle() {
+    use crate::bundle::*;
+
+    let ast = ast::Parser::default().parse(include_str!("projects/snapshot.xml")).unwrap();
+    let bundle = Bundle::compile(&ast.name, &ast.roles[0], Default::default()).unwrap();
+    let content = bundle.save();
+
+    let bundle = Bundle::load(&content).unwrap();
+    assert_eq!(bundle.info.project, ast.name);
+    assert_eq!(bundle.info.role, ast.roles[0].name);
+    assert_eq!(bundle.info.vm_version, env!("CARGO_PKG_VERSION"));
+
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let mut env = EnvArena::new(Default::default(), |mc| {
+        let mut proj = Project::from_init(mc, &bundle.init_info, Rc::new(bundle.bytecode), Settings::default(), system).unwrap();
+        proj.input(mc, Input::Start);
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+    let res = env.mutate_root(|mc, env| {
+        run_till_term(mc, &mut *env.proj.borrow_mut(mc)).unwrap();
+        let global_context = env.proj.borrow().get_global_context();
+        let global_context = global_context.borrow();
+        let res = json!([
+            global_context.globals.lookup("total").unwrap().get().to_json().unwrap(),
+            global_context.globals.lookup("res").unwrap().get().to_json().unwrap(),
+        ]);
+        res
+    });
+    assert_eq!(res, json!([500.0, 600.0]));
+
+    let mut modified = parse_json_slice::<Json>(&content).unwrap();
+    modified["version"] = json!(BUNDLE_FORMAT_VERSION + 1);
+    match Bundle::load(&serde_json::to_vec(&modified).unwrap()) {
+        Err(BundleError::IncompatibleVersion { version, vm_version }) => {
+            assert_eq!(version, BUNDLE_FORMAT_VERSION as u64 + 1);
+            assert_eq!(vm_version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        }
+        x => panic!("{:?}", x.err()),
+    }
+
+    modified["version"] = json!(BUNDLE_FORMAT_VERSION);
+    modified["bytecode"]["code"] = json!("ff");
+    assert!(matches!(Bundle::load(&serde_json::to_vec(&modified).unwrap()), Err(BundleError::VerifyError { .. })));
+
+    assert!(matches!(Bundle::load(include_bytes!("projects/snapshot.xml")), Err(BundleError::NotABundle)));
+    assert!(matches!(Bundle::load(br#"{"format":"something else","version":1}"#), Err(BundleError::NotABundle)));
+}
+
+#[test]
+fn test_proj_debugger() {
+    let ast = ast::Parser::default().parse(include_str!("projects/debugger.xml")).unwrap();
+    let (bytecode, init_info, locs, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = EnvArena::new(Default::default(), |mc| {
+        let mut proj = Project::from_init(mc, &init_info, Rc::new(bytecode), Settings::default(), system).unwrap();
+        assert!(proj.add_breakpoint(&locs, "item_4"));
+        assert!(!proj.add_breakpoint(&locs, "item_999"));
+        proj.input(mc, Input::Start);
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    fn run_till_break<'gc>(mc: &Mutation<'gc>, proj: &mut Project<'gc, C, StdSystem<C>>) {
+        let events = run_till_term(mc, proj).unwrap();
+        assert!(matches!(events.as_slice(), [SpecialEvent::Break]), "{events:?}");
+    }
+
+    proj.mutate(|mc, env| {
+        let mut proj = env.proj.borrow_mut(mc);
+        let global = |proj: &Project<'_, C, StdSystem<C>>, name: &str| proj.get_global_context().borrow().globals.lookup(name).unwrap().get().to_json().unwrap();
+        let at_block = |proj: &Project<'_, C, StdSystem<C>>, loc: &str| locs.find(loc).contains(&proj.get_paused_process().unwrap().get_pos());
+        let depth = |proj: &Project<'_, C, StdSystem<C>>| proj.get_paused_process().unwrap().get_call_stack().len();
+        let top = |proj: &Project<'_, C, StdSystem<C>>| proj.get_paused_process().unwrap().get_value_stack().last().map(|x| x.to_json().unwrap());
+
+        run_till_break(mc, &mut proj);
+        assert!(matches!(proj.step(mc), ProjectStep::Break));
+        assert!(at_block(&proj, "item_4"));
+        assert_eq!(global(&proj, "x"), json!("0"));
+
+        let summary = DebugSummary::extract(proj.get_paused_process().unwrap(), &locs);
+        assert_eq!(summary.location.as_deref(), Some("item_4"));
+        assert_eq!(summary.entity, "Sprite");
+        assert_eq!(summary.trace.len(), 1);
+        assert_eq!(summary.value_stack.len(), 1); // the repeat counter
+
+        proj.resume(&locs, StepMode::Step);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_4"));
+        assert_eq!(global(&proj, "x"), json!(1.0));
+
+        proj.resume(&locs, StepMode::Continue);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_4"));
+        assert_eq!(global(&proj, "x"), json!(2.0));
+
+        proj.clear_breakpoints();
+        assert!(proj.add_breakpoint(&locs, "item_6"));
+        proj.resume(&locs, StepMode::Continue);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_6"));
+        assert_eq!(global(&proj, "x"), json!(3.0));
+        assert_eq!(depth(&proj), 1);
+
+        proj.resume(&locs, StepMode::StepOver);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_8"));
+        assert_eq!(global(&proj, "y"), json!(6.0));
+
+        proj.remove_breakpoint(&locs, "item_6");
+        assert!(proj.add_breakpoint(&locs, "item_9"));
+        proj.resume(&locs, StepMode::Continue);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_9"));
+
+        proj.resume(&locs, StepMode::Step);
+        run_till_break(mc, &mut proj);
+        assert!(at_block(&proj, "item_21"));
+        assert_eq!(depth(&proj), 2);
+
+        proj.resume(&locs, StepMode::StepOut);
+        run_till_break(mc, &mut proj);
+        assert_eq!(depth(&proj), 1);
+        assert_eq!(top(&proj), Some(json!(10.0)));
+
+        proj.resume(&locs, StepMode::Continue);
+        assert!(run_till_term(mc, &mut proj).unwrap().is_empty());
+        assert!(proj.get_paused_process().is_none());
+        assert_eq!(global(&proj, "z"), json!(10.0));
+    });
+}
+
+#[test]
+fn test_proj_profiler() {
+    let ast = ast::Parser::default().parse(include_str!("projects/debugger.xml")).unwrap();
+    let (bytecode, init_info, locs, script_info) = ByteCode::compile(&ast.roles[0]).unwrap();
+    let bytecode = Rc::new(bytecode);
+
+    let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+    let proj = EnvArena::new(Default::default(), |mc| {
+        let proj = Project::from_init(mc, &init_info, bytecode.clone(), Settings::default(), system).unwrap();
+        let ticks = Cell::new(0);
+        proj.get_global_context().borrow_mut(mc).profiler = Some(Profiler::new(Box::new(move || { ticks.set(ticks.get() + 1); ticks.get() })));
+        Env { proj: Gc::new(mc, RefLock::new(proj)) }
+    });
+
+    proj.mutate(|mc, env| {
+        let mut proj = env.proj.borrow_mut(mc);
+        proj.input(mc, Input::Start);
+        assert!(run_till_term(mc, &mut proj).unwrap().is_empty());
+
+        let global_context = proj.get_global_context();
+        let global_context = global_context.borrow();
+        let profiler = global_context.profiler.as_ref().unwrap();
+
+        let report = profiler.report(&bytecode, &locs, &script_info);
+        assert_eq!(report.total.instructions, report.total.time);
+        assert_eq!(report.blocks.iter().map(|x| x.instructions).sum::<u64>(), report.total.instructions);
+        assert_eq!(report.blocks[0], ProfileEntry { name: "item_4".into(), instructions: 23, time: 23 });
+        assert_eq!(report.blocks.iter().find(|x| x.name == "item_22").unwrap().instructions, 4);
+        assert_eq!(report.custom_blocks, [ProfileEntry { name: "double _".into(), instructions: 8, time: 8 }]);
+
+        let folded = profiler.folded(&bytecode, &locs, &script_info, ProfileMetric::Instructions);
+        assert_eq!(folded.lines().map(|x| x.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap()).sum::<u64>(), report.total.instructions);
+        assert!(folded.contains("Sprite script item_1;item_4 23\n"), "{folded}");
+        assert!(folded.contains("Sprite script item_1;item_6;double _;item_22 2\n"), "{folded}");
+        assert!(folded.contains("Sprite script item_1;item_9;double _;item_22 2\n"), "{folded}");
+    });
+}
+
+#[test]
+fn test_proj_scheduling() {
+    fn run(scheduling: Scheduling, priorities: &[(&str, i32)], late_priorities: &[(&str, i32)]) -> (String, usize) {
+        let system = Rc::new(StdSystem::new_offline(None, Config::default(), UtcOffset::UTC, Default::default()));
+        let ast = ast::Parser::default().parse(include_str!("projects/scheduling.xml")).unwrap();
+        let env = EnvArena::new(Default::default(), |mc| {
+            let (bytecode, init_info, _, _) = ByteCode::compile(&ast.roles[0]).unwrap();
+            let mut proj = Project::from_init(mc, &init_info, Rc::new(bytecode), Settings { scheduling, ..Default::default() }, system).unwrap();
+            for (entity, priority) in priorities {
+                proj.set_priority(entity, *priority);
+            }
+            proj.input(mc, Input::Start);
+            Env { proj: Gc::new(mc, RefLock::new(proj)) }
+        });
+        env.mutate(|mc, env| {
+            let mut proj = env.proj.borrow_mut(mc);
+            let mut frames = 0;
+            for i in 0.. {
+                if i == 1 {
+                    for (entity, priority) in late_priorities {
+                        proj.set_priority(entity, *priority);
+                    }
+                }
+                match proj.step(mc) {
+                    ProjectStep::Idle => break,
+                    ProjectStep::FrameEnd => frames += 1,
+                    ProjectStep::Error { error, .. } => panic!("{:?}", error.cause),
+                    _ => (),
+                }
+            }
+            let global_context = proj.get_global_context();
+            let global_context = global_context.borrow();
+            let log = global_context.globals.lookup("log").unwrap().get().as_list().unwrap();
+            let log = log.borrow().iter().map(|x| x.to_string().unwrap().into_owned()).collect::<String>();
+            (log, frames)
+        })
+    }
+
+    assert_eq!(run(Scheduling::UntilYield, &[], &[]), ("sssppp".into(), 0)); // warp never yields, so the sprite must wait
+    assert_eq!(run(Scheduling::RoundRobin { quantum: 1 }, &[], &[]), ("pspsps".into(), 0));
+    assert_eq!(run(Scheduling::RoundRobin { quantum: 8 }, &[], &[]), ("psspsp".into(), 0));
+    assert_eq!(run(Scheduling::Priority { quantum: 1000 }, &[], &[]), ("sssppp".into(), 0));
+    assert_eq!(run(Scheduling::Priority { quantum: 1000 }, &[("Sprite", 1)], &[]), ("pssspp".into(), 0));
+    assert_eq!(run(Scheduling::Priority { quantum: 4 }, &[("Sprite", 1)], &[]), ("pspsps".into(), 0));
+    assert_eq!(run(Scheduling::Priority { quantum: 4 }, &[], &[("Sprite", 1)]), ("pspsps".into(), 0)); // changing a priority applies to already scheduled processes
+    assert_eq!(run(Scheduling::Frames, &[], &[]), ("sssppp".into(), 3));
+}
@@ -1,4 +1,5 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 
 pub struct LosslessJoin {
     content: String,
@@ -23,6 +24,15 @@ pub fn lossless_split(src: &str) -> impl Iterator<Item = &str> {
     src.split('\0').skip(1)
 }
 
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let chunks = hex.as_bytes().chunks_exact(2);
+    if !chunks.remainder().is_empty() { return None }
+    chunks.map(|x| u8::from_str_radix(core::str::from_utf8(x).ok()?, 16).ok()).collect()
+}
+
 #[test]
 fn test_lossless_split() {
     fn assert_round_trip(input: &[&str], output: &str) {